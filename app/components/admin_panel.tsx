@@ -3,7 +3,15 @@
 import { useCallback, useEffect, useState } from 'react'
 import { Trash2 } from 'lucide-react'
 import * as api from '../lib/api'
-import type { ServerDetail, ServerSummaryLite } from '../lib/types'
+import type {
+  AdminInvite,
+  AdminReport,
+  AdminServerConfig,
+  AnnouncementLevel,
+  PurgeCounts,
+  ServerDetail,
+  ServerSummaryLite,
+} from '../lib/types'
 import { useStore } from '../lib/store'
 import { UserAvatar } from './user_avatar'
 import {
@@ -125,10 +133,76 @@ export function SiteSwitches() {
         value={settings.guests_enabled}
         onChange={v => void updateSettings({ guests_enabled: v })}
       />
+      <ServerCap />
     </div>
   )
 }
 
+function ServerCap() {
+  const cap = useStore(s => s.settings.max_servers_per_user)
+  const updateSettings = useStore(s => s.updateSettings)
+  const [value, setValue] = useState(cap ? String(cap) : '')
+  useEffect(() => setValue(cap ? String(cap) : ''), [cap])
+  return (
+    <form
+      onSubmit={e => {
+        e.preventDefault()
+        const raw = value.trim()
+        const n = Number(raw)
+        if (!raw) void updateSettings({ max_servers_per_user: null })
+        else if (Number.isInteger(n) && n >= 0) void updateSettings({ max_servers_per_user: n })
+      }}
+      className="flex gap-2 px-2 py-1.5"
+    >
+      <input
+        value={value}
+        onChange={e => setValue(e.target.value)}
+        inputMode="numeric"
+        placeholder="Servers per user (server default)"
+        className={fieldCls}
+      />
+      <button className={textBtn}>Save</button>
+    </form>
+  )
+}
+
+function Announce() {
+  const announce = useStore(s => s.announce)
+  const [message, setMessage] = useState('')
+  const [level, setLevel] = useState<AnnouncementLevel>('info')
+  const [post, setPost] = useState(false)
+  return (
+    <form
+      onSubmit={e => {
+        e.preventDefault()
+        if (message.trim()) void announce(message, level, post).then(() => setMessage(''))
+      }}
+      className="flex flex-wrap items-center gap-2 py-1.5"
+    >
+      <input
+        value={message}
+        onChange={e => setMessage(e.target.value)}
+        placeholder="Announcement"
+        className={fieldCls}
+      />
+      <select
+        value={level}
+        onChange={e => setLevel(e.target.value as AnnouncementLevel)}
+        className={fieldCls}
+      >
+        <option value="info">Info</option>
+        <option value="warning">Warning</option>
+        <option value="critical">Critical</option>
+      </select>
+      <label className="flex items-center gap-1 text-sm">
+        <input type="checkbox" checked={post} onChange={e => setPost(e.target.checked)} />
+        Post in RChat
+      </label>
+      <button className={textBtn}>Announce</button>
+    </form>
+  )
+}
+
 function DangerTools() {
   const adminDeleteMessage = useStore(s => s.adminDeleteMessage)
   const [id, setId] = useState('')
@@ -208,6 +282,7 @@ function Panel() {
   const loadAdminUsers = useStore(s => s.loadAdminUsers)
   const loadAdminServers = useStore(s => s.loadAdminServers)
   const adminDeleteServer = useStore(s => s.adminDeleteServer)
+  const runCleanup = useStore(s => s.runCleanup)
   const closeDialog = useStore(s => s.closeDialog)
   const openDialog = useStore(s => s.openDialog)
   const me = useStore(s => s.me)
@@ -232,6 +307,12 @@ function Panel() {
     <Dialog full title="Site Administration" onClose={closeDialog}>
       <p className={sectionCls}>Settings</p>
       <SiteSwitches />
+      <button onClick={() => void runCleanup()} className={textBtn}>
+        Run cleanup now
+      </button>
+      <Invites />
+      <Reports />
+      <Announce />
       <p className={sectionCls}>Servers{overview ? ` (${overview.server_count})` : ''}</p>
       <div className="mb-2 flex">
         <input
@@ -285,6 +366,7 @@ function Panel() {
               <UserAvatar
                 username={u.username}
                 avatarKind={u.avatar_kind}
+                avatarUrl={u.avatar_url}
                 avatarColor={u.avatar_color}
                 size={28}
               />
@@ -327,6 +409,113 @@ function Panel() {
   )
 }
 
+function Invites() {
+  const setError = useStore(s => s.setError)
+  const open = useStore(s => s.settings.registration_open)
+  const [invites, setInvites] = useState<AdminInvite[]>([])
+  const refresh = useCallback(
+    () =>
+      api
+        .adminInvites()
+        .then(setInvites)
+        .catch((e: unknown) => setError(e instanceof Error ? e.message : String(e))),
+    [setError]
+  )
+  useEffect(() => {
+    void refresh()
+  }, [refresh])
+  const run = (fn: () => Promise<unknown>) =>
+    void fn()
+      .then(refresh)
+      .catch((e: unknown) => setError(e instanceof Error ? e.message : String(e)))
+  return (
+    <>
+      <p className={sectionCls}>Invites</p>
+      <p className="mb-2 text-xs text-on-surface-variant">
+        {open
+          ? 'Registration is open; invites are only needed when REGISTRATION_OPEN=false.'
+          : 'Registration is closed. New accounts need one of these single-use codes.'}
+      </p>
+      <div className="space-y-1">
+        {invites.map(inv => (
+          <div key={inv.code} className="flex items-center gap-2 rounded-xl px-2 py-1.5">
+            <code className="min-w-0 flex-1 truncate text-sm">{inv.code}</code>
+            {inv.used_by ? (
+              <span className="text-xs text-on-surface-variant">used by {inv.used_by}</span>
+            ) : (
+              <button
+                onClick={() => run(() => api.adminDeleteInvite(inv.code))}
+                aria-label={`Revoke ${inv.code}`}
+                className={dangerBtn}
+              >
+                <Trash2 size={16} />
+              </button>
+            )}
+          </div>
+        ))}
+      </div>
+      <button onClick={() => run(api.adminCreateInvite)} className={textBtn}>
+        New invite
+      </button>
+    </>
+  )
+}
+
+function Reports() {
+  const setError = useStore(s => s.setError)
+  const adminDeleteMessage = useStore(s => s.adminDeleteMessage)
+  const [reports, setReports] = useState<AdminReport[]>([])
+  const refresh = useCallback(
+    () =>
+      api
+        .adminReports('open')
+        .then(setReports)
+        .catch((e: unknown) => setError(e instanceof Error ? e.message : String(e))),
+    [setError]
+  )
+  useEffect(() => {
+    void refresh()
+  }, [refresh])
+  const run = (fn: () => Promise<unknown>) =>
+    void fn()
+      .then(refresh)
+      .catch((e: unknown) => setError(e instanceof Error ? e.message : String(e)))
+  const remove = (id: number) => () => run(() => adminDeleteMessage(id))
+  return (
+    <>
+      <p className={sectionCls}>Reports ({reports.length})</p>
+      <div className="space-y-1">
+        {reports.map(r => (
+          <div key={r.id} className="flex items-center gap-2 rounded-xl px-2 py-1.5">
+            <div className="min-w-0 flex-1 text-sm">
+              <p className="truncate">
+                {r.message
+                  ? `${r.message.author.display_name}: ${r.message.content}`
+                  : `${r.author}: ${r.content} (deleted)`}
+              </p>
+              <p className="truncate text-xs text-on-surface-variant">
+                {r.reporter}: {r.reason}
+              </p>
+            </div>
+            {r.message && (
+              <button
+                onClick={remove(r.message.id)}
+                aria-label={`Delete message ${r.message.id}`}
+                className={dangerBtn}
+              >
+                <Trash2 size={16} />
+              </button>
+            )}
+            <button onClick={() => run(() => api.adminResolveReport(r.id))} className={textBtn}>
+              Resolve
+            </button>
+          </div>
+        ))}
+      </div>
+    </>
+  )
+}
+
 function ServerManage({ name, onCollapse }: { name: string; onCollapse: () => void }) {
   const setError = useStore(s => s.setError)
   const renameServer = useStore(s => s.renameServer)
@@ -339,13 +528,16 @@ function ServerManage({ name, onCollapse }: { name: string; onCollapse: () => vo
   const cache = useStore(s => s.members[name])
   const loadMembers = useStore(s => s.loadMembers)
   const [detail, setDetail] = useState<ServerDetail | null>(null)
+  const [config, setConfig] = useState<AdminServerConfig | null>(null)
   const [editingAdmin, setEditingAdmin] = useState<string | null>(null)
 
   const refresh = useCallback(
     () =>
-      api
-        .getServer(name)
-        .then(setDetail)
+      Promise.all([api.getServer(name), api.adminServer(name)])
+        .then(([d, c]) => {
+          setDetail(d)
+          setConfig(c)
+        })
         .catch((e: unknown) => setError(e instanceof Error ? e.message : String(e))),
     [name, setError]
   )
@@ -362,6 +554,12 @@ function ServerManage({ name, onCollapse }: { name: string; onCollapse: () => vo
 
   return (
     <div className="space-y-2 border-t border-outline-variant px-3 py-3">
+      {config && (
+        <p className="text-xs text-on-surface-variant">
+          {config.member_count} members · {config.message_count} messages · {config.role_count}{' '}
+          roles · created {new Date(config.created_at * 1000).toLocaleDateString()}
+        </p>
+      )}
       {name !== 'rchat' && (
         <>
           <NameForm
@@ -382,6 +580,7 @@ function ServerManage({ name, onCollapse }: { name: string; onCollapse: () => vo
           server={name}
           channel={c}
           roles={detail.roles}
+          order={detail.channels.map(ch => ch.id)}
           refresh={refresh}
           lead={
             <>
@@ -416,6 +615,7 @@ function ServerManage({ name, onCollapse }: { name: string; onCollapse: () => vo
             <UserAvatar
               username={m.username}
               avatarKind={m.avatar_kind}
+              avatarUrl={m.avatar_url}
               avatarColor={m.avatar_color}
               size={24}
             />
@@ -491,18 +691,45 @@ function DeleteUserConfirm({ username }: { username: string }) {
 function BanConfirm({ username }: { username: string }) {
   const banUser = useStore(s => s.banUser)
   const closeDialog = useStore(s => s.closeDialog)
+  const [purge, setPurge] = useState(false)
+  const [impact, setImpact] = useState<PurgeCounts | null>(null)
+  useEffect(() => {
+    if (!purge) return
+    let live = true
+    api
+      .banUser(username, true, true)
+      .then(r => live && setImpact(r.affected))
+      .catch(() => {})
+    return () => {
+      live = false
+    }
+  }, [username, purge])
   return (
     <Dialog title="Ban User" onClose={closeDialog}>
       <p className="text-sm text-on-surface-variant">
-        Ban {username} site-wide? This deletes their account and every message they have sent, and
-        permanently blocks the username.
+        Ban {username} site-wide? This signs them out and permanently blocks the username.
       </p>
+      <label className="mt-4 flex items-center gap-1.5 text-sm">
+        <input
+          type="checkbox"
+          checked={purge}
+          onChange={() => setPurge(!purge)}
+          className="accent-primary"
+        />
+        Also delete their account and every message they have sent
+      </label>
+      {purge && impact && (
+        <p className="mt-2 text-xs text-on-surface-variant">
+          This deletes {impact.messages} messages, {impact.dms} DMs, {impact.files} attachments
+          and {impact.memberships} server memberships.
+        </p>
+      )}
       <div className="mt-6 flex justify-end gap-2">
         <button onClick={closeDialog} className={textBtn}>
           Cancel
         </button>
         <button
-          onClick={() => void banUser(username).then(closeDialog)}
+          onClick={() => void banUser(username, purge).then(closeDialog)}
           className="rounded-full bg-error px-4 py-2 text-sm font-medium text-on-error hover:opacity-90"
         >
           Ban
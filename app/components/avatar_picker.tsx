@@ -7,28 +7,45 @@ interface Props {
   username: string
   kind: AvatarKind
   color: string
+  imageUrl?: string | null
   onKind: (kind: AvatarKind) => void
   onColor: (color: string) => void
+  onImage?: (file: File) => void
 }
 
-export function AvatarPicker({ username, kind, color, onKind, onColor }: Props) {
+const optionCls = (active: boolean) =>
+  `flex flex-1 flex-col items-center gap-2 rounded-2xl border p-3 transition-colors ${
+    active
+      ? 'border-primary bg-primary-container/30'
+      : 'border-outline-variant hover:bg-surface-container-high'
+  }`
+
+export function AvatarPicker({
+  username,
+  kind,
+  color,
+  imageUrl,
+  onKind,
+  onColor,
+  onImage,
+}: Props) {
+  const preview = (value: AvatarKind) => (
+    <UserAvatar
+      username={username.trim().toLowerCase() || 'preview'}
+      avatarKind={value}
+      avatarColor={color}
+      avatarUrl={imageUrl}
+      size={48}
+    />
+  )
   const option = (value: AvatarKind, label: string) => (
     <button
       type="button"
       title={`${label} avatar`}
       onClick={() => onKind(value)}
-      className={`flex flex-1 flex-col items-center gap-2 rounded-2xl border p-3 transition-colors ${
-        kind === value
-          ? 'border-primary bg-primary-container/30'
-          : 'border-outline-variant hover:bg-surface-container-high'
-      }`}
+      className={optionCls(kind === value)}
     >
-      <UserAvatar
-        username={username.trim().toLowerCase() || 'preview'}
-        avatarKind={value}
-        avatarColor={color}
-        size={48}
-      />
+      {preview(value)}
       <span className="text-xs text-on-surface-variant">{label}</span>
     </button>
   )
@@ -38,6 +55,22 @@ export function AvatarPicker({ username, kind, color, onKind, onColor }: Props)
       <div className="flex gap-3">
         {option('identicon', 'Identicon')}
         {option('color', 'Color')}
+        {onImage && (
+          <label title="Image avatar" className={`cursor-pointer ${optionCls(kind === 'image')}`}>
+            {preview(imageUrl ? 'image' : 'color')}
+            <span className="text-xs text-on-surface-variant">Image</span>
+            <input
+              type="file"
+              accept="image/png,image/jpeg,image/gif,image/webp"
+              className="hidden"
+              onChange={e => {
+                const file = e.target.files?.[0]
+                if (file) onImage(file)
+                e.target.value = ''
+              }}
+            />
+          </label>
+        )}
       </div>
       {kind === 'color' && (
         <label className="flex items-center gap-3 text-sm text-on-surface-variant">
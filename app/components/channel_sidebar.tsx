@@ -23,6 +23,8 @@ export function ChannelSidebar() {
   const dms = useStore(s => s.dms)
   const openDm = useStore(s => s.openDm)
   const startDm = useStore(s => s.startDm)
+  const pinDm = useStore(s => s.pinDm)
+  const muteDm = useStore(s => s.muteDm)
   const openChannel = useStore(s => s.openChannel)
   const openDialog = useStore(s => s.openDialog)
   const openContextMenu = useStore(s => s.openContextMenu)
@@ -45,7 +47,9 @@ export function ChannelSidebar() {
   const [dmInput, setDmInput] = useState<string | null>(null)
 
   if (view?.kind === 'dm') {
-    const sortedDms = [...dms].sort((a, b) => Number(b.is_self) - Number(a.is_self))
+    const sortedDms = [...dms].sort(
+      (a, b) => Number(b.is_self) - Number(a.is_self) || Number(b.pinned) - Number(a.pinned)
+    )
     const submitDm = () => {
       const target = (dmInput ?? '').trim().toLowerCase()
       setDmInput(null)
@@ -81,7 +85,7 @@ export function ChannelSidebar() {
         )}
         <div className="flex flex-1 flex-col overflow-y-auto pb-3">
           {sortedDms.map(dm => {
-            const { id, other, is_self } = dm
+            const { id, other, is_self, pinned, muted, status } = dm
             const active = view.dmId === id
             return (
               <button
@@ -89,6 +93,14 @@ export function ChannelSidebar() {
                 onClick={() => void openDm(id)}
                 {...longPress((x, y) =>
                   openContextMenu(x, y, [
+                    {
+                      label: pinned ? 'Unpin' : 'Pin',
+                      action: () => void pinDm(id, !pinned),
+                    },
+                    {
+                      label: muted ? 'Unmute' : 'Mute',
+                      action: () => void muteDm(id, !muted),
+                    },
                     {
                       label: 'Copy DM ID',
                       action: () => void navigator.clipboard.writeText(String(id)),
@@ -105,12 +117,23 @@ export function ChannelSidebar() {
                     : 'text-on-surface-variant hover:bg-surface-container hover:text-on-surface'
                 }`}
               >
-                <UserAvatar
-                  username={other.username}
-                  avatarKind={other.avatar_kind}
-                  avatarColor={other.avatar_color}
-                  size={28}
-                />
+                <div className="relative shrink-0">
+                  <UserAvatar
+                    username={other.username}
+                    avatarKind={other.avatar_kind}
+                    avatarUrl={other.avatar_url}
+                    avatarColor={other.avatar_color}
+                    size={28}
+                  />
+                  {!is_self && status !== 'offline' && (
+                    <span
+                      title={status === 'away' ? 'Away' : 'Online'}
+                      className={`absolute -bottom-0.5 -right-0.5 h-2.5 w-2.5 rounded-full border-2 border-surface-container ${
+                        status === 'away' ? 'bg-tertiary' : 'bg-primary'
+                      }`}
+                    />
+                  )}
+                </div>
                 <span className="streamer truncate">{other.display_name}</span>
                 {unread(`d${id}`) && (
                   <span
@@ -336,6 +359,7 @@ export function ChannelSidebar() {
                       <UserAvatar
                         username={user.username}
                         avatarKind={user.avatar_kind}
+                        avatarUrl={user.avatar_url}
                         avatarColor={user.avatar_color}
                         size={18}
                       />
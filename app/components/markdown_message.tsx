@@ -3,7 +3,18 @@
 import { useEffect, useState, type ReactNode } from 'react'
 import ReactMarkdown, { type Components } from 'react-markdown'
 import remarkGfm from 'remark-gfm'
-import { Clock, Download, EllipsisVertical, EyeOff, FileText, Share2, X } from 'lucide-react'
+import {
+  Clock,
+  Download,
+  EllipsisVertical,
+  EyeOff,
+  FileText,
+  Forward,
+  Lock,
+  Share2,
+  TriangleAlert,
+  X,
+} from 'lucide-react'
 import { mediaUrl } from '../lib/api'
 import { base64ToBlob, p2p } from '../lib/p2p'
 import { userRefFor, useStore, type ContextMenuItem } from '../lib/store'
@@ -420,10 +431,30 @@ export function MarkdownMessage({
 }) {
   const deleteMedia = useStore(s => s.deleteMedia)
   const previews = useStore(s => s.settings.asset_previews)
-  const { content, media, embeds } = message
+  const { content, media, embeds, filter_status, forwarded_from, encryption } = message
   return (
     <div className="min-w-0 text-sm leading-relaxed break-words">
-      {content.trim() !== '' && (
+      {forwarded_from !== null && (
+        <span className="flex items-center gap-1 text-xs italic text-on-surface-variant">
+          <Forward size={12} />
+          Forwarded
+        </span>
+      )}
+      {filter_status === 'warning' && (
+        <span className="flex items-center gap-1 text-xs text-on-surface-variant">
+          <TriangleAlert size={12} />
+          May contain inappropriate language
+        </span>
+      )}
+      {encryption !== null ? (
+        <span
+          title={`${encryption.algorithm} · key ${encryption.key_id}`}
+          className="flex items-center gap-1 text-xs italic text-on-surface-variant"
+        >
+          <Lock size={12} />
+          Encrypted message
+        </span>
+      ) : content.trim() !== '' && (
         <ReactMarkdown remarkPlugins={[remarkGfm, remarkSpoiler]} components={components}>
           {content}
         </ReactMarkdown>
@@ -121,12 +121,21 @@ export default function MemberSidebar() {
           m.online ? '' : 'opacity-50'
         }`}
       >
-        <UserAvatar
-          username={m.username}
-          avatarKind={m.avatar_kind}
-          avatarColor={m.avatar_color}
-          size={28}
-        />
+        <div className="relative shrink-0">
+          <UserAvatar
+            username={m.username}
+            avatarKind={m.avatar_kind}
+            avatarUrl={m.avatar_url}
+            avatarColor={m.avatar_color}
+            size={28}
+          />
+          {m.status === 'away' && (
+            <span
+              title="Away"
+              className="absolute -bottom-0.5 -right-0.5 h-2.5 w-2.5 rounded-full border-2 border-surface-container bg-tertiary"
+            />
+          )}
+        </div>
         <span
           style={{ color: roleColor(detail.roles, m.role_ids) }}
           className="streamer min-w-0 flex-1 truncate text-sm"
@@ -184,6 +193,7 @@ export default function MemberSidebar() {
               <UserAvatar
                 username={u.username}
                 avatarKind={u.avatar_kind}
+                avatarUrl={u.avatar_url}
                 avatarColor={u.avatar_color}
                 size={28}
               />
@@ -12,7 +12,7 @@ import {
   Share2,
   X,
 } from 'lucide-react'
-import { useStore } from '../lib/store'
+import { useStore, viewKey } from '../lib/store'
 import { Dialog, fieldCls, filledBtn } from './server_settings'
 
 const PRESETS: { label: string; seconds: number | null }[] = [
@@ -89,6 +89,28 @@ function ExpiryDialog({
   )
 }
 
+function TypingLine() {
+  const typists = useStore(s => (s.view ? s.typing[viewKey(s.view)] : undefined))
+  const view = useStore(s => s.view)
+  const members = useStore(s =>
+    s.view?.kind === 'channel' ? s.members[s.view.server] : undefined
+  )
+  const dms = useStore(s => s.dms)
+  if (!typists?.length) return null
+  const nameOf = (username: string) =>
+    members?.list.find(m => m.username === username)?.display_name ??
+    dms.find(d => d.id === (view?.kind === 'dm' ? view.dmId : null))?.other.display_name ??
+    username
+  const names = typists.map(nameOf)
+  const text =
+    names.length === 1
+      ? `${names[0]} is typing…`
+      : names.length <= 3
+        ? `${names.slice(0, -1).join(', ')} and ${names[names.length - 1]} are typing…`
+        : 'Several people are typing…'
+  return <p className="streamer truncate px-3 pt-1 text-xs text-on-surface-variant">{text}</p>
+}
+
 export function MessageComposer({ thread = false }: { thread?: boolean }) {
   const guest = useStore(s => s.guest)
   const logout = useStore(s => s.logout)
@@ -102,6 +124,7 @@ export function MessageComposer({ thread = false }: { thread?: boolean }) {
   const toggleUploadMode = useStore(s => s.toggleUploadMode)
   const sendMessage = useStore(s => s.sendMessage)
   const sendThreadMessage = useStore(s => s.sendThreadMessage)
+  const setTyping = useStore(s => s.setTyping)
   const [text, setText] = useState('')
   const [expiryOpen, setExpiryOpen] = useState(false)
   const fileRef = useRef<HTMLInputElement>(null)
@@ -128,6 +151,7 @@ export function MessageComposer({ thread = false }: { thread?: boolean }) {
 
   const doSend = (p2pExpiresIn?: number | null) => {
     void (thread ? sendThreadMessage(text, p2pExpiresIn) : sendMessage(text, p2pExpiresIn))
+    if (!thread) setTyping(false)
     setText('')
   }
 
@@ -221,6 +245,7 @@ export function MessageComposer({ thread = false }: { thread?: boolean }) {
           value={text}
           onChange={e => {
             setText(e.target.value)
+            if (!thread) setTyping(e.target.value.trim() !== '')
             e.target.style.height = 'auto'
             e.target.style.height = `${Math.min(e.target.scrollHeight, 160)}px`
           }}
@@ -251,6 +276,7 @@ export function MessageComposer({ thread = false }: { thread?: boolean }) {
           <SendHorizontal size={16} />
         </button>
       </div>
+      {!thread && <TypingLine />}
     </div>
   )
 }
@@ -16,6 +16,7 @@ import {
   Volume2,
 } from 'lucide-react'
 import {
+  forwardMenuItems,
   myPerms,
   roleColor,
   roleMenuItems,
@@ -140,6 +141,7 @@ export function OutboxRows({ msgKey, size = 36 }: { msgKey: string; size?: numbe
               <UserAvatar
                 username={me.username}
                 avatarKind={me.avatar_kind}
+                avatarUrl={me.avatar_url}
                 avatarColor={me.avatar_color}
                 size={size}
               />
@@ -328,6 +330,16 @@ export function MessagePane({ onMenu, onMembers }: { onMenu: () => void; onMembe
         danger: true,
         action: () => void deleteMessage(msg.id),
       })
+    if (msg.kind === 'user')
+      items.push({
+        label: 'Forward',
+        action: () => openContextMenu(x, y, forwardMenuItems(msg.id)),
+      })
+    if (!self)
+      items.push({
+        label: 'Report',
+        action: () => openDialog({ kind: 'report_message', messageId: msg.id }),
+      })
     items.push(
       {
         label: 'Copy Message ID',
@@ -392,6 +404,7 @@ export function MessagePane({ onMenu, onMembers }: { onMenu: () => void; onMembe
             <UserAvatar
               username={dm.other.username}
               avatarKind={dm.other.avatar_kind}
+              avatarUrl={dm.other.avatar_url}
               avatarColor={dm.other.avatar_color}
               size={24}
             />
@@ -511,6 +524,7 @@ export function MessagePane({ onMenu, onMembers }: { onMenu: () => void; onMembe
                       <UserAvatar
                         username={m.author.username}
                         avatarKind={m.author.avatar_kind}
+                        avatarUrl={m.author.avatar_url}
                         avatarColor={m.author.avatar_color}
                         size={36}
                       />
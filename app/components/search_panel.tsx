@@ -153,6 +153,7 @@ export default function SearchPanel() {
               <UserAvatar
                 username={r.message.author.username}
                 avatarKind={r.message.author.avatar_kind}
+                avatarUrl={r.message.author.avatar_url}
                 avatarColor={r.message.author.avatar_color}
                 size={20}
               />
@@ -42,17 +42,20 @@ function Dialog({ title, children }: { title: string; children: React.ReactNode
 
 function AddServerDialog() {
   const guest = useStore(s => s.guest)
+  const canCreate = useStore(
+    s => !s.guest && (!s.settings.servers_admin_only || Boolean(s.me?.is_site_admin))
+  )
   const guestGrants = useStore(s => s.guestGrants)
   const createServer = useStore(s => s.createServer)
   const joinServer = useStore(s => s.joinServer)
   const guestJoinServer = useStore(s => s.guestJoinServer)
   const closeDialog = useStore(s => s.closeDialog)
-  const [tab, setTab] = useState<'create' | 'join'>(guest ? 'join' : 'create')
+  const [tab, setTab] = useState<'create' | 'join'>(canCreate ? 'create' : 'join')
   const [name, setName] = useState('')
   const [password, setPassword] = useState('')
   const [protectedServer, setProtectedServer] = useState(false)
   const [suggestions, setSuggestions] = useState<ServerMatch[]>([])
-  const creating = tab === 'create' && !guest
+  const creating = tab === 'create' && canCreate
   const joining = !creating
   const id = name.trim().toLowerCase()
   const hasGrant = guest && Boolean(guestGrants[id])
@@ -85,19 +88,13 @@ function AddServerDialog() {
       void guestJoinServer(trimmed, pw)
       return
     }
-    switch (tab) {
-      case 'create':
-        void createServer(trimmed, pw)
-        return
-      case 'join':
-        void joinServer(trimmed, pw)
-        return
-    }
+    if (creating) void createServer(trimmed, pw)
+    else void joinServer(trimmed, pw)
   }
 
   return (
     <Dialog title={guest ? 'Join a server' : 'Add a server'}>
-      {!guest && (
+      {canCreate && (
         <div className="mb-4 flex rounded-full bg-surface-container p-1">
           {(['create', 'join'] as const).map(t => (
             <button
@@ -179,7 +176,96 @@ function AddServerDialog() {
   )
 }
 
+function ReportDialog({ messageId }: { messageId: number }) {
+  const reportMessage = useStore(s => s.reportMessage)
+  const closeDialog = useStore(s => s.closeDialog)
+  const [reason, setReason] = useState('')
+  const submit = () => {
+    if (reason.trim()) void reportMessage(messageId, reason.trim()).then(closeDialog)
+  }
+  return (
+    <Dialog title="Report Message">
+      <textarea
+        value={reason}
+        onChange={e => setReason(e.target.value)}
+        maxLength={1000}
+        rows={3}
+        placeholder="What is wrong with this message?"
+        className="w-full resize-none rounded-xl border border-outline bg-transparent px-3 py-2 text-sm outline-none focus:border-primary"
+      />
+      <div className="mt-5 flex justify-end gap-2">
+        <button
+          onClick={closeDialog}
+          className="rounded-full px-4 py-2 text-sm text-primary hover:bg-surface-container"
+        >
+          Cancel
+        </button>
+        <button
+          onClick={submit}
+          disabled={!reason.trim()}
+          className="rounded-full bg-primary px-4 py-2 text-sm text-on-primary hover:opacity-90 disabled:opacity-40"
+        >
+          Report
+        </button>
+      </div>
+    </Dialog>
+  )
+}
+
+function TransferDialog({ server }: { server: string }) {
+  const transfer = useStore(s => s.transfers.find(t => t.server === server))
+  const acceptTransfer = useStore(s => s.acceptTransfer)
+  const declineTransfer = useStore(s => s.declineTransfer)
+  const closeDialog = useStore(s => s.closeDialog)
+  return (
+    <Dialog title="Admin Transfer">
+      <p className="text-sm text-on-surface-variant">
+        {transfer ? (
+          <>
+            {transfer.from_user} wants to hand you their admin role in {server}. Accepting
+            removes it from them.
+          </>
+        ) : (
+          'This transfer is no longer pending.'
+        )}
+      </p>
+      {transfer && (
+        <p className="mt-2 text-xs text-on-surface-variant">
+          Expires {new Date(transfer.expires_at * 1000).toLocaleString()}
+        </p>
+      )}
+      <div className="mt-5 flex justify-end gap-2">
+        <button
+          onClick={() =>
+            transfer ? void declineTransfer(server).then(closeDialog) : closeDialog()
+          }
+          className="rounded-full px-4 py-2 text-sm text-primary hover:bg-surface-container"
+        >
+          {transfer ? 'Decline' : 'Close'}
+        </button>
+        {transfer && (
+          <button
+            onClick={() => void acceptTransfer(server).then(closeDialog)}
+            className="rounded-full bg-primary px-4 py-2 text-sm text-on-primary hover:opacity-90"
+          >
+            Accept
+          </button>
+        )}
+      </div>
+    </Dialog>
+  )
+}
+
 export function ServerDialogs() {
   const dialog = useStore(s => s.activeDialog)
-  return dialog?.kind === 'add_server' ? <AddServerDialog /> : null
+  switch (dialog?.kind) {
+    case 'add_server':
+      return <AddServerDialog />
+    case 'report_message':
+      return <ReportDialog messageId={dialog.messageId} />
+    case 'transfer':
+      return <TransferDialog server={dialog.server} />
+    default:
+      return null
+  }
 }
@@ -20,6 +20,8 @@ export function ServerRail() {
   const openDialog = useStore(s => s.openDialog)
   const openContextMenu = useStore(s => s.openContextMenu)
   const leaveServer = useStore(s => s.leaveServer)
+  const markServerRead = useStore(s => s.markServerRead)
+  const transfers = useStore(s => s.transfers)
   const logout = useStore(s => s.logout)
   useStore(s => s.reads)
   const dot = (
@@ -69,6 +71,17 @@ export function ServerRail() {
             onContextMenu={e => {
               e.preventDefault()
               openContextMenu(e.clientX, e.clientY, [
+                ...(me
+                  ? [{ label: 'Mark As Read', action: () => void markServerRead(name) }]
+                  : []),
+                ...(transfers.some(t => t.server === name && t.to_user === me?.username)
+                  ? [
+                      {
+                        label: 'Review Admin Transfer',
+                        action: () => openDialog({ kind: 'transfer', server: name }),
+                      },
+                    ]
+                  : []),
                 {
                   label: 'Copy Server ID',
                   action: () => void navigator.clipboard.writeText(name),
@@ -1,7 +1,7 @@
 'use client'
 
 import { useCallback, useEffect, useRef, useState, type ReactNode } from 'react'
-import { Hash, Trash2, X } from 'lucide-react'
+import { ChevronDown, ChevronUp, Hash, Trash2, X } from 'lucide-react'
 import * as api from '../lib/api'
 import { roleMenuItems, serverAdminPerms, useStore } from '../lib/store'
 import { ALL_PERMS, Perm, hasPerm } from '../lib/types'
@@ -84,6 +84,7 @@ const PERM_ITEMS: { label: string; bit: number }[] = [
   { label: 'Kick', bit: Perm.Kick },
   { label: 'Delete server', bit: Perm.DeleteServer },
   { label: 'Manage admins', bit: Perm.ManageAdmins },
+  { label: 'Mention @everyone', bit: Perm.MentionEveryone },
 ]
 
 function PermChecks({ value, onChange }: { value: number; onChange: (v: number) => void }) {
@@ -342,19 +343,34 @@ export function ChannelControls({
   server,
   channel,
   roles,
+  order,
   refresh,
   lead,
 }: {
   server: string
   channel: Channel
   roles: Role[]
+  order: number[]
   refresh?: () => void
   lead?: ReactNode
 }) {
   const setSlowmode = useStore(s => s.setSlowmode)
+  const reorderChannels = useStore(s => s.reorderChannels)
+  const at = order.indexOf(channel.id)
+  const move = (to: number) => {
+    const ids = order.filter(id => id !== channel.id)
+    ids.splice(to, 0, channel.id)
+    void reorderChannels(server, ids).then(() => refresh?.())
+  }
   const [slow, setSlow] = useState(String(channel.slowmode_seconds))
+  const setRetention = useStore(s => s.setRetention)
+  const retention = channel.message_retention_hours ?? 0
+  const [keep, setKeep] = useState(String(retention))
+  const setContentTypes = useStore(s => s.setContentTypes)
+  const textOnly = channel.allowed_content_types?.includes('attachment') === false
   const [open, setOpen] = useState(false)
   useEffect(() => setSlow(String(channel.slowmode_seconds)), [channel.slowmode_seconds])
+  useEffect(() => setKeep(String(retention)), [retention])
   return (
     <div className="rounded-xl px-2 py-1.5">
       <div className="flex flex-wrap items-center gap-2">
@@ -384,6 +400,57 @@ export function ChannelControls({
           </label>
           <button className={textBtn}>Set</button>
         </form>
+        <form
+          onSubmit={e => {
+            e.preventDefault()
+            const n = Number(keep)
+            if (Number.isInteger(n) && n >= 0 && n !== retention)
+              void setRetention(channel.id, n).then(() => refresh?.())
+          }}
+          className="flex items-center gap-1.5"
+          title="Delete messages older than this many hours (0 keeps everything)"
+        >
+          <label className="flex items-center gap-1.5 text-xs text-on-surface-variant">
+            Keep (h)
+            <input
+              value={keep}
+              onChange={e => setKeep(e.target.value)}
+              inputMode="numeric"
+              className="w-16 rounded-lg border border-outline bg-transparent px-2 py-1 text-sm text-on-surface outline-none focus:border-primary"
+            />
+          </label>
+          <button className={textBtn}>Set</button>
+        </form>
+        <label
+          className="flex items-center gap-1 text-xs text-on-surface-variant"
+          title="Reject messages with file attachments"
+        >
+          <input
+            type="checkbox"
+            checked={textOnly}
+            onChange={() =>
+              void setContentTypes(channel.id, textOnly ? [] : ['text']).then(() => refresh?.())
+            }
+            className="accent-primary"
+          />
+          Text only
+        </label>
+        <button
+          disabled={at <= 0}
+          onClick={() => move(at - 1)}
+          aria-label={`Move ${channel.name} up`}
+          className={textBtn}
+        >
+          <ChevronUp size={16} />
+        </button>
+        <button
+          disabled={at < 0 || at >= order.length - 1}
+          onClick={() => move(at + 1)}
+          aria-label={`Move ${channel.name} down`}
+          className={textBtn}
+        >
+          <ChevronDown size={16} />
+        </button>
         <button onClick={() => setOpen(!open)} className={textBtn}>
           {open ? 'Hide perms' : 'Perms'}
         </button>
@@ -393,6 +460,34 @@ export function ChannelControls({
   )
 }
 
+function ServerWelcomeField({ server, current }: { server: string; current: string | null }) {
+  const setServerWelcome = useStore(s => s.setServerWelcome)
+  const [message, setMessage] = useState(current ?? '')
+  return (
+    <>
+      <p className={sectionCls}>Welcome message</p>
+      <form
+        onSubmit={e => {
+          e.preventDefault()
+          void setServerWelcome(server, message.trim())
+        }}
+        className="flex gap-2"
+      >
+        <input
+          value={message}
+          onChange={e => setMessage(e.target.value)}
+          maxLength={2000}
+          placeholder="Shown to new members (blank for none)"
+          className={fieldCls}
+        />
+        <button disabled={message.trim() === (current ?? '')} className={filledBtn}>
+          Save
+        </button>
+      </form>
+    </>
+  )
+}
+
 export function ServerPasswordField({
   server,
   hasPassword,
@@ -503,6 +598,7 @@ function Settings({ server }: { server: string }) {
         <UserAvatar
           username={m.username}
           avatarKind={m.avatar_kind}
+          avatarUrl={m.avatar_url}
           avatarColor={m.avatar_color}
           size={28}
         />
@@ -536,6 +632,7 @@ function Settings({ server }: { server: string }) {
             <button className={filledBtn}>Rename</button>
           </form>
           <ServerPasswordField server={server} hasPassword={detail.has_password} />
+          <ServerWelcomeField server={server} current={detail.welcome_message} />
         </>
       )}
       {hasPerm(perms, Perm.ManageAdmins) && (
@@ -548,7 +645,13 @@ function Settings({ server }: { server: string }) {
         <>
           <p className={sectionCls}>Channels</p>
           {detail.channels.map(c => (
-            <ChannelControls key={c.id} server={server} channel={c} roles={detail.roles} />
+            <ChannelControls
+              key={c.id}
+              server={server}
+              channel={c}
+              roles={detail.roles}
+              order={detail.channels.map(ch => ch.id)}
+            />
           ))}
         </>
       )}
@@ -2,6 +2,7 @@
 
 import { useEffect, useState } from 'react'
 import { X } from 'lucide-react'
+import * as api from '../lib/api'
 import { useStore } from '../lib/store'
 import type { AvatarKind } from '../lib/types'
 import { AvatarPicker } from './avatar_picker'
@@ -20,7 +21,40 @@ function AccountSection() {
   const patchMe = useStore(s => s.patchMe)
   const [kind, setKind] = useState<AvatarKind>(me?.avatar_kind ?? 'identicon')
   const [color, setColor] = useState(me?.avatar_color ?? '#6750a4')
+  const [imageId, setImageId] = useState<string | null>(null)
+  const [imageError, setImageError] = useState<string | null>(null)
+  const [exporting, setExporting] = useState(false)
+  const [exportError, setExportError] = useState<string | null>(null)
   if (!me) return null
+
+  const pickImage = async (file: File) => {
+    setImageError(null)
+    try {
+      const { id } = await api.uploadMedia(file)
+      setImageId(id)
+      setKind('image')
+    } catch (e) {
+      setImageError(e instanceof Error ? e.message : String(e))
+    }
+  }
+
+  const download = async () => {
+    setExporting(true)
+    setExportError(null)
+    try {
+      const url = URL.createObjectURL(await api.exportMe())
+      const a = document.createElement('a')
+      a.href = url
+      a.download = `rchat-export-${me.username}.json`
+      a.click()
+      URL.revokeObjectURL(url)
+    } catch (e) {
+      setExportError(e instanceof Error ? e.message : String(e))
+    } finally {
+      setExporting(false)
+    }
+  }
+
   return (
     <section>
       <p className={sectionCls}>Account</p>
@@ -31,15 +65,32 @@ function AccountSection() {
         username={me.username}
         kind={kind}
         color={color}
+        imageUrl={imageId ? `/api/media/${imageId}` : me.avatar_url}
         onKind={setKind}
         onColor={setColor}
+        onImage={file => void pickImage(file)}
       />
+      {imageError && <p className="mt-2 text-sm text-error">{imageError}</p>}
       <button
-        onClick={() => void patchMe(kind, kind === 'color' ? color : undefined)}
-        className={`mt-4 ${filledBtn}`}
+        disabled={kind === 'image' && !imageId}
+        onClick={() =>
+          void patchMe(kind, kind === 'color' ? color : undefined, imageId ?? undefined)
+        }
+        className={`mt-4 ${filledBtn} disabled:opacity-40`}
       >
         Save avatar
       </button>
+      <p className="mt-6 mb-2 text-sm text-on-surface-variant">
+        Download your profile, memberships, uploads and messages as JSON.
+      </p>
+      <button
+        disabled={exporting}
+        onClick={() => void download()}
+        className={`${filledBtn} disabled:opacity-40`}
+      >
+        {exporting ? 'Preparing…' : 'Export my data'}
+      </button>
+      {exportError && <p className="mt-2 text-sm text-error">{exportError}</p>}
     </section>
   )
 }
@@ -107,8 +158,8 @@ function InformationSection() {
       </Info>
       <Info title="Files and attachments">
         <p>
-          Server uploads are capped at 25MB and deleted exactly one day after posting; the message
-          stays and shows a removal notice. Uploaded blobs live in the server database.
+          Server uploads are capped at 25MB by default and deleted exactly one day after posting;
+          the message stays and shows a removal notice. Uploaded blobs live in the server database.
         </p>
         <p>
           P2P attachments never touch the server. The file is stored in the sender&apos;s browser
@@ -94,6 +94,7 @@ export default function ThreadPanel() {
           <UserAvatar
             username={m.author.username}
             avatarKind={m.author.avatar_kind}
+            avatarUrl={m.author.avatar_url}
             avatarColor={m.author.avatar_color}
             size={30}
           />
@@ -3,30 +3,32 @@
 import { useMemo } from 'react'
 import { minidenticon } from 'minidenticons'
 import { User } from 'lucide-react'
+import { assetUrl } from '../lib/api'
 import type { AvatarKind } from '../lib/types'
 
 interface Props {
   username: string
   avatarKind: AvatarKind
   avatarColor: string | null
+  avatarUrl?: string | null
   size?: number
 }
 
-export function UserAvatar({ username, avatarKind, avatarColor, size = 32 }: Props) {
-  const src = useMemo(
-    () =>
-      avatarKind === 'identicon'
-        ? `data:image/svg+xml;utf8,${encodeURIComponent(minidenticon(username, 95, 45))}`
-        : null,
-    [username, avatarKind]
-  )
+export function UserAvatar({ username, avatarKind, avatarColor, avatarUrl, size = 32 }: Props) {
+  const src = useMemo(() => {
+    if (avatarKind === 'image' && avatarUrl) return assetUrl(avatarUrl)
+    return avatarKind === 'color'
+      ? null
+      : `data:image/svg+xml;utf8,${encodeURIComponent(minidenticon(username, 95, 45))}`
+  }, [username, avatarKind, avatarUrl])
   return src ? (
     <img
       src={src}
       alt={username}
       width={size}
       height={size}
-      className="shrink-0 rounded-full bg-surface-container-highest"
+      style={{ width: size, height: size }}
+      className="shrink-0 rounded-full bg-surface-container-highest object-cover"
     />
   ) : (
     <span
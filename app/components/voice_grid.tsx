@@ -83,6 +83,7 @@ function Tile({
         <UserAvatar
           username={user.username}
           avatarKind={user.avatar_kind}
+          avatarUrl={user.avatar_url}
           avatarColor={user.avatar_color}
           size={56}
         />
@@ -15,6 +15,7 @@ export const user = (username: string, over: Partial<UserRef> = {}): UserRef =>
   display_name: username,
   avatar_kind: 'identicon',
   avatar_color: null,
+  avatar_url: null,
   ...over,
 })
 
@@ -45,6 +46,12 @@ export const msg = (id: number, over: Partial<Message> = {}): Message => ({
   embeds: [],
   kind: 'user',
   call: null,
+  filter_status: 'clean',
+  forwarded_from: null,
+  encryption: null,
+  seq: null,
+  mass_mention: null,
+  deleted_at: null,
   ...over,
 })
 
@@ -64,6 +71,8 @@ export const member = (username: string, over: Partial<Member> = {}): Member =>
   is_admin: false,
   is_creator: false,
   online: false,
+  status: 'offline',
+  last_seen: null,
   perms: 0,
   role_ids: [],
   ...over,
@@ -74,6 +83,7 @@ export const serverDetail = (name: string, over: Partial<ServerDetail> = {}): Se
   display_name: name,
   creator: null,
   has_password: false,
+  welcome_message: null,
   channels: [],
   roles: [],
   member_count: 0,
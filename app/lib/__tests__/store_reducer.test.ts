@@ -14,6 +14,7 @@ beforeEach(() => {
     servers: {},
     members: {},
     interacted: {},
+    typing: {},
     dms: [],
     panel: null,
     adminOverview: null,
@@ -24,7 +25,13 @@ beforeEach(() => {
 describe('message', () => {
   test('channel message appends to the channel cache', () => {
     useStore.setState({ messages: { c1: [msg(10)] } })
-    dispatch({ type: 'message', ...scoped, message: msg(11, { channel_id: 1 }) })
+    dispatch({
+      type: 'message',
+      ...scoped,
+      message: msg(11, { channel_id: 1 }),
+      muted: false,
+      mentioned: false,
+    })
     expect(st().messages.c1.map(m => m.id)).toEqual([10, 11])
   })
 
@@ -40,6 +47,8 @@ describe('message', () => {
       type: 'message',
       ...scoped,
       message: msg(52, { channel_id: 1, thread_root_id: 50 }),
+      muted: false,
+      mentioned: false,
     })
     const s = st()
     expect(s.messages.t50.map(m => m.id)).toEqual([51, 52])
@@ -109,7 +118,13 @@ describe('media / embeds patching via patchMessage', () => {
   test('embeds_resolved before the message is inserted still lands on the later insert', () => {
     useStore.setState({ messages: { c1: [msg(10)] } })
     dispatch({ type: 'embeds_resolved', ...scoped, message_id: 80, embeds: [embed(0)] })
-    dispatch({ type: 'message', ...scoped, message: msg(80, { channel_id: 1 }) })
+    dispatch({
+      type: 'message',
+      ...scoped,
+      message: msg(80, { channel_id: 1 }),
+      muted: false,
+      mentioned: false,
+    })
     const stored = st().messages.c1.find(m => m.id === 80)
     expect(stored?.embeds.map(e => e.ord)).toEqual([0])
   })
@@ -117,7 +132,13 @@ describe('media / embeds patching via patchMessage', () => {
   test('a later duplicate message echo does not clobber resolved embeds', () => {
     useStore.setState({ messages: { c1: [msg(80, { channel_id: 1 })] } })
     dispatch({ type: 'embeds_resolved', ...scoped, message_id: 80, embeds: [embed(0)] })
-    dispatch({ type: 'message', ...scoped, message: msg(80, { channel_id: 1, embeds: [] }) })
+    dispatch({
+      type: 'message',
+      ...scoped,
+      message: msg(80, { channel_id: 1, embeds: [] }),
+      muted: false,
+      mentioned: false,
+    })
     expect(st().messages.c1[0].embeds.map(e => e.ord)).toEqual([0])
   })
 
@@ -133,16 +154,49 @@ describe('media / embeds patching via patchMessage', () => {
   })
 })
 
+describe('typing', () => {
+  test('tracks typists until they stop or post', () => {
+    dispatch({ type: 'typing', ...scoped, username: 'bob' })
+    dispatch({ type: 'typing', ...scoped, username: 'cat' })
+    expect(st().typing.c1).toEqual(['bob', 'cat'])
+    dispatch({ type: 'typing_stopped', ...scoped, username: 'bob' })
+    expect(st().typing.c1).toEqual(['cat'])
+    dispatch({
+      type: 'message',
+      ...scoped,
+      message: msg(11, { channel_id: 1, author: user('cat') }),
+      muted: false,
+      mentioned: false,
+    })
+    expect(st().typing.c1).toEqual([])
+  })
+})
+
 describe('presence_changed', () => {
   test('patches loaded rows and adjusts online_count', () => {
     useStore.setState({
       servers: { s: serverDetail('s', { online_count: 0 }) },
       members: { s: paged([member('bob', { online: false })]) },
     })
-    dispatch({ type: 'presence_changed', server: 's', username: 'bob', online: true })
+    dispatch({
+      type: 'presence_changed',
+      server: 's',
+      username: 'bob',
+      online: true,
+      status: 'online',
+    })
     expect(st().servers.s.online_count).toBe(1)
     expect(st().members.s.list[0].online).toBe(true)
-    dispatch({ type: 'presence_changed', server: 's', username: 'bob', online: false })
+    dispatch({ type: 'status_changed', server: 's', username: 'bob', status: 'away' })
+    expect(st().servers.s.online_count).toBe(1)
+    expect(st().members.s.list[0].status).toBe('away')
+    dispatch({
+      type: 'presence_changed',
+      server: 's',
+      username: 'bob',
+      online: false,
+      status: 'offline',
+    })
     expect(st().servers.s.online_count).toBe(0)
     expect(st().members.s.list[0].online).toBe(false)
   })
@@ -187,12 +241,21 @@ describe('membership count math', () => {
       me: me('alice'),
       servers: { s: serverDetail('s', { member_count: 3 }) },
       members: { s: paged([member('alice'), member('bob')]) },
-      dms: [{ id: 1, other: user('bob'), is_self: false }],
+      dms: [
+        {
+          id: 1,
+          other: user('bob'),
+          is_self: false,
+          pinned: false,
+          muted: false,
+          status: 'offline',
+        },
+      ],
       messages: { c1: [msg(1, { author: user('bob') }), msg(2, { author: user('alice') })] },
       adminOverview: { user_count: 5, server_count: 2 },
       adminUsers: paged([user('bob')]),
     })
-    dispatch({ type: 'banned', username: 'bob' })
+    dispatch({ type: 'banned', username: 'bob', purged: true })
     const s = st()
     expect(s.members.s.list.map(m => m.username)).toEqual(['alice'])
     expect(s.adminUsers.list.map(u => u.username)).toEqual([])
@@ -1,21 +1,42 @@
 import type {
+  Activity,
+  AdminInvite,
+  AdminMembership,
   AdminOverview,
+  AdminReport,
+  AdminServerConfig,
+  AnnouncementLevel,
   AuthResponse,
   AvatarKind,
+  BanResult,
+  Bootstrap,
+  BulkMemberResult,
+  Capabilities,
   Channel,
   ChannelKind,
   ChannelPerm,
+  ContentType,
+  DmSent,
   DmSummary,
+  Encryption,
+  GlobalSearch,
   Me,
   Member,
   Message,
+  MessageContext,
+  MessageReport,
+  PendingTransfer,
+  PendingUpload,
+  ReportStatus,
   Role,
   SearchResult,
   ServerDetail,
   ServerMatch,
+  ServerStats,
   ServerSummaryLite,
   SiteSettings,
   Unread,
+  UserPresence,
   UserRef,
 } from './types'
 
@@ -57,6 +78,14 @@ export class ApiError extends Error {
     super(message)
     this.name = 'ApiError'
   }
+
+  get field(): string | undefined {
+    return (this.data as { field?: string } | undefined)?.field
+  }
+
+  get code(): string | undefined {
+    return (this.data as { code?: string } | undefined)?.code
+  }
 }
 
 async function handleResponse<T>(response: Response): Promise<T> {
@@ -67,8 +96,13 @@ async function handleResponse<T>(response: Response): Promise<T> {
   return response.json()
 }
 
-async function req<T>(method: string, path: string, body?: unknown): Promise<T> {
-  const headers: Record<string, string> = {}
+async function req<T>(
+  method: string,
+  path: string,
+  body?: unknown,
+  extra?: Record<string, string>
+): Promise<T> {
+  const headers: Record<string, string> = { ...extra }
   if (authToken) {
     headers.Authorization = `Bearer ${authToken}`
   } else {
@@ -76,7 +110,7 @@ async function req<T>(method: string, path: string, body?: unknown): Promise<T>
     if (grants.length) headers['X-Guest-Grant'] = grants.join(',')
   }
   const init: RequestInit = { method, headers }
-  if (body instanceof FormData) {
+  if (body instanceof FormData || body instanceof Blob) {
     init.body = body
   } else if (body !== undefined) {
     headers['Content-Type'] = 'application/json'
@@ -87,12 +121,27 @@ async function req<T>(method: string, path: string, body?: unknown): Promise<T>
 
 const seg = encodeURIComponent
 
+export async function exportMe(): Promise<Blob> {
+  const headers: Record<string, string> = authToken ? { Authorization: `Bearer ${authToken}` } : {}
+  const response = await fetch(`${API}/me/export`, { headers })
+  if (!response.ok) return handleResponse<Blob>(response)
+  return response.blob()
+}
+
+export async function exportServer(name: string): Promise<Blob> {
+  const headers: Record<string, string> = authToken ? { Authorization: `Bearer ${authToken}` } : {}
+  const response = await fetch(`${API}/servers/${seg(name)}/export`, { headers })
+  if (!response.ok) return handleResponse<Blob>(response)
+  return response.blob()
+}
+
 export interface RegisterRequest {
   username: string
   password?: string
   words?: string[]
   avatar_kind: AvatarKind
   avatar_color?: string
+  invite?: string
 }
 
 export interface LoginRequest {
@@ -103,11 +152,16 @@ export interface LoginRequest {
 
 export const register = (body: RegisterRequest) => req<AuthResponse>('POST', '/auth/register', body)
 export const login = (body: LoginRequest) => req<AuthResponse>('POST', '/auth/login', body)
+export const logout = () => req<unknown>('POST', '/auth/logout')
 export const words = (username: string) =>
   req<{ words: string[] }>('GET', `/auth/words/${seg(username)}`)
 export const me = () => req<Me>('GET', '/me')
-export const patchMe = (avatar_kind: AvatarKind, avatar_color?: string) =>
-  req<UserRef>('PATCH', '/me', { avatar_kind, avatar_color })
+export const getBootstrap = () => req<Bootstrap>('GET', '/bootstrap')
+export const patchMe = (
+  avatar_kind: AvatarKind,
+  avatar_color?: string,
+  avatar_media_id?: string
+) => req<UserRef>('PATCH', '/me', { avatar_kind, avatar_color, avatar_media_id })
 
 export const createServer = (name: string, password?: string) =>
   req<unknown>('POST', '/servers', { name, password })
@@ -121,25 +175,57 @@ export const listMembers = (name: string, offset: number) =>
   req<Member[]>('GET', `/servers/${seg(name)}/members?offset=${offset}&limit=50`)
 export const listInteracted = (name: string, offset: number) =>
   req<UserRef[]>('GET', `/servers/${seg(name)}/interacted?offset=${offset}&limit=50`)
+export const serverActivity = (name: string, offset: number) =>
+  req<Activity[]>('GET', `/servers/${seg(name)}/activity?offset=${offset}&limit=50`)
+export const serverStats = (name: string, days: number) =>
+  req<ServerStats>('GET', `/servers/${seg(name)}/stats?days=${days}`)
+export const userPresence = (username: string) =>
+  req<UserPresence>('GET', `/users/${seg(username)}/presence`)
 export const joinServer = (name: string, password?: string) =>
   req<unknown>('POST', `/servers/${seg(name)}/join`, { password })
 export const leaveServer = (name: string) => req<unknown>('POST', `/servers/${seg(name)}/leave`)
-export const updateServer = (name: string, patch: { name?: string; password?: string }) =>
+export const markServerRead = (name: string) =>
+  req<{ updated: number }>('POST', `/servers/${seg(name)}/mark_read`)
+export const updateServer = (
+  name: string,
+  patch: { name?: string; password?: string; welcome_message?: string }
+) =>
   req<ServerSummaryLite>('PATCH', `/servers/${seg(name)}`, patch)
 export const deleteServer = (name: string) => req<unknown>('DELETE', `/servers/${seg(name)}`)
+export const serverPermissions = (name: string) =>
+  req<Capabilities>('GET', `/servers/${seg(name)}/permissions`)
+export const channelPermissions = (id: number) =>
+  req<Capabilities>('GET', `/channels/${id}/permissions`)
+export const myChannels = () => req<Record<string, Channel[]>>('GET', '/servers/channels')
 export const createChannel = (server: string, name: string, kind: ChannelKind = 'text') =>
   req<Channel>('POST', `/servers/${seg(server)}/channels`, { name, kind })
-export const updateChannel = (id: number, patch: { name?: string; slowmode_seconds?: number }) =>
-  req<Channel>('PATCH', `/channels/${id}`, patch)
+export const updateChannel = (
+  id: number,
+  patch: {
+    name?: string
+    slowmode_seconds?: number
+    message_retention_hours?: number
+    allowed_content_types?: ContentType[]
+  }
+) => req<Channel>('PATCH', `/channels/${id}`, patch)
 export const deleteChannel = (id: number) => req<unknown>('DELETE', `/channels/${id}`)
+export const reorderChannels = (server: string, ids: number[]) =>
+  req<unknown>('POST', `/servers/${seg(server)}/channels/reorder`, { ids })
 export const kickMember = (server: string, username: string) =>
   req<unknown>('POST', `/servers/${seg(server)}/kick`, { username })
+export const bulkAddMembers = (server: string, usernames: string[]) =>
+  req<BulkMemberResult[]>('POST', `/servers/${seg(server)}/members/bulk`, { usernames })
 export const grantAdmin = (server: string, username: string) =>
   req<unknown>('POST', `/servers/${seg(server)}/admins`, { username })
 export const revokeAdmin = (server: string, username: string) =>
   req<unknown>('DELETE', `/servers/${seg(server)}/admins/${seg(username)}`)
 export const transferAdmin = (server: string, username: string) =>
-  req<unknown>('POST', `/servers/${seg(server)}/transfer_admin`, { username })
+  req<PendingTransfer>('POST', `/servers/${seg(server)}/transfer_admin`, { username })
+export const acceptTransfer = (server: string) =>
+  req<unknown>('POST', `/servers/${seg(server)}/transfer/accept`)
+export const declineTransfer = (server: string) =>
+  req<unknown>('POST', `/servers/${seg(server)}/transfer/decline`)
+export const listTransfers = () => req<PendingTransfer[]>('GET', '/transfers')
 export const setAdminPerms = (server: string, username: string, perms: number) =>
   req<unknown>('PATCH', `/servers/${seg(server)}/admins/${seg(username)}/perms`, { perms })
 export const createRole = (server: string, body: { name: string; color: string; perms: number }) =>
@@ -173,26 +259,67 @@ export interface SendOpts {
   media_id?: string
   media_spoiler?: boolean
   p2p?: P2pAttachment
+  encryption?: Encryption
+  idempotency_key?: string
+}
+
+const sendMessage = (path: string, content: string, opts: SendOpts) => {
+  const { idempotency_key, ...rest } = opts
+  return req<Message>(
+    'POST',
+    path,
+    { content, ...rest },
+    idempotency_key ? { 'Idempotency-Key': idempotency_key } : undefined
+  )
 }
 
 export const channelMessages = (id: number, before?: number, limit?: number) =>
   req<Message[]>('GET', `/channels/${id}/messages${query(before, limit)}`)
+export const channelMedia = (id: number, before?: number, limit?: number) =>
+  req<Message[]>('GET', `/channels/${id}/media${query(before, limit)}`)
 export const sendChannelMessage = (id: number, content: string, opts: SendOpts = {}) =>
-  req<Message>('POST', `/channels/${id}/messages`, { content, ...opts })
-export const listDms = () => req<DmSummary[]>('GET', '/dms')
+  sendMessage(`/channels/${id}/messages`, content, opts)
+export const listDms = (page?: { offset: number; limit: number; unread?: boolean }) =>
+  req<DmSummary[]>(
+    'GET',
+    page
+      ? `/dms?offset=${page.offset}&limit=${page.limit}${page.unread ? '&unread=true' : ''}`
+      : '/dms'
+  )
 export const openDm = (username: string) => req<DmSummary>('POST', '/dms', { username })
+export const pinDm = (id: number, pinned: boolean) =>
+  req<DmSummary>('PUT', `/dms/${id}/pin`, { pinned })
+export const muteDm = (id: number, muted: boolean) =>
+  req<DmSummary>('PUT', `/dms/${id}/mute`, { muted })
 export const dmMessages = (id: number, before?: number, limit?: number) =>
   req<Message[]>('GET', `/dms/${id}/messages${query(before, limit)}`)
 export const sendDmMessage = (id: number, content: string, opts: SendOpts = {}) =>
-  req<Message>('POST', `/dms/${id}/messages`, { content, ...opts })
+  sendMessage(`/dms/${id}/messages`, content, opts)
+export const sendNewDm = (username: string, content: string, opts: SendOpts = {}) => {
+  const { idempotency_key, ...rest } = opts
+  return req<DmSent>(
+    'POST',
+    '/dms/send',
+    { username, content, ...rest },
+    idempotency_key ? { 'Idempotency-Key': idempotency_key } : undefined
+  )
+}
 export const deleteMessage = (id: number) => req<unknown>('DELETE', `/messages/${id}`)
+export const restoreMessage = (id: number) => req<Message>('POST', `/messages/${id}/restore`)
+export const reportMessage = (id: number, reason: string) =>
+  req<MessageReport>('POST', `/messages/${id}/report`, { reason })
+export const messageContext = (id: number) =>
+  req<MessageContext>('GET', `/messages/${id}/context`)
+export type ForwardTarget = { channel_id: number } | { dm_id: number }
+export const forwardMessage = (id: number, target: ForwardTarget) =>
+  req<Message>('POST', `/messages/${id}/forward`, target)
 export const deleteMedia = (id: number) => req<unknown>('DELETE', `/messages/${id}/media`)
 export const deleteEmbed = (id: number, ord: number, banner: boolean) =>
   req<unknown>('DELETE', `/messages/${id}/embeds/${ord}${banner ? '?banner=1' : ''}`)
 export const threadMessages = (id: number, before?: number, limit?: number) =>
   req<Message[]>('GET', `/messages/${id}/thread${query(before, limit)}`)
 export const sendThreadMessage = (id: number, content: string, opts: SendOpts = {}) =>
-  req<Message>('POST', `/messages/${id}/thread`, { content, ...opts })
+  sendMessage(`/messages/${id}/thread`, content, opts)
 
 export interface SearchParams {
   q?: string
@@ -214,11 +341,44 @@ export const search = (params: SearchParams) => {
   return req<SearchResult[]>('GET', `/search?${qs.toString()}`)
 }
 
+export const searchGlobal = (q: string, offset = 0) =>
+  req<GlobalSearch>('GET', `/messages/search/global?q=${seg(q)}&offset=${offset}`)
+
 export const getUnreads = () => req<{ items: Unread[] }>('GET', '/unreads')
 export const postRead = (scope: string, last_read: number) =>
   req<void>('POST', '/read', { scope, last_read })
 
+export const UPLOAD_CHUNK_BYTES = 4 * 1024 * 1024
+
+async function uploadChunked(file: File) {
+  const upload = await req<PendingUpload>('POST', '/media/uploads', {
+    filename: file.name,
+    mime: file.type || undefined,
+    size: file.size,
+  })
+  const path = `/media/uploads/${seg(upload.id)}`
+  let received = 0
+  let failures = 0
+  while (received < file.size) {
+    const chunk = file.slice(received, received + UPLOAD_CHUNK_BYTES)
+    try {
+      received = (await req<PendingUpload>('PUT', `${path}/chunk?offset=${received}`, chunk))
+        .received
+      failures = 0
+    } catch (e) {
+      failures += 1
+      if (failures > 3) throw e
+      received = await req<PendingUpload>('GET', path).then(
+        (u) => u.received,
+        () => received
+      )
+    }
+  }
+  return req<{ id: string }>('POST', `${path}/complete`)
+}
+
 export const uploadMedia = (file: File) => {
+  if (file.size > UPLOAD_CHUNK_BYTES) return uploadChunked(file)
   const form = new FormData()
   form.append('file', file, file.name)
   return req<{ id: string }>('POST', '/media', form)
@@ -228,6 +388,7 @@ export const mediaUrl = (id: string, server?: string) => {
   const suffix = grant ? `?grant=${seg(grant)}` : ''
   return `${API}/media/${seg(id)}${suffix}`
 }
+export const assetUrl = (path: string) => `${API}${path.replace(/^\/api/, '')}`
 
 export const getSettings = () => req<SiteSettings>('GET', '/settings')
 export const patchSettings = (body: Partial<SiteSettings>) =>
@@ -238,6 +399,13 @@ export const adminUsers = (offset: number, q: string) =>
   req<UserRef[]>('GET', `/admin/users?offset=${offset}&limit=50&q=${seg(q)}`)
 export const adminServers = (offset: number, q: string) =>
   req<ServerSummaryLite[]>('GET', `/admin/servers?offset=${offset}&limit=50&q=${seg(q)}`)
+export const adminMemberships = (offset: number, server: string, username: string) =>
+  req<AdminMembership[]>(
+    'GET',
+    `/admin/memberships?offset=${offset}&limit=50&server=${seg(server)}&username=${seg(username)}`
+  )
+export const adminServer = (name: string) =>
+  req<AdminServerConfig>('GET', `/admin/servers/${seg(name)}`)
 export const adminDeleteServer = (name: string) =>
   req<unknown>('DELETE', `/admin/servers/${seg(name)}`)
 export const adminDeleteUser = (username: string) =>
@@ -245,7 +413,19 @@ export const adminDeleteUser = (username: string) =>
 export const adminUserServers = (username: string) =>
   req<ServerSummaryLite[]>('GET', `/admin/users/${seg(username)}/servers`)
 export const adminDeleteMessage = (id: number) => req<unknown>('DELETE', `/admin/messages/${id}`)
-export const banUser = (username: string) => req<unknown>('POST', '/admin/ban', { username })
+export const adminCleanup = () => req<unknown>('POST', '/admin/cleanup')
+export const adminAnnounce = (message: string, level: AnnouncementLevel, post: boolean) =>
+  req<unknown>('POST', '/admin/announce', { message, level, post })
+export const adminInvites = () => req<AdminInvite[]>('GET', '/admin/invites')
+export const adminCreateInvite = () => req<AdminInvite>('POST', '/admin/invites')
+export const adminDeleteInvite = (code: string) =>
+  req<unknown>('DELETE', `/admin/invites/${seg(code)}`)
+export const adminReports = (status: ReportStatus) =>
+  req<AdminReport[]>('GET', `/admin/reports?status=${status}`)
+export const adminResolveReport = (id: number) =>
+  req<MessageReport>('POST', `/admin/reports/${id}/resolve`)
+export const banUser = (username: string, purge: boolean, dry_run = false) =>
+  req<BanResult>('POST', '/admin/ban', { username, purge, dry_run })
 
 function query(before?: number, limit?: number) {
   const parts: string[] = []
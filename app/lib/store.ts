@@ -6,17 +6,21 @@ import { newP2pId, p2p } from './p2p'
 import { ALL_PERMS } from './types'
 import type {
   AdminOverview,
+  AnnouncementLevel,
   AuthResponse,
   AvatarKind,
   CallKind,
   ChannelKind,
   ChannelPerm,
+  ContentType,
   DmSummary,
   Embed,
   Me,
   Member,
   Message,
   P2pAvailability,
+  PendingTransfer,
+  PresenceStatus,
   Role,
   SearchResult,
   ServerDetail,
@@ -71,7 +75,9 @@ export type ActiveDialog =
   | { kind: 'server_settings'; server: string }
   | { kind: 'admin_panel' }
   | { kind: 'ban_confirm'; username: string }
+  | { kind: 'report_message'; messageId: number }
   | { kind: 'delete_user_confirm'; username: string }
+  | { kind: 'transfer'; server: string }
   | { kind: 'settings' }
 
 export type Panel = { kind: 'thread'; root: Message } | { kind: 'search' }
@@ -149,7 +155,6 @@ export const filterSearchPage = (
   return { filtered, hasMore: raw.length === 25 && !past }
 }
 
-export const MAX_UPLOAD = 25 * 1024 * 1024
 export const PAGE = 50
 
 export interface Paged<T> {
@@ -181,9 +186,14 @@ const merge = (list: Message[], incoming: Message[]) => {
   return [...map.values()].sort(byId)
 }
 
+const TYPING_PING_MS = 4_000
+let typingKey: string | null = null
+let typingSentAt = 0
+
 const OPT_BASE = 1e15
 let optSeq = 0
 const draining = new Set<string>()
+const SEND_NONCE = Math.random().toString(36).slice(2)
 
 const pendingEmbeds = new Map<number, Embed[]>()
 const PENDING_EMBEDS_MAX = 256
@@ -222,6 +232,7 @@ interface RChatState {
   messages: Record<string, Message[]>
   outbox: Record<string, Outgoing[]>
   reads: Record<string, { lastRead: number; latest: number }>
+  typing: Record<string, string[]>
   unreadAnchor: Record<string, number>
   atBottom: boolean
   authExpired: boolean
@@ -234,6 +245,7 @@ interface RChatState {
   pending: PendingUpload | null
   threadPending: PendingUpload | null
   notices: Notice[]
+  transfers: PendingTransfer[]
   search: SearchState
   error: string | null
   adminOverview: AdminOverview | null
@@ -242,7 +254,11 @@ interface RChatState {
   loadSettings: () => Promise<void>
   setTheme: (theme: Theme) => void
   setStreamer: (streamer: boolean) => void
-  patchMe: (avatar_kind: AvatarKind, avatar_color?: string) => Promise<void>
+  patchMe: (
+    avatar_kind: AvatarKind,
+    avatar_color?: string,
+    avatar_media_id?: string
+  ) => Promise<void>
   syncFromUrl: (nav: 'replace' | 'none') => Promise<void>
   register: (body: api.RegisterRequest) => Promise<void>
   login: (body: api.LoginRequest) => Promise<void>
@@ -253,8 +269,11 @@ interface RChatState {
   openChannel: (server: string, channelId: number, nav?: Nav) => Promise<void>
   openDm: (dmId: number, nav?: Nav) => Promise<void>
   startDm: (username: string) => Promise<void>
+  pinDm: (id: number, pinned: boolean) => Promise<void>
+  muteDm: (id: number, muted: boolean) => Promise<void>
   sendMessage: (content: string, p2pExpiresIn?: number | null) => void
   markRead: (scope: string, messageId: number) => void
+  setTyping: (active: boolean) => void
   setAtBottom: (v: boolean) => void
   retryOutgoing: (key: string, tempId: number) => void
   cancelOutgoing: (key: string, tempId: number) => void
@@ -270,22 +289,32 @@ interface RChatState {
   searchRun: (args: SearchArgs, reset: boolean) => Promise<void>
   dismissNotice: (id: number) => void
   deleteMessage: (id: number) => Promise<void>
+  restoreMessage: (id: number) => Promise<void>
+  reportMessage: (id: number, reason: string) => Promise<void>
+  forwardMessage: (id: number, target: api.ForwardTarget) => Promise<void>
   deleteMedia: (messageId: number) => Promise<void>
   deleteEmbed: (messageId: number, ord: number, banner: boolean) => Promise<void>
   createServer: (name: string, password?: string) => Promise<void>
   joinServer: (name: string, password?: string) => Promise<void>
   leaveServer: (name: string) => Promise<void>
+  markServerRead: (name: string) => Promise<void>
   renameServer: (name: string, newName: string) => Promise<void>
   setServerPassword: (name: string, password: string) => Promise<void>
+  setServerWelcome: (name: string, message: string) => Promise<void>
   deleteServer: (name: string) => Promise<void>
   createChannel: (server: string, name: string, kind?: ChannelKind) => Promise<void>
   renameChannel: (id: number, name: string) => Promise<void>
   setSlowmode: (id: number, seconds: number) => Promise<void>
+  setRetention: (id: number, hours: number) => Promise<void>
+  setContentTypes: (id: number, types: ContentType[]) => Promise<void>
+  reorderChannels: (server: string, ids: number[]) => Promise<void>
   deleteChannel: (id: number) => Promise<void>
   kickMember: (server: string, username: string) => Promise<void>
   grantAdmin: (server: string, username: string) => Promise<void>
   revokeAdmin: (server: string, username: string) => Promise<void>
   transferAdmin: (server: string, username: string) => Promise<void>
+  acceptTransfer: (server: string) => Promise<void>
+  declineTransfer: (server: string) => Promise<void>
   setAdminPerms: (server: string, username: string, perms: number) => Promise<void>
   createRole: (
     server: string,
@@ -303,6 +332,7 @@ interface RChatState {
   clearChannelPerm: (id: number, subject: string) => Promise<void>
   adminDeleteUser: (username: string) => Promise<void>
   adminDeleteMessage: (id: number) => Promise<void>
+  announce: (message: string, level: AnnouncementLevel, post: boolean) => Promise<void>
   loadMembers: (server: string, reset?: boolean) => Promise<void>
   loadInteracted: (server: string, reset?: boolean) => Promise<void>
   joinVoice: (server: string, channelId: number) => Promise<void>
@@ -319,7 +349,8 @@ interface RChatState {
   loadAdminUsers: (q: string, reset?: boolean) => Promise<void>
   loadAdminServers: (q: string, reset?: boolean) => Promise<void>
   adminDeleteServer: (name: string) => Promise<void>
-  banUser: (username: string) => Promise<void>
+  banUser: (username: string, purge: boolean) => Promise<void>
+  runCleanup: () => Promise<void>
   updateSettings: (patch: Partial<SiteSettings>) => Promise<void>
   setViewing: (server: string | null) => void
   applyWsEvent: (ev: WsEvent) => void
@@ -373,7 +404,13 @@ export const userRefFor = (s: RChatState, username: string): UserRef => {
     const u = cache.list.find(x => x.username === username)
     if (u) return u
   }
-  return { username, display_name: username, avatar_kind: 'identicon', avatar_color: null }
+  return {
+    username,
+    display_name: username,
+    avatar_kind: 'identicon',
+    avatar_color: null,
+    avatar_url: null,
+  }
 }
 
 export const roleMenuItems = (server: string, username: string): ContextMenuItem[] => {
@@ -393,6 +430,24 @@ export const roleMenuItems = (server: string, username: string): ContextMenuItem
   })
 }
 
+export const forwardMenuItems = (id: number): ContextMenuItem[] => {
+  const s = useStore.getState()
+  const channels =
+    s.view?.kind === 'channel'
+      ? (s.servers[s.view.server]?.channels ?? []).filter(c => c.kind === 'text')
+      : []
+  return [
+    ...channels.map(c => ({
+      label: `#${c.name}`,
+      action: () => void s.forwardMessage(id, { channel_id: c.id }),
+    })),
+    ...s.dms.map(d => ({
+      label: d.is_self ? 'Saved messages' : `@${d.other.display_name}`,
+      action: () => void s.forwardMessage(id, { dm_id: d.id }),
+    })),
+  ]
+}
+
 export const useStore = create<RChatState>()((set, get) => {
   const fail = (e: unknown) => {
     const message = e instanceof Error ? e.message : String(e)
@@ -512,6 +567,12 @@ export const useStore = create<RChatState>()((set, get) => {
       )
       .catch(() => {})
 
+  const loadTransfers = () =>
+    api
+      .listTransfers()
+      .then(transfers => set({ transfers }))
+      .catch(() => {})
+
   const startWs = () => {
     wsClient.onEvent = ev => get().applyWsEvent(ev)
     wsClient.onStatus = wsStatus => {
@@ -534,7 +595,10 @@ export const useStore = create<RChatState>()((set, get) => {
     }
     wsClient.start(get().token)
     p2p.ensurePurge()
-    if (get().me) void loadUnreads()
+    if (get().me) {
+      void loadUnreads()
+      void loadTransfers()
+    }
     if (get().guest) wsClient.subscribe(get().guestServers)
   }
 
@@ -653,6 +717,16 @@ export const useStore = create<RChatState>()((set, get) => {
     return null
   }
 
+  const preview = (m: Message) =>
+    m.encryption ? 'Encrypted message' : m.content || m.media?.filename || 'Attachment'
+
+  const setTypist = (key: string, username: string, typing: boolean) => {
+    set(s => {
+      const rest = (s.typing[key] ?? []).filter(u => u !== username)
+      return { typing: { ...s.typing, [key]: typing ? [...rest, username] : rest } }
+    })
+  }
+
   const patchMessage = (id: number, fn: (m: Message) => Message) => {
     set(s => ({
       messages: Object.fromEntries(
@@ -682,6 +756,12 @@ export const useStore = create<RChatState>()((set, get) => {
     })
   }
 
+  const patchDmStatus = (username: string, status: PresenceStatus) => {
+    set(s => ({
+      dms: s.dms.map(d => (d.other.username === username && !d.is_self ? { ...d, status } : d)),
+    }))
+  }
+
   const matches = (q: string, ...names: string[]) => {
     const needle = q.toLowerCase()
     return !needle || names.some(n => n.toLowerCase().includes(needle))
@@ -746,8 +826,9 @@ export const useStore = create<RChatState>()((set, get) => {
       fail(new Error('File uploads are disabled'))
       return false
     }
-    if (file.size > MAX_UPLOAD) {
-      fail(new Error('File exceeds 25MB limit'))
+    const { max_upload_mb } = get().settings
+    if (file.size > max_upload_mb * 1024 * 1024) {
+      fail(new Error(`File exceeds ${max_upload_mb}MB limit`))
       return false
     }
     return true
@@ -813,7 +894,7 @@ export const useStore = create<RChatState>()((set, get) => {
         try {
           const { opts, p2pId } = await buildSendOpts(head.pending, head.p2pExpiresIn)
           try {
-            real = await head.send(opts)
+            real = await head.send({ ...opts, idempotency_key: `${SEND_NONCE}-${head.tempId}` })
           } catch (e) {
             if (p2pId !== null) p2p.removeFile(p2pId)
             throw e
@@ -860,6 +941,7 @@ export const useStore = create<RChatState>()((set, get) => {
     messages: {},
     outbox: {},
     reads: {},
+    typing: {},
     unreadAnchor: {},
     atBottom: true,
     authExpired: false,
@@ -872,6 +954,7 @@ export const useStore = create<RChatState>()((set, get) => {
     pending: null,
     threadPending: null,
     notices: [],
+    transfers: [],
     search: emptySearch(),
     error: null,
     adminOverview: null,
@@ -880,6 +963,10 @@ export const useStore = create<RChatState>()((set, get) => {
       asset_previews: true,
       asset_uploads: true,
       guests_enabled: true,
+      max_upload_mb: 25,
+      servers_admin_only: false,
+      registration_open: true,
+      max_servers_per_user: null,
     },
 
     loadSettings: async () => {
@@ -902,9 +989,9 @@ export const useStore = create<RChatState>()((set, get) => {
       set({ streamer })
     },
 
-    patchMe: (avatar_kind, avatar_color) =>
+    patchMe: (avatar_kind, avatar_color, avatar_media_id) =>
       act(async () => {
-        const user = await api.patchMe(avatar_kind, avatar_color)
+        const user = await api.patchMe(avatar_kind, avatar_color, avatar_media_id)
         set(s => (s.me ? { me: { ...s.me, ...user } } : {}))
       }),
 
@@ -946,6 +1033,7 @@ export const useStore = create<RChatState>()((set, get) => {
     login: body => act(async () => enter(await api.login(body))),
 
     logout: () => {
+      if (get().token) void api.logout().catch(() => {})
       wsClient.stop()
       rtc.leave()
       p2p.endMedia()
@@ -974,6 +1062,7 @@ export const useStore = create<RChatState>()((set, get) => {
         messages: {},
         outbox: {},
         reads: {},
+        typing: {},
         unreadAnchor: {},
         atBottom: true,
         p2pAvailability: {},
@@ -984,6 +1073,7 @@ export const useStore = create<RChatState>()((set, get) => {
         pending: null,
         threadPending: null,
         notices: [],
+        transfers: [],
         search: emptySearch(),
         adminOverview: null,
       })
@@ -1065,6 +1155,18 @@ export const useStore = create<RChatState>()((set, get) => {
         await get().openDm(dm.id)
       }),
 
+    pinDm: (id, pinned) =>
+      act(async () => {
+        await api.pinDm(id, pinned)
+        set(s => ({ dms: s.dms.map(d => (d.id === id ? { ...d, pinned } : d)) }))
+      }),
+
+    muteDm: (id, muted) =>
+      act(async () => {
+        await api.muteDm(id, muted)
+        set(s => ({ dms: s.dms.map(d => (d.id === id ? { ...d, muted } : d)) }))
+      }),
+
     sendMessage: (content, p2pExpiresIn) => {
       const { view, pending, me } = get()
       if (!view || !me) return
@@ -1092,6 +1194,12 @@ export const useStore = create<RChatState>()((set, get) => {
           embeds: [],
           kind: 'user',
           call: null,
+          filter_status: 'clean',
+          forwarded_from: null,
+          encryption: null,
+          seq: null,
+          mass_mention: null,
+          deleted_at: null,
         },
         send: opts =>
           view.kind === 'channel'
@@ -1117,6 +1225,25 @@ export const useStore = create<RChatState>()((set, get) => {
       void api.postRead(scope, messageId).catch(() => {})
     },
 
+    setTyping: active => {
+      const { view, guest } = get()
+      if (guest || !view) return
+      const key = viewKey(view)
+      const target =
+        view.kind === 'channel'
+          ? { channel_id: view.channelId, dm_id: null }
+          : { channel_id: null, dm_id: view.dmId }
+      if (active) {
+        if (typingKey === key && Date.now() - typingSentAt < TYPING_PING_MS) return
+        typingKey = key
+        typingSentAt = Date.now()
+        wsClient.sendVoice({ type: 'typing', ...target })
+      } else if (typingKey === key) {
+        typingKey = null
+        wsClient.sendVoice({ type: 'stop_typing', ...target })
+      }
+    },
+
     setAtBottom: v => set({ atBottom: v }),
 
     retryOutgoing: (key, tempId) => {
@@ -1220,6 +1347,12 @@ export const useStore = create<RChatState>()((set, get) => {
           embeds: [],
           kind: 'user',
           call: null,
+          filter_status: 'clean',
+          forwarded_from: null,
+          encryption: null,
+          seq: null,
+          mass_mention: null,
+          deleted_at: null,
         },
         send: opts => api.sendThreadMessage(rootId, content, opts),
       })
@@ -1273,6 +1406,11 @@ export const useStore = create<RChatState>()((set, get) => {
 
     dismissNotice: id => set(s => ({ notices: s.notices.filter(n => n.id !== id) })),
 
+    forwardMessage: (id, target) =>
+      act(async () => {
+        await api.forwardMessage(id, target)
+      }),
+
     deleteMessage: id =>
       act(async () => {
         await api.deleteMessage(id)
@@ -1281,6 +1419,18 @@ export const useStore = create<RChatState>()((set, get) => {
             Object.entries(s.messages).map(([k, list]) => [k, list.filter(m => m.id !== id)])
           ),
         }))
+        notify('Message deleted', 'Click to undo', () => void get().restoreMessage(id))
+      }),
+
+    restoreMessage: id =>
+      act(async () => {
+        await api.restoreMessage(id)
+      }),
+
+    reportMessage: (id, reason) =>
+      act(async () => {
+        await api.reportMessage(id, reason)
+        notify('Report sent', 'The site admins will review the message', () => {})
       }),
 
     deleteMedia: messageId =>
@@ -1334,16 +1484,29 @@ export const useStore = create<RChatState>()((set, get) => {
         dropServer(name)
       }),
 
+    markServerRead: name => act(async () => void (await api.markServerRead(name))),
+
     renameServer: (name, newName) =>
       act(async () => void (await api.updateServer(name, { name: newName }))),
     setServerPassword: (name, password) =>
       act(async () => void (await api.updateServer(name, { password }))),
+    setServerWelcome: (name, message) =>
+      act(async () => {
+        await api.updateServer(name, { welcome_message: message })
+        patchServer(name, d => ({ ...d, welcome_message: message.trim() || null }))
+      }),
     deleteServer: name => act(async () => void (await api.deleteServer(name))),
     createChannel: (server, name, kind = 'text') =>
       act(async () => void (await api.createChannel(server, name, kind))),
     renameChannel: (id, name) => act(async () => void (await api.updateChannel(id, { name }))),
     setSlowmode: (id, seconds) =>
       act(async () => void (await api.updateChannel(id, { slowmode_seconds: seconds }))),
+    setRetention: (id, hours) =>
+      act(async () => void (await api.updateChannel(id, { message_retention_hours: hours }))),
+    setContentTypes: (id, types) =>
+      act(async () => void (await api.updateChannel(id, { allowed_content_types: types }))),
+    reorderChannels: (server, ids) =>
+      act(async () => void (await api.reorderChannels(server, ids))),
     deleteChannel: id => act(async () => void (await api.deleteChannel(id))),
     kickMember: (server, username) =>
       act(async () => void (await api.kickMember(server, username))),
@@ -1353,6 +1516,8 @@ export const useStore = create<RChatState>()((set, get) => {
       act(async () => void (await api.revokeAdmin(server, username))),
     transferAdmin: (server, username) =>
       act(async () => void (await api.transferAdmin(server, username))),
+    acceptTransfer: server => act(async () => void (await api.acceptTransfer(server))),
+    declineTransfer: server => act(async () => void (await api.declineTransfer(server))),
     setAdminPerms: (server, username, perms) =>
       act(async () => void (await api.setAdminPerms(server, username, perms))),
     createRole: (server, body) => act(async () => void (await api.createRole(server, body))),
@@ -1368,6 +1533,8 @@ export const useStore = create<RChatState>()((set, get) => {
       act(async () => void (await api.clearChannelPerm(id, subject))),
     adminDeleteUser: username => act(async () => void (await api.adminDeleteUser(username))),
     adminDeleteMessage: id => act(async () => void (await api.adminDeleteMessage(id))),
+    announce: (message, level, post) =>
+      act(async () => void (await api.adminAnnounce(message, level, post))),
 
     loadMembers: (server, reset = false) =>
       act(() =>
@@ -1537,7 +1704,8 @@ export const useStore = create<RChatState>()((set, get) => {
 
     adminDeleteServer: name => act(async () => void (await api.adminDeleteServer(name))),
 
-    banUser: username => act(async () => void (await api.banUser(username))),
+    banUser: (username, purge) => act(async () => void (await api.banUser(username, purge))),
+    runCleanup: () => act(async () => void (await api.adminCleanup())),
 
     updateSettings: patch =>
       act(async () => {
@@ -1553,6 +1721,9 @@ export const useStore = create<RChatState>()((set, get) => {
         case 'message': {
           const m = ev.message
           const rootId = m.thread_root_id
+          const authorKey = messageKey(m.channel_id, m.dm_id)
+          if (authorKey && get().typing[authorKey]?.includes(m.author.username))
+            setTypist(authorKey, m.author.username, false)
           if (rootId !== null) {
             set(s => {
               const messages = { ...s.messages }
@@ -1610,18 +1781,18 @@ export const useStore = create<RChatState>()((set, get) => {
           if (!me || m.author.username === me.username) return
           const { server, channel_id, dm_id } = ev
           if (dm_id !== null) {
-            if (view?.kind === 'dm' && view.dmId === dm_id) return
-            notify(m.author.display_name, m.content || m.media?.filename || 'Attachment', () => {
+            if (ev.muted || (view?.kind === 'dm' && view.dmId === dm_id)) return
+            notify(m.author.display_name, preview(m), () => {
               void get().openDm(dm_id)
             })
           } else if (channel_id !== null && server !== null) {
-            if (!m.content.toLowerCase().includes(`@${me.username}`)) return
+            if (!ev.mentioned) return
             const viewingIt =
               rootId !== null
                 ? panel?.kind === 'thread' && panel.root.id === rootId
                 : view?.kind === 'channel' && view.channelId === channel_id
             if (viewingIt) return
-            notify(`${m.author.display_name} mentioned you`, m.content, () => {
+            notify(`${m.author.display_name} mentioned you`, preview(m), () => {
               void get().openServer(server, channel_id)
             })
           }
@@ -1631,6 +1802,19 @@ export const useStore = create<RChatState>()((set, get) => {
           patchMessage(ev.message.id, () => ev.message)
           return
         }
+        case 'message_restored': {
+          const m = ev.message
+          const key =
+            m.thread_root_id !== null ? `t${m.thread_root_id}` : messageKey(m.channel_id, m.dm_id)
+          if (key) {
+            set(s =>
+              s.messages[key]
+                ? { messages: { ...s.messages, [key]: applyEmbeds(merge(s.messages[key], [m])) } }
+                : {}
+            )
+          }
+          return
+        }
         case 'message_deleted': {
           set(s => {
             const rootOf = ev.thread_root_id
@@ -1662,6 +1846,18 @@ export const useStore = create<RChatState>()((set, get) => {
           })
           return
         }
+        case 'messages_purged': {
+          const gone = new Set(ev.ids)
+          set(s => ({
+            messages: Object.fromEntries(
+              Object.entries(s.messages)
+                .filter(([k]) => !(k.startsWith('t') && gone.has(Number(k.slice(1)))))
+                .map(([k, list]) => [k, list.filter(m => !gone.has(m.id))])
+            ),
+            panel: s.panel?.kind === 'thread' && gone.has(s.panel.root.id) ? null : s.panel,
+          }))
+          return
+        }
         case 'media_removed': {
           patchMessage(ev.message_id, m =>
             m.media
@@ -1702,6 +1898,16 @@ export const useStore = create<RChatState>()((set, get) => {
           }))
           return
         }
+        case 'channels_reordered': {
+          const rank = new Map(ev.ids.map((id, i) => [id, i]))
+          patchServer(ev.server, d => ({
+            ...d,
+            channels: [...d.channels].sort(
+              (a, b) => (rank.get(a.id) ?? ev.ids.length) - (rank.get(b.id) ?? ev.ids.length)
+            ),
+          }))
+          return
+        }
         case 'channel_deleted': {
           patchServer(ev.server, d => ({
             ...d,
@@ -1834,6 +2040,13 @@ export const useStore = create<RChatState>()((set, get) => {
           if (self) void refetchMe()
           return
         }
+        case 'server_welcome': {
+          const title = get().servers[ev.server]?.display_name ?? ev.server
+          notify(`Welcome to ${title}`, ev.message, () => {
+            void get().openServer(ev.server)
+          })
+          return
+        }
         case 'member_left':
         case 'member_kicked': {
           if (get().me?.username === ev.username) {
@@ -1929,8 +2142,24 @@ export const useStore = create<RChatState>()((set, get) => {
             online_count: Math.max(0, d.online_count + (ev.online ? 1 : -1)),
           }))
           patchMembers(ev.server, list =>
-            list.map(m => (m.username === ev.username ? { ...m, online: ev.online } : m))
+            list.map(m =>
+              m.username === ev.username ? { ...m, online: ev.online, status: ev.status } : m
+            )
+          )
+          if (ev.online) patchDmStatus(ev.username, ev.status)
+          return
+        }
+        case 'status_changed': {
+          patchMembers(ev.server, list =>
+            list.map(m => (m.username === ev.username ? { ...m, status: ev.status } : m))
           )
+          patchDmStatus(ev.username, ev.status)
+          return
+        }
+        case 'typing':
+        case 'typing_stopped': {
+          const key = messageKey(ev.channel_id, ev.dm_id)
+          if (key) setTypist(key, ev.username, ev.type === 'typing')
           return
         }
         case 'read_updated': {
@@ -2032,6 +2261,14 @@ export const useStore = create<RChatState>()((set, get) => {
           if (endedChannel || mine) fail(new Error(ev.reason))
           return
         }
+        case 'resync_required': {
+          void refreshView()
+          if (get().me) {
+            void refetchMe()
+            void loadUnreads()
+          }
+          return
+        }
         case 'error': {
           fail(new Error(ev.message))
           const { me, voice, call, voiceUsers } = get()
@@ -2048,6 +2285,42 @@ export const useStore = create<RChatState>()((set, get) => {
           if (p2p.mediaActive()) p2p.endMedia()
           return
         }
+        case 'transfer_offered': {
+          const { transfer } = ev
+          set(s => ({
+            transfers: [...s.transfers.filter(t => t.server !== transfer.server), transfer],
+          }))
+          if (transfer.to_user === get().me?.username)
+            notify('Admin transfer', `${transfer.from_user} offered you ${transfer.server}`, () =>
+              get().openDialog({ kind: 'transfer', server: transfer.server })
+            )
+          return
+        }
+        case 'transfer_closed': {
+          set(s => ({ transfers: s.transfers.filter(t => t.server !== ev.server) }))
+          return
+        }
+        case 'dm_pinned': {
+          if (get().me?.username !== ev.username) return
+          set(s => ({
+            dms: s.dms.map(d => (d.id === ev.dm_id ? { ...d, pinned: ev.pinned } : d)),
+          }))
+          return
+        }
+        case 'dm_muted': {
+          if (get().me?.username !== ev.username) return
+          set(s => ({
+            dms: s.dms.map(d => (d.id === ev.dm_id ? { ...d, muted: ev.muted } : d)),
+          }))
+          return
+        }
+        case 'message_reported': {
+          if (!get().me?.is_site_admin) return
+          notify('Message reported', `${ev.report.reporter}: ${ev.report.reason}`, () =>
+            get().openDialog({ kind: 'admin_panel' })
+          )
+          return
+        }
         case 'dm_created': {
           const { me } = get()
           if (me && ev.dm_users.includes(me.username)) {
@@ -2063,6 +2336,7 @@ export const useStore = create<RChatState>()((set, get) => {
             expireSession()
             return
           }
+          if (!ev.purged) return
           set(s => ({
             messages: Object.fromEntries(
               Object.entries(s.messages).map(([k, list]) => [
@@ -2123,6 +2397,11 @@ export const useStore = create<RChatState>()((set, get) => {
           set({ settings: ev.settings })
           return
         }
+        case 'announcement': {
+          const title = ev.level === 'info' ? 'Announcement' : `Announcement (${ev.level})`
+          notify(title, ev.message, () => {})
+          return
+        }
       }
     },
 
@@ -1,4 +1,4 @@
-export type AvatarKind = 'identicon' | 'color'
+export type AvatarKind = 'identicon' | 'color' | 'image'
 
 export const Perm = {
   ManageChannels: 1,
@@ -6,9 +6,10 @@ export const Perm = {
   Kick: 4,
   DeleteServer: 8,
   ManageAdmins: 16,
+  MentionEveryone: 32,
 } as const
 
-export const ALL_PERMS = 31
+export const ALL_PERMS = 63
 
 export const hasPerm = (perms: number, perm: number) => (perms & perm) !== 0
 
@@ -17,6 +18,7 @@ export interface UserRef {
   display_name: string
   avatar_kind: AvatarKind
   avatar_color: string | null
+  avatar_url: string | null
 }
 
 export interface ServerSummary {
@@ -40,8 +42,12 @@ export interface Channel {
   name: string
   kind: ChannelKind
   slowmode_seconds: number
+  message_retention_hours: number | null
+  allowed_content_types: ContentType[] | null
 }
 
+export type ContentType = 'text' | 'attachment'
+
 export interface Role {
   id: number
   name: string
@@ -56,19 +62,52 @@ export interface ChannelPerm {
   can_read_history: boolean
 }
 
+export type PresenceStatus = 'online' | 'away' | 'offline'
+
 export interface Member extends UserRef {
   is_admin: boolean
   is_creator: boolean
   online: boolean
+  status: PresenceStatus
+  last_seen: number | null
   perms: number
   role_ids: number[]
 }
 
+export interface Capabilities {
+  is_member: boolean
+  effective_perms: number
+  can_view: boolean
+  can_read_history: boolean
+  can_post: boolean
+  can_delete_others: boolean
+  can_manage_channels: boolean
+  can_kick: boolean
+  can_manage_admins: boolean
+  can_delete_server: boolean
+  can_mention_everyone: boolean
+}
+
+export interface UserPresence {
+  username: string
+  status: PresenceStatus
+  last_seen: number | null
+  servers: { server: string; status: PresenceStatus }[]
+}
+
+export type BulkOutcome = 'added' | 'already_member' | 'banned' | 'not_found' | 'at_limit'
+
+export interface BulkMemberResult {
+  username: string
+  outcome: BulkOutcome
+}
+
 export interface ServerDetail {
   name: string
   display_name: string
   creator: string | null
   has_password: boolean
+  welcome_message: string | null
   channels: Channel[]
   roles: Role[]
   member_count: number
@@ -79,6 +118,38 @@ export interface DmSummary {
   id: number
   other: UserRef
   is_self: boolean
+  pinned: boolean
+  muted: boolean
+  status: PresenceStatus
+}
+
+export interface DmSent {
+  dm: DmSummary
+  message: Message
+}
+
+export type ActivityKind =
+  | 'member_joined'
+  | 'member_left'
+  | 'member_kicked'
+  | 'channel_created'
+  | 'channel_deleted'
+
+export interface Activity {
+  id: number
+  kind: ActivityKind
+  actor: UserRef | null
+  target: string | null
+  channel_id: number | null
+  created_at: number
+}
+
+export interface ServerStats {
+  since: number
+  members: number
+  joins: { day: number; count: number }[]
+  channels: { channel_id: number; name: string; messages: number }[]
+  top_members: { user: UserRef; messages: number }[]
 }
 
 export type MediaKind = 'server' | 'p2p'
@@ -106,7 +177,16 @@ export interface Embed {
   banner_removed: boolean
 }
 
-export type MessageKind = 'user' | 'call'
+export type MessageKind = 'user' | 'call' | 'encrypted' | 'announcement'
+
+export type AnnouncementLevel = 'info' | 'warning' | 'critical'
+
+export interface Encryption {
+  key_id: string
+  algorithm: string
+}
+
+export type FilterStatus = 'clean' | 'warning' | 'filtered'
 
 export type CallOutcome = 'missed' | 'declined' | 'completed'
 
@@ -130,14 +210,62 @@ export interface Message {
   embeds: Embed[]
   kind: MessageKind
   call: CallLog | null
+  filter_status: FilterStatus
+  forwarded_from: number | null
+  encryption: Encryption | null
+  seq: number | null
+  mass_mention: MassMention | null
+  deleted_at: number | null
 }
 
+export type MassMention = 'everyone' | 'here'
+
 export interface SearchResult {
   message: Message
   server: string
   channel_name: string
 }
 
+export interface SearchHit {
+  message_id: number
+  author: string
+  created_at: number
+  snippet: string
+}
+
+export interface MessageContext {
+  target_type: 'channel' | 'dm'
+  target_id: number
+  server_name: string | null
+  thread_root_id: number | null
+}
+
+export interface PendingTransfer {
+  server: string
+  from_user: string
+  to_user: string
+  expires_at: number
+}
+
+export interface PendingUpload {
+  id: string
+  size: number
+  received: number
+}
+
+export interface SearchGroup {
+  target_type: 'channel' | 'dm'
+  target_id: number
+  server: string | null
+  name: string
+  hits: SearchHit[]
+}
+
+export interface GlobalSearch {
+  groups: SearchGroup[]
+  next_offset: number | null
+}
+
 export interface ServerMatch {
   name: string
   display_name: string
@@ -160,6 +288,10 @@ export interface SiteSettings {
   asset_previews: boolean
   asset_uploads: boolean
   guests_enabled: boolean
+  max_upload_mb: number
+  servers_admin_only: boolean
+  registration_open: boolean
+  max_servers_per_user: number | null
 }
 
 export interface AdminOverview {
@@ -167,12 +299,85 @@ export interface AdminOverview {
   user_count: number
 }
 
+export interface AdminServerConfig extends ServerSummaryLite {
+  created_at: number
+  member_count: number
+  message_count: number
+  role_count: number
+  channels: Channel[]
+}
+
+export interface AdminMembership {
+  server: string
+  username: string
+  is_admin: boolean
+  perms: number
+  joined_at: number
+  online: boolean
+}
+
+export interface AdminInvite {
+  code: string
+  created_by: string
+  created_at: number
+  used_by: string | null
+  used_at: number | null
+}
+
+export type ReportStatus = 'open' | 'resolved'
+
+export interface MessageReport {
+  id: number
+  message_id: number | null
+  author: string
+  content: string
+  reporter: string
+  reason: string
+  status: ReportStatus
+  created_at: number
+  resolved_by: string | null
+  resolved_at: number | null
+}
+
+export interface AdminReport extends MessageReport {
+  message: Message | null
+}
+
+export interface PurgeCounts {
+  messages: number
+  dms: number
+  files: number
+  memberships: number
+}
+
+export interface BanResult {
+  ok: boolean
+  dry_run: boolean
+  affected: PurgeCounts
+}
+
 export interface Unread {
   scope: string
   last_read: number
   latest: number
 }
 
+export interface BootstrapServer extends ServerSummary {
+  channels: Channel[]
+}
+
+export interface BootstrapDm extends DmSummary {
+  last_message: Message | null
+}
+
+export interface Bootstrap {
+  user: UserRef
+  is_site_admin: boolean
+  servers: BootstrapServer[]
+  dms: BootstrapDm[]
+  unreads: Unread[]
+}
+
 interface Scoped {
   server: string | null
   channel_id: number | null
@@ -204,6 +409,7 @@ export type VoiceMsg =
   | { type: 'call_accept' | 'call_decline' | 'call_leave'; dm_id: number }
   | { type: 'p2p_hosting'; peer_id: string; ids: string[] }
   | { type: 'p2p_who'; hosters: string[] }
+  | { type: 'typing' | 'stop_typing'; channel_id: number | null; dm_id: number | null }
   | {
       type: 'rtc_signal'
       to: string
@@ -213,9 +419,11 @@ export type VoiceMsg =
     }
 
 export type WsEvent =
-  | ({ type: 'message'; message: Message } & Scoped)
+  | ({ type: 'message'; message: Message; muted: boolean; mentioned: boolean } & Scoped)
   | { type: 'message_updated'; message: Message }
   | ({ type: 'message_deleted'; id: number; thread_root_id: number | null } & Scoped)
+  | ({ type: 'message_restored'; message: Message } & Scoped)
+  | { type: 'messages_purged'; server: string; channel_id: number; ids: number[] }
   | ({
       type: 'media_removed'
       message_id: number
@@ -227,19 +435,40 @@ export type WsEvent =
   | { type: 'channel_created'; server: string; channel: Channel }
   | { type: 'channel_renamed'; server: string; channel: Channel }
   | { type: 'channel_deleted'; server: string; channel_id: number }
+  | { type: 'channels_reordered'; server: string; ids: number[] }
   | { type: 'server_created'; server: ServerSummaryLite }
   | { type: 'server_renamed'; old_name: string; server: ServerSummaryLite }
   | { type: 'server_deleted'; name: string }
   | { type: 'member_joined'; server: string; member: Member }
   | { type: 'member_left'; server: string; username: string }
   | { type: 'member_kicked'; server: string; username: string }
+  | { type: 'server_welcome'; server: string; username: string; message: string }
   | { type: 'admin_changed'; server: string; username: string; is_admin: boolean; perms: number }
   | { type: 'roles_changed'; server: string }
   | { type: 'channel_perms_changed'; server: string; channel_id: number }
   | { type: 'user_updated'; user: UserRef }
   | { type: 'user_registered'; user: UserRef }
-  | { type: 'presence_changed'; server: string; username: string; online: boolean }
+  | {
+      type: 'presence_changed'
+      server: string
+      username: string
+      online: boolean
+      status: PresenceStatus
+    }
+  | { type: 'status_changed'; server: string; username: string; status: PresenceStatus }
+  | ({ type: 'typing' | 'typing_stopped'; username: string } & Scoped)
   | { type: 'read_updated'; username: string; scope: string; last_read: number }
+  | { type: 'dm_pinned'; username: string; dm_id: number; pinned: boolean }
+  | { type: 'dm_muted'; username: string; dm_id: number; muted: boolean }
+  | { type: 'transfer_offered'; transfer: PendingTransfer }
+  | {
+      type: 'transfer_closed'
+      server: string
+      from_user: string
+      to_user: string
+      accepted: boolean
+    }
+  | { type: 'message_reported'; report: MessageReport }
   | { type: 'voice_state'; server: string; channel_id: number; users: string[] }
   | {
       type: 'call_state'
@@ -260,9 +489,11 @@ export type WsEvent =
     }
   | ({ type: 'voice_ended'; reason: string } & Scoped)
   | { type: 'error'; message: string }
+  | { type: 'resync_required'; missed: number }
   | { type: 'dm_created'; dm_users: string[] }
-  | { type: 'banned'; username: string }
+  | { type: 'banned'; username: string; purged: boolean }
   | { type: 'settings_changed'; settings: SiteSettings }
+  | { type: 'announcement'; message: string; level: AnnouncementLevel }
 
 export type WsStatus = 'green' | 'yellow' | 'red'
 
@@ -30,6 +30,8 @@ class WsClient {
   private reconnectTimer: ReturnType<typeof setTimeout> | null = null
   private degradedTimer: ReturnType<typeof setInterval> | null = null
   private connectTimer: ReturnType<typeof setTimeout> | null = null
+  private lastActive = 0
+  private watching = false
 
   onEvent: (ev: WsEvent) => void = () => {}
   onStatus: (status: WsStatus) => void = () => {}
@@ -39,6 +41,7 @@ class WsClient {
     this.stop()
     this.stopped = false
     this.token = token
+    this.watchActivity()
     this.connect()
   }
 
@@ -96,6 +99,19 @@ class WsClient {
     return true
   }
 
+  private watchActivity() {
+    if (this.watching) return
+    this.watching = true
+    const active = () => {
+      if (!this.token || Date.now() - this.lastActive < 60_000) return
+      this.lastActive = Date.now()
+      this.send({ type: 'presence', status: 'online' })
+    }
+    for (const name of ['pointerdown', 'pointermove', 'keydown', 'wheel']) {
+      window.addEventListener(name, active, { passive: true })
+    }
+  }
+
   private send(payload: unknown) {
     if (this.ws?.readyState === WebSocket.OPEN) this.ws.send(JSON.stringify(payload))
   }
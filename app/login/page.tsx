@@ -23,6 +23,7 @@ export default function LoginPage() {
   const register = useStore(s => s.register)
   const enterGuest = useStore(s => s.enterGuest)
   const guestsEnabled = useStore(s => s.settings.guests_enabled)
+  const registrationOpen = useStore(s => s.settings.registration_open)
   const loadSettings = useStore(s => s.loadSettings)
 
   useEffect(() => {
@@ -37,6 +38,7 @@ export default function LoginPage() {
   const [selected, setSelected] = useState<string[]>([])
   const [avatarKind, setAvatarKind] = useState<AvatarKind>('identicon')
   const [avatarColor, setAvatarColor] = useState('#6750a4')
+  const [invite, setInvite] = useState('')
   const [busy, setBusy] = useState(false)
 
   useEffect(() => {
@@ -84,6 +86,7 @@ export default function LoginPage() {
         ...cred,
         avatar_kind: avatarKind,
         ...(avatarKind === 'color' ? { avatar_color: avatarColor } : {}),
+        ...(invite.trim() ? { invite: invite.trim() } : {}),
       })
     }
     setBusy(false)
@@ -167,6 +170,17 @@ export default function LoginPage() {
               Type a username to see its 20 words, then pick 7 in order.
             </p>
           )}
+          {mode === 'register' && !registrationOpen && (
+            <label className="flex flex-col gap-1">
+              <span className="text-sm text-on-surface-variant">Invite code</span>
+              <input
+                value={invite}
+                onChange={e => setInvite(e.target.value)}
+                autoComplete="off"
+                className="rounded-lg border border-outline bg-transparent px-3 py-2 outline-none focus:border-primary"
+              />
+            </label>
+          )}
           {mode === 'register' && (
             <AvatarPicker
               username={username}
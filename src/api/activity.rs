@@ -0,0 +1,240 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::servers::{is_member, require_server, role_ids};
+use crate::api::{ApiError, Authed, Paged, UserRef, user_ref};
+use crate::db::{ActivityKind, Perm, has_perm, now};
+use crate::state::AppState;
+
+const ACTIVITY_RETENTION_SECS: i64 = 90 * 86400;
+
+#[derive(Serialize, ToSchema)]
+pub struct Activity {
+    pub id: i64,
+    pub kind: ActivityKind,
+    pub actor: Option<UserRef>,
+    pub target: Option<String>,
+    pub channel_id: Option<i64>,
+    pub created_at: i64,
+}
+
+#[derive(Default, Deserialize, IntoParams)]
+pub struct ActivityQuery {
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+fn hidden_channels_filter(subjects: usize) -> String {
+    let ph: Vec<String> = (0..subjects).map(|i| format!("${}", i + 2)).collect();
+    format!(
+        " AND (a.kind != 'channel_created' OR EXISTS(SELECT 1 FROM channels ch WHERE ch.id = a.channel_id) AND (NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = a.channel_id) OR EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = a.channel_id AND cp.can_view != 0 AND cp.subject IN ({}))))",
+        ph.join(", ")
+    )
+}
+
+#[utoipa::path(get, path = "/api/servers/{name}/activity", params(("name" = String, Path), ActivityQuery), responses((status = 200, body = Vec<Activity>)), security(("bearer" = [])))]
+pub(crate) async fn server_activity(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+    Query(q): Query<ActivityQuery>,
+) -> Result<Paged<Vec<Activity>>, ApiError> {
+    let db = &state.db;
+    let server = require_server(db, &name.to_lowercase()).await?;
+    if !(user.is_site_admin || is_member(db, &server.name, &user.username).await?) {
+        return Err(ApiError(StatusCode::FORBIDDEN, "Not a member".to_string()));
+    }
+    let sees_kickers = has_perm(db, &server.name, &user, Perm::Kick).await;
+    let sees_channels = has_perm(db, &server.name, &user, Perm::ManageChannels).await;
+    let mut subjects = Vec::new();
+    if !sees_channels {
+        subjects.push(format!("u:{}", user.username));
+        for id in role_ids(db, &server.name, &user.username).await? {
+            subjects.push(format!("r:{id}"));
+        }
+    }
+    let filter = match sees_channels {
+        true => String::new(),
+        false => hidden_channels_filter(subjects.len()),
+    };
+    let offset = q.offset.unwrap_or(0).max(0);
+    let limit = q.limit.unwrap_or(50).clamp(1, 50);
+    let count_sql = format!("SELECT COUNT(*) FROM server_activity a WHERE a.server = $1{filter}");
+    let mut count = sqlx::query(&count_sql).bind(&server.name);
+    for subject in &subjects {
+        count = count.bind(subject);
+    }
+    let total: i64 = count.fetch_one(db).await?.try_get(0)?;
+    let page_sql = format!(
+        "SELECT a.id, a.kind, a.actor, a.target, a.channel_id, a.created_at FROM server_activity a WHERE a.server = $1{filter} ORDER BY a.id DESC LIMIT ${} OFFSET ${}",
+        subjects.len() + 2,
+        subjects.len() + 3
+    );
+    let mut page = sqlx::query(&page_sql).bind(&server.name);
+    for subject in &subjects {
+        page = page.bind(subject);
+    }
+    let rows = page.bind(limit).bind(offset).fetch_all(db).await?;
+    let mut items = Vec::with_capacity(rows.len());
+    for r in &rows {
+        let kind = ActivityKind::parse(&r.try_get::<String, _>(1)?)?;
+        let actor: Option<String> = match (kind, sees_kickers) {
+            (ActivityKind::MemberKicked, false) => None,
+            (_, _) => r.try_get(2)?,
+        };
+        let target: Option<String> = match (kind, sees_channels) {
+            (ActivityKind::ChannelDeleted, false) => None,
+            (_, _) => r.try_get(3)?,
+        };
+        items.push(Activity {
+            id: r.try_get(0)?,
+            kind,
+            actor: match actor {
+                Some(a) => Some(user_ref(db, &a).await),
+                None => None,
+            },
+            target,
+            channel_id: r.try_get(4)?,
+            created_at: r.try_get(5)?,
+        });
+    }
+    Ok(Paged {
+        items,
+        total,
+        limit,
+        offset,
+    })
+}
+
+pub async fn sweep_activity(state: &AppState) {
+    if let Err(e) = sqlx::query("DELETE FROM server_activity WHERE created_at <= $1")
+        .bind(now() - ACTIVITY_RETENTION_SECS)
+        .execute(&state.db)
+        .await
+    {
+        tracing::warn!("server activity sweep failed: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::servers::{create_channel, delete_channel, kick_member};
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+    use axum::Json;
+
+    #[tokio::test]
+    async fn feed_shows_channels_and_hides_moderators() {
+        let (state, path) = temp_state("activity").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('den', 'Den', 'amy', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        add_member(db, "den", "amy", 1, 0, 0).await;
+        add_member(db, "den", "bob", 0, 0, 0).await;
+        add_member(db, "den", "cat", 0, 0, 0).await;
+        let create = |name: &str| {
+            create_channel(
+                State(state.clone()),
+                Authed(mem_user("root", true)),
+                Path("den".to_string()),
+                Json(serde_json::from_value(serde_json::json!({ "name": name })).expect("req")),
+            )
+        };
+        let Json(plans) = create("plans").await.expect("create plans");
+        let Json(secret) = create("secret").await.expect("create secret");
+        let _ = create("lounge").await.expect("create lounge");
+        sqlx::query("INSERT INTO channel_perms(channel_id, subject, can_view, can_send, can_read_history) VALUES($1, 'u:amy', 1, 1, 1)")
+            .bind(secret.id)
+            .execute(db)
+            .await
+            .expect("restrict channel");
+        let _ = kick_member(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("den".to_string()),
+            Json(serde_json::from_value(serde_json::json!({ "username": "cat" })).expect("req")),
+        )
+        .await
+        .expect("kick");
+
+        let Json(_) = delete_channel(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path(plans.id),
+        )
+        .await
+        .expect("delete plans");
+        let Json(_) = delete_channel(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path(secret.id),
+        )
+        .await
+        .expect("delete secret");
+        add_member(db, "den", "kit", 1, Perm::Kick as i64, 0).await;
+
+        let feed = |name: &str, offset: Option<i64>, limit: Option<i64>| {
+            server_activity(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path("Den".to_string()),
+                Query(ActivityQuery { offset, limit }),
+            )
+        };
+        let summary =
+            |page: Paged<Vec<Activity>>| -> Vec<(ActivityKind, Option<String>, Option<String>)> {
+                page.items
+                    .into_iter()
+                    .map(|a| (a.kind, a.actor.map(|u| u.username), a.target))
+                    .collect()
+            };
+        let root = || Some("root".to_string());
+        let named = |s: &str| Some(s.to_string());
+        let admin = summary(feed("amy", None, None).await.expect("admin feed"));
+        assert_eq!(
+            admin,
+            vec![
+                (ActivityKind::ChannelDeleted, root(), named("secret")),
+                (ActivityKind::ChannelDeleted, root(), named("plans")),
+                (ActivityKind::MemberKicked, root(), named("cat")),
+                (ActivityKind::ChannelCreated, root(), named("lounge")),
+                (ActivityKind::ChannelCreated, root(), named("secret")),
+                (ActivityKind::ChannelCreated, root(), named("plans")),
+            ]
+        );
+        let member = summary(feed("bob", None, None).await.expect("member feed"));
+        assert_eq!(
+            member,
+            vec![
+                (ActivityKind::ChannelDeleted, root(), None),
+                (ActivityKind::ChannelDeleted, root(), None),
+                (ActivityKind::MemberKicked, None, named("cat")),
+                (ActivityKind::ChannelCreated, root(), named("lounge")),
+            ]
+        );
+        let kicker = summary(feed("kit", None, None).await.expect("kicker feed"));
+        assert_eq!(
+            kicker,
+            vec![
+                (ActivityKind::ChannelDeleted, root(), None),
+                (ActivityKind::ChannelDeleted, root(), None),
+                (ActivityKind::MemberKicked, root(), named("cat")),
+                (ActivityKind::ChannelCreated, root(), named("lounge")),
+            ]
+        );
+        let page = feed("bob", Some(2), Some(1)).await.expect("third page");
+        assert_eq!(page.total, 4);
+        assert_eq!(
+            summary(page),
+            vec![(ActivityKind::MemberKicked, None, named("cat"))]
+        );
+        let outsider = feed("cat", None, None).await;
+        assert!(matches!(outsider, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        done(state, path).await;
+    }
+}
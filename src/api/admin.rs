@@ -1,14 +1,22 @@
 use axum::Json;
 use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::Row;
 
-use crate::api::messages::{MsgScope, message_scope};
-use crate::api::{ApiError, Authed, ServerSummaryLite, Settings, UserRef};
-use crate::db::{AvatarKind, User, get_user};
+use crate::api::auth::new_token;
+use crate::api::messages::{
+    REPORT_COLS, check_length, hide_message, load_message, message_scope, post_announcement,
+    row_report,
+};
+use crate::api::servers::{ServerPatch, apply_server_patch, guard_rchat, require_server};
+use crate::api::{
+    ApiError, Authed, Channel, Message, MessageReport, OkResp, Paged, ServerSummaryLite, Settings,
+    UserRef, cleanup_now, ok,
+};
+use crate::db::{ChannelKind, ContentType, Db, ReportStatus, User, get_user, now};
 use crate::state::AppState;
-use crate::ws::WsEvent;
+use crate::ws::{AnnouncementLevel, WsEvent};
 
 #[derive(Serialize)]
 pub struct Overview {
@@ -68,14 +76,97 @@ pub(crate) fn like_pattern(q: &str, fuzzy: bool) -> String {
     p
 }
 
+#[derive(Deserialize)]
+pub struct MembershipQuery {
+    offset: Option<i64>,
+    limit: Option<i64>,
+    server: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MembershipEntry {
+    pub server: String,
+    pub username: String,
+    pub is_admin: bool,
+    pub perms: i64,
+    pub joined_at: i64,
+    pub online: bool,
+}
+
+#[derive(Serialize)]
+pub struct ServerConfig {
+    pub name: String,
+    pub display_name: String,
+    pub creator: Option<String>,
+    pub has_password: bool,
+    pub created_at: i64,
+    pub member_count: i64,
+    pub message_count: i64,
+    pub role_count: i64,
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Serialize)]
+pub struct Invite {
+    pub code: String,
+    pub created_by: String,
+    pub created_at: i64,
+    pub used_by: Option<String>,
+    pub used_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct Impersonation {
+    pub token: String,
+    pub username: String,
+    pub impersonated_by: String,
+    pub expires_at: i64,
+}
+
 #[derive(Deserialize)]
 pub struct BanReq {
     username: String,
+    #[serde(default)]
+    purge: bool,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct PurgeCounts {
+    pub messages: i64,
+    pub dms: i64,
+    pub files: i64,
+    pub memberships: i64,
 }
 
 #[derive(Serialize)]
-pub struct OkResp {
+pub struct BanResp {
     pub ok: bool,
+    pub dry_run: bool,
+    pub affected: PurgeCounts,
+}
+
+#[derive(Deserialize)]
+pub struct AnnounceReq {
+    message: String,
+    #[serde(default)]
+    level: AnnouncementLevel,
+    #[serde(default)]
+    post: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    status: Option<ReportStatus>,
+}
+
+#[derive(Serialize)]
+pub struct ReportEntry {
+    #[serde(flatten)]
+    pub report: MessageReport,
+    pub message: Option<Message>,
 }
 
 #[derive(Deserialize)]
@@ -84,6 +175,12 @@ pub struct SettingsPatch {
     asset_previews: Option<bool>,
     asset_uploads: Option<bool>,
     guests_enabled: Option<bool>,
+    #[serde(default, deserialize_with = "present")]
+    max_servers_per_user: Option<Option<i64>>,
+}
+
+fn present<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Option<i64>>, D::Error> {
+    Option::<i64>::deserialize(d).map(Some)
 }
 
 fn require_site_admin(user: &User) -> Result<(), ApiError> {
@@ -95,7 +192,7 @@ fn require_site_admin(user: &User) -> Result<(), ApiError> {
 
 #[utoipa::path(get, path = "/api/settings", responses((status = 200, body = Settings)))]
 pub(crate) async fn get_settings(State(state): State<AppState>) -> Json<Settings> {
-    Json(Settings::load(&state.db).await)
+    Json(Settings::load(&state.db, &state.config).await)
 }
 
 pub(crate) async fn patch_settings(
@@ -109,6 +206,7 @@ pub(crate) async fn patch_settings(
         asset_previews,
         asset_uploads,
         guests_enabled,
+        max_servers_per_user,
     } = req;
     let pairs = [
         ("profanity_filter", profanity_filter),
@@ -130,7 +228,23 @@ pub(crate) async fn patch_settings(
             .await?;
         }
     }
-    let settings = Settings::load(&state.db).await;
+    match max_servers_per_user {
+        Some(Some(cap)) => {
+            sqlx::query(
+                "INSERT INTO settings(key, value) VALUES('max_servers_per_user', $1) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            )
+            .bind(cap.max(0).to_string())
+            .execute(&state.db)
+            .await?;
+        }
+        Some(None) => {
+            sqlx::query("DELETE FROM settings WHERE key = 'max_servers_per_user'")
+                .execute(&state.db)
+                .await?;
+        }
+        None => {}
+    }
+    let settings = Settings::load(&state.db, &state.config).await;
     state.hub.broadcast(WsEvent::SettingsChanged { settings });
     Ok(Json(settings))
 }
@@ -158,13 +272,19 @@ pub(crate) async fn list_users(
     State(state): State<AppState>,
     Authed(user): Authed,
     Query(query): Query<AdminQuery>,
-) -> Result<Json<Vec<UserRef>>, ApiError> {
+) -> Result<Paged<Vec<UserRef>>, ApiError> {
     require_site_admin(&user)?;
     let (offset, limit, q) = query.page();
     let fuzzy = is_fuzzy(&q);
-    let rows = sqlx::query(
-        "SELECT username, display_name, avatar_kind, avatar_color FROM users WHERE lower(username) LIKE $1 ESCAPE '\\' OR lower(display_name) LIKE $1 ESCAPE '\\' ORDER BY username LIMIT $2 OFFSET $3",
-    )
+    let filter = "lower(username) LIKE $1 ESCAPE '\\' OR lower(display_name) LIKE $1 ESCAPE '\\'";
+    let total: i64 = sqlx::query(&format!("SELECT COUNT(*) FROM users WHERE {filter}"))
+        .bind(like_pattern(&q, fuzzy))
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
+    let rows = sqlx::query(&format!(
+        "SELECT * FROM users WHERE {filter} ORDER BY username LIMIT $2 OFFSET $3"
+    ))
     .bind(like_pattern(&q, fuzzy))
     .bind(limit)
     .bind(offset)
@@ -172,32 +292,37 @@ pub(crate) async fn list_users(
     .await?;
     let mut users = Vec::with_capacity(rows.len());
     for r in &rows {
-        let username: String = r.try_get(0)?;
-        let display_name: String = r.try_get(1)?;
-        if fuzzy && !(fuzzy_match(&q, &username) || fuzzy_match(&q, &display_name)) {
+        let user = User::from_row(r)?;
+        if fuzzy && !(fuzzy_match(&q, &user.username) || fuzzy_match(&q, &user.display_name)) {
             continue;
         }
-        users.push(UserRef {
-            username,
-            display_name,
-            avatar_kind: AvatarKind::parse(&r.try_get::<String, _>(2)?)?,
-            avatar_color: r.try_get(3)?,
-        });
+        users.push(UserRef::from_user(&user));
     }
-    Ok(Json(users))
+    Ok(Paged {
+        items: users,
+        total,
+        limit,
+        offset,
+    })
 }
 
 pub(crate) async fn list_servers(
     State(state): State<AppState>,
     Authed(user): Authed,
     Query(query): Query<AdminQuery>,
-) -> Result<Json<Vec<ServerSummaryLite>>, ApiError> {
+) -> Result<Paged<Vec<ServerSummaryLite>>, ApiError> {
     require_site_admin(&user)?;
     let (offset, limit, q) = query.page();
     let fuzzy = is_fuzzy(&q);
-    let rows = sqlx::query(
-        "SELECT name, display_name, creator, password_hash FROM servers WHERE lower(name) LIKE $1 ESCAPE '\\' OR lower(display_name) LIKE $1 ESCAPE '\\' ORDER BY name LIMIT $2 OFFSET $3",
-    )
+    let filter = "lower(name) LIKE $1 ESCAPE '\\' OR lower(display_name) LIKE $1 ESCAPE '\\'";
+    let total: i64 = sqlx::query(&format!("SELECT COUNT(*) FROM servers WHERE {filter}"))
+        .bind(like_pattern(&q, fuzzy))
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
+    let rows = sqlx::query(&format!(
+        "SELECT name, display_name, creator, password_hash FROM servers WHERE {filter} ORDER BY name LIMIT $2 OFFSET $3"
+    ))
     .bind(like_pattern(&q, fuzzy))
     .bind(limit)
     .bind(offset)
@@ -217,7 +342,79 @@ pub(crate) async fn list_servers(
             has_password: r.try_get::<Option<String>, _>(3)?.is_some(),
         });
     }
-    Ok(Json(servers))
+    Ok(Paged {
+        items: servers,
+        total,
+        limit,
+        offset,
+    })
+}
+
+async fn server_config(db: &Db, lite: ServerSummaryLite) -> Result<ServerConfig, ApiError> {
+    let row = sqlx::query(
+        "SELECT s.created_at, (SELECT COUNT(*) FROM members WHERE server = s.name), (SELECT COUNT(*) FROM messages m JOIN channels c ON c.id = m.channel_id WHERE c.server = s.name AND m.deleted_at IS NULL), (SELECT COUNT(*) FROM roles WHERE server = s.name) FROM servers s WHERE s.name = $1",
+    )
+    .bind(&lite.name)
+    .fetch_one(db)
+    .await?;
+    let rows = sqlx::query(
+        "SELECT id, name, kind, slowmode_seconds, message_retention_hours, allowed_content_types FROM channels WHERE server = $1 ORDER BY position, id",
+    )
+    .bind(&lite.name)
+    .fetch_all(db)
+    .await?;
+    let mut channels = Vec::with_capacity(rows.len());
+    for r in &rows {
+        channels.push(Channel {
+            id: r.try_get(0)?,
+            name: r.try_get(1)?,
+            kind: ChannelKind::parse(&r.try_get::<String, _>(2)?)?,
+            slowmode_seconds: r.try_get(3)?,
+            message_retention_hours: r.try_get(4)?,
+            allowed_content_types: ContentType::parse_list(r.try_get(5)?)?,
+        });
+    }
+    let ServerSummaryLite {
+        name,
+        display_name,
+        creator,
+        has_password,
+    } = lite;
+    Ok(ServerConfig {
+        name,
+        display_name,
+        creator,
+        has_password,
+        created_at: row.try_get(0)?,
+        member_count: row.try_get(1)?,
+        message_count: row.try_get(2)?,
+        role_count: row.try_get(3)?,
+        channels,
+    })
+}
+
+pub(crate) async fn get_server(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Json<ServerConfig>, ApiError> {
+    require_site_admin(&user)?;
+    let lite = require_server(&state.db, &name.to_lowercase()).await?;
+    Ok(Json(server_config(&state.db, lite).await?))
+}
+
+pub(crate) async fn patch_server(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+    Json(req): Json<ServerPatch>,
+) -> Result<Json<ServerConfig>, ApiError> {
+    require_site_admin(&user)?;
+    let key = name.to_lowercase();
+    guard_rchat(&key)?;
+    let lite = require_server(&state.db, &key).await?;
+    let lite = apply_server_patch(&state, key, lite, req).await?;
+    Ok(Json(server_config(&state.db, lite).await?))
 }
 
 pub(crate) async fn delete_server(
@@ -245,7 +442,7 @@ pub(crate) async fn delete_server(
         )),
         _ => {
             state.hub.broadcast(WsEvent::ServerDeleted { name });
-            Ok(Json(OkResp { ok: true }))
+            Ok(ok())
         }
     }
 }
@@ -254,9 +451,13 @@ pub(crate) async fn ban_user(
     State(state): State<AppState>,
     Authed(user): Authed,
     Json(req): Json<BanReq>,
-) -> Result<Json<OkResp>, ApiError> {
+) -> Result<Json<BanResp>, ApiError> {
     require_site_admin(&user)?;
-    let BanReq { username } = req;
+    let BanReq {
+        username,
+        purge,
+        dry_run,
+    } = req;
     let key = username.to_lowercase();
     if key == user.username {
         return Err(ApiError(
@@ -274,40 +475,32 @@ pub(crate) async fn ban_user(
             ));
         }
     }
-    let servers = member_servers(&mut tx, &key).await?;
-    sqlx::query("DELETE FROM messages WHERE author = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM dms WHERE user_a = $1 OR user_b = $2")
-        .bind(&key)
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM members WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM user_roles WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM channel_perms WHERE subject = $1")
-        .bind(format!("u:{key}"))
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM interactions WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM tokens WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM users WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
+    let affected = match purge {
+        true => purge_counts(&mut tx, &key).await?,
+        false => PurgeCounts::default(),
+    };
+    if dry_run {
+        tx.rollback().await?;
+        return Ok(Json(BanResp {
+            ok: true,
+            dry_run,
+            affected,
+        }));
+    }
+    let servers = match purge {
+        true => {
+            let servers = member_servers(&mut tx, &key).await?;
+            purge_user(&mut tx, &key).await?;
+            servers
+        }
+        false => {
+            sqlx::query("DELETE FROM tokens WHERE username = $1")
+                .bind(&key)
+                .execute(&mut *tx)
+                .await?;
+            Vec::new()
+        }
+    };
     sqlx::query(
         "INSERT INTO banned_usernames(username) VALUES($1) ON CONFLICT(username) DO NOTHING",
     )
@@ -315,8 +508,12 @@ pub(crate) async fn ban_user(
     .execute(&mut *tx)
     .await?;
     tx.commit().await?;
-    remove_user_events(&state, key, servers);
-    Ok(Json(OkResp { ok: true }))
+    remove_user_events(&state, key, servers, purge);
+    Ok(Json(BanResp {
+        ok: true,
+        dry_run,
+        affected,
+    }))
 }
 
 async fn member_servers(
@@ -334,9 +531,83 @@ async fn member_servers(
     Ok(servers)
 }
 
-fn remove_user_events(state: &AppState, username: String, servers: Vec<String>) {
+const PURGED_MESSAGES: &str = "author = $1 OR dm_id IN (SELECT id FROM dms WHERE user_a = $1 OR user_b = $1) OR thread_root_id IN (SELECT id FROM messages WHERE author = $1)";
+const PURGED_DMS: &str = "user_a = $1 OR user_b = $1";
+
+async fn purge_counts(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    username: &str,
+) -> Result<PurgeCounts, ApiError> {
+    let count = async |tx: &mut sqlx::Transaction<'_, sqlx::Any>, sql: String| {
+        sqlx::query(&sql)
+            .bind(username)
+            .fetch_one(&mut **tx)
+            .await?
+            .try_get::<i64, _>(0)
+    };
+    Ok(PurgeCounts {
+        messages: count(
+            tx,
+            format!("SELECT COUNT(*) FROM messages WHERE {PURGED_MESSAGES}"),
+        )
+        .await?,
+        dms: count(tx, format!("SELECT COUNT(*) FROM dms WHERE {PURGED_DMS}")).await?,
+        files: count(
+            tx,
+            format!("SELECT COUNT(*) FROM media WHERE uploader = $1 OR id IN (SELECT media_id FROM messages WHERE {PURGED_MESSAGES})"),
+        )
+        .await?,
+        memberships: count(
+            tx,
+            "SELECT COUNT(*) FROM members WHERE username = $1".to_string(),
+        )
+        .await?,
+    })
+}
+
+async fn purge_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    username: &str,
+) -> Result<(), ApiError> {
+    sqlx::query(&format!("DELETE FROM messages WHERE {PURGED_MESSAGES}"))
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(&format!("DELETE FROM dms WHERE {PURGED_DMS}"))
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM members WHERE username = $1")
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM user_roles WHERE username = $1")
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM channel_perms WHERE subject = $1")
+        .bind(format!("u:{username}"))
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM interactions WHERE username = $1")
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM tokens WHERE username = $1")
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM users WHERE username = $1")
+        .bind(username)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+fn remove_user_events(state: &AppState, username: String, servers: Vec<String>, purged: bool) {
     state.hub.broadcast(WsEvent::Banned {
         username: username.clone(),
+        purged,
     });
     for server in servers {
         state.hub.broadcast(WsEvent::MemberLeft {
@@ -370,42 +641,10 @@ pub(crate) async fn delete_user(
         }
     }
     let servers = member_servers(&mut tx, &key).await?;
-    sqlx::query("DELETE FROM messages WHERE author = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM dms WHERE user_a = $1 OR user_b = $2")
-        .bind(&key)
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM members WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM user_roles WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM channel_perms WHERE subject = $1")
-        .bind(format!("u:{key}"))
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM interactions WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM tokens WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
-    sqlx::query("DELETE FROM users WHERE username = $1")
-        .bind(&key)
-        .execute(&mut *tx)
-        .await?;
+    purge_user(&mut tx, &key).await?;
     tx.commit().await?;
-    remove_user_events(&state, key, servers);
-    Ok(Json(OkResp { ok: true }))
+    remove_user_events(&state, key, servers, true);
+    Ok(ok())
 }
 
 pub(crate) async fn user_servers(
@@ -432,6 +671,53 @@ pub(crate) async fn user_servers(
     Ok(Json(servers))
 }
 
+pub(crate) async fn list_memberships(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Query(query): Query<MembershipQuery>,
+) -> Result<Paged<Vec<MembershipEntry>>, ApiError> {
+    require_site_admin(&user)?;
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 50);
+    let key = |v: &Option<String>| v.as_deref().unwrap_or("").trim().to_lowercase();
+    let (server, username) = (key(&query.server), key(&query.username));
+    let filter = "($1 = '' OR server = $1) AND ($2 = '' OR username = $2)";
+    let total: i64 = sqlx::query(&format!("SELECT COUNT(*) FROM members WHERE {filter}"))
+        .bind(&server)
+        .bind(&username)
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
+    let rows = sqlx::query(&format!(
+        "SELECT server, username, is_admin, perms, joined_at FROM members WHERE {filter} ORDER BY server, joined_at, username LIMIT $3 OFFSET $4"
+    ))
+    .bind(&server)
+    .bind(&username)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await?;
+    let mut items = Vec::with_capacity(rows.len());
+    for r in &rows {
+        let server: String = r.try_get(0)?;
+        let username: String = r.try_get(1)?;
+        items.push(MembershipEntry {
+            online: state.hub.is_online(&server, &username),
+            server,
+            username,
+            is_admin: r.try_get::<i64, _>(2)? != 0,
+            perms: r.try_get(3)?,
+            joined_at: r.try_get(4)?,
+        });
+    }
+    Ok(Paged {
+        items,
+        total,
+        limit,
+        offset,
+    })
+}
+
 pub(crate) async fn delete_message(
     State(state): State<AppState>,
     Authed(user): Authed,
@@ -439,41 +725,235 @@ pub(crate) async fn delete_message(
 ) -> Result<Json<OkResp>, ApiError> {
     require_site_admin(&user)?;
     let scope = message_scope(&state.db, id).await?;
-    sqlx::query("DELETE FROM messages WHERE id = $1")
-        .bind(id)
+    hide_message(&state, id, scope).await?;
+    Ok(ok())
+}
+
+pub(crate) async fn run_cleanup(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Json<OkResp>, ApiError> {
+    require_site_admin(&user)?;
+    cleanup_now(&state).await;
+    Ok(ok())
+}
+
+pub(crate) async fn announce(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Json(req): Json<AnnounceReq>,
+) -> Result<Json<OkResp>, ApiError> {
+    require_site_admin(&user)?;
+    let AnnounceReq {
+        message,
+        level,
+        post,
+    } = req;
+    let message = message.trim().to_string();
+    if message.is_empty() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Announcement is empty".to_string(),
+        ));
+    }
+    check_length(&message)?;
+    state.announces.check(&user.username).map_err(|wait| {
+        ApiError(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Announcing too often: wait {wait}s"),
+        )
+    })?;
+    if post && let Some(posted) = post_announcement(&state.db, &user, &message).await? {
+        state.hub.broadcast(WsEvent::Message {
+            server: Some("rchat".to_string()),
+            channel_id: posted.channel_id,
+            dm_id: None,
+            dm_users: None,
+            message: Box::new(posted),
+            muted: false,
+            mentioned: false,
+        });
+    }
+    state
+        .hub
+        .broadcast(WsEvent::Announcement { message, level });
+    Ok(ok())
+}
+
+const IMPERSONATION_SECS: i64 = 15 * 60;
+
+pub(crate) async fn impersonate(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(username): Path<String>,
+) -> Result<Json<Impersonation>, ApiError> {
+    require_site_admin(&user)?;
+    let target = get_user(&state.db, &username.to_lowercase())
+        .await?
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "User not found".to_string()))?;
+    if target.is_site_admin {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "Site admins cannot be impersonated".to_string(),
+        ));
+    }
+    let t = now();
+    let grant = Impersonation {
+        token: format!("imp_{}", new_token()),
+        username: target.username,
+        impersonated_by: user.username,
+        expires_at: t + IMPERSONATION_SECS,
+    };
+    let mut tx = state.db.begin().await?;
+    sqlx::query(
+        "INSERT INTO tokens(token, username, created_at, expires_at, impersonated_by) VALUES($1, $2, $3, $4, $5)",
+    )
+    .bind(&grant.token)
+    .bind(&grant.username)
+    .bind(t)
+    .bind(grant.expires_at)
+    .bind(&grant.impersonated_by)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO impersonation_log(admin, username, created_at, expires_at) VALUES($1, $2, $3, $4)",
+    )
+    .bind(&grant.impersonated_by)
+    .bind(&grant.username)
+    .bind(t)
+    .bind(grant.expires_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    tracing::warn!(
+        "{} started impersonating {} until {}",
+        grant.impersonated_by,
+        grant.username,
+        grant.expires_at
+    );
+    Ok(Json(grant))
+}
+
+pub(crate) async fn list_invites(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Json<Vec<Invite>>, ApiError> {
+    require_site_admin(&user)?;
+    let rows = sqlx::query(
+        "SELECT code, created_by, created_at, used_by, used_at FROM site_invites ORDER BY created_at DESC, code",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let mut invites = Vec::with_capacity(rows.len());
+    for r in &rows {
+        invites.push(Invite {
+            code: r.try_get(0)?,
+            created_by: r.try_get(1)?,
+            created_at: r.try_get(2)?,
+            used_by: r.try_get(3)?,
+            used_at: r.try_get(4)?,
+        });
+    }
+    Ok(Json(invites))
+}
+
+pub(crate) async fn create_invite(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Json<Invite>, ApiError> {
+    require_site_admin(&user)?;
+    let invite = Invite {
+        code: new_token()[..16].to_string(),
+        created_by: user.username,
+        created_at: now(),
+        used_by: None,
+        used_at: None,
+    };
+    sqlx::query("INSERT INTO site_invites(code, created_by, created_at) VALUES($1, $2, $3)")
+        .bind(&invite.code)
+        .bind(&invite.created_by)
+        .bind(invite.created_at)
         .execute(&state.db)
         .await?;
-    let MsgScope {
-        channel_id,
-        dm_id,
-        thread_root_id,
-        author: _,
-        media_id: _,
-        media_filename: _,
-        media_kind: _,
-        server,
-        dm_users,
-    } = scope;
-    state.hub.broadcast(WsEvent::MessageDeleted {
-        server,
-        channel_id,
-        dm_id,
-        dm_users,
-        id,
-        thread_root_id,
-    });
-    Ok(Json(OkResp { ok: true }))
+    Ok(Json(invite))
+}
+
+pub(crate) async fn delete_invite(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(code): Path<String>,
+) -> Result<Json<OkResp>, ApiError> {
+    require_site_admin(&user)?;
+    sqlx::query("DELETE FROM site_invites WHERE code = $1 AND used_by IS NULL")
+        .bind(&code)
+        .execute(&state.db)
+        .await?;
+    Ok(ok())
+}
+
+pub(crate) async fn list_reports(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<Vec<ReportEntry>>, ApiError> {
+    require_site_admin(&user)?;
+    let status = query.status.unwrap_or(ReportStatus::Open);
+    let rows = sqlx::query(&format!(
+        "SELECT {REPORT_COLS} FROM message_reports WHERE status = $1 ORDER BY created_at DESC, id DESC"
+    ))
+    .bind(status.as_str())
+    .fetch_all(&state.db)
+    .await?;
+    let mut reports = Vec::with_capacity(rows.len());
+    for r in &rows {
+        let report = row_report(r)?;
+        let message = match report.message_id {
+            Some(id) => load_message(&state.db, id).await?,
+            None => None,
+        };
+        reports.push(ReportEntry { message, report });
+    }
+    Ok(Json(reports))
+}
+
+pub(crate) async fn resolve_report(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<i64>,
+) -> Result<Json<MessageReport>, ApiError> {
+    require_site_admin(&user)?;
+    sqlx::query(
+        "UPDATE message_reports SET status = 'resolved', resolved_by = $1, resolved_at = $2 WHERE id = $3 AND status = 'open'",
+    )
+    .bind(&user.username)
+    .bind(now())
+    .bind(id)
+    .execute(&state.db)
+    .await?;
+    let row = sqlx::query(&format!(
+        "SELECT {REPORT_COLS} FROM message_reports WHERE id = $1"
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "Report not found".to_string()))?;
+    Ok(Json(row_report(&row)?))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api::auth::{AuthResp, RegisterReq, register};
-    use crate::api::test_util::{done, temp_state};
+    use crate::api::auth::{
+        AuthResp, LoginReq, RegisterReq, login, register, sweep_expired_tokens,
+    };
+    use crate::api::messages::report_message;
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+    use crate::api::{FormError, user_for_token};
     use crate::db::{Db, now};
+    use axum::extract::FromRequestParts;
     use serde_json::json;
 
-    async fn reg(state: &AppState, name: &str) -> Result<Json<AuthResp>, ApiError> {
+    async fn reg(state: &AppState, name: &str) -> Result<Json<AuthResp>, FormError> {
         let req: RegisterReq = serde_json::from_value(json!({
             "username": name,
             "password": "a",
@@ -492,6 +972,114 @@ mod tests {
             .expect("count value")
     }
 
+    #[tokio::test]
+    async fn ban_dry_run_changes_nothing() {
+        let (state, path) = temp_state("ban_dry_run").await;
+        let _ = reg(&state, "alice").await.expect("register alice");
+        let _ = reg(&state, "bob").await.expect("register bob");
+        let db = &state.db;
+        let cid: i64 = sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("general channel")
+            .try_get(0)
+            .expect("channel id");
+        let root: i64 = sqlx::query("INSERT INTO messages(channel_id, author, content, media_id, media_filename, created_at) VALUES($1, 'bob', 'pic', 'f1', 'a.png', $2) RETURNING id")
+            .bind(cid)
+            .bind(now())
+            .fetch_one(db)
+            .await
+            .expect("bob message")
+            .try_get(0)
+            .expect("message id");
+        sqlx::query("INSERT INTO messages(channel_id, thread_root_id, author, content, created_at) VALUES($1, $2, 'alice', 'nice', $3)")
+            .bind(cid)
+            .bind(root)
+            .bind(now())
+            .execute(db)
+            .await
+            .expect("thread reply");
+        let dm: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('alice', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("open dm")
+                .try_get(0)
+                .expect("dm id");
+        sqlx::query("INSERT INTO messages(dm_id, author, content, created_at) VALUES($1, 'alice', 'yo', $2)")
+            .bind(dm)
+            .bind(now())
+            .execute(db)
+            .await
+            .expect("dm message");
+        for (id, uploader) in [("f1", "bob"), ("f2", "bob"), ("f3", "alice")] {
+            sqlx::query("INSERT INTO media(id, filename, mime, size, uploader, uploaded_at) VALUES($1, 'a.png', 'image/png', 1, $2, 0)")
+                .bind(id)
+                .bind(uploader)
+                .execute(db)
+                .await
+                .expect("insert media");
+        }
+        sqlx::query("UPDATE messages SET media_id = 'f3' WHERE dm_id = $1")
+            .bind(dm)
+            .execute(db)
+            .await
+            .expect("attach to dm");
+        let totals = async || {
+            (
+                count(db, "SELECT COUNT(*) FROM messages").await,
+                count(db, "SELECT COUNT(*) FROM dms").await,
+                count(db, "SELECT COUNT(*) FROM members").await,
+                count(db, "SELECT COUNT(*) FROM users").await,
+            )
+        };
+        let before = totals().await;
+        let ban = async |dry_run: bool| {
+            let alice = get_user(db, "alice").await.unwrap().expect("alice");
+            ban_user(
+                State(state.clone()),
+                Authed(alice),
+                Json(BanReq {
+                    username: "bob".to_string(),
+                    purge: true,
+                    dry_run,
+                }),
+            )
+            .await
+            .expect("ban")
+            .0
+        };
+        let preview = ban(true).await;
+        assert!(preview.dry_run);
+        assert_eq!(totals().await, before);
+        assert_eq!(count(db, "SELECT COUNT(*) FROM banned_usernames").await, 0);
+        assert_eq!(
+            count(db, "SELECT COUNT(*) FROM tokens WHERE username = 'bob'").await,
+            1
+        );
+        assert_eq!(
+            preview.affected,
+            PurgeCounts {
+                messages: 3,
+                dms: count(
+                    db,
+                    "SELECT COUNT(*) FROM dms WHERE user_a = 'bob' OR user_b = 'bob'"
+                )
+                .await,
+                files: 3,
+                memberships: 1,
+            }
+        );
+        let done_ban = ban(false).await;
+        assert_eq!(done_ban.affected, preview.affected);
+        let after = totals().await;
+        assert_eq!(before.0 - after.0, preview.affected.messages);
+        assert_eq!(before.1 - after.1, preview.affected.dms);
+        assert_eq!(before.2 - after.2, preview.affected.memberships);
+        assert_eq!(before.3 - after.3, 1);
+        done(state, path).await;
+    }
+
     #[tokio::test]
     async fn ban_cascade() {
         let (state, path) = temp_state("ban").await;
@@ -539,6 +1127,8 @@ mod tests {
             Authed(alice),
             Json(BanReq {
                 username: "Bob".to_string(),
+                purge: true,
+                dry_run: false,
             }),
         )
         .await
@@ -585,9 +1175,523 @@ mod tests {
         );
         let again = reg(&state, "bob").await;
         match again {
-            Err(ApiError(StatusCode::BAD_REQUEST, msg)) => assert_eq!(msg, "Username is banned"),
+            Err(FormError::Field(e)) => {
+                assert_eq!((e.field, e.code), ("username", "banned"));
+                assert_eq!(e.message, "Username is banned");
+            }
             _ => panic!("expected banned re-register rejection"),
         }
         done(state, path).await;
     }
+
+    #[tokio::test]
+    async fn impersonation_token_is_scoped() {
+        let (state, path) = temp_state("impersonate").await;
+        let _ = reg(&state, "root").await.expect("register root");
+        let _ = reg(&state, "amy").await.expect("register amy");
+        let db = &state.db;
+        let denied = impersonate(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path("root".to_string()),
+        )
+        .await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let admin_target = impersonate(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("root".to_string()),
+        )
+        .await;
+        assert!(matches!(
+            admin_target,
+            Err(ApiError(StatusCode::FORBIDDEN, _))
+        ));
+        let Json(grant) = impersonate(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("Amy".to_string()),
+        )
+        .await
+        .expect("impersonate amy");
+        assert!(grant.token.starts_with("imp_"));
+        assert_eq!(grant.impersonated_by, "root");
+        let user = user_for_token(&state, &grant.token)
+            .await
+            .expect("token authenticates");
+        assert_eq!(user.username, "amy");
+        for (method, allowed) in [
+            ("GET", true),
+            ("HEAD", true),
+            ("POST", false),
+            ("DELETE", false),
+        ] {
+            let (mut parts, ()) = axum::http::Request::builder()
+                .method(method)
+                .header("authorization", format!("Bearer {}", grant.token))
+                .body(())
+                .expect("request")
+                .into_parts();
+            let authed = Authed::from_request_parts(&mut parts, &state).await;
+            match allowed {
+                true => assert!(matches!(authed, Ok(Authed(u)) if u.username == "amy")),
+                false => assert!(
+                    matches!(authed, Err(ApiError(StatusCode::FORBIDDEN, _))),
+                    "{method}"
+                ),
+            }
+        }
+        let by: Option<String> = sqlx::query("SELECT impersonated_by FROM tokens WHERE token = $1")
+            .bind(&grant.token)
+            .fetch_one(db)
+            .await
+            .expect("token row")
+            .try_get(0)
+            .expect("impersonated_by");
+        assert_eq!(by.as_deref(), Some("root"));
+        sqlx::query("UPDATE tokens SET expires_at = $1 WHERE token = $2")
+            .bind(now())
+            .bind(&grant.token)
+            .execute(db)
+            .await
+            .expect("expire token");
+        assert!(user_for_token(&state, &grant.token).await.is_none());
+        sweep_expired_tokens(&state).await;
+        assert_eq!(
+            count(db, "SELECT COUNT(*) FROM tokens WHERE token LIKE 'imp_%'").await,
+            0
+        );
+        let rows = sqlx::query("SELECT admin, username, expires_at FROM impersonation_log")
+            .fetch_all(db)
+            .await
+            .expect("audit rows");
+        let logged: Vec<(String, String, i64)> = rows
+            .iter()
+            .map(|r| {
+                (
+                    r.try_get(0).expect("admin"),
+                    r.try_get(1).expect("username"),
+                    r.try_get(2).expect("expires_at"),
+                )
+            })
+            .collect();
+        assert_eq!(
+            logged,
+            vec![("root".to_string(), "amy".to_string(), grant.expires_at)]
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn soft_ban_keeps_content() {
+        let (state, path) = temp_state("softban").await;
+        let _ = reg(&state, "alice").await.expect("register alice");
+        let _ = reg(&state, "bob").await.expect("register bob");
+        let db = &state.db;
+        let cid: i64 = sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("general channel")
+            .try_get(0)
+            .expect("channel id");
+        sqlx::query("INSERT INTO messages(channel_id, author, content, created_at) VALUES($1, 'bob', 'hi', $2)")
+            .bind(cid)
+            .bind(now())
+            .execute(db)
+            .await
+            .expect("bob message");
+        let alice = get_user(db, "alice")
+            .await
+            .expect("query alice")
+            .expect("alice");
+        let _ = ban_user(
+            State(state.clone()),
+            Authed(alice),
+            Json(BanReq {
+                username: "bob".to_string(),
+                purge: false,
+                dry_run: false,
+            }),
+        )
+        .await
+        .expect("ban");
+        assert_eq!(
+            count(db, "SELECT COUNT(*) FROM users WHERE username = 'bob'").await,
+            1
+        );
+        assert_eq!(
+            count(db, "SELECT COUNT(*) FROM messages WHERE author = 'bob'").await,
+            1
+        );
+        assert_eq!(
+            count(db, "SELECT COUNT(*) FROM members WHERE username = 'bob'").await,
+            1
+        );
+        assert_eq!(
+            count(db, "SELECT COUNT(*) FROM tokens WHERE username = 'bob'").await,
+            0
+        );
+        let req: LoginReq = serde_json::from_value(json!({
+            "username": "bob",
+            "password": "a"
+        }))
+        .expect("login req");
+        let denied = login(State(state.clone()), Json(req)).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn cleanup_removes_expired_media() {
+        let (state, path) = temp_state("cleanup").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploaded_at) VALUES('old', 'a.txt', 'text/plain', 1, $1, $2)")
+            .bind(vec![0u8])
+            .bind(now() - crate::api::media::MEDIA_TTL_SECS - 1)
+            .execute(db)
+            .await
+            .expect("insert media");
+        let denied = run_cleanup(State(state.clone()), Authed(mem_user("amy", false))).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        assert_eq!(count(db, "SELECT COUNT(*) FROM media").await, 1);
+        let _ = run_cleanup(State(state.clone()), Authed(mem_user("root", true)))
+            .await
+            .expect("cleanup");
+        assert_eq!(count(db, "SELECT COUNT(*) FROM media").await, 0);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn admin_updates_server_config() {
+        let (state, path) = temp_state("admin_server").await;
+        sqlx::query(
+            "INSERT INTO servers(name, display_name, creator, created_at) VALUES('club', 'Club', 'amy', $1)",
+        )
+        .bind(now())
+        .execute(&state.db)
+        .await
+        .expect("insert server");
+        let patch: ServerPatch =
+            serde_json::from_value(json!({ "password": "door" })).expect("patch");
+        let denied = patch_server(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path("club".to_string()),
+            Json(patch),
+        )
+        .await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let patch: ServerPatch =
+            serde_json::from_value(json!({ "password": "door" })).expect("patch");
+        let _ = patch_server(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("club".to_string()),
+            Json(patch),
+        )
+        .await
+        .expect("patch server");
+        let Json(config) = get_server(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("Club".to_string()),
+        )
+        .await
+        .expect("get server");
+        assert!(config.has_password);
+        assert_eq!(config.creator.as_deref(), Some("amy"));
+        assert_eq!(config.member_count, 0);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn announcement_reaches_sockets() {
+        let (state, path) = temp_state("announce").await;
+        let mut rx = state.hub.subscribe();
+        let say = |name: &str, admin: bool, body: serde_json::Value| {
+            announce(
+                State(state.clone()),
+                Authed(mem_user(name, admin)),
+                Json(serde_json::from_value(body).expect("announce req")),
+            )
+        };
+        let denied = say("amy", false, json!({ "message": "hi" })).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let blank = say("root", true, json!({ "message": "  " })).await;
+        assert!(matches!(blank, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let _ = say(
+            "root",
+            true,
+            json!({ "message": "maintenance at noon", "level": "warning" }),
+        )
+        .await
+        .expect("announce");
+        match rx.try_recv().expect("announcement event") {
+            WsEvent::Announcement { message, level } => {
+                assert_eq!(message, "maintenance at noon");
+                assert_eq!(level, AnnouncementLevel::Warning);
+            }
+            _ => panic!("expected announcement"),
+        }
+        assert!(rx.try_recv().is_err());
+        sqlx::query("INSERT INTO channel_perms(channel_id, subject) SELECT id, 'u:root' FROM channels WHERE server = 'rchat'")
+            .execute(&state.db)
+            .await
+            .expect("restrict channels");
+        let open: i64 = sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES('rchat', 'news', 9, 0) RETURNING id")
+            .fetch_one(&state.db)
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        let _ = say(
+            "root",
+            true,
+            json!({ "message": "welcome all", "post": true }),
+        )
+        .await
+        .expect("announce and post");
+        match rx.try_recv().expect("posted message") {
+            WsEvent::Message {
+                server,
+                channel_id,
+                dm_id: _,
+                dm_users: _,
+                message,
+                muted: _,
+                mentioned: _,
+            } => {
+                assert_eq!(server.as_deref(), Some("rchat"));
+                assert_eq!(channel_id, Some(open));
+                assert_eq!(message.kind, "announcement");
+                assert_eq!(message.content, "welcome all");
+            }
+            _ => panic!("expected posted message"),
+        }
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::Announcement {
+                message: _,
+                level: AnnouncementLevel::Info,
+            })
+        ));
+        let _ = say("root", true, json!({ "message": "third" }))
+            .await
+            .expect("within limit");
+        let spam = say("root", true, json!({ "message": "fourth" })).await;
+        assert!(matches!(
+            spam,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn pagination_headers_match_totals() {
+        let (state, path) = temp_state("paged").await;
+        for name in ["root", "amy", "bob"] {
+            let _ = reg(&state, name).await.expect("register");
+        }
+        let page = |offset, limit| AdminQuery {
+            offset: Some(offset),
+            limit: Some(limit),
+            q: None,
+        };
+        let root = || Authed(mem_user("root", true));
+        let all = list_users(State(state.clone()), root(), Query(page(0, 50)))
+            .await
+            .expect("all users");
+        assert_eq!(all.total, all.items.len() as i64);
+        let second = list_users(State(state.clone()), root(), Query(page(1, 1)))
+            .await
+            .expect("second user");
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].username, all.items[1].username);
+        let resp = axum::response::IntoResponse::into_response(second);
+        let header = |name| resp.headers()[name].to_str().expect("ascii").to_string();
+        assert_eq!(header(crate::api::TOTAL_COUNT), all.total.to_string());
+        assert_eq!(header(crate::api::PAGE_LIMIT), "1");
+        assert_eq!(header(crate::api::PAGE_OFFSET), "1");
+        let servers = list_servers(State(state.clone()), root(), Query(page(5, 10)))
+            .await
+            .expect("servers");
+        assert!(servers.items.is_empty());
+        assert_eq!(servers.total, 1);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn memberships_filter_by_server_and_user() {
+        let (state, path) = temp_state("memberships").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('club', 'Club', 'amy', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        add_member(db, "rchat", "amy", 0, 0, 1).await;
+        add_member(db, "rchat", "bob", 0, 0, 2).await;
+        add_member(db, "club", "amy", 1, 0, 3).await;
+        state.hub.set_viewing("amy", None, Some("club"));
+        let query = |server: Option<&str>, username: Option<&str>, offset: i64| MembershipQuery {
+            offset: Some(offset),
+            limit: Some(1),
+            server: server.map(str::to_string),
+            username: username.map(str::to_string),
+        };
+        let rows = |page: Paged<Vec<MembershipEntry>>| -> Vec<(String, String, bool, bool)> {
+            page.items
+                .into_iter()
+                .map(|m| (m.server, m.username, m.is_admin, m.online))
+                .collect()
+        };
+        let list = |q: MembershipQuery, admin: bool| {
+            list_memberships(
+                State(state.clone()),
+                Authed(mem_user("root", admin)),
+                Query(q),
+            )
+        };
+        let amy = list(query(None, Some("AMY"), 0), true)
+            .await
+            .expect("by user");
+        assert_eq!(amy.total, 2);
+        assert_eq!(
+            rows(amy),
+            vec![("club".to_string(), "amy".to_string(), true, true)]
+        );
+        let rchat = list(query(Some("rchat"), None, 1), true)
+            .await
+            .expect("by server");
+        assert_eq!(rchat.total, 2);
+        assert_eq!(
+            rows(rchat),
+            vec![("rchat".to_string(), "bob".to_string(), false, false)]
+        );
+        let both = list(query(Some("rchat"), Some("amy"), 0), true)
+            .await
+            .expect("by both");
+        assert_eq!((both.total, both.items[0].joined_at), (1, 1));
+        assert!(matches!(
+            list(query(None, None, 0), false).await,
+            Err(ApiError(StatusCode::NOT_FOUND, _))
+        ));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn admins_resolve_reports() {
+        let (state, path) = temp_state("reports").await;
+        let db = &state.db;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        let id: i64 = sqlx::query("INSERT INTO messages(channel_id, author, content, created_at) SELECT id, 'amy', 'buy now', $1 FROM channels WHERE server = 'rchat' RETURNING id")
+            .bind(now())
+            .fetch_one(db)
+            .await
+            .expect("insert message")
+            .try_get(0)
+            .expect("message id");
+        let req = serde_json::from_value(json!({ "reason": "spam" })).expect("report req");
+        let Json(report) = report_message(
+            State(state.clone()),
+            Path(id),
+            Authed(mem_user("bob", false)),
+            axum::http::HeaderMap::new(),
+            Json(req),
+        )
+        .await
+        .expect("report");
+        let list = |user, status| {
+            list_reports(
+                State(state.clone()),
+                Authed(user),
+                Query(ReportQuery { status }),
+            )
+        };
+        let hidden = list(mem_user("bob", false), None).await;
+        assert!(matches!(hidden, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let Json(open) = list(mem_user("root", true), None).await.expect("open");
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].report.id, report.id);
+        assert_eq!(
+            open[0].message.as_ref().map(|m| m.content.as_str()),
+            Some("buy now")
+        );
+        let resolve = |id| {
+            resolve_report(
+                State(state.clone()),
+                Authed(mem_user("root", true)),
+                Path(id),
+            )
+        };
+        let Json(done_report) = resolve(report.id).await.expect("resolve");
+        assert_eq!(done_report.status, ReportStatus::Resolved);
+        assert_eq!(done_report.resolved_by.as_deref(), Some("root"));
+        let Json(open) = list(mem_user("root", true), None).await.expect("open");
+        assert!(open.is_empty());
+        let Json(resolved) = list(mem_user("root", true), Some(ReportStatus::Resolved))
+            .await
+            .expect("resolved");
+        assert_eq!(resolved.len(), 1);
+        let missing = resolve(report.id + 1).await;
+        assert!(matches!(missing, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn admin_delete_hides_the_message() {
+        let (state, path) = temp_state("admin_delete").await;
+        let db = &state.db;
+        let id: i64 = sqlx::query("INSERT INTO messages(channel_id, author, content, created_at) SELECT id, 'amy', 'buy now', $1 FROM channels WHERE server = 'rchat' RETURNING id")
+            .bind(now())
+            .fetch_one(db)
+            .await
+            .expect("insert message")
+            .try_get(0)
+            .expect("message id");
+        let mut rx = state.hub.subscribe();
+        let _ = delete_message(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path(id),
+        )
+        .await
+        .expect("delete");
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::MessageDeleted { .. })));
+        let deleted_at: Option<i64> = sqlx::query("SELECT deleted_at FROM messages WHERE id = $1")
+            .bind(id)
+            .fetch_one(db)
+            .await
+            .expect("row kept")
+            .try_get(0)
+            .expect("deleted_at");
+        assert!(deleted_at.is_some());
+        assert!(load_message(db, id).await.expect("load").is_none());
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn null_clears_the_server_cap_override() {
+        let (state, path) = temp_state("cap_override").await;
+        let patch = |body: serde_json::Value| {
+            patch_settings(
+                State(state.clone()),
+                Authed(mem_user("root", true)),
+                Json(serde_json::from_value(body).expect("patch")),
+            )
+        };
+        let Json(set) = patch(json!({ "max_servers_per_user": 3 }))
+            .await
+            .expect("set cap");
+        assert_eq!(set.max_servers_per_user, Some(3));
+        let Json(kept) = patch(json!({ "guests_enabled": true }))
+            .await
+            .expect("other setting");
+        assert_eq!(kept.max_servers_per_user, Some(3));
+        let Json(cleared) = patch(json!({ "max_servers_per_user": null }))
+            .await
+            .expect("clear cap");
+        assert_eq!(cleared.max_servers_per_user, None);
+        done(state, path).await;
+    }
 }
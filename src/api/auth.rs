@@ -9,15 +9,21 @@ use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sqlx::Row;
+use unicode_normalization::is_nfc;
 use utoipa::ToSchema;
 
+use crate::api::dms::{DmQuery, user_dms};
+use crate::api::media::check_avatar_media;
 use crate::api::{
-    ApiError, Authed, DmSummary, Member, ServerSummary, UserRef, check_profanity, user_ref,
-    valid_color,
+    ApiError, Authed, DmSummary, FieldError, FormError, Member, OkResp, ServerSummary, UserRef,
+    avatar_url, check_profanity, normalize_color, ok, request_token, user_for_token,
+};
+use crate::config::AppConfig;
+use crate::db::{
+    ActivityKind, AvatarKind, Db, SYSTEM_USERNAME, User, get_user, now, record_activity,
 };
-use crate::db::{AvatarKind, Db, User, get_user, now};
 use crate::state::AppState;
-use crate::ws::WsEvent;
+use crate::ws::{PresenceStatus, WsEvent};
 
 #[derive(Serialize, ToSchema)]
 pub struct Me {
@@ -25,6 +31,7 @@ pub struct Me {
     pub display_name: String,
     pub avatar_kind: AvatarKind,
     pub avatar_color: Option<String>,
+    pub avatar_url: Option<String>,
     pub is_site_admin: bool,
     pub servers: Vec<ServerSummary>,
     pub dms: Vec<DmSummary>,
@@ -48,6 +55,7 @@ pub struct RegisterReq {
     words: Option<Vec<String>>,
     avatar_kind: AvatarKind,
     avatar_color: Option<String>,
+    invite: Option<String>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -57,13 +65,38 @@ pub struct LoginReq {
     words: Option<Vec<String>>,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct VerifyReq {
+    token: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VerifyResp {
+    pub valid: bool,
+    pub username: String,
+    pub expires_at: Option<i64>,
+}
+
+const WORD_SET_SIZE: usize = 20;
+const WORD_PICKS: usize = 7;
+const _: () = assert!(memorable_wordlist::WORDS.len() > WORD_SET_SIZE);
+
 fn word_set(username: &str) -> Vec<String> {
+    sample_words(memorable_wordlist::WORDS, username, WORD_SET_SIZE).unwrap_or_default()
+}
+
+fn sample_words(list: &[&str], username: &str, size: usize) -> Option<Vec<String>> {
+    if list.len() <= size {
+        return None;
+    }
     let seed: [u8; 32] = Sha256::digest(username.to_lowercase().as_bytes()).into();
     let mut rng = ChaCha8Rng::from_seed(seed);
-    rand::seq::index::sample(&mut rng, memorable_wordlist::WORDS.len(), 20)
-        .into_iter()
-        .map(|i| memorable_wordlist::WORDS[i].to_string())
-        .collect()
+    Some(
+        rand::seq::index::sample(&mut rng, list.len(), size)
+            .into_iter()
+            .map(|i| list[i].to_string())
+            .collect(),
+    )
 }
 
 pub(crate) fn hash_password(secret: &str) -> Result<String, ApiError> {
@@ -89,44 +122,197 @@ pub(crate) fn new_token() -> String {
     hex::encode(bytes)
 }
 
-fn bad(msg: &str) -> ApiError {
-    ApiError(StatusCode::BAD_REQUEST, msg.to_string())
+const DEFAULT_PALETTE: [&str; 12] = [
+    "#e53935", "#d81b60", "#8e24aa", "#5e35b1", "#3949ab", "#1e88e5", "#00897b", "#43a047",
+    "#7cb342", "#fdd835", "#fb8c00", "#6d4c41",
+];
+
+fn parse_palette(raw: &str) -> Vec<String> {
+    let mut palette = Vec::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match normalize_color(entry) {
+            Some(color) => palette.push(color),
+            None => tracing::warn!("AVATAR_PALETTE: ignoring invalid color {entry:?}"),
+        }
+    }
+    match palette.is_empty() {
+        true => DEFAULT_PALETTE.iter().map(|c| c.to_string()).collect(),
+        false => palette,
+    }
+}
+
+fn avatar_palette(config: &AppConfig) -> Vec<String> {
+    parse_palette(config.avatar_palette.as_deref().unwrap_or_default())
+}
+
+fn palette_color(username: &str, palette: &[String]) -> String {
+    let digest = Sha256::digest(username.to_lowercase().as_bytes());
+    let pick = u64::from_be_bytes(digest[..8].try_into().expect("8 bytes"));
+    palette[(pick % palette.len() as u64) as usize].clone()
 }
 
 fn check_avatar(
+    username: &str,
     avatar_kind: AvatarKind,
     avatar_color: Option<String>,
-) -> Result<Option<String>, ApiError> {
+    palette: &[String],
+) -> Result<Option<String>, FieldError> {
     match (avatar_kind, avatar_color) {
         (AvatarKind::Identicon, _) => Ok(None),
-        (AvatarKind::Color, Some(color)) if valid_color(&color) => Ok(Some(color.to_lowercase())),
-        (AvatarKind::Color, _) => Err(bad("Invalid avatar color")),
+        (AvatarKind::Color, None) => Ok(Some(palette_color(username, palette))),
+        (AvatarKind::Color, Some(color)) => match normalize_color(color.trim()) {
+            Some(color) => Ok(Some(color)),
+            None => Err(FieldError::new(
+                "avatar_color",
+                "invalid",
+                "Invalid avatar color",
+            )),
+        },
+        (AvatarKind::Image, _) => Err(FieldError::new(
+            "avatar_kind",
+            "invalid",
+            "Upload an image avatar after signing up",
+        )),
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+fn script_of(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => {
+            Some(Script::Latin)
+        }
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PasswordPolicy {
+    min_len: usize,
+    min_classes: usize,
+}
+
+fn password_policy(config: &AppConfig) -> PasswordPolicy {
+    PasswordPolicy {
+        min_len: config.password_min_length.max(1),
+        min_classes: config.password_min_classes.clamp(1, 4),
+    }
+}
+
+fn check_password(password: &str, policy: PasswordPolicy) -> Result<(), FieldError> {
+    let len = password.chars().count();
+    if len == 0 {
+        return Err(FieldError::new("password", "required", "Password required"));
+    }
+    if len < policy.min_len {
+        return Err(FieldError::new(
+            "password",
+            "too_short",
+            format!("Password must be at least {} characters", policy.min_len),
+        ));
+    }
+    let classes = [
+        password.chars().any(char::is_lowercase),
+        password.chars().any(char::is_uppercase),
+        password.chars().any(|c| c.is_numeric()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|&has| has)
+    .count();
+    if classes < policy.min_classes {
+        return Err(FieldError::new(
+            "password",
+            "too_simple",
+            format!(
+                "Password must mix at least {} of lowercase, uppercase, digits and symbols",
+                policy.min_classes
+            ),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn sweep_expired_tokens(state: &AppState) {
+    if let Err(e) = sqlx::query("DELETE FROM tokens WHERE expires_at <= $1")
+        .bind(now())
+        .execute(&state.db)
+        .await
+    {
+        tracing::warn!("token sweep failed: {e}");
+    }
+}
+
+fn check_username(username: &str, ascii_only: bool) -> Result<(), FieldError> {
+    let invalid = |code, message| Err(FieldError::new("username", code, message));
+    if username.trim().is_empty() {
+        return invalid("required", "Username required");
+    }
+    if ascii_only && !username.chars().all(|c| matches!(c, ' '..='~')) {
+        return invalid("not_ascii", "Username must be printable ASCII");
+    }
+    let hidden = username.chars().any(|c| {
+        c.is_control()
+            || matches!(
+                c,
+                '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2069}' | '\u{FEFF}'
+            )
+    });
+    if hidden || !is_nfc(username) {
+        return invalid("invalid_characters", "Username contains invalid characters");
+    }
+    let mut scripts = username.chars().filter_map(script_of);
+    if let Some(first) = scripts.next()
+        && scripts.any(|s| s != first)
+    {
+        return invalid(
+            "mixed_scripts",
+            "Username mixes Latin, Greek or Cyrillic letters",
+        );
+    }
+    Ok(())
+}
+
 fn secret_from(
     username: &str,
     password: Option<String>,
     words: Option<Vec<String>>,
     validate_words: bool,
-) -> Result<(String, &'static str), ApiError> {
+) -> Result<(String, &'static str), FieldError> {
     match (password, words) {
         (Some(password), None) => Ok((password, "text")),
         (None, Some(words)) => {
             let words: Vec<String> = words.into_iter().map(|w| w.to_lowercase()).collect();
             if validate_words {
                 let set = word_set(username);
-                if words.len() != 7 || words.iter().any(|w| !set.contains(w)) {
-                    return Err(bad("Pick 7 words from your word set"));
+                if words.len() != WORD_PICKS || words.iter().any(|w| !set.contains(w)) {
+                    return Err(FieldError::new(
+                        "words",
+                        "not_in_set",
+                        format!("Pick {WORD_PICKS} words from your word set"),
+                    ));
                 }
             }
             Ok((words.join(" "), "words"))
         }
-        (_, _) => Err(bad("Provide exactly one of password or words")),
+        (_, _) => Err(FieldError::new(
+            "password",
+            "exclusive",
+            "Provide exactly one of password or words",
+        )),
     }
 }
 
-pub(crate) async fn me_payload(db: &Db, user: &User) -> Result<Me, ApiError> {
+pub(crate) async fn me_payload(state: &AppState, user: &User) -> Result<Me, ApiError> {
+    let db = &state.db;
     let rows = sqlx::query(
         "SELECT s.name, s.display_name, s.creator, m.is_admin FROM members m JOIN servers s ON s.name = m.server WHERE m.username = $1 ORDER BY m.joined_at",
     )
@@ -142,32 +328,13 @@ pub(crate) async fn me_payload(db: &Db, user: &User) -> Result<Me, ApiError> {
             is_admin: r.try_get::<i64, _>(3)? != 0,
         });
     }
-    let rows = sqlx::query(
-        "SELECT id, user_a, user_b FROM dms WHERE user_a = $1 OR user_b = $2 ORDER BY id",
-    )
-    .bind(&user.username)
-    .bind(&user.username)
-    .fetch_all(db)
-    .await?;
-    let mut dms = Vec::with_capacity(rows.len());
-    for r in &rows {
-        let (id, a, b): (i64, String, String) = (r.try_get(0)?, r.try_get(1)?, r.try_get(2)?);
-        let is_self = a == b;
-        let other = match a == user.username {
-            true => b,
-            false => a,
-        };
-        dms.push(DmSummary {
-            id,
-            other: user_ref(db, &other).await,
-            is_self,
-        });
-    }
+    let dms = user_dms(db, &state.hub, &user.username, &DmQuery::default()).await?;
     Ok(Me {
         username: user.username.clone(),
         display_name: user.display_name.clone(),
         avatar_kind: user.avatar_kind,
         avatar_color: user.avatar_color.clone(),
+        avatar_url: avatar_url(user),
         is_site_admin: user.is_site_admin,
         servers,
         dms,
@@ -185,38 +352,76 @@ pub(crate) async fn words(Path(username): Path<String>) -> Json<WordsResp> {
 pub(crate) async fn register(
     State(state): State<AppState>,
     Json(req): Json<RegisterReq>,
-) -> Result<Json<AuthResp>, ApiError> {
+) -> Result<Json<AuthResp>, FormError> {
+    create_account(&state, req, state.config.registration_open).await
+}
+
+async fn create_account(
+    state: &AppState,
+    req: RegisterReq,
+    open: bool,
+) -> Result<Json<AuthResp>, FormError> {
     let RegisterReq {
         username,
         password,
         words,
         avatar_kind,
         avatar_color,
+        invite,
     } = req;
-    if username.trim().is_empty() {
-        return Err(bad("Username required"));
-    }
+    check_username(&username, state.config.ascii_usernames)?;
     let key = username.to_lowercase();
+    if key == SYSTEM_USERNAME {
+        return Err(FieldError::new("username", "reserved", "Username is reserved").into());
+    }
     let (secret, password_kind) = secret_from(&username, password, words, true)?;
-    let avatar_color = check_avatar(avatar_kind, avatar_color)?;
+    if password_kind == "text" {
+        check_password(&secret, password_policy(&state.config))?;
+    }
+    let avatar_color = check_avatar(
+        &username,
+        avatar_kind,
+        avatar_color,
+        &avatar_palette(&state.config),
+    )?;
     let mut tx = state.db.begin().await?;
-    check_profanity(&mut *tx, &username).await?;
+    check_profanity(&mut *tx, &username)
+        .await
+        .map_err(|ApiError(_, message)| FieldError::new("username", "blocked", message))?;
     let banned = sqlx::query("SELECT 1 FROM banned_usernames WHERE username = $1")
         .bind(&key)
         .fetch_optional(&mut *tx)
         .await?
         .is_some();
     if banned {
-        return Err(bad("Username is banned"));
+        return Err(FieldError::new("username", "banned", "Username is banned").into());
     }
     if get_user(&mut *tx, &key).await?.is_some() {
-        return Err(bad("Username is taken"));
+        return Err(FieldError::new("username", "taken", "Username is taken").into());
     }
     let user_count: i64 = sqlx::query("SELECT COUNT(*) FROM users")
         .fetch_one(&mut *tx)
         .await?
         .try_get(0)?;
     let t = now();
+    if !open && user_count > 0 {
+        let claimed = sqlx::query(
+            "UPDATE site_invites SET used_by = $1, used_at = $2 WHERE code = $3 AND used_by IS NULL",
+        )
+        .bind(&key)
+        .bind(t)
+        .bind(invite.as_deref().map(str::trim).unwrap_or(""))
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            return Err(ApiError(
+                StatusCode::FORBIDDEN,
+                "Registration requires a valid invite".to_string(),
+            )
+            .into());
+        }
+    }
     sqlx::query(
         "INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, avatar_color, is_site_admin, created_at) VALUES($1, $2, $3, $4, $5, $6, $7, $8)",
     )
@@ -237,6 +442,15 @@ pub(crate) async fn register(
     .bind(t)
     .execute(&mut *tx)
     .await?;
+    record_activity(
+        &mut *tx,
+        "rchat",
+        ActivityKind::MemberJoined,
+        &key,
+        &key,
+        None,
+    )
+    .await?;
     sqlx::query("INSERT INTO dms(user_a, user_b) VALUES($1, $2)")
         .bind(&key)
         .bind(&key)
@@ -254,7 +468,7 @@ pub(crate) async fn register(
         Some(user) => user,
         None => return Err(anyhow::anyhow!("user missing after insert").into()),
     };
-    let me = me_payload(&state.db, &user).await?;
+    let me = me_payload(state, &user).await?;
     state.hub.broadcast(WsEvent::MemberJoined {
         server: "rchat".to_string(),
         member: Member {
@@ -262,6 +476,8 @@ pub(crate) async fn register(
             is_admin: false,
             is_creator: false,
             online: false,
+            status: PresenceStatus::Offline,
+            last_seen: None,
             perms: 0,
             role_ids: Vec::new(),
         },
@@ -272,22 +488,10 @@ pub(crate) async fn register(
     Ok(Json(AuthResp { token, user: me }))
 }
 
-#[utoipa::path(post, path = "/api/auth/login", request_body = LoginReq, responses((status = 200, body = AuthResp)))]
-pub(crate) async fn login(
-    State(state): State<AppState>,
-    Json(req): Json<LoginReq>,
-) -> Result<Json<AuthResp>, ApiError> {
-    let LoginReq {
-        username,
-        password,
-        words,
-    } = req;
-    let key = username.to_lowercase();
-    let db = &state.db;
-    let t = now();
+async fn throttle_login(db: &Db, key: &str, t: i64) -> Result<(), ApiError> {
     let day = (t / 86400).to_string();
     let row = sqlx::query("SELECT day, count, last_at FROM login_attempts WHERE username = $1")
-        .bind(&key)
+        .bind(key)
         .fetch_optional(db)
         .await?;
     let prev: Option<(String, i64, i64)> = match &row {
@@ -301,7 +505,7 @@ pub(crate) async fn login(
     sqlx::query(
         "INSERT INTO login_attempts(username, day, count, last_at) VALUES($1, $2, $3, $4) ON CONFLICT(username) DO UPDATE SET day = excluded.day, count = excluded.count, last_at = excluded.last_at",
     )
-    .bind(&key)
+    .bind(key)
     .bind(&day)
     .bind(count)
     .bind(t)
@@ -314,14 +518,28 @@ pub(crate) async fn login(
         ));
     }
     match &prev {
-        Some((d, _, last)) if *d == day && t - last < 3 => {
-            return Err(ApiError(
-                StatusCode::TOO_MANY_REQUESTS,
-                "Wait 3 seconds between attempts".to_string(),
-            ));
-        }
-        _ => {}
+        Some((d, _, last)) if *d == day && t - last < 3 => Err(ApiError(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Wait 3 seconds between attempts".to_string(),
+        )),
+        _ => Ok(()),
     }
+}
+
+#[utoipa::path(post, path = "/api/auth/login", request_body = LoginReq, responses((status = 200, body = AuthResp)))]
+pub(crate) async fn login(
+    State(state): State<AppState>,
+    Json(req): Json<LoginReq>,
+) -> Result<Json<AuthResp>, ApiError> {
+    let LoginReq {
+        username,
+        password,
+        words,
+    } = req;
+    let key = username.to_lowercase();
+    let db = &state.db;
+    let t = now();
+    throttle_login(db, &key, t).await?;
     let (secret, _) = secret_from(&username, password, words, false)?;
     let user = match get_user(db, &key).await? {
         Some(user) if verify_password(&secret, &user.password_hash) => user,
@@ -332,6 +550,17 @@ pub(crate) async fn login(
             ));
         }
     };
+    let banned = sqlx::query("SELECT 1 FROM banned_usernames WHERE username = $1")
+        .bind(&key)
+        .fetch_optional(db)
+        .await?
+        .is_some();
+    if banned {
+        return Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "Account is banned".to_string(),
+        ));
+    }
     let token = new_token();
     sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES($1, $2, $3)")
         .bind(&token)
@@ -339,22 +568,57 @@ pub(crate) async fn login(
         .bind(t)
         .execute(db)
         .await?;
-    let me = me_payload(db, &user).await?;
+    let me = me_payload(&state, &user).await?;
     Ok(Json(AuthResp { token, user: me }))
 }
 
+#[utoipa::path(post, path = "/api/auth/logout", responses((status = 200, description = "Token revoked", body = OkResp)), security(("bearer" = [])))]
+pub(crate) async fn logout(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<OkResp>, ApiError> {
+    let token = request_token(&headers)
+        .ok_or_else(|| ApiError(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))?;
+    sqlx::query("DELETE FROM tokens WHERE token = $1")
+        .bind(&token)
+        .execute(&state.db)
+        .await?;
+    Ok(ok())
+}
+
+#[utoipa::path(post, path = "/api/auth/verify", request_body = VerifyReq, responses((status = 200, body = VerifyResp), (status = 401, description = "Token is invalid")))]
+pub(crate) async fn verify(
+    State(state): State<AppState>,
+    Json(req): Json<VerifyReq>,
+) -> Result<Json<VerifyResp>, ApiError> {
+    let user = user_for_token(&state, &req.token)
+        .await
+        .ok_or_else(|| ApiError(StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+    let expires_at = sqlx::query("SELECT expires_at FROM tokens WHERE token = $1")
+        .bind(&req.token)
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
+    Ok(Json(VerifyResp {
+        valid: true,
+        username: user.username,
+        expires_at,
+    }))
+}
+
 #[utoipa::path(get, path = "/api/me", responses((status = 200, body = Me)), security(("bearer" = [])))]
 pub(crate) async fn me(
     State(state): State<AppState>,
     Authed(user): Authed,
 ) -> Result<Json<Me>, ApiError> {
-    Ok(Json(me_payload(&state.db, &user).await?))
+    Ok(Json(me_payload(&state, &user).await?))
 }
 
 #[derive(Deserialize, ToSchema)]
 pub struct MePatch {
     avatar_kind: AvatarKind,
     avatar_color: Option<String>,
+    avatar_media_id: Option<String>,
 }
 
 #[utoipa::path(patch, path = "/api/me", request_body = MePatch, responses((status = 200, body = UserRef)), security(("bearer" = [])))]
@@ -362,24 +626,43 @@ pub(crate) async fn patch_me(
     State(state): State<AppState>,
     Authed(user): Authed,
     Json(req): Json<MePatch>,
-) -> Result<Json<UserRef>, ApiError> {
+) -> Result<Json<UserRef>, FormError> {
     let MePatch {
         avatar_kind,
         avatar_color,
+        avatar_media_id,
     } = req;
-    let avatar_color = check_avatar(avatar_kind, avatar_color)?;
-    sqlx::query("UPDATE users SET avatar_kind = $1, avatar_color = $2 WHERE username = $3")
-        .bind(avatar_kind.as_str())
-        .bind(&avatar_color)
-        .bind(&user.username)
-        .execute(&state.db)
-        .await?;
-    let user_ref = UserRef {
-        username: user.username,
-        display_name: user.display_name,
-        avatar_kind,
-        avatar_color,
-    };
+    match avatar_kind {
+        AvatarKind::Image => {
+            let media_id = avatar_media_id
+                .ok_or_else(|| FieldError::new("avatar_media_id", "required", "Choose an image"))?;
+            check_avatar_media(&state, &user.username, &media_id).await?;
+            sqlx::query("UPDATE users SET avatar_kind = $1, avatar_color = NULL, avatar_media_id = $2 WHERE username = $3")
+                .bind(AvatarKind::Image.as_str())
+                .bind(&media_id)
+                .bind(&user.username)
+                .execute(&state.db)
+                .await?;
+        }
+        _ => {
+            let avatar_color = check_avatar(
+                &user.username,
+                avatar_kind,
+                avatar_color,
+                &avatar_palette(&state.config),
+            )?;
+            sqlx::query("UPDATE users SET avatar_kind = $1, avatar_color = $2, avatar_media_id = NULL WHERE username = $3")
+                .bind(avatar_kind.as_str())
+                .bind(&avatar_color)
+                .bind(&user.username)
+                .execute(&state.db)
+                .await?;
+        }
+    }
+    let user = get_user(&state.db, &user.username)
+        .await?
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "User not found".to_string()))?;
+    let user_ref = UserRef::from_user(&user);
     state.hub.broadcast(WsEvent::UserUpdated {
         user: user_ref.clone(),
     });
@@ -389,7 +672,9 @@ pub(crate) async fn patch_me(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::media::AVATAR_MAX_BYTES;
     use crate::api::test_util::{done, temp_state};
+    use crate::api::user_for_token;
     use crate::state::AppState;
 
     #[test]
@@ -402,7 +687,307 @@ mod tests {
         assert_ne!(a, word_set("bob"));
     }
 
-    async fn register_text(state: &AppState, name: &str) -> Result<Json<AuthResp>, ApiError> {
+    #[test]
+    fn short_wordlists_yield_no_set() {
+        assert_eq!(sample_words(&["a", "b", "c"], "alice", 20), None);
+        assert_eq!(sample_words(&["a", "b", "c"], "alice", 3), None);
+        let small = sample_words(&["a", "b", "c"], "alice", 2).expect("enough words");
+        assert_eq!(small.len(), 2);
+        assert_ne!(small[0], small[1]);
+    }
+
+    #[test]
+    fn avatar_colors() {
+        for (input, expected) in [
+            ("#A1B2C3", "#a1b2c3"),
+            ("#abc", "#aabbcc"),
+            (" #00ff00 ", "#00ff00"),
+        ] {
+            assert_eq!(
+                check_avatar(
+                    "amy",
+                    AvatarKind::Color,
+                    Some(input.to_string()),
+                    &parse_palette("")
+                )
+                .expect(input),
+                Some(expected.to_string())
+            );
+        }
+        for input in ["not-a-color", "#12345", "#ggg", "123456", "#1234567", ""] {
+            assert!(
+                check_avatar(
+                    "amy",
+                    AvatarKind::Color,
+                    Some(input.to_string()),
+                    &parse_palette("")
+                )
+                .is_err()
+            );
+        }
+        let fallback = check_avatar("amy", AvatarKind::Color, None, &parse_palette(""))
+            .expect("fallback")
+            .expect("color");
+        assert!(normalize_color(&fallback).is_some_and(|c| c == fallback));
+        assert_eq!(
+            check_avatar("AMY", AvatarKind::Color, None, &parse_palette("")).expect("same user"),
+            Some(fallback)
+        );
+        assert_eq!(
+            check_avatar(
+                "amy",
+                AvatarKind::Identicon,
+                Some("junk".to_string()),
+                &parse_palette("")
+            )
+            .expect("identicon"),
+            None
+        );
+    }
+
+    #[test]
+    fn configured_palette_constrains_colors() {
+        let palette = parse_palette(" #ABC, not-a-color, #123456 ,, ");
+        assert_eq!(palette, ["#aabbcc", "#123456"]);
+        let names = [
+            "amy", "bob", "carol", "dave", "erin", "frank", "grace", "heidi",
+        ];
+        let picked: std::collections::HashSet<String> = names
+            .iter()
+            .map(|name| palette_color(name, &palette))
+            .collect();
+        assert!(picked.iter().all(|c| palette.contains(c)));
+        assert_eq!(picked.len(), 2);
+        assert_eq!(
+            palette_color("Amy", &palette),
+            palette_color("amy", &palette)
+        );
+        assert_eq!(parse_palette("").len(), DEFAULT_PALETTE.len());
+        assert_eq!(parse_palette("#nope").len(), DEFAULT_PALETTE.len());
+    }
+
+    #[test]
+    fn username_policy() {
+        for name in [
+            "alice",
+            "Zoë",
+            "José García",
+            "Ελένη",
+            "Дмитрий",
+            "山田",
+            "cat 🐱",
+            "a.b-c_d!",
+        ] {
+            assert!(check_username(name, false).is_ok(), "{name}");
+        }
+        for name in [
+            "",
+            "   ",
+            "evil\u{202E}gnp",
+            "zero\u{200B}width",
+            "tab\tname",
+            "p\u{0430}ypal",
+            "\u{03BF}live",
+            "e\u{0301}mile",
+        ] {
+            assert!(check_username(name, false).is_err(), "{name:?}");
+        }
+        assert!(check_username("plain name!", true).is_ok());
+        assert!(check_username("Zoë", true).is_err());
+        assert!(check_username("cat 🐱", true).is_err());
+    }
+
+    #[test]
+    fn password_policy_rules() {
+        let loose = PasswordPolicy {
+            min_len: 1,
+            min_classes: 1,
+        };
+        assert!(check_password("a", loose).is_ok());
+        assert!(check_password("", loose).is_err());
+        let strict = PasswordPolicy {
+            min_len: 10,
+            min_classes: 3,
+        };
+        for weak in [
+            "a",
+            "Short1!",
+            "passwordpassword",
+            "aaaaaaaaaa11",
+            "ZZZZZZZZ!!!!",
+        ] {
+            assert!(check_password(weak, strict).is_err(), "{weak}");
+        }
+        for strong in ["Password12", "Correct horse 9", "Ünïcödé-Pässwörd"] {
+            assert!(check_password(strong, strict).is_ok(), "{strong}");
+        }
+    }
+
+    #[tokio::test]
+    async fn validation_errors_name_the_field() {
+        let code = |r: Result<(), FieldError>| r.map_err(|e| (e.field, e.code)).err();
+        assert_eq!(
+            code(check_username(" ", false)),
+            Some(("username", "required"))
+        );
+        assert_eq!(
+            code(check_username("Zoë", true)),
+            Some(("username", "not_ascii"))
+        );
+        assert_eq!(
+            code(check_username("a\u{200b}b", false)),
+            Some(("username", "invalid_characters"))
+        );
+        assert_eq!(
+            code(check_username("p\u{0430}ypal", false)),
+            Some(("username", "mixed_scripts"))
+        );
+        let strict = PasswordPolicy {
+            min_len: 10,
+            min_classes: 3,
+        };
+        assert_eq!(
+            code(check_password("", strict)),
+            Some(("password", "required"))
+        );
+        assert_eq!(
+            code(check_password("Short1!", strict)),
+            Some(("password", "too_short"))
+        );
+        assert_eq!(
+            code(check_password("passwordpassword", strict)),
+            Some(("password", "too_simple"))
+        );
+        assert_eq!(
+            code(
+                check_avatar(
+                    "amy",
+                    AvatarKind::Color,
+                    Some("nope".to_string()),
+                    &parse_palette("")
+                )
+                .map(|_| ())
+            ),
+            Some(("avatar_color", "invalid"))
+        );
+        assert_eq!(
+            code(secret_from("amy", Some("a".to_string()), Some(vec![]), true).map(|_| ())),
+            Some(("password", "exclusive"))
+        );
+        assert_eq!(
+            code(secret_from("amy", None, Some(vec!["zzz".to_string()]), true).map(|_| ())),
+            Some(("words", "not_in_set"))
+        );
+
+        let (state, path) = temp_state("field_errors").await;
+        let _ = register_text(&state, "amy").await.expect("register");
+        let Err(taken) = register_text(&state, "AMY").await else {
+            panic!("duplicate username accepted");
+        };
+        let resp = axum::response::IntoResponse::into_response(taken);
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .expect("body");
+        let body: serde_json::Value = serde_json::from_slice(&body).expect("json");
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": "Username is taken", "field": "username", "code": "taken" })
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn image_avatars_need_an_uploaded_image() {
+        let (state, path) = temp_state("image_avatar").await;
+        let _ = register_text(&state, "amy").await.expect("register amy");
+        let png = b"\x89PNG\r\n\x1a\n....".to_vec();
+        for (id, mime, size, data, uploader) in [
+            ("pic", "image/png", 12, png.clone(), "amy"),
+            ("notes", "text/plain", 5, b"hello".to_vec(), "amy"),
+            ("fake", "image/png", 5, b"hello".to_vec(), "amy"),
+            (
+                "huge",
+                "image/png",
+                AVATAR_MAX_BYTES + 1,
+                png.clone(),
+                "amy",
+            ),
+            ("bobs", "image/png", 12, png.clone(), "bob"),
+        ] {
+            sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploader, uploaded_at) VALUES($1, 'f', $2, $3, $4, $5, 0)")
+                .bind(id)
+                .bind(mime)
+                .bind(size)
+                .bind(data)
+                .bind(uploader)
+                .execute(&state.db)
+                .await
+                .expect("insert media");
+        }
+        let set = async |media_id: Option<&str>| {
+            let amy = get_user(&state.db, "amy").await.unwrap().expect("amy");
+            patch_me(
+                State(state.clone()),
+                Authed(amy),
+                Json(MePatch {
+                    avatar_kind: AvatarKind::Image,
+                    avatar_color: None,
+                    avatar_media_id: media_id.map(str::to_string),
+                }),
+            )
+            .await
+        };
+        for (media_id, code) in [
+            (None, "required"),
+            (Some("notes"), "not_image"),
+            (Some("fake"), "not_image"),
+            (Some("huge"), "too_large"),
+            (Some("bobs"), "not_found"),
+            (Some("gone"), "not_found"),
+        ] {
+            match set(media_id).await {
+                Err(FormError::Field(e)) => {
+                    assert_eq!((e.field, e.code), ("avatar_media_id", code))
+                }
+                _ => panic!("{media_id:?} accepted as an avatar"),
+            }
+        }
+        let Json(updated) = set(Some("pic")).await.expect("image avatar");
+        assert_eq!(updated.avatar_kind, AvatarKind::Image);
+        assert_eq!(updated.avatar_url.as_deref(), Some("/api/media/pic"));
+        let amy = get_user(&state.db, "amy").await.unwrap().expect("amy");
+        let me = me_payload(&state, &amy).await.expect("me");
+        assert_eq!(me.avatar_kind, AvatarKind::Image);
+        assert_eq!(me.avatar_url.as_deref(), Some("/api/media/pic"));
+
+        crate::api::media::sweep_expired(&state).await;
+        let kept: Vec<String> = sqlx::query("SELECT id FROM media ORDER BY id")
+            .fetch_all(&state.db)
+            .await
+            .expect("media")
+            .iter()
+            .map(|r| r.try_get(0).unwrap())
+            .collect();
+        assert_eq!(kept, vec!["pic"]);
+
+        let Json(reverted) = patch_me(
+            State(state.clone()),
+            Authed(amy),
+            Json(MePatch {
+                avatar_kind: AvatarKind::Identicon,
+                avatar_color: None,
+                avatar_media_id: None,
+            }),
+        )
+        .await
+        .expect("identicon");
+        assert_eq!(reverted.avatar_kind, AvatarKind::Identicon);
+        assert_eq!(reverted.avatar_url, None);
+        done(state, path).await;
+    }
+
+    async fn register_text(state: &AppState, name: &str) -> Result<Json<AuthResp>, FormError> {
         register(
             State(state.clone()),
             Json(RegisterReq {
@@ -411,11 +996,106 @@ mod tests {
                 words: None,
                 avatar_kind: AvatarKind::Identicon,
                 avatar_color: None,
+                invite: None,
             }),
         )
         .await
     }
 
+    #[tokio::test]
+    async fn verify_reports_only_live_tokens() {
+        let (state, path) = temp_state("verify_token").await;
+        let Json(auth) = register_text(&state, "amy").await.expect("register");
+        let check = |token: &str| {
+            verify(
+                State(state.clone()),
+                Json(VerifyReq {
+                    token: token.to_string(),
+                }),
+            )
+        };
+        let Json(live) = check(&auth.token).await.expect("valid token");
+        assert!(live.valid);
+        assert_eq!(live.username, "amy");
+        assert_eq!(live.expires_at, None);
+
+        sqlx::query("INSERT INTO tokens(token, username, created_at, expires_at) VALUES('old', 'amy', 0, 1)")
+            .execute(&state.db)
+            .await
+            .expect("insert expired token");
+        let padded = format!(" {} ", auth.token);
+        for token in ["old", "", "not a token", "\u{0}", &padded] {
+            assert!(
+                matches!(
+                    check(token).await,
+                    Err(ApiError(StatusCode::UNAUTHORIZED, ref message)) if message == "Invalid token"
+                ),
+                "{token:?}"
+            );
+        }
+        done(state, path).await;
+    }
+
+    async fn join(
+        state: &AppState,
+        name: &str,
+        invite: Option<&str>,
+        open: bool,
+    ) -> Result<Json<AuthResp>, FormError> {
+        let req = RegisterReq {
+            username: name.to_string(),
+            password: Some("a".to_string()),
+            words: None,
+            avatar_kind: AvatarKind::Identicon,
+            avatar_color: None,
+            invite: invite.map(str::to_string),
+        };
+        create_account(state, req, open).await
+    }
+
+    #[tokio::test]
+    async fn closed_registration_needs_invite() {
+        let (state, path) = temp_state("invite_only").await;
+        let Json(first) = join(&state, "root", None, false)
+            .await
+            .expect("first account bootstraps");
+        assert!(first.user.is_site_admin);
+        let blocked = join(&state, "amy", None, false).await;
+        assert!(matches!(
+            blocked,
+            Err(FormError::Api(ApiError(StatusCode::FORBIDDEN, _)))
+        ));
+        let wrong = join(&state, "amy", Some("nope"), false).await;
+        assert!(matches!(
+            wrong,
+            Err(FormError::Api(ApiError(StatusCode::FORBIDDEN, _)))
+        ));
+        sqlx::query(
+            "INSERT INTO site_invites(code, created_by, created_at) VALUES('welcome', 'root', 0)",
+        )
+        .execute(&state.db)
+        .await
+        .expect("insert invite");
+        let _ = join(&state, "amy", Some("welcome"), false)
+            .await
+            .expect("invited signup");
+        let used_by: Option<String> =
+            sqlx::query("SELECT used_by FROM site_invites WHERE code = 'welcome'")
+                .fetch_one(&state.db)
+                .await
+                .expect("invite row")
+                .try_get(0)
+                .expect("used_by");
+        assert_eq!(used_by.as_deref(), Some("amy"));
+        let reused = join(&state, "bob", Some("welcome"), false).await;
+        assert!(matches!(
+            reused,
+            Err(FormError::Api(ApiError(StatusCode::FORBIDDEN, _)))
+        ));
+        let _ = join(&state, "bob", None, true).await.expect("open signup");
+        done(state, path).await;
+    }
+
     #[tokio::test]
     async fn word_register_login_roundtrip() {
         let (state, path) = temp_state("word_auth").await;
@@ -429,6 +1109,7 @@ mod tests {
                 words: Some(picked.clone()),
                 avatar_kind: AvatarKind::Identicon,
                 avatar_color: None,
+                invite: None,
             }),
         )
         .await
@@ -444,12 +1125,13 @@ mod tests {
                 words: Some(vec!["definitelynotintheset".to_string(); 7]),
                 avatar_kind: AvatarKind::Identicon,
                 avatar_color: None,
+                invite: None,
             }),
         )
         .await;
         assert!(matches!(
             bad_words,
-            Err(ApiError(StatusCode::BAD_REQUEST, _))
+            Err(FormError::Field(FieldError { field: "words", .. }))
         ));
         let shouted: Vec<String> = picked.iter().map(|w| w.to_uppercase()).collect();
         let ok = login(
@@ -502,4 +1184,50 @@ mod tests {
         assert!(matches!(locked, Err(ApiError(StatusCode::LOCKED, _))));
         done(state, path).await;
     }
+
+    #[tokio::test]
+    async fn logout_revokes_token() {
+        let (state, path) = temp_state("logout").await;
+        let Json(resp) = register_text(&state, "lou").await.expect("register");
+        assert!(user_for_token(&state, &resp.token).await.is_some());
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            axum::http::header::AUTHORIZATION,
+            format!("Bearer {}", resp.token).parse().expect("header"),
+        );
+        let _ = logout(State(state.clone()), headers).await.expect("logout");
+        assert!(user_for_token(&state, &resp.token).await.is_none());
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn login_throttle_follows_the_clock() {
+        let (state, path) = temp_state("login_clock").await;
+        let db = &state.db;
+        let t = 100 * 86400 + 10;
+        throttle_login(db, "amy", t).await.expect("first attempt");
+        assert!(matches!(
+            throttle_login(db, "amy", t + 2).await,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        throttle_login(db, "amy", t + 5)
+            .await
+            .expect("after the gap");
+        sqlx::query("UPDATE login_attempts SET count = 1000 WHERE username = 'amy'")
+            .execute(db)
+            .await
+            .expect("exhaust attempts");
+        assert!(matches!(
+            throttle_login(db, "amy", t + 60).await,
+            Err(ApiError(StatusCode::LOCKED, _))
+        ));
+        assert!(matches!(
+            throttle_login(db, "amy", t + 86400 - 11).await,
+            Err(ApiError(StatusCode::LOCKED, _))
+        ));
+        throttle_login(db, "amy", t + 86400)
+            .await
+            .expect("unlocked the next day");
+        done(state, path).await;
+    }
 }
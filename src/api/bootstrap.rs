@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use serde::Serialize;
+use sqlx::Row;
+use utoipa::ToSchema;
+
+use crate::api::auth::me_payload;
+use crate::api::messages::{Unread, load_messages, user_unreads};
+use crate::api::servers::visible_channels;
+use crate::api::{ApiError, Authed, Channel, DmSummary, Message, ServerSummary, UserRef};
+use crate::state::AppState;
+
+#[derive(Serialize, ToSchema)]
+pub struct BootstrapServer {
+    #[serde(flatten)]
+    pub server: ServerSummary,
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BootstrapDm {
+    #[serde(flatten)]
+    pub dm: DmSummary,
+    pub last_message: Option<Message>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Bootstrap {
+    pub user: UserRef,
+    pub is_site_admin: bool,
+    pub servers: Vec<BootstrapServer>,
+    pub dms: Vec<BootstrapDm>,
+    pub unreads: Vec<Unread>,
+}
+
+#[utoipa::path(get, path = "/api/bootstrap", responses((status = 200, body = Bootstrap)), security(("bearer" = [])))]
+pub(crate) async fn bootstrap(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Json<Bootstrap>, ApiError> {
+    let db = &state.db;
+    let me = me_payload(&state, &user).await?;
+    let mut channels = visible_channels(db, &user).await?;
+    let rows = sqlx::query(
+        "SELECT m.dm_id, MAX(m.id) FROM messages m JOIN dms d ON d.id = m.dm_id WHERE (d.user_a = $1 OR d.user_b = $1) AND m.thread_root_id IS NULL AND m.deleted_at IS NULL GROUP BY m.dm_id",
+    )
+    .bind(&user.username)
+    .fetch_all(db)
+    .await?;
+    let mut ids = Vec::with_capacity(rows.len());
+    for r in &rows {
+        ids.push(r.try_get::<i64, _>(1)?);
+    }
+    let mut latest: HashMap<i64, Message> = HashMap::with_capacity(ids.len());
+    for message in load_messages(db, &ids).await? {
+        if let Some(dm_id) = message.dm_id {
+            latest.insert(dm_id, message);
+        }
+    }
+    let dms = me
+        .dms
+        .into_iter()
+        .map(|dm| BootstrapDm {
+            last_message: latest.remove(&dm.id),
+            dm,
+        })
+        .collect();
+    let servers = me
+        .servers
+        .into_iter()
+        .map(|server| BootstrapServer {
+            channels: channels.remove(&server.name).unwrap_or_default(),
+            server,
+        })
+        .collect();
+    Ok(Json(Bootstrap {
+        user: UserRef::from_user(&user),
+        is_site_admin: user.is_site_admin,
+        servers,
+        dms,
+        unreads: user_unreads(db, &user.username).await?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+
+    #[tokio::test]
+    async fn bootstrap_has_every_section() {
+        let (state, path) = temp_state("bootstrap").await;
+        let db = &state.db;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('den', 'Den', 'amy', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        add_member(db, "den", "amy", 1, 0, 1).await;
+        sqlx::query("INSERT INTO channels(server, name, created_at) VALUES('den', 'lobby', 0)")
+            .execute(db)
+            .await
+            .expect("insert channel");
+        let dm: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("open dm")
+                .try_get(0)
+                .expect("dm id");
+        for content in ["first", "latest"] {
+            sqlx::query(
+                "INSERT INTO messages(dm_id, author, content, created_at) VALUES($1, 'bob', $2, 0)",
+            )
+            .bind(dm)
+            .bind(content)
+            .execute(db)
+            .await
+            .expect("dm message");
+        }
+        let mut others = Vec::new();
+        for peer in ["cat", "dan"] {
+            let id: i64 =
+                sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', $1) RETURNING id")
+                    .bind(peer)
+                    .fetch_one(db)
+                    .await
+                    .expect("open dm")
+                    .try_get(0)
+                    .expect("dm id");
+            others.push(id);
+        }
+        sqlx::query(
+            "INSERT INTO messages(dm_id, author, content, created_at) VALUES($1, 'cat', 'meow', 0)",
+        )
+        .bind(others[0])
+        .execute(db)
+        .await
+        .expect("dm message");
+
+        let Json(boot) = bootstrap(State(state.clone()), Authed(mem_user("amy", false)))
+            .await
+            .expect("bootstrap");
+        assert_eq!(boot.user.username, "amy");
+        assert!(!boot.is_site_admin);
+        let servers: Vec<(&str, Vec<&str>)> = boot
+            .servers
+            .iter()
+            .map(|s| {
+                (
+                    s.server.name.as_str(),
+                    s.channels.iter().map(|c| c.name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            servers,
+            vec![("rchat", vec!["general"]), ("den", vec!["lobby"])]
+        );
+        let with_bob = boot
+            .dms
+            .iter()
+            .find(|d| d.dm.id == dm)
+            .expect("dm with bob");
+        assert_eq!(with_bob.dm.other.username, "bob");
+        assert_eq!(
+            with_bob.last_message.as_ref().map(|m| m.content.as_str()),
+            Some("latest")
+        );
+        let last = |id: i64| {
+            boot.dms
+                .iter()
+                .find(|d| d.dm.id == id)
+                .expect("dm")
+                .last_message
+                .as_ref()
+                .map(|m| m.content.as_str())
+        };
+        assert_eq!(last(others[0]), Some("meow"));
+        assert_eq!(last(others[1]), None);
+        let scope = format!("d{dm}");
+        let unread = boot
+            .unreads
+            .iter()
+            .find(|u| u.scope == scope)
+            .expect("dm unread");
+        assert_eq!(unread.last_read, 0);
+        done(state, path).await;
+    }
+}
@@ -1,47 +1,103 @@
 use axum::Json;
-use axum::extract::State;
-use axum::http::StatusCode;
-use serde::Deserialize;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
 use sqlx::Row;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::api::{ApiError, Authed, DmSummary, user_ref};
-use crate::db::get_user;
+use crate::api::messages::{
+    SendReq, deliver_dm, draft_message, idempotency_key, send_budget, send_to_dm,
+};
+use crate::api::{ApiError, Authed, DmSummary, Message, user_ref};
+use crate::db::{Db, SYSTEM_USERNAME, User, get_user};
 use crate::state::AppState;
-use crate::ws::WsEvent;
+use crate::ws::{Hub, WsEvent};
 
 #[derive(Deserialize, ToSchema)]
 pub struct OpenDmReq {
     username: String,
 }
 
-#[utoipa::path(get, path = "/api/dms", responses((status = 200, body = Vec<DmSummary>)), security(("bearer" = [])))]
-pub(crate) async fn list_dms(
-    State(state): State<AppState>,
-    Authed(user): Authed,
-) -> Result<Json<Vec<DmSummary>>, ApiError> {
-    let rows = sqlx::query(
-        "SELECT id, user_a, user_b FROM dms WHERE user_a = $1 OR user_b = $2 ORDER BY id",
-    )
-    .bind(&user.username)
-    .bind(&user.username)
-    .fetch_all(&state.db)
-    .await?;
+#[derive(Deserialize, ToSchema)]
+pub struct SendDmReq {
+    username: String,
+    #[serde(flatten)]
+    message: SendReq,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DmSent {
+    pub dm: DmSummary,
+    pub message: Message,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PinDmReq {
+    pinned: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MuteDmReq {
+    muted: bool,
+}
+
+#[derive(Default, Deserialize, IntoParams)]
+pub struct DmQuery {
+    offset: Option<i64>,
+    limit: Option<i64>,
+    unread: Option<bool>,
+}
+
+pub(crate) async fn user_dms(
+    db: &Db,
+    hub: &Hub,
+    username: &str,
+    q: &DmQuery,
+) -> Result<Vec<DmSummary>, ApiError> {
+    let unread = match q.unread.unwrap_or(false) {
+        true => {
+            " AND d.user_a <> d.user_b AND EXISTS (SELECT 1 FROM messages m WHERE m.dm_id = d.id AND m.deleted_at IS NULL AND m.id > COALESCE((SELECT rs.last_read FROM read_state rs WHERE rs.username = $1 AND rs.scope = 'd' || d.id), 0))"
+        }
+        false => "",
+    };
+    let sql = format!(
+        "SELECT d.id, d.user_a, d.user_b, CASE WHEN p.dm_id IS NULL THEN 0 ELSE 1 END AS pinned, CASE WHEN mu.dm_id IS NULL THEN 0 ELSE 1 END FROM dms d LEFT JOIN dm_pins p ON p.dm_id = d.id AND p.username = $1 LEFT JOIN dm_mutes mu ON mu.dm_id = d.id AND mu.username = $1 WHERE (d.user_a = $1 OR d.user_b = $1){unread} ORDER BY CASE WHEN d.user_a = d.user_b THEN 0 ELSE 1 END, pinned DESC, d.id LIMIT $2 OFFSET $3"
+    );
+    let rows = sqlx::query(&sql)
+        .bind(username)
+        .bind(q.limit.map_or(i64::MAX, |l| l.clamp(1, 100)))
+        .bind(q.offset.unwrap_or(0).max(0))
+        .fetch_all(db)
+        .await?;
     let mut dms = Vec::with_capacity(rows.len());
     for r in &rows {
         let (id, a, b): (i64, String, String) = (r.try_get(0)?, r.try_get(1)?, r.try_get(2)?);
         let is_self = a == b;
-        let other = match a == user.username {
+        let other = match a == username {
             true => b,
             false => a,
         };
         dms.push(DmSummary {
             id,
-            other: user_ref(&state.db, &other).await,
+            status: hub.user_status(&other),
+            other: user_ref(db, &other).await,
             is_self,
+            pinned: r.try_get::<i64, _>(3)? != 0,
+            muted: r.try_get::<i64, _>(4)? != 0,
         });
     }
-    Ok(Json(dms))
+    Ok(dms)
+}
+
+#[utoipa::path(get, path = "/api/dms", params(DmQuery), responses((status = 200, body = Vec<DmSummary>)), security(("bearer" = [])))]
+pub(crate) async fn list_dms(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Query(q): Query<DmQuery>,
+) -> Result<Json<Vec<DmSummary>>, ApiError> {
+    Ok(Json(
+        user_dms(&state.db, &state.hub, &user.username, &q).await?,
+    ))
 }
 
 #[utoipa::path(post, path = "/api/dms", request_body = OpenDmReq, responses((status = 200, body = DmSummary)), security(("bearer" = [])))]
@@ -50,9 +106,58 @@ pub(crate) async fn open_dm(
     Authed(user): Authed,
     Json(req): Json<OpenDmReq>,
 ) -> Result<Json<DmSummary>, ApiError> {
-    let OpenDmReq { username } = req;
+    Ok(Json(get_or_create_dm(&state, &user, &req.username).await?))
+}
+
+#[utoipa::path(post, path = "/api/dms/send", request_body = SendDmReq, responses((status = 200, body = DmSent)), security(("bearer" = [])))]
+pub(crate) async fn send_dm(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    headers: HeaderMap,
+    Json(req): Json<SendDmReq>,
+) -> Result<Json<DmSent>, ApiError> {
+    let key = idempotency_key(&headers);
+    let pair = find_dm(&state.db, &user, &req.username).await?;
+    let (id, message) = match pair.id {
+        Some(id) => (id, send_to_dm(&state, &user, id, key, req.message).await?),
+        None => {
+            let draft = draft_message(&state.db, &user, req.message).await?;
+            dm_open_budget(&state, &user, &pair)?;
+            send_budget(&state, &user)?;
+            let id = create_dm(&state, &pair).await?;
+            let message = deliver_dm(&state, &user, id, pair.users(), draft, key).await?;
+            (id, message)
+        }
+    };
+    let dm = dm_summary(&state, &user, &pair, id).await?;
+    Ok(Json(DmSent { dm, message }))
+}
+
+struct DmPair {
+    target: String,
+    a: String,
+    b: String,
+    id: Option<i64>,
+}
+
+impl DmPair {
+    fn users(&self) -> Vec<String> {
+        match self.a == self.b {
+            true => vec![self.a.clone()],
+            false => vec![self.a.clone(), self.b.clone()],
+        }
+    }
+}
+
+async fn find_dm(db: &Db, user: &User, username: &str) -> Result<DmPair, ApiError> {
     let target = username.to_lowercase();
-    match get_user(&state.db, &target).await? {
+    if target == SYSTEM_USERNAME {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Cannot message the system user".to_string(),
+        ));
+    }
+    match get_user(db, &target).await? {
         Some(_) => {}
         None => {
             return Err(ApiError(
@@ -65,38 +170,581 @@ pub(crate) async fn open_dm(
         true => (user.username.clone(), target.clone()),
         false => (target.clone(), user.username.clone()),
     };
-    let existing: Option<i64> =
-        match sqlx::query("SELECT id FROM dms WHERE user_a = $1 AND user_b = $2")
-            .bind(&a)
-            .bind(&b)
-            .fetch_optional(&state.db)
-            .await?
-        {
-            Some(r) => Some(r.try_get(0)?),
-            None => None,
-        };
-    let (id, created) = match existing {
-        Some(id) => (id, false),
-        None => {
-            let id: i64 =
-                sqlx::query("INSERT INTO dms(user_a, user_b) VALUES($1, $2) RETURNING id")
-                    .bind(&a)
-                    .bind(&b)
-                    .fetch_one(&state.db)
-                    .await?
-                    .try_get(0)?;
-            (id, true)
-        }
+    let id = match sqlx::query("SELECT id FROM dms WHERE user_a = $1 AND user_b = $2")
+        .bind(&a)
+        .bind(&b)
+        .fetch_optional(db)
+        .await?
+    {
+        Some(r) => Some(r.try_get(0)?),
+        None => None,
     };
-    let dm = DmSummary {
+    Ok(DmPair { target, a, b, id })
+}
+
+fn dm_open_budget(state: &AppState, user: &User, pair: &DmPair) -> Result<(), ApiError> {
+    match pair.a == pair.b {
+        true => Ok(()),
+        false => state.dm_opens.check(&user.username).map_err(|wait| {
+            ApiError(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Opening DMs too fast: wait {wait}s"),
+            )
+        }),
+    }
+}
+
+async fn create_dm(state: &AppState, pair: &DmPair) -> Result<i64, ApiError> {
+    let id: i64 = sqlx::query("INSERT INTO dms(user_a, user_b) VALUES($1, $2) RETURNING id")
+        .bind(&pair.a)
+        .bind(&pair.b)
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
+    state.hub.broadcast(WsEvent::DmCreated {
+        dm_users: vec![pair.a.clone(), pair.b.clone()],
+    });
+    Ok(id)
+}
+
+async fn dm_summary(
+    state: &AppState,
+    user: &User,
+    pair: &DmPair,
+    id: i64,
+) -> Result<DmSummary, ApiError> {
+    Ok(DmSummary {
         id,
-        other: user_ref(&state.db, &target).await,
-        is_self: a == b,
+        other: user_ref(&state.db, &pair.target).await,
+        is_self: pair.a == pair.b,
+        pinned: has_flag(&state.db, DmFlag::Pin, &user.username, id).await?,
+        muted: has_flag(&state.db, DmFlag::Mute, &user.username, id).await?,
+        status: state.hub.user_status(&pair.target),
+    })
+}
+
+async fn get_or_create_dm(
+    state: &AppState,
+    user: &User,
+    username: &str,
+) -> Result<DmSummary, ApiError> {
+    let pair = find_dm(&state.db, user, username).await?;
+    let id = match pair.id {
+        Some(id) => id,
+        None => {
+            dm_open_budget(state, user, &pair)?;
+            create_dm(state, &pair).await?
+        }
     };
-    if created {
-        state.hub.broadcast(WsEvent::DmCreated {
-            dm_users: vec![a, b],
-        });
+    dm_summary(state, user, &pair, id).await
+}
+
+#[derive(Clone, Copy)]
+enum DmFlag {
+    Pin,
+    Mute,
+}
+
+impl DmFlag {
+    fn table(self) -> &'static str {
+        match self {
+            DmFlag::Pin => "dm_pins",
+            DmFlag::Mute => "dm_mutes",
+        }
     }
+}
+
+async fn has_flag(db: &Db, flag: DmFlag, username: &str, id: i64) -> Result<bool, ApiError> {
+    let sql = format!(
+        "SELECT 1 FROM {} WHERE username = $1 AND dm_id = $2",
+        flag.table()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(username)
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .is_some())
+}
+
+pub(crate) async fn dm_muted(db: &Db, username: &str, id: i64) -> bool {
+    has_flag(db, DmFlag::Mute, username, id)
+        .await
+        .unwrap_or(false)
+}
+
+async fn set_flag(
+    state: &AppState,
+    user: &User,
+    id: i64,
+    flag: DmFlag,
+    on: bool,
+) -> Result<DmSummary, ApiError> {
+    let row = sqlx::query(
+        "SELECT user_a, user_b FROM dms WHERE id = $1 AND (user_a = $2 OR user_b = $2)",
+    )
+    .bind(id)
+    .bind(&user.username)
+    .fetch_optional(&state.db)
+    .await?;
+    let (a, b): (String, String) = match &row {
+        Some(r) => (r.try_get(0)?, r.try_get(1)?),
+        None => return Err(ApiError(StatusCode::NOT_FOUND, "DM not found".to_string())),
+    };
+    let sql = match on {
+        true => format!(
+            "INSERT INTO {}(username, dm_id) VALUES($1, $2) ON CONFLICT(username, dm_id) DO NOTHING",
+            flag.table()
+        ),
+        false => format!(
+            "DELETE FROM {} WHERE username = $1 AND dm_id = $2",
+            flag.table()
+        ),
+    };
+    sqlx::query(&sql)
+        .bind(&user.username)
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    let other = match a == user.username {
+        true => b,
+        false => a,
+    };
+    Ok(DmSummary {
+        id,
+        status: state.hub.user_status(&other),
+        other: user_ref(&state.db, &other).await,
+        is_self: other == user.username,
+        pinned: has_flag(&state.db, DmFlag::Pin, &user.username, id).await?,
+        muted: has_flag(&state.db, DmFlag::Mute, &user.username, id).await?,
+    })
+}
+
+#[utoipa::path(put, path = "/api/dms/{id}/pin", params(("id" = i64, Path)), request_body = PinDmReq, responses((status = 200, body = DmSummary)), security(("bearer" = [])))]
+pub(crate) async fn pin_dm(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<i64>,
+    Json(req): Json<PinDmReq>,
+) -> Result<Json<DmSummary>, ApiError> {
+    let PinDmReq { pinned } = req;
+    let dm = set_flag(&state, &user, id, DmFlag::Pin, pinned).await?;
+    state.hub.broadcast(WsEvent::DmPinned {
+        username: user.username.clone(),
+        dm_id: id,
+        pinned,
+    });
+    Ok(Json(dm))
+}
+
+#[utoipa::path(put, path = "/api/dms/{id}/mute", params(("id" = i64, Path)), request_body = MuteDmReq, responses((status = 200, body = DmSummary)), security(("bearer" = [])))]
+pub(crate) async fn mute_dm(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<i64>,
+    Json(req): Json<MuteDmReq>,
+) -> Result<Json<DmSummary>, ApiError> {
+    let MuteDmReq { muted } = req;
+    let dm = set_flag(&state, &user, id, DmFlag::Mute, muted).await?;
+    state.hub.broadcast(WsEvent::DmMuted {
+        username: user.username.clone(),
+        dm_id: id,
+        muted,
+    });
     Ok(Json(dm))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{done, mem_user, self_dm, temp_state};
+    use crate::rate_limit::SendLimiter;
+    use crate::ws::PresenceStatus;
+
+    #[tokio::test]
+    async fn dm_list_pages_and_filters_unread() {
+        let (state, path) = temp_state("dm_page").await;
+        let db = &state.db;
+        self_dm(db, "amy").await;
+        let mut ids = Vec::new();
+        for other in ["bob", "cat", "dan"] {
+            let id: i64 =
+                sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', $1) RETURNING id")
+                    .bind(other)
+                    .fetch_one(db)
+                    .await
+                    .expect("insert dm")
+                    .try_get(0)
+                    .expect("dm id");
+            ids.push(id);
+        }
+        let msg: i64 = sqlx::query(
+            "INSERT INTO messages(dm_id, author, content, created_at) VALUES($1, 'cat', 'hi', 0) RETURNING id",
+        )
+        .bind(ids[1])
+        .fetch_one(db)
+        .await
+        .expect("insert message")
+        .try_get(0)
+        .expect("message id");
+        let page = |offset, limit, unread| DmQuery {
+            offset: Some(offset),
+            limit: Some(limit),
+            unread: Some(unread),
+        };
+        let first = user_dms(db, &state.hub, "amy", &page(0, 2, false))
+            .await
+            .expect("page 1");
+        assert!(first[0].is_self);
+        assert_eq!(first[1].other.username, "bob");
+        let rest = user_dms(db, &state.hub, "amy", &page(2, 2, false))
+            .await
+            .expect("page 2");
+        let names: Vec<&str> = rest.iter().map(|d| d.other.username.as_str()).collect();
+        assert_eq!(names, vec!["cat", "dan"]);
+        let unread = user_dms(db, &state.hub, "amy", &page(0, 50, true))
+            .await
+            .expect("unread");
+        assert_eq!(
+            unread.iter().map(|d| d.id).collect::<Vec<_>>(),
+            vec![ids[1]]
+        );
+        sqlx::query("INSERT INTO read_state(username, scope, last_read) VALUES('amy', $1, $2)")
+            .bind(format!("d{}", ids[1]))
+            .bind(msg)
+            .execute(db)
+            .await
+            .expect("mark read");
+        let unread = user_dms(db, &state.hub, "amy", &page(0, 50, true))
+            .await
+            .expect("unread");
+        assert!(unread.is_empty());
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn dm_entries_carry_profile_and_status() {
+        let (state, path) = temp_state("dm_profile").await;
+        let db = &state.db;
+        for (name, display, color) in [("amy", "Amy", None), ("bob", "Bobby", Some("#ff0000"))] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, avatar_color, is_site_admin, created_at) VALUES($1, $2, 'x', 'text', 'color', $3, 0, 0)")
+                .bind(name)
+                .bind(display)
+                .bind(color)
+                .execute(db)
+                .await
+                .expect("insert user");
+        }
+        self_dm(db, "amy").await;
+        sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob')")
+            .execute(db)
+            .await
+            .expect("insert dm");
+        state.hub.connect("bob", 1);
+        state.hub.set_viewing("bob", None, Some("rchat"));
+        let dms = user_dms(db, &state.hub, "amy", &DmQuery::default())
+            .await
+            .expect("list dms");
+        assert_eq!(dms.len(), 2);
+        assert!(dms[0].is_self);
+        assert_eq!(dms[0].other.display_name, "Amy");
+        assert_eq!(dms[0].status, PresenceStatus::Offline);
+        assert_eq!(dms[1].other.display_name, "Bobby");
+        assert_eq!(dms[1].other.avatar_color.as_deref(), Some("#ff0000"));
+        assert_eq!(dms[1].status, PresenceStatus::Online);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn pins_reorder_only_the_pinner() {
+        let (state, path) = temp_state("dm_pins").await;
+        let db = &state.db;
+        for name in ["amy", "bob", "cat"] {
+            self_dm(db, name).await;
+        }
+        let mut ids = Vec::new();
+        for (a, b) in [("amy", "bob"), ("amy", "cat"), ("bob", "cat")] {
+            let id: i64 =
+                sqlx::query("INSERT INTO dms(user_a, user_b) VALUES($1, $2) RETURNING id")
+                    .bind(a)
+                    .bind(b)
+                    .fetch_one(db)
+                    .await
+                    .expect("insert dm")
+                    .try_get(0)
+                    .expect("dm id");
+            ids.push(id);
+        }
+        let hub = &state.hub;
+        let others = |name: &'static str| async move {
+            user_dms(db, hub, name, &DmQuery::default())
+                .await
+                .expect("list dms")
+                .into_iter()
+                .map(|d| (d.other.username, d.pinned))
+                .collect::<Vec<_>>()
+        };
+        let pin = |name: &str, id: i64, pinned: bool| {
+            pin_dm(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path(id),
+                Json(PinDmReq { pinned }),
+            )
+        };
+        let Json(dm) = pin("amy", ids[1], true).await.expect("pin");
+        assert!(dm.pinned);
+        assert_eq!(dm.other.username, "cat");
+        let entry = |u: &str, p| (u.to_string(), p);
+        assert_eq!(
+            others("amy").await,
+            vec![entry("amy", false), entry("cat", true), entry("bob", false)]
+        );
+        assert_eq!(
+            others("cat").await,
+            vec![
+                entry("cat", false),
+                entry("amy", false),
+                entry("bob", false)
+            ]
+        );
+        let outsider = pin("bob", ids[1], true).await;
+        assert!(matches!(outsider, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let _ = pin("amy", ids[1], false).await.expect("unpin");
+        assert_eq!(
+            others("amy").await,
+            vec![
+                entry("amy", false),
+                entry("bob", false),
+                entry("cat", false)
+            ]
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn opening_many_new_dms_is_limited() {
+        let (mut state, path) = temp_state("dm_open_limit").await;
+        state.dm_opens = SendLimiter::new(3, std::time::Duration::from_secs(600));
+        for name in ["amy", "bob", "cat", "dan", "eve"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(&state.db)
+                .await
+                .expect("insert user");
+        }
+        let open = |target: &str| {
+            open_dm(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Json(OpenDmReq {
+                    username: target.to_string(),
+                }),
+            )
+        };
+        for target in ["bob", "cat", "dan"] {
+            let _ = open(target).await.expect("under the limit");
+        }
+        let limited = open("eve").await;
+        assert!(matches!(
+            limited,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        let Json(again) = open("Bob").await.expect("existing dm");
+        assert_eq!(again.other.username, "bob");
+        let Json(own) = open("amy").await.expect("self dm");
+        assert!(own.is_self);
+        let _ = open_dm(
+            State(state.clone()),
+            Authed(mem_user("eve", false)),
+            Json(OpenDmReq {
+                username: "amy".to_string(),
+            }),
+        )
+        .await
+        .expect("limit is per user");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn send_opens_the_dm_once() {
+        let (state, path) = temp_state("dm_send").await;
+        for name in ["amy", "bob"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(&state.db)
+                .await
+                .expect("insert user");
+        }
+        let mut rx = state.hub.subscribe();
+        let send = |content: &str| {
+            let req: SendDmReq = serde_json::from_value(
+                serde_json::json!({ "username": "Bob", "content": content }),
+            )
+            .expect("request");
+            send_dm(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                HeaderMap::new(),
+                Json(req),
+            )
+        };
+        let empty = send(" ").await;
+        assert!(matches!(empty, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        assert!(rx.try_recv().is_err());
+        let Json(first) = send("hi bob").await.expect("new dm");
+        assert_eq!(first.dm.other.username, "bob");
+        assert_eq!(first.message.dm_id, Some(first.dm.id));
+        assert_eq!(first.message.content, "hi bob");
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::DmCreated { .. })));
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Message { .. })));
+
+        let Json(second) = send("again").await.expect("existing dm");
+        assert_eq!(second.dm.id, first.dm.id);
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Message { .. })));
+        let count: i64 = sqlx::query("SELECT COUNT(*) FROM dms")
+            .fetch_one(&state.db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count value");
+        assert_eq!(count, 1);
+
+        let req: SendDmReq =
+            serde_json::from_value(serde_json::json!({ "username": "nobody", "content": "x" }))
+                .expect("request");
+        let missing = send_dm(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            HeaderMap::new(),
+            Json(req),
+        )
+        .await;
+        assert!(matches!(missing, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn system_user_cannot_be_messaged() {
+        let (state, path) = temp_state("dm_system").await;
+        for name in ["amy", "system"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(&state.db)
+                .await
+                .expect("insert user");
+        }
+        let opened = open_dm(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Json(OpenDmReq {
+                username: "System".to_string(),
+            }),
+        )
+        .await;
+        assert!(matches!(opened, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let req: SendDmReq =
+            serde_json::from_value(serde_json::json!({ "username": "system", "content": "hi" }))
+                .expect("request");
+        let sent = send_dm(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            HeaderMap::new(),
+            Json(req),
+        )
+        .await;
+        assert!(matches!(sent, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let dms: i64 = sqlx::query("SELECT COUNT(*) FROM dms")
+            .fetch_one(&state.db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count");
+        assert_eq!(dms, 0);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn muting_quiets_only_the_muter() {
+        let (state, path) = temp_state("dm_mute").await;
+        for name in ["amy", "bob"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(&state.db)
+                .await
+                .expect("insert user");
+        }
+        let Json(dm) = open_dm(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Json(OpenDmReq {
+                username: "bob".to_string(),
+            }),
+        )
+        .await
+        .expect("open dm");
+        let mute = |name: &str, muted: bool| {
+            mute_dm(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path(dm.id),
+                Json(MuteDmReq { muted }),
+            )
+        };
+        let Json(muted) = mute("amy", true).await.expect("mute");
+        assert!(muted.muted);
+        assert!(!muted.pinned);
+        let flags = |name: &'static str| {
+            let state = state.clone();
+            async move {
+                user_dms(&state.db, &state.hub, name, &DmQuery::default())
+                    .await
+                    .expect("list dms")
+                    .into_iter()
+                    .find(|d| !d.is_self)
+                    .map(|d| d.muted)
+            }
+        };
+        assert_eq!(flags("amy").await, Some(true));
+        assert_eq!(flags("bob").await, Some(false));
+
+        let mut rx = state.hub.subscribe();
+        let req: SendDmReq =
+            serde_json::from_value(serde_json::json!({ "username": "amy", "content": "ping" }))
+                .expect("request");
+        let Json(sent) = send_dm(
+            State(state.clone()),
+            Authed(mem_user("bob", false)),
+            HeaderMap::new(),
+            Json(req),
+        )
+        .await
+        .expect("send");
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::Message {
+                dm_id: Some(id),
+                muted: false,
+                mentioned: false,
+                ..
+            }) if id == dm.id
+        ));
+        assert!(dm_muted(&state.db, "amy", dm.id).await);
+        assert!(!dm_muted(&state.db, "bob", dm.id).await);
+        let unreads = crate::api::messages::user_unreads(&state.db, "amy")
+            .await
+            .expect("unreads");
+        let scope = format!("d{}", dm.id);
+        let unread = unreads
+            .iter()
+            .find(|u| u.scope == scope)
+            .expect("muted dm still counts");
+        assert_eq!(unread.latest, sent.message.id);
+
+        let outsider = mute("cat", true).await;
+        assert!(matches!(outsider, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let _ = mute("amy", false).await.expect("unmute");
+        assert!(!dm_muted(&state.db, "amy", dm.id).await);
+        done(state, path).await;
+    }
+}
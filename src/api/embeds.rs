@@ -11,7 +11,7 @@ use url::{Host, Url};
 use utoipa::IntoParams;
 
 use crate::api::messages::{message_scope, require_can_delete};
-use crate::api::{ApiError, Authed, Embed};
+use crate::api::{ApiError, Authed, Embed, OkResp, ok};
 use crate::db::{Db, now};
 use crate::state::AppState;
 use crate::ws::WsEvent;
@@ -361,13 +361,13 @@ pub struct BannerQuery {
     banner: Option<i64>,
 }
 
-#[utoipa::path(delete, path = "/api/messages/{id}/embeds/{ord}", params(("id" = i64, Path), ("ord" = i64, Path), BannerQuery), responses((status = 200, description = "Removed")), security(("bearer" = [])))]
+#[utoipa::path(delete, path = "/api/messages/{id}/embeds/{ord}", params(("id" = i64, Path), ("ord" = i64, Path), BannerQuery), responses((status = 200, description = "Removed", body = OkResp)), security(("bearer" = [])))]
 pub(crate) async fn delete_embed(
     State(state): State<AppState>,
     Path((id, ord)): Path<(i64, i64)>,
     Query(q): Query<BannerQuery>,
     Authed(user): Authed,
-) -> Result<Json<serde_json::Value>, ApiError> {
+) -> Result<Json<OkResp>, ApiError> {
     let scope = message_scope(&state.db, id).await?;
     require_can_delete(&state.db, &user, &scope).await?;
     let banner = q.banner == Some(1);
@@ -396,5 +396,5 @@ pub(crate) async fn delete_embed(
         ord,
         banner,
     });
-    Ok(Json(serde_json::json!({ "ok": true })))
+    Ok(ok())
 }
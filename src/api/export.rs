@@ -0,0 +1,470 @@
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::Response;
+use serde::Serialize;
+use sqlx::Row;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::api::servers::{require_perm, require_server};
+use crate::api::{ApiError, Authed, ServerSummaryLite};
+use crate::db::{AvatarKind, ChannelKind, Db, Perm, User, channel_access};
+use crate::state::AppState;
+
+const EXPORT_PAGE: i64 = 500;
+
+type Chunk = Result<String, std::io::Error>;
+
+#[derive(Serialize)]
+struct ExportProfile<'a> {
+    username: &'a str,
+    display_name: &'a str,
+    avatar_kind: AvatarKind,
+    avatar_color: Option<&'a str>,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct ExportMembership {
+    server: String,
+    is_admin: bool,
+    joined_at: i64,
+}
+
+#[derive(Serialize)]
+struct ExportDm {
+    id: i64,
+    with: String,
+}
+
+#[derive(Serialize)]
+struct ExportMedia {
+    id: String,
+    filename: String,
+    mime: String,
+    size: i64,
+    uploaded_at: i64,
+}
+
+#[derive(Serialize)]
+struct ExportMessage {
+    id: i64,
+    server: Option<String>,
+    channel_id: Option<i64>,
+    dm_id: Option<i64>,
+    thread_root_id: Option<i64>,
+    content: String,
+    media_filename: Option<String>,
+    enc_key_id: Option<String>,
+    enc_alg: Option<String>,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct ExportServer<'a> {
+    name: &'a str,
+    display_name: &'a str,
+    creator: Option<&'a str>,
+}
+
+struct ExportChannel {
+    id: i64,
+    name: String,
+    kind: ChannelKind,
+}
+
+#[derive(Serialize)]
+struct ExportChannelMessage {
+    id: i64,
+    author: String,
+    thread_root_id: Option<i64>,
+    kind: String,
+    content: String,
+    media_filename: Option<String>,
+    created_at: i64,
+}
+
+async fn emit(tx: &mpsc::Sender<Chunk>, chunk: String) -> Result<(), ApiError> {
+    tx.send(Ok(chunk)).await.map_err(|_| {
+        ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Export aborted".to_string(),
+        )
+    })
+}
+
+fn json<T: Serialize>(value: &T) -> Result<String, ApiError> {
+    serde_json::to_string(value)
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn write_export(db: &Db, user: &User, tx: &mpsc::Sender<Chunk>) -> Result<(), ApiError> {
+    let profile = ExportProfile {
+        username: &user.username,
+        display_name: &user.display_name,
+        avatar_kind: user.avatar_kind,
+        avatar_color: user.avatar_color.as_deref(),
+        created_at: user.created_at,
+    };
+    emit(tx, format!("{{\"user\":{}", json(&profile)?)).await?;
+    let rows = sqlx::query(
+        "SELECT server, is_admin, joined_at FROM members WHERE username = $1 ORDER BY server",
+    )
+    .bind(&user.username)
+    .fetch_all(db)
+    .await?;
+    let mut servers = Vec::with_capacity(rows.len());
+    for r in &rows {
+        servers.push(ExportMembership {
+            server: r.try_get(0)?,
+            is_admin: r.try_get::<i64, _>(1)? != 0,
+            joined_at: r.try_get(2)?,
+        });
+    }
+    emit(tx, format!(",\"servers\":{}", json(&servers)?)).await?;
+    let rows = sqlx::query(
+        "SELECT id, user_a, user_b FROM dms WHERE user_a = $1 OR user_b = $1 ORDER BY id",
+    )
+    .bind(&user.username)
+    .fetch_all(db)
+    .await?;
+    let mut dms = Vec::with_capacity(rows.len());
+    for r in &rows {
+        let (a, b): (String, String) = (r.try_get(1)?, r.try_get(2)?);
+        dms.push(ExportDm {
+            id: r.try_get(0)?,
+            with: match a == user.username {
+                true => b,
+                false => a,
+            },
+        });
+    }
+    emit(tx, format!(",\"dms\":{}", json(&dms)?)).await?;
+    emit(tx, ",\"media\":[".to_string()).await?;
+    let mut after = String::new();
+    let mut first = true;
+    loop {
+        let rows = sqlx::query(
+            "SELECT id, filename, mime, size, uploaded_at FROM media WHERE uploader = $1 AND id > $2 ORDER BY id LIMIT $3",
+        )
+        .bind(&user.username)
+        .bind(&after)
+        .bind(EXPORT_PAGE)
+        .fetch_all(db)
+        .await?;
+        for r in &rows {
+            let item = ExportMedia {
+                id: r.try_get(0)?,
+                filename: r.try_get(1)?,
+                mime: r.try_get(2)?,
+                size: r.try_get(3)?,
+                uploaded_at: r.try_get(4)?,
+            };
+            let sep = match first {
+                true => "",
+                false => ",",
+            };
+            first = false;
+            emit(tx, format!("{sep}{}", json(&item)?)).await?;
+            after = item.id;
+        }
+        if (rows.len() as i64) < EXPORT_PAGE {
+            break;
+        }
+    }
+    emit(tx, "],\"messages\":[".to_string()).await?;
+    let mut after = 0i64;
+    let mut first = true;
+    loop {
+        let rows = sqlx::query(
+            "SELECT m.id, c.server, m.channel_id, m.dm_id, m.thread_root_id, m.content, m.media_filename, m.created_at, m.enc_key_id, m.enc_alg FROM messages m LEFT JOIN channels c ON c.id = m.channel_id WHERE m.author = $1 AND m.kind IN ('user', 'encrypted') AND m.deleted_at IS NULL AND m.id > $2 ORDER BY m.id LIMIT $3",
+        )
+        .bind(&user.username)
+        .bind(after)
+        .bind(EXPORT_PAGE)
+        .fetch_all(db)
+        .await?;
+        for r in &rows {
+            let item = ExportMessage {
+                id: r.try_get(0)?,
+                server: r.try_get(1)?,
+                channel_id: r.try_get(2)?,
+                dm_id: r.try_get(3)?,
+                thread_root_id: r.try_get(4)?,
+                content: r.try_get(5)?,
+                media_filename: r.try_get(6)?,
+                enc_key_id: r.try_get(8)?,
+                enc_alg: r.try_get(9)?,
+                created_at: r.try_get(7)?,
+            };
+            let sep = match first {
+                true => "",
+                false => ",",
+            };
+            first = false;
+            emit(tx, format!("{sep}{}", json(&item)?)).await?;
+            after = item.id;
+        }
+        if (rows.len() as i64) < EXPORT_PAGE {
+            break;
+        }
+    }
+    emit(tx, "]}".to_string()).await
+}
+
+async fn write_server_export(
+    db: &Db,
+    user: &User,
+    server: &ServerSummaryLite,
+    tx: &mpsc::Sender<Chunk>,
+) -> Result<(), ApiError> {
+    let info = ExportServer {
+        name: &server.name,
+        display_name: &server.display_name,
+        creator: server.creator.as_deref(),
+    };
+    emit(tx, format!("{{\"server\":{},\"channels\":[", json(&info)?)).await?;
+    let rows =
+        sqlx::query("SELECT id, name, kind FROM channels WHERE server = $1 ORDER BY position, id")
+            .bind(&server.name)
+            .fetch_all(db)
+            .await?;
+    let mut first_channel = true;
+    for r in &rows {
+        let channel = ExportChannel {
+            id: r.try_get(0)?,
+            name: r.try_get(1)?,
+            kind: ChannelKind::parse(&r.try_get::<String, _>(2)?)?,
+        };
+        let access = channel_access(db, &server.name, channel.id, Some(user)).await?;
+        if !(access.view && access.history) {
+            continue;
+        }
+        let sep = match first_channel {
+            true => "",
+            false => ",",
+        };
+        first_channel = false;
+        emit(
+            tx,
+            format!(
+                "{sep}{{\"id\":{},\"name\":{},\"kind\":{},\"messages\":[",
+                json(&channel.id)?,
+                json(&channel.name)?,
+                json(&channel.kind)?
+            ),
+        )
+        .await?;
+        let mut after = 0i64;
+        let mut first = true;
+        loop {
+            let rows = sqlx::query(
+                "SELECT id, author, thread_root_id, kind, content, media_filename, created_at FROM messages WHERE channel_id = $1 AND deleted_at IS NULL AND id > $2 ORDER BY id LIMIT $3",
+            )
+            .bind(channel.id)
+            .bind(after)
+            .bind(EXPORT_PAGE)
+            .fetch_all(db)
+            .await?;
+            for r in &rows {
+                let item = ExportChannelMessage {
+                    id: r.try_get(0)?,
+                    author: r.try_get(1)?,
+                    thread_root_id: r.try_get(2)?,
+                    kind: r.try_get(3)?,
+                    content: r.try_get(4)?,
+                    media_filename: r.try_get(5)?,
+                    created_at: r.try_get(6)?,
+                };
+                let sep = match first {
+                    true => "",
+                    false => ",",
+                };
+                first = false;
+                emit(tx, format!("{sep}{}", json(&item)?)).await?;
+                after = item.id;
+            }
+            if (rows.len() as i64) < EXPORT_PAGE {
+                break;
+            }
+        }
+        emit(tx, "]}".to_string()).await?;
+    }
+    emit(tx, "]}".to_string()).await
+}
+
+fn stream_export(filename: &str, rx: mpsc::Receiver<Chunk>) -> Result<Response, ApiError> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .body(Body::from_stream(ReceiverStream::new(rx)))
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[utoipa::path(get, path = "/api/me/export", responses((status = 200, description = "JSON archive of the caller's profile, memberships, DMs, uploads and messages", content_type = "application/json")), security(("bearer" = [])))]
+pub(crate) async fn export_me(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Response, ApiError> {
+    let (tx, rx) = mpsc::channel::<Chunk>(16);
+    let filename = format!("rchat-export-{}.json", user.username);
+    tokio::spawn(async move {
+        if let Err(ApiError(_, message)) = write_export(&state.db, &user, &tx).await {
+            tracing::warn!("export for {} failed: {message}", user.username);
+            let _ = tx.send(Err(std::io::Error::other(message))).await;
+        }
+    });
+    stream_export(&filename, rx)
+}
+
+#[utoipa::path(get, path = "/api/servers/{name}/export", params(("name" = String, Path)), responses((status = 200, description = "JSON archive of the server's channels and messages the caller can read", content_type = "application/json")), security(("bearer" = [])))]
+pub(crate) async fn export_server(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Response, ApiError> {
+    let server = require_server(&state.db, &name.to_lowercase()).await?;
+    require_perm(&state.db, &server.name, &user, Perm::DeleteServer).await?;
+    let (tx, rx) = mpsc::channel::<Chunk>(16);
+    let filename = format!("rchat-server-{}.json", server.name);
+    tokio::spawn(async move {
+        if let Err(ApiError(_, message)) = write_server_export(&state.db, &user, &server, &tx).await
+        {
+            tracing::warn!("export of {} failed: {message}", server.name);
+            let _ = tx.send(Err(std::io::Error::other(message))).await;
+        }
+    });
+    stream_export(&filename, rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+
+    #[tokio::test]
+    async fn export_only_has_own_content() {
+        let (state, path) = temp_state("export").await;
+        let db = &state.db;
+        let dm: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("insert dm")
+                .try_get(0)
+                .expect("dm id");
+        for (author, content) in [("amy", "mine"), ("bob", "secret from bob")] {
+            sqlx::query(
+                "INSERT INTO messages(dm_id, author, content, created_at) VALUES($1, $2, $3, 0)",
+            )
+            .bind(dm)
+            .bind(author)
+            .bind(content)
+            .execute(db)
+            .await
+            .expect("insert message");
+        }
+        sqlx::query(
+            "INSERT INTO messages(dm_id, author, content, kind, enc_key_id, enc_alg, created_at) VALUES($1, 'amy', 'c2VhbGVk', 'encrypted', 'k1', 'aes-256-gcm', 0)",
+        )
+        .bind(dm)
+        .execute(db)
+        .await
+        .expect("insert encrypted message");
+        let res = export_me(State(state.clone()), Authed(mem_user("amy", false)))
+            .await
+            .expect("export");
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .expect("body");
+        let doc: serde_json::Value = serde_json::from_slice(&bytes).expect("valid json");
+        assert_eq!(doc["user"]["username"], "amy");
+        assert_eq!(doc["dms"][0]["with"], "bob");
+        let contents: Vec<&str> = doc["messages"]
+            .as_array()
+            .expect("messages")
+            .iter()
+            .filter_map(|m| m["content"].as_str())
+            .collect();
+        assert_eq!(contents, vec!["mine", "c2VhbGVk"]);
+        assert_eq!(doc["messages"][0]["enc_key_id"], serde_json::Value::Null);
+        assert_eq!(doc["messages"][1]["enc_key_id"], "k1");
+        assert_eq!(doc["messages"][1]["enc_alg"], "aes-256-gcm");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn server_export_stays_in_its_server() {
+        let (state, path) = temp_state("export_server").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('den', 'Den', 'bob', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        add_member(db, "rchat", "amy", 1, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        add_member(db, "rchat", "kit", 1, Perm::Kick as i64, 0).await;
+        let general: i64 = sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("general")
+            .try_get(0)
+            .expect("general id");
+        let lobby: i64 = sqlx::query(
+            "INSERT INTO channels(server, name, created_at) VALUES('den', 'lobby', 0) RETURNING id",
+        )
+        .fetch_one(db)
+        .await
+        .expect("insert channel")
+        .try_get(0)
+        .expect("lobby id");
+        for (cid, content) in [(general, "hello rchat"), (lobby, "den only")] {
+            sqlx::query(
+                "INSERT INTO messages(channel_id, author, content, created_at) VALUES($1, 'amy', $2, 0)",
+            )
+            .bind(cid)
+            .bind(content)
+            .execute(db)
+            .await
+            .expect("insert message");
+        }
+
+        for name in ["bob", "kit"] {
+            let denied = export_server(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path("rchat".into()),
+            )
+            .await;
+            assert!(matches!(denied, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        }
+
+        let res = export_server(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path("rchat".into()),
+        )
+        .await
+        .expect("export");
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .expect("body");
+        let doc: serde_json::Value = serde_json::from_slice(&bytes).expect("valid json");
+        assert_eq!(doc["server"]["name"], "rchat");
+        assert_eq!(doc["channels"][0]["name"], "general");
+        assert_eq!(doc["channels"][0]["kind"], "text");
+        let contents: Vec<&str> = doc["channels"]
+            .as_array()
+            .expect("channels")
+            .iter()
+            .flat_map(|c| c["messages"].as_array().expect("messages"))
+            .filter_map(|m| m["content"].as_str())
+            .collect();
+        assert_eq!(contents, vec!["hello rchat"]);
+        done(state, path).await;
+    }
+}
@@ -1,5 +1,6 @@
 use axum::Json;
 use axum::body::Body;
+use axum::extract::multipart::Field;
 use axum::extract::{Multipart, Path, State};
 use axum::http::{StatusCode, header};
 use axum::response::Response;
@@ -9,15 +10,16 @@ use sqlx::Row;
 
 use crate::api::messages::{MsgScope, message_scope, require_can_delete};
 use crate::api::{
-    ApiError, Authed, MediaRef, grant_matches, header_grants, request_token, require_guest_ok,
-    user_for_token,
+    ApiError, Authed, FieldError, FormError, MediaRef, OkResp, grant_matches, header_grants, ok,
+    request_token, require_guest_ok, user_for_token,
 };
 use crate::db::{Db, MediaKind, User, now, setting_on};
 use crate::state::AppState;
 use crate::ws::WsEvent;
 
-const MAX_SIZE: usize = 25 * 1024 * 1024;
 pub(crate) const MEDIA_TTL_SECS: i64 = 86400;
+pub(crate) const AVATAR_MAX_BYTES: i64 = 8 * 1024 * 1024;
+const AVATAR_MIMES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
 
 type SweptMessage = (i64, Option<i64>, Option<i64>, Option<String>);
 
@@ -33,6 +35,23 @@ async fn s3_delete(s3: Option<&Bucket>, id: &str) {
     }
 }
 
+async fn read_capped(mut field: Field<'_>, max: usize) -> Result<Vec<u8>, ApiError> {
+    let too_large = || {
+        ApiError(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File exceeds {}MB limit", max / (1024 * 1024)),
+        )
+    };
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(|_| too_large())? {
+        if data.len() + chunk.len() > max {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
 async fn fresh_id(db: &Db) -> Result<String, ApiError> {
     loop {
         let mut bytes = [0u8; 16];
@@ -49,18 +68,23 @@ async fn fresh_id(db: &Db) -> Result<String, ApiError> {
     }
 }
 
+pub(crate) async fn require_uploads_on(db: &Db) -> Result<(), ApiError> {
+    match setting_on(db, "asset_uploads").await {
+        true => Ok(()),
+        false => Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "File uploads are disabled".to_string(),
+        )),
+    }
+}
+
 #[utoipa::path(post, path = "/api/media", request_body(content = Vec<u8>, content_type = "multipart/form-data"), responses((status = 200, body = MediaRef)), security(("bearer" = [])))]
 pub(crate) async fn upload_media(
     State(state): State<AppState>,
-    Authed(_user): Authed,
+    Authed(user): Authed,
     mut multipart: Multipart,
 ) -> Result<Json<MediaRef>, ApiError> {
-    if !setting_on(&state.db, "asset_uploads").await {
-        return Err(ApiError(
-            StatusCode::FORBIDDEN,
-            "File uploads are disabled".to_string(),
-        ));
-    }
+    require_uploads_on(&state.db).await?;
     let field = multipart
         .next_field()
         .await
@@ -71,16 +95,20 @@ pub(crate) async fn upload_media(
         .content_type()
         .unwrap_or("application/octet-stream")
         .to_string();
-    let data = field
-        .bytes()
-        .await
-        .map_err(|e| ApiError(StatusCode::PAYLOAD_TOO_LARGE, e.to_string()))?;
-    if data.len() > MAX_SIZE {
-        return Err(ApiError(
-            StatusCode::PAYLOAD_TOO_LARGE,
-            "File exceeds 25MB limit".to_string(),
-        ));
-    }
+    let data = read_capped(field, state.config.max_upload_mb * 1024 * 1024).await?;
+    Ok(Json(
+        store_media(&state, &user, filename, mime, data).await?,
+    ))
+}
+
+pub(crate) async fn store_media(
+    state: &AppState,
+    user: &User,
+    filename: String,
+    mime: String,
+    data: Vec<u8>,
+) -> Result<MediaRef, ApiError> {
+    let size = data.len() as i64;
     let id = fresh_id(&state.db).await?;
     let uploaded_at = now();
     let blob: Option<Vec<u8>> = match &state.s3 {
@@ -91,33 +119,110 @@ pub(crate) async fn upload_media(
                 .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
             None
         }
-        None => Some(data.to_vec()),
+        None => Some(data),
     };
     sqlx::query(
-        "INSERT INTO media(id, filename, mime, size, data, uploaded_at) VALUES($1, $2, $3, $4, $5, $6)",
+        "INSERT INTO media(id, filename, mime, size, data, uploader, uploaded_at) VALUES($1, $2, $3, $4, $5, $6, $7)",
     )
     .bind(&id)
     .bind(&filename)
     .bind(&mime)
-    .bind(data.len() as i64)
+    .bind(size)
     .bind(blob)
+    .bind(&user.username)
     .bind(uploaded_at)
     .execute(&state.db)
     .await?;
-    Ok(Json(MediaRef::server(
+    Ok(MediaRef::server(
         id,
         filename,
         false,
         uploaded_at + MEDIA_TTL_SECS,
-    )))
+        size,
+        mime,
+    ))
+}
+
+fn is_image(data: &[u8]) -> bool {
+    matches!(
+        data,
+        [0x89, b'P', b'N', b'G', ..]
+            | [0xFF, 0xD8, 0xFF, ..]
+            | [b'G', b'I', b'F', b'8', ..]
+            | [
+                b'R',
+                b'I',
+                b'F',
+                b'F',
+                _,
+                _,
+                _,
+                _,
+                b'W',
+                b'E',
+                b'B',
+                b'P',
+                ..
+            ]
+    )
+}
+
+pub(crate) async fn check_avatar_media(
+    state: &AppState,
+    username: &str,
+    id: &str,
+) -> Result<(), FormError> {
+    let row = sqlx::query("SELECT mime, size, data, uploader FROM media WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    let (mime, size, data, uploader): (String, i64, Option<Vec<u8>>, Option<String>) = match &row {
+        Some(r) => (r.try_get(0)?, r.try_get(1)?, r.try_get(2)?, r.try_get(3)?),
+        None => {
+            return Err(FieldError::new("avatar_media_id", "not_found", "Upload not found").into());
+        }
+    };
+    if uploader.as_deref() != Some(username) {
+        return Err(FieldError::new("avatar_media_id", "not_found", "Upload not found").into());
+    }
+    let head = match (data, state.s3.as_deref()) {
+        (Some(bytes), _) => bytes,
+        (None, Some(bucket)) => bucket
+            .get_object_range(s3_key(id), 0, Some(15))
+            .await
+            .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .to_vec(),
+        (None, None) => Vec::new(),
+    };
+    let image = AVATAR_MIMES.contains(&mime.as_str()) && is_image(&head);
+    if !image {
+        return Err(FieldError::new(
+            "avatar_media_id",
+            "not_image",
+            "Avatars must be PNG, JPEG, GIF or WebP images",
+        )
+        .into());
+    }
+    if size > AVATAR_MAX_BYTES {
+        return Err(FieldError::new(
+            "avatar_media_id",
+            "too_large",
+            format!(
+                "Avatars are limited to {} MB",
+                AVATAR_MAX_BYTES / 1024 / 1024
+            ),
+        )
+        .into());
+    }
+    Ok(())
 }
 
-#[utoipa::path(delete, path = "/api/messages/{id}/media", params(("id" = i64, Path)), responses((status = 200, description = "Removed")), security(("bearer" = [])))]
+#[utoipa::path(delete, path = "/api/messages/{id}/media", params(("id" = i64, Path)), responses((status = 200, description = "Removed", body = OkResp)), security(("bearer" = [])))]
 pub(crate) async fn delete_media(
     State(state): State<AppState>,
     Path(id): Path<i64>,
     Authed(user): Authed,
-) -> Result<Json<serde_json::Value>, ApiError> {
+) -> Result<Json<OkResp>, ApiError> {
     let scope = message_scope(&state.db, id).await?;
     require_can_delete(&state.db, &user, &scope).await?;
     let MsgScope {
@@ -146,16 +251,27 @@ pub(crate) async fn delete_media(
         }
         MediaKind::Server => {
             let mut tx = state.db.begin().await?;
-            sqlx::query("DELETE FROM media WHERE id = $1")
-                .bind(&media_id)
-                .execute(&mut *tx)
-                .await?;
             sqlx::query("UPDATE messages SET media_removed = 2 WHERE id = $1")
                 .bind(id)
                 .execute(&mut *tx)
                 .await?;
+            let shared = sqlx::query(
+                "SELECT 1 FROM messages WHERE media_id = $1 AND media_removed = 0 UNION ALL SELECT 1 FROM users WHERE avatar_media_id = $1 LIMIT 1",
+            )
+            .bind(&media_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+            if !shared {
+                sqlx::query("DELETE FROM media WHERE id = $1")
+                    .bind(&media_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
             tx.commit().await?;
-            s3_delete(state.s3.as_deref(), &media_id).await;
+            if !shared {
+                s3_delete(state.s3.as_deref(), &media_id).await;
+            }
         }
     }
     state.hub.broadcast(WsEvent::MediaRemoved {
@@ -167,7 +283,7 @@ pub(crate) async fn delete_media(
         filename: media_filename.unwrap_or_default(),
         removed_by_author: true,
     });
-    Ok(Json(serde_json::json!({ "ok": true })))
+    Ok(ok())
 }
 
 #[derive(serde::Deserialize, utoipa::IntoParams)]
@@ -182,6 +298,14 @@ async fn media_view_ok(
     user: Option<&User>,
     query_grant: Option<&str>,
 ) -> Result<(), ApiError> {
+    let avatar = sqlx::query("SELECT 1 FROM users WHERE avatar_media_id = $1")
+        .bind(media_id)
+        .fetch_optional(&state.db)
+        .await?
+        .is_some();
+    if avatar {
+        return Ok(());
+    }
     let rows = sqlx::query(
         "SELECT s.name, s.password_hash, d.user_a, d.user_b FROM messages m LEFT JOIN channels c ON c.id = m.channel_id LEFT JOIN dms d ON d.id = m.dm_id LEFT JOIN servers s ON s.name = c.server WHERE m.media_id = $1 AND m.media_removed = 0",
     )
@@ -310,10 +434,12 @@ pub(crate) async fn download_media(
         .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-async fn sweep(db: &Db, s3: Option<&Bucket>) -> sqlx::Result<Vec<WsEvent>> {
-    let cutoff = now() - MEDIA_TTL_SECS;
+async fn sweep(db: &Db, s3: Option<&Bucket>, at: i64) -> sqlx::Result<Vec<WsEvent>> {
+    let cutoff = at - MEDIA_TTL_SECS;
     let mut tx = db.begin().await?;
-    let rows = sqlx::query("SELECT id FROM media WHERE uploaded_at <= $1")
+    let rows = sqlx::query(
+        "SELECT id FROM media WHERE uploaded_at <= $1 AND NOT EXISTS(SELECT 1 FROM users WHERE avatar_media_id = media.id)",
+    )
         .bind(cutoff)
         .fetch_all(&mut *tx)
         .await?;
@@ -389,7 +515,7 @@ async fn sweep(db: &Db, s3: Option<&Bucket>) -> sqlx::Result<Vec<WsEvent>> {
 }
 
 pub async fn sweep_expired(state: &AppState) {
-    let events = match sweep(&state.db, state.s3.as_deref()).await {
+    let events = match sweep(&state.db, state.s3.as_deref(), now()).await {
         Ok(events) => events,
         Err(e) => {
             tracing::warn!("media sweep failed: {e}");
@@ -403,10 +529,40 @@ pub async fn sweep_expired(state: &AppState) {
 
 #[cfg(test)]
 mod tests {
-    use super::sweep;
+    use super::{MEDIA_TTL_SECS, read_capped, sweep};
+    use crate::api::ApiError;
     use crate::db::{now, open};
+    use axum::body::Body;
+    use axum::extract::{FromRequest, Multipart};
+    use axum::http::{Request, StatusCode, header};
     use sqlx::Row;
 
+    async fn multipart(payload: &[u8]) -> Multipart {
+        let mut body =
+            b"--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\r\n"
+                .to_vec();
+        body.extend_from_slice(payload);
+        body.extend_from_slice(b"\r\n--b--\r\n");
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=b")
+            .body(Body::from(body))
+            .expect("request");
+        Multipart::from_request(req, &()).await.expect("multipart")
+    }
+
+    #[tokio::test]
+    async fn upload_stops_at_limit() {
+        let mut form = multipart(&[7u8; 64]).await;
+        let field = form.next_field().await.expect("field").expect("file");
+        assert!(matches!(
+            read_capped(field, 16).await,
+            Err(ApiError(StatusCode::PAYLOAD_TOO_LARGE, _))
+        ));
+        let mut form = multipart(&[7u8; 8]).await;
+        let field = form.next_field().await.expect("field").expect("file");
+        assert!(matches!(read_capped(field, 16).await, Ok(data) if data == vec![7u8; 8]));
+    }
+
     #[tokio::test]
     async fn sweep_skips_p2p() {
         let path = std::env::temp_dir().join(format!("rchat_sweep_{}.db", std::process::id()));
@@ -433,7 +589,7 @@ mod tests {
             .execute(&db)
             .await
             .expect("insert p2p message");
-        sweep(&db, None).await.expect("sweep");
+        sweep(&db, None, now()).await.expect("sweep");
         let rows =
             sqlx::query("SELECT media_kind, media_removed, media_hoster FROM messages ORDER BY id")
                 .fetch_all(&db)
@@ -452,4 +608,36 @@ mod tests {
         db.close().await;
         let _ = std::fs::remove_file(&path);
     }
+
+    #[tokio::test]
+    async fn sweep_waits_for_ttl() {
+        let path = std::env::temp_dir().join(format!("rchat_ttl_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = open(path.to_str()).await.expect("open db");
+        let uploaded = 1_000_000;
+        sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploaded_at) VALUES('pic', 'a.png', 'image/png', 1, $1, $2)")
+            .bind(vec![0u8])
+            .bind(uploaded)
+            .execute(&db)
+            .await
+            .expect("insert media");
+        let count = || async {
+            sqlx::query("SELECT COUNT(*) FROM media")
+                .fetch_one(&db)
+                .await
+                .expect("count media")
+                .try_get::<i64, _>(0)
+                .expect("count")
+        };
+        sweep(&db, None, uploaded + MEDIA_TTL_SECS - 1)
+            .await
+            .expect("early sweep");
+        assert_eq!(count().await, 1);
+        sweep(&db, None, uploaded + MEDIA_TTL_SECS)
+            .await
+            .expect("sweep at expiry");
+        assert_eq!(count().await, 0);
+        db.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -6,25 +6,49 @@ use axum::http::{HeaderMap, StatusCode};
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use sqlx::any::AnyRow;
+use unicode_segmentation::UnicodeSegmentation;
 use utoipa::{IntoParams, ToSchema};
 
+use crate::api::moderation::moderate;
 use crate::api::{
-    ApiError, Authed, CallLog, Embed, MaybeAuthed, MediaRef, Message, UserRef, check_profanity,
-    embeds, header_grants, media::MEDIA_TTL_SECS, require_guest_ok, require_server_view, user_ref,
+    ApiError, Authed, CallLog, Embed, Encryption, MaybeAuthed, MediaRef, Message, MessageReport,
+    OkResp, Paged, UserRef, embeds, header_grants, media::MEDIA_TTL_SECS, ok, require_guest_ok,
+    require_server_view, user_ref,
 };
+use crate::config::MassMentionPolicy;
 use crate::db::{
-    ChannelAccess, ChannelKind, Db, MediaKind, Perm, User, channel_access, effective_perms,
-    has_perm, now, touch_interaction,
+    ChannelAccess, ChannelKind, ContentType, Db, FilterStatus, MassMention, MediaKind, Perm,
+    ReportStatus, User, channel_access, effective_perms, has_perm, mark_seen, next_seq, now,
+    touch_interaction,
 };
 use crate::state::AppState;
 use crate::ws::WsEvent;
 
-const COLS: &str = "m.id, m.channel_id, m.dm_id, m.thread_root_id, m.author, m.content, m.media_id, m.media_filename, m.media_removed, m.media_spoiler, m.created_at, (SELECT COUNT(*) FROM messages r WHERE r.thread_root_id = m.id), m.media_kind, m.media_hoster, m.media_expires_at, m.media_size, m.media_mime, m.kind, m.call_answered_at, m.call_ended_at, m.call_outcome";
+const COLS: &str = "m.id, m.channel_id, m.dm_id, m.thread_root_id, m.author, m.content, m.media_id, m.media_filename, m.media_removed, m.media_spoiler, m.created_at, (SELECT COUNT(*) FROM messages r WHERE r.thread_root_id = m.id AND r.deleted_at IS NULL), m.media_kind, m.media_hoster, m.media_expires_at, m.media_size, m.media_mime, m.kind, m.call_answered_at, m.call_ended_at, m.call_outcome, m.filter_status, m.forwarded_from, m.enc_key_id, m.enc_alg, m.seq, m.mass_mention, m.deleted_at";
+
+const MAX_CONTENT_GRAPHEMES: usize = 4000;
+const MAX_CONTENT_BYTES: usize = 32 * 1024;
+const MIN_CIPHERTEXT_BYTES: usize = 28;
+const IDEMPOTENCY_WINDOW_SECS: i64 = 600;
+const SEARCH_PAGE: i64 = 25;
+const GLOBAL_SEARCH_PAGE: i64 = 50;
+const GLOBAL_SEARCH_CAP: i64 = 500;
+const SNIPPET_CONTEXT: usize = 40;
+const RESTORE_WINDOW_SECS: i64 = 5 * 60;
+const DELETED_REVIEW_SECS: i64 = 30 * 24 * 3600;
+const MAX_REPORT_REASON: usize = 1000;
 
 #[derive(Deserialize, IntoParams)]
 pub struct PageQuery {
     before: Option<i64>,
     limit: Option<i64>,
+    include_deleted: Option<bool>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GalleryQuery {
+    before: Option<i64>,
+    limit: Option<i64>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -33,6 +57,18 @@ pub struct SendReq {
     media_id: Option<String>,
     media_spoiler: Option<bool>,
     p2p: Option<P2pAttachment>,
+    encryption: Option<Encryption>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ForwardReq {
+    channel_id: Option<i64>,
+    dm_id: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ReportReq {
+    reason: String,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -64,6 +100,50 @@ pub struct SearchResult {
     pub channel_name: String,
 }
 
+#[derive(Deserialize, IntoParams)]
+pub struct GlobalSearchQuery {
+    q: String,
+    offset: Option<i64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetType {
+    Channel,
+    Dm,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MessageContext {
+    pub target_type: TargetType,
+    pub target_id: i64,
+    pub server_name: Option<String>,
+    pub thread_root_id: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchHit {
+    pub message_id: i64,
+    pub author: String,
+    pub created_at: i64,
+    pub snippet: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchGroup {
+    pub target_type: TargetType,
+    pub target_id: i64,
+    pub server: Option<String>,
+    pub name: String,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GlobalSearch {
+    pub groups: Vec<SearchGroup>,
+    pub next_offset: Option<i64>,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct Unread {
     pub scope: String,
@@ -88,6 +168,13 @@ pub struct ReadState {
     pub last_read: i64,
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct ServerRead {
+    pub updated: i64,
+}
+
+const UPSERT_READ: &str = "INSERT INTO read_state(username, scope, last_read) VALUES($1, $2, $3) ON CONFLICT(username, scope) DO UPDATE SET last_read = CASE WHEN excluded.last_read > read_state.last_read THEN excluded.last_read ELSE read_state.last_read END RETURNING last_read";
+
 fn valid_scope(scope: &str) -> bool {
     let bytes = scope.as_bytes();
     bytes.len() >= 2
@@ -124,6 +211,10 @@ async fn row_message(db: &Db, r: &AnyRow) -> Result<Message, ApiError> {
         }),
         _ => None,
     };
+    let encryption = match (r.try_get(23)?, r.try_get(24)?) {
+        (Some(key_id), Some(algorithm)) => Some(Encryption { key_id, algorithm }),
+        _ => None,
+    };
     Ok(Message {
         id: r.try_get(0)?,
         channel_id: r.try_get(1)?,
@@ -137,17 +228,47 @@ async fn row_message(db: &Db, r: &AnyRow) -> Result<Message, ApiError> {
         embeds: Vec::new(),
         kind,
         call,
+        filter_status: FilterStatus::parse(&r.try_get::<String, _>(21)?)?,
+        forwarded_from: r.try_get(22)?,
+        encryption,
+        seq: r.try_get(25)?,
+        mass_mention: r
+            .try_get::<Option<String>, _>(26)?
+            .map(|s| MassMention::parse(&s))
+            .transpose()?,
+        deleted_at: r.try_get(27)?,
     })
 }
 
 pub(crate) async fn load_message(db: &Db, id: i64) -> Result<Option<Message>, ApiError> {
-    let sql = format!("SELECT {COLS} FROM messages m WHERE m.id = $1");
+    let sql = format!("SELECT {COLS} FROM messages m WHERE m.id = $1 AND m.deleted_at IS NULL");
     match sqlx::query(&sql).bind(id).fetch_optional(db).await? {
         Some(r) => Ok(Some(row_message(db, &r).await?)),
         None => Ok(None),
     }
 }
 
+pub(crate) async fn load_messages(db: &Db, ids: &[i64]) -> Result<Vec<Message>, ApiError> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ph: Vec<String> = (1..=ids.len()).map(|n| format!("${n}")).collect();
+    let sql = format!(
+        "SELECT {COLS} FROM messages m WHERE m.id IN ({}) AND m.deleted_at IS NULL",
+        ph.join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for id in ids {
+        query = query.bind(*id);
+    }
+    let rows = query.fetch_all(db).await?;
+    let mut msgs = Vec::with_capacity(rows.len());
+    for r in &rows {
+        msgs.push(row_message(db, r).await?);
+    }
+    Ok(msgs)
+}
+
 async fn attach_embeds(db: &Db, msgs: &mut [Message]) -> Result<(), ApiError> {
     if msgs.is_empty() {
         return Ok(());
@@ -188,9 +309,14 @@ async fn page(
     key: i64,
     q: &PageQuery,
     min_ts: Option<i64>,
+    include_deleted: bool,
 ) -> Result<Vec<Message>, ApiError> {
+    let deleted = match include_deleted {
+        true => "",
+        false => " AND m.deleted_at IS NULL",
+    };
     let sql = format!(
-        "SELECT {COLS} FROM messages m WHERE {cond} AND m.id < $2 AND m.created_at >= $3 ORDER BY m.id DESC LIMIT $4"
+        "SELECT {COLS} FROM messages m WHERE {cond}{deleted} AND m.id < $2 AND m.created_at >= $3 ORDER BY m.id DESC LIMIT $4"
     );
     let rows = sqlx::query(&sql)
         .bind(key)
@@ -208,19 +334,84 @@ async fn page(
     Ok(msgs)
 }
 
-async fn insert_message(
+async fn deleted_gate(
     db: &Db,
-    channel_id: Option<i64>,
-    dm_id: Option<i64>,
-    thread_root_id: Option<i64>,
-    user: &User,
-    req: SendReq,
-) -> Result<Message, ApiError> {
+    channel_id: i64,
+    user: Option<&User>,
+    q: &PageQuery,
+) -> Result<bool, ApiError> {
+    if q.include_deleted != Some(true) {
+        return Ok(false);
+    }
+    let server = channel_server(db, channel_id).await?;
+    match user {
+        Some(user) if has_perm(db, &server, user, Perm::DeleteMessages).await => Ok(true),
+        _ => Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "Only moderators can see deleted messages".to_string(),
+        )),
+    }
+}
+
+fn check_encryption(content: &str, enc: &Encryption) -> Result<(), ApiError> {
+    let valid = |s: &str| !s.trim().is_empty() && s.len() <= 128;
+    if !valid(&enc.key_id) || !valid(&enc.algorithm) {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Invalid encryption metadata".to_string(),
+        ));
+    }
+    if content.len() > MAX_CONTENT_BYTES {
+        return Err(ApiError(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Encrypted payload exceeds {MAX_CONTENT_BYTES} bytes"),
+        ));
+    }
+    match base64_len(content) {
+        Some(n) if n >= MIN_CIPHERTEXT_BYTES => Ok(()),
+        _ => Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Encrypted content must be base64 ciphertext".to_string(),
+        )),
+    }
+}
+
+fn base64_len(s: &str) -> Option<usize> {
+    let body = s.trim_end_matches('=');
+    let padding = s.len() - body.len();
+    let alphabet = body
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/');
+    match alphabet && s.len().is_multiple_of(4) && padding <= 2 {
+        true => Some(s.len() / 4 * 3 - padding),
+        false => None,
+    }
+}
+
+pub(crate) fn check_length(content: &str) -> Result<(), ApiError> {
+    if content.len() > MAX_CONTENT_BYTES || content.graphemes(true).count() > MAX_CONTENT_GRAPHEMES
+    {
+        return Err(ApiError(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Message exceeds {MAX_CONTENT_GRAPHEMES} characters"),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) struct Draft {
+    content: String,
+    media: Option<MediaRef>,
+    encryption: Option<Encryption>,
+}
+
+pub(crate) async fn draft_message(db: &Db, user: &User, req: SendReq) -> Result<Draft, ApiError> {
     let SendReq {
         content,
         media_id,
         media_spoiler,
         p2p,
+        encryption,
     } = req;
     let spoiler = media_spoiler.unwrap_or(false);
     let media = match (&media_id, p2p) {
@@ -231,17 +422,37 @@ async fn insert_message(
             ));
         }
         (Some(id), None) => {
-            let row = sqlx::query("SELECT filename, uploaded_at FROM media WHERE id = $1")
-                .bind(id)
-                .fetch_optional(db)
-                .await?;
+            let row = sqlx::query(
+                "SELECT filename, uploaded_at, uploader, size, mime FROM media WHERE id = $1",
+            )
+            .bind(id)
+            .fetch_optional(db)
+            .await?;
             match row {
-                Some(r) => Some(MediaRef::server(
-                    id.clone(),
-                    r.try_get(0)?,
-                    spoiler,
-                    r.try_get::<i64, _>(1)? + MEDIA_TTL_SECS,
-                )),
+                Some(r) => {
+                    let expires_at = r.try_get::<i64, _>(1)? + MEDIA_TTL_SECS;
+                    let uploader: Option<String> = r.try_get(2)?;
+                    if uploader.is_some_and(|u| u != user.username) {
+                        return Err(ApiError(
+                            StatusCode::FORBIDDEN,
+                            "Media was uploaded by another user".to_string(),
+                        ));
+                    }
+                    if expires_at <= now() {
+                        return Err(ApiError(
+                            StatusCode::BAD_REQUEST,
+                            "Media has expired".to_string(),
+                        ));
+                    }
+                    Some(MediaRef::server(
+                        id.clone(),
+                        r.try_get(0)?,
+                        spoiler,
+                        expires_at,
+                        r.try_get(3)?,
+                        r.try_get(4)?,
+                    ))
+                }
                 None => {
                     return Err(ApiError(
                         StatusCode::BAD_REQUEST,
@@ -292,9 +503,104 @@ async fn insert_message(
             "Message is empty".to_string(),
         ));
     }
+    match &encryption {
+        Some(enc) => check_encryption(&content, enc)?,
+        None => check_length(&content)?,
+    }
+    Ok(Draft {
+        content,
+        media,
+        encryption,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn insert_message(
+    db: &Db,
+    channel_id: Option<i64>,
+    dm_id: Option<i64>,
+    thread_root_id: Option<i64>,
+    user: &User,
+    draft: Draft,
+    filter_status: FilterStatus,
+    mass_mention: Option<MassMention>,
+    idem: Option<IdemKey>,
+) -> Result<Stored, ApiError> {
+    let Draft {
+        content,
+        media,
+        encryption,
+    } = draft;
+    store_message(
+        db,
+        user,
+        NewMessage {
+            channel_id,
+            dm_id,
+            thread_root_id,
+            content,
+            media,
+            filter_status,
+            forwarded_from: None,
+            encryption,
+            mass_mention,
+            idem,
+        },
+    )
+    .await
+}
+
+enum Stored {
+    New(Message),
+    Replayed(Message),
+}
+
+impl Stored {
+    fn into_message(self) -> Message {
+        match self {
+            Stored::New(m) | Stored::Replayed(m) => m,
+        }
+    }
+}
+
+struct NewMessage {
+    channel_id: Option<i64>,
+    dm_id: Option<i64>,
+    thread_root_id: Option<i64>,
+    content: String,
+    media: Option<MediaRef>,
+    filter_status: FilterStatus,
+    forwarded_from: Option<i64>,
+    encryption: Option<Encryption>,
+    mass_mention: Option<MassMention>,
+    idem: Option<IdemKey>,
+}
+
+async fn store_message(db: &Db, user: &User, new: NewMessage) -> Result<Stored, ApiError> {
+    let NewMessage {
+        channel_id,
+        dm_id,
+        thread_root_id,
+        content,
+        media,
+        filter_status,
+        forwarded_from,
+        encryption,
+        mass_mention,
+        idem,
+    } = new;
+    let kind = match encryption {
+        Some(_) => "encrypted",
+        None => "user",
+    };
     let t = now();
+    let mut tx = db.begin().await?;
+    let seq = match channel_id {
+        Some(cid) => Some(next_seq(&mut *tx, cid).await?),
+        None => None,
+    };
     let id: i64 = sqlx::query(
-        "INSERT INTO messages(channel_id, dm_id, thread_root_id, author, content, media_id, media_filename, media_removed, media_spoiler, media_kind, media_hoster, media_expires_at, media_size, media_mime, created_at) VALUES($1, $2, $3, $4, $5, $6, $7, 0, $8, $9, $10, $11, $12, $13, $14) RETURNING id",
+        "INSERT INTO messages(channel_id, dm_id, thread_root_id, author, content, media_id, media_filename, media_removed, media_spoiler, media_kind, media_hoster, media_expires_at, media_size, media_mime, filter_status, forwarded_from, kind, enc_key_id, enc_alg, seq, mass_mention, created_at) VALUES($1, $2, $3, $4, $5, $6, $7, 0, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21) RETURNING id",
     )
     .bind(channel_id)
     .bind(dm_id)
@@ -303,17 +609,46 @@ async fn insert_message(
     .bind(&content)
     .bind(media.as_ref().map(|m| m.id.clone()))
     .bind(media.as_ref().map(|m| m.filename.clone()))
-    .bind(spoiler as i64)
+    .bind(media.as_ref().is_some_and(|m| m.spoiler) as i64)
     .bind(media.as_ref().map_or(MediaKind::Server, |m| m.kind).as_str())
     .bind(media.as_ref().and_then(|m| m.hoster.clone()))
     .bind(media.as_ref().and_then(|m| m.expires_at))
     .bind(media.as_ref().and_then(|m| m.size))
     .bind(media.as_ref().and_then(|m| m.mime.clone()))
+    .bind(filter_status.as_str())
+    .bind(forwarded_from)
+    .bind(kind)
+    .bind(encryption.as_ref().map(|e| e.key_id.clone()))
+    .bind(encryption.as_ref().map(|e| e.algorithm.clone()))
+    .bind(seq)
+    .bind(mass_mention.map(MassMention::as_str))
     .bind(t)
-    .fetch_one(db)
+    .fetch_one(&mut *tx)
     .await?
     .try_get(0)?;
-    Ok(Message {
+    if let Some(k) = &idem {
+        let claimed = sqlx::query(
+            "INSERT INTO idempotency_keys(username, idem_key, target, message_id, created_at) VALUES($1, $2, $3, $4, $5) ON CONFLICT(username, idem_key) DO UPDATE SET target = excluded.target, message_id = excluded.message_id, created_at = excluded.created_at WHERE idempotency_keys.created_at <= $6",
+        )
+        .bind(&user.username)
+        .bind(&k.key)
+        .bind(&k.target)
+        .bind(id)
+        .bind(t)
+        .bind(t - IDEMPOTENCY_WINDOW_SECS)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            tx.rollback().await?;
+            return match replayed(db, user, Some(k)).await? {
+                Some(m) => Ok(Stored::Replayed(m)),
+                None => Err(key_reused()),
+            };
+        }
+    }
+    tx.commit().await?;
+    Ok(Stored::New(Message {
         id,
         channel_id,
         dm_id,
@@ -324,11 +659,108 @@ async fn insert_message(
         reply_count: 0,
         media,
         embeds: Vec::new(),
-        kind: "user".to_string(),
+        kind: kind.to_string(),
         call: None,
+        filter_status,
+        forwarded_from,
+        encryption,
+        seq,
+        mass_mention,
+        deleted_at: None,
+    }))
+}
+
+pub(crate) async fn post_announcement(
+    db: &Db,
+    user: &User,
+    content: &str,
+) -> Result<Option<Message>, ApiError> {
+    let channel = sqlx::query(
+        "SELECT c.id FROM channels c WHERE c.server = 'rchat' AND c.kind = 'text' AND NOT EXISTS (SELECT 1 FROM channel_perms p WHERE p.channel_id = c.id) ORDER BY c.position, c.id LIMIT 1",
+    )
+    .fetch_optional(db)
+    .await?;
+    let channel_id: i64 = match channel {
+        Some(r) => r.try_get(0)?,
+        None => return Ok(None),
+    };
+    let mut tx = db.begin().await?;
+    let seq = next_seq(&mut *tx, channel_id).await?;
+    let id: i64 = sqlx::query(
+        "INSERT INTO messages(channel_id, author, content, kind, seq, created_at) VALUES($1, $2, $3, 'announcement', $4, $5) RETURNING id",
+    )
+    .bind(channel_id)
+    .bind(&user.username)
+    .bind(content)
+    .bind(seq)
+    .bind(now())
+    .fetch_one(&mut *tx)
+    .await?
+    .try_get(0)?;
+    tx.commit().await?;
+    load_message(db, id).await
+}
+
+pub(crate) fn send_budget(state: &AppState, user: &User) -> Result<(), ApiError> {
+    state.sends.check(&user.username).map_err(|wait| {
+        ApiError(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Sending too fast: wait {wait}s"),
+        )
     })
 }
 
+pub(crate) fn idempotency_key(headers: &HeaderMap) -> Option<String> {
+    let key = headers.get("idempotency-key")?.to_str().ok()?.trim();
+    match key.is_empty() || key.len() > 128 {
+        true => None,
+        false => Some(key.to_string()),
+    }
+}
+
+struct IdemKey {
+    key: String,
+    target: String,
+}
+
+impl IdemKey {
+    fn new(key: Option<String>, target: String) -> Option<IdemKey> {
+        key.map(|key| IdemKey { key, target })
+    }
+}
+
+fn key_reused() -> ApiError {
+    ApiError(
+        StatusCode::CONFLICT,
+        "Idempotency key was already used for another conversation".to_string(),
+    )
+}
+
+async fn replayed(
+    db: &Db,
+    user: &User,
+    idem: Option<&IdemKey>,
+) -> Result<Option<Message>, ApiError> {
+    let Some(k) = idem else {
+        return Ok(None);
+    };
+    let row = sqlx::query(
+        "SELECT message_id, target FROM idempotency_keys WHERE username = $1 AND idem_key = $2 AND created_at > $3",
+    )
+    .bind(&user.username)
+    .bind(&k.key)
+    .bind(now() - IDEMPOTENCY_WINDOW_SECS)
+    .fetch_optional(db)
+    .await?;
+    let Some(r) = row else {
+        return Ok(None);
+    };
+    match r.try_get::<Option<String>, _>(1)?.as_deref() == Some(k.target.as_str()) {
+        true => load_message(db, r.try_get(0)?).await,
+        false => Err(key_reused()),
+    }
+}
+
 async fn channel_server(db: &Db, id: i64) -> Result<String, ApiError> {
     let row = sqlx::query("SELECT server FROM channels WHERE id = $1")
         .bind(id)
@@ -336,21 +768,38 @@ async fn channel_server(db: &Db, id: i64) -> Result<String, ApiError> {
         .await?;
     match row {
         Some(r) => Ok(r.try_get(0)?),
-        None => Err(ApiError(
-            StatusCode::NOT_FOUND,
-            "Channel not found".to_string(),
-        )),
+        None => Err(channel_missing()),
     }
 }
 
+fn channel_missing() -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, "Channel not found".to_string())
+}
+
+fn message_missing() -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, "Message not found".to_string())
+}
+
 async fn dm_users(db: &Db, id: i64) -> Result<Vec<String>, ApiError> {
     let row = sqlx::query("SELECT user_a, user_b FROM dms WHERE id = $1")
         .bind(id)
         .fetch_optional(db)
         .await?;
-    match row {
-        Some(r) => Ok(vec![r.try_get(0)?, r.try_get(1)?]),
-        None => Err(ApiError(StatusCode::NOT_FOUND, "DM not found".to_string())),
+    let (a, b): (String, String) = match row {
+        Some(r) => (r.try_get(0)?, r.try_get(1)?),
+        None => return Err(ApiError(StatusCode::NOT_FOUND, "DM not found".to_string())),
+    };
+    match a == b {
+        true => Ok(vec![a]),
+        false => Ok(vec![a, b]),
+    }
+}
+
+async fn dm_participants(db: &Db, id: i64, username: &str) -> Result<Vec<String>, ApiError> {
+    let users = dm_users(db, id).await?;
+    match users.iter().any(|u| u == username) {
+        true => Ok(users),
+        false => Err(ApiError(StatusCode::NOT_FOUND, "DM not found".to_string())),
     }
 }
 
@@ -366,11 +815,35 @@ async fn require_member(db: &Db, server: &str, username: &str) -> Result<(), Api
     }
 }
 
-async fn thread_root_channel(db: &Db, id: i64) -> Result<i64, ApiError> {
-    let row = sqlx::query("SELECT channel_id, thread_root_id FROM messages WHERE id = $1")
-        .bind(id)
+async fn require_channel_sender(
+    db: &Db,
+    server: &str,
+    channel_id: i64,
+    user: &User,
+) -> Result<(), ApiError> {
+    let member = sqlx::query("SELECT 1 FROM members WHERE server = $1 AND username = $2")
+        .bind(server)
+        .bind(&user.username)
         .fetch_optional(db)
-        .await?;
+        .await?
+        .is_some();
+    match member
+        && channel_access(db, server, channel_id, Some(user))
+            .await?
+            .view
+    {
+        true => Ok(()),
+        false => Err(channel_missing()),
+    }
+}
+
+async fn thread_root_channel(db: &Db, id: i64) -> Result<i64, ApiError> {
+    let row = sqlx::query(
+        "SELECT channel_id, thread_root_id FROM messages WHERE id = $1 AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
     let (channel_id, root): (Option<i64>, Option<i64>) = match &row {
         Some(r) => (r.try_get(0)?, r.try_get(1)?),
         None => {
@@ -404,7 +877,7 @@ async fn read_gate(
         history,
     } = channel_access(db, &server, channel_id, user).await?;
     if !view {
-        return Err(ApiError(StatusCode::FORBIDDEN, "Not allowed".to_string()));
+        return Err(channel_missing());
     }
     match history {
         true => Ok(None),
@@ -427,11 +900,71 @@ async fn read_gate(
     }
 }
 
-async fn send_gate(db: &Db, server: &str, channel_id: i64, user: &User) -> Result<(), ApiError> {
-    let row = sqlx::query("SELECT kind, slowmode_seconds FROM channels WHERE id = $1")
-        .bind(channel_id)
-        .fetch_one(db)
-        .await?;
+fn mass_mention_in(content: &str) -> Option<MassMention> {
+    let mut found = None;
+    for word in content.split_whitespace() {
+        let word = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+        if word.eq_ignore_ascii_case("@everyone") {
+            return Some(MassMention::Everyone);
+        }
+        if word.eq_ignore_ascii_case("@here") {
+            found = Some(MassMention::Here);
+        }
+    }
+    found
+}
+
+async fn mass_mention_gate(
+    db: &Db,
+    server: &str,
+    user: &User,
+    req: &SendReq,
+    reject: bool,
+) -> Result<Option<MassMention>, ApiError> {
+    let mention = match req.encryption {
+        Some(_) => None,
+        None => mass_mention_in(&req.content),
+    };
+    let Some(mention) = mention else {
+        return Ok(None);
+    };
+    match (
+        has_perm(db, server, user, Perm::MentionEveryone).await,
+        reject,
+    ) {
+        (true, _) => Ok(Some(mention)),
+        (false, true) => Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "No permission to mention @everyone or @here".to_string(),
+        )),
+        (false, false) => Ok(None),
+    }
+}
+
+fn carried(content: &str, attachment: bool) -> Vec<ContentType> {
+    let mut types = Vec::new();
+    if !content.trim().is_empty() {
+        types.push(ContentType::Text);
+    }
+    if attachment {
+        types.push(ContentType::Attachment);
+    }
+    types
+}
+
+async fn send_gate(
+    db: &Db,
+    server: &str,
+    channel_id: i64,
+    user: &User,
+    carried: &[ContentType],
+) -> Result<(), ApiError> {
+    let row = sqlx::query(
+        "SELECT kind, slowmode_seconds, allowed_content_types FROM channels WHERE id = $1",
+    )
+    .bind(channel_id)
+    .fetch_one(db)
+    .await?;
     if ChannelKind::parse(&row.try_get::<String, _>(0)?)? == ChannelKind::Voice {
         return Err(ApiError(
             StatusCode::BAD_REQUEST,
@@ -439,16 +972,27 @@ async fn send_gate(db: &Db, server: &str, channel_id: i64, user: &User) -> Resul
         ));
     }
     let ChannelAccess {
-        view: _,
+        view,
         send,
         history: _,
     } = channel_access(db, server, channel_id, Some(user)).await?;
+    if !view {
+        return Err(channel_missing());
+    }
     if !send {
         return Err(ApiError(
             StatusCode::FORBIDDEN,
             "No permission to send in this channel".to_string(),
         ));
     }
+    if let Some(allowed) = ContentType::parse_list(row.try_get(2)?)?
+        && let Some(kind) = carried.iter().find(|t| !allowed.contains(t))
+    {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("This channel does not allow {} messages", kind.as_str()),
+        ));
+    }
     let slow: i64 = row.try_get(1)?;
     if slow <= 0 {
         return Ok(());
@@ -485,6 +1029,7 @@ pub(crate) async fn channel_messages(
 ) -> Result<Json<Vec<Message>>, ApiError> {
     require_guest_ok(&state.db, user.as_ref()).await?;
     let min_ts = read_gate(&state.db, &headers, id, user.as_ref()).await?;
+    let deleted = deleted_gate(&state.db, id, user.as_ref(), &q).await?;
     Ok(Json(
         page(
             &state.db,
@@ -492,39 +1037,128 @@ pub(crate) async fn channel_messages(
             id,
             &q,
             min_ts,
+            deleted,
         )
         .await?,
     ))
 }
 
+#[utoipa::path(get, path = "/api/channels/{id}/media", params(("id" = i64, Path), GalleryQuery), responses((status = 200, body = Vec<Message>)), security(("bearer" = [])))]
+pub(crate) async fn channel_media(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Query(q): Query<GalleryQuery>,
+) -> Result<Json<Vec<Message>>, ApiError> {
+    let server = channel_server(&state.db, id).await?;
+    if !user.is_site_admin {
+        require_member(&state.db, &server, &user.username).await?;
+    }
+    let min_ts = read_gate(&state.db, &headers, id, Some(&user)).await?;
+    let q = PageQuery {
+        before: q.before,
+        limit: Some(q.limit.unwrap_or(50).clamp(1, 100)),
+        include_deleted: None,
+    };
+    let mut items = page(
+        &state.db,
+        "m.channel_id = $1 AND m.media_id IS NOT NULL AND m.media_removed = 0 AND (m.media_mime LIKE 'image/%' OR m.media_mime LIKE 'video/%' OR m.media_mime LIKE 'audio/%')",
+        id,
+        &q,
+        min_ts,
+        false,
+    )
+    .await?;
+    items.reverse();
+    Ok(Json(items))
+}
+
 #[utoipa::path(post, path = "/api/channels/{id}/messages", params(("id" = i64, Path)), request_body = SendReq, responses((status = 200, body = Message)), security(("bearer" = [])))]
 pub(crate) async fn send_channel_message(
     State(state): State<AppState>,
     Path(id): Path<i64>,
     Authed(user): Authed,
+    headers: HeaderMap,
     Json(req): Json<SendReq>,
 ) -> Result<Json<Message>, ApiError> {
-    check_profanity(&state.db, &req.content).await?;
+    let idem = IdemKey::new(idempotency_key(&headers), format!("c{id}"));
     let server = channel_server(&state.db, id).await?;
-    require_member(&state.db, &server, &user.username).await?;
-    send_gate(&state.db, &server, id, &user).await?;
-    let message = insert_message(&state.db, Some(id), None, None, &user, req).await?;
-    touch_interaction(&state.db, &server, &user.username).await?;
-    embeds::spawn_unfurl(
-        &state,
-        Some(server.clone()),
+    require_channel_sender(&state.db, &server, id, &user).await?;
+    if let Some(message) = replayed(&state.db, &user, idem.as_ref()).await? {
+        return Ok(Json(message));
+    }
+    let attachment = req.media_id.is_some() || req.p2p.is_some();
+    send_gate(
+        &state.db,
+        &server,
+        id,
+        &user,
+        &carried(&req.content, attachment),
+    )
+    .await?;
+    let mention = mass_mention_gate(
+        &state.db,
+        &server,
+        &user,
+        &req,
+        state.config.mass_mention_policy == MassMentionPolicy::Reject,
+    )
+    .await?;
+    let draft = draft_message(&state.db, &user, req).await?;
+    send_budget(&state, &user)?;
+    let status = match draft.encryption {
+        Some(_) => FilterStatus::Clean,
+        None => {
+            moderate(
+                &state.db,
+                &state.http,
+                state.config.moderation_webhook.as_ref(),
+                &draft.content,
+                &user.username,
+                &server,
+                id,
+            )
+            .await?
+        }
+    };
+    let message = match insert_message(
+        &state.db,
         Some(id),
         None,
         None,
-        message.id,
-        &message.content,
-    );
+        &user,
+        draft,
+        status,
+        mention,
+        idem,
+    )
+    .await?
+    {
+        Stored::New(m) => m,
+        Stored::Replayed(m) => return Ok(Json(m)),
+    };
+    touch_interaction(&state.db, &server, &user.username).await?;
+    mark_seen(&state.db, &user.username).await?;
+    if message.encryption.is_none() {
+        embeds::spawn_unfurl(
+            &state,
+            Some(server.clone()),
+            Some(id),
+            None,
+            None,
+            message.id,
+            &message.content,
+        );
+    }
     state.hub.broadcast(WsEvent::Message {
         server: Some(server),
         channel_id: Some(id),
         dm_id: None,
         dm_users: None,
         message: Box::new(message.clone()),
+        muted: false,
+        mentioned: false,
     });
     Ok(Json(message))
 }
@@ -540,8 +1174,9 @@ pub(crate) async fn thread_messages(
     require_guest_ok(&state.db, user.as_ref()).await?;
     let channel_id = thread_root_channel(&state.db, id).await?;
     let min_ts = read_gate(&state.db, &headers, channel_id, user.as_ref()).await?;
+    let deleted = deleted_gate(&state.db, channel_id, user.as_ref(), &q).await?;
     Ok(Json(
-        page(&state.db, "m.thread_root_id = $1", id, &q, min_ts).await?,
+        page(&state.db, "m.thread_root_id = $1", id, &q, min_ts, deleted).await?,
     ))
 }
 
@@ -550,30 +1185,86 @@ pub(crate) async fn send_thread_message(
     State(state): State<AppState>,
     Path(id): Path<i64>,
     Authed(user): Authed,
+    headers: HeaderMap,
     Json(req): Json<SendReq>,
 ) -> Result<Json<Message>, ApiError> {
+    let idem = IdemKey::new(idempotency_key(&headers), format!("t{id}"));
     let channel_id = thread_root_channel(&state.db, id).await?;
-    check_profanity(&state.db, &req.content).await?;
     let server = channel_server(&state.db, channel_id).await?;
-    require_member(&state.db, &server, &user.username).await?;
-    send_gate(&state.db, &server, channel_id, &user).await?;
-    let message = insert_message(&state.db, Some(channel_id), None, Some(id), &user, req).await?;
-    touch_interaction(&state.db, &server, &user.username).await?;
-    embeds::spawn_unfurl(
-        &state,
-        Some(server.clone()),
+    require_channel_sender(&state.db, &server, channel_id, &user).await?;
+    if let Some(message) = replayed(&state.db, &user, idem.as_ref()).await? {
+        return Ok(Json(message));
+    }
+    let attachment = req.media_id.is_some() || req.p2p.is_some();
+    send_gate(
+        &state.db,
+        &server,
+        channel_id,
+        &user,
+        &carried(&req.content, attachment),
+    )
+    .await?;
+    let mention = mass_mention_gate(
+        &state.db,
+        &server,
+        &user,
+        &req,
+        state.config.mass_mention_policy == MassMentionPolicy::Reject,
+    )
+    .await?;
+    let draft = draft_message(&state.db, &user, req).await?;
+    send_budget(&state, &user)?;
+    let status = match draft.encryption {
+        Some(_) => FilterStatus::Clean,
+        None => {
+            moderate(
+                &state.db,
+                &state.http,
+                state.config.moderation_webhook.as_ref(),
+                &draft.content,
+                &user.username,
+                &server,
+                channel_id,
+            )
+            .await?
+        }
+    };
+    let message = match insert_message(
+        &state.db,
         Some(channel_id),
         None,
-        None,
-        message.id,
-        &message.content,
-    );
+        Some(id),
+        &user,
+        draft,
+        status,
+        mention,
+        idem,
+    )
+    .await?
+    {
+        Stored::New(m) => m,
+        Stored::Replayed(m) => return Ok(Json(m)),
+    };
+    touch_interaction(&state.db, &server, &user.username).await?;
+    if message.encryption.is_none() {
+        embeds::spawn_unfurl(
+            &state,
+            Some(server.clone()),
+            Some(channel_id),
+            None,
+            None,
+            message.id,
+            &message.content,
+        );
+    }
     state.hub.broadcast(WsEvent::Message {
         server: Some(server),
         channel_id: Some(channel_id),
         dm_id: None,
         dm_users: None,
         message: Box::new(message.clone()),
+        muted: false,
+        mentioned: false,
     });
     Ok(Json(message))
 }
@@ -585,14 +1276,16 @@ pub(crate) async fn dm_messages(
     Authed(user): Authed,
     Query(q): Query<PageQuery>,
 ) -> Result<Json<Vec<Message>>, ApiError> {
-    let users = dm_users(&state.db, id).await?;
-    if !users.contains(&user.username) {
+    dm_participants(&state.db, id, &user.username).await?;
+    if q.include_deleted == Some(true) {
         return Err(ApiError(
             StatusCode::FORBIDDEN,
-            "Not a participant".to_string(),
+            "Only moderators can see deleted messages".to_string(),
         ));
     }
-    Ok(Json(page(&state.db, "m.dm_id = $1", id, &q, None).await?))
+    Ok(Json(
+        page(&state.db, "m.dm_id = $1", id, &q, None, false).await?,
+    ))
 }
 
 #[utoipa::path(post, path = "/api/dms/{id}/messages", params(("id" = i64, Path)), request_body = SendReq, responses((status = 200, body = Message)), security(("bearer" = [])))]
@@ -600,31 +1293,238 @@ pub(crate) async fn send_dm_message(
     State(state): State<AppState>,
     Path(id): Path<i64>,
     Authed(user): Authed,
+    headers: HeaderMap,
     Json(req): Json<SendReq>,
 ) -> Result<Json<Message>, ApiError> {
-    let users = dm_users(&state.db, id).await?;
-    if !users.contains(&user.username) {
+    let key = idempotency_key(&headers);
+    Ok(Json(send_to_dm(&state, &user, id, key, req).await?))
+}
+
+pub(crate) async fn send_to_dm(
+    state: &AppState,
+    user: &User,
+    id: i64,
+    key: Option<String>,
+    req: SendReq,
+) -> Result<Message, ApiError> {
+    let users = dm_participants(&state.db, id, &user.username).await?;
+    let idem = IdemKey::new(key.clone(), format!("d{id}"));
+    if let Some(message) = replayed(&state.db, user, idem.as_ref()).await? {
+        return Ok(message);
+    }
+    let draft = draft_message(&state.db, user, req).await?;
+    send_budget(state, user)?;
+    deliver_dm(state, user, id, users, draft, key).await
+}
+
+pub(crate) async fn deliver_dm(
+    state: &AppState,
+    user: &User,
+    id: i64,
+    users: Vec<String>,
+    draft: Draft,
+    key: Option<String>,
+) -> Result<Message, ApiError> {
+    let idem = IdemKey::new(key, format!("d{id}"));
+    let message = match insert_message(
+        &state.db,
+        None,
+        Some(id),
+        None,
+        user,
+        draft,
+        FilterStatus::Clean,
+        None,
+        idem,
+    )
+    .await?
+    {
+        Stored::New(m) => m,
+        Stored::Replayed(m) => return Ok(m),
+    };
+    if message.encryption.is_none() {
+        embeds::spawn_unfurl(
+            state,
+            None,
+            None,
+            Some(id),
+            Some(users.clone()),
+            message.id,
+            &message.content,
+        );
+    }
+    state.hub.broadcast(WsEvent::Message {
+        server: None,
+        channel_id: None,
+        dm_id: Some(id),
+        dm_users: Some(users),
+        message: Box::new(message.clone()),
+        muted: false,
+        mentioned: false,
+    });
+    Ok(message)
+}
+
+async fn require_message_view(
+    db: &Db,
+    headers: &HeaderMap,
+    message: &Message,
+    user: &User,
+) -> Result<(), ApiError> {
+    match (message.channel_id, message.dm_id) {
+        (Some(cid), _) => {
+            let min_ts = read_gate(db, headers, cid, Some(user)).await?;
+            match min_ts.is_some_and(|t| message.created_at < t) {
+                true => Err(message_missing()),
+                false => Ok(()),
+            }
+        }
+        (None, Some(did)) => match dm_users(db, did).await?.contains(&user.username) {
+            true => Ok(()),
+            false => Err(message_missing()),
+        },
+        (None, None) => Err(message_missing()),
+    }
+}
+
+#[utoipa::path(get, path = "/api/messages/{id}/context", params(("id" = i64, Path)), responses((status = 200, body = MessageContext)), security(("bearer" = [])))]
+pub(crate) async fn message_context(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Authed(user): Authed,
+    headers: HeaderMap,
+) -> Result<Json<MessageContext>, ApiError> {
+    let message = match load_message(&state.db, id).await? {
+        Some(m) => m,
+        None => return Err(message_missing()),
+    };
+    require_message_view(&state.db, &headers, &message, &user).await?;
+    let (target_type, target_id, server_name) = match (message.channel_id, message.dm_id) {
+        (Some(cid), _) => (
+            TargetType::Channel,
+            cid,
+            Some(channel_server(&state.db, cid).await?),
+        ),
+        (None, Some(did)) => (TargetType::Dm, did, None),
+        (None, None) => return Err(message_missing()),
+    };
+    Ok(Json(MessageContext {
+        target_type,
+        target_id,
+        server_name,
+        thread_root_id: message.thread_root_id,
+    }))
+}
+
+#[utoipa::path(post, path = "/api/messages/{id}/forward", params(("id" = i64, Path)), request_body = ForwardReq, responses((status = 200, body = Message)), security(("bearer" = [])))]
+pub(crate) async fn forward_message(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Authed(user): Authed,
+    headers: HeaderMap,
+    Json(req): Json<ForwardReq>,
+) -> Result<Json<Message>, ApiError> {
+    let source = match load_message(&state.db, id).await? {
+        Some(m) => m,
+        None => return Err(message_missing()),
+    };
+    if source.kind != "user" {
         return Err(ApiError(
-            StatusCode::FORBIDDEN,
-            "Not a participant".to_string(),
+            StatusCode::BAD_REQUEST,
+            "Only user messages can be forwarded".to_string(),
+        ));
+    }
+    require_message_view(&state.db, &headers, &source, &user).await?;
+    let media = match source.media {
+        Some(m) if m.removed || m.expires_at.is_some_and(|t| t <= now()) => None,
+        Some(m) if m.kind == MediaKind::P2p && m.hoster.as_deref() != Some(&user.username) => {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                "P2P attachments can only be forwarded by their host".to_string(),
+            ));
+        }
+        other => other,
+    };
+    if source.content.trim().is_empty() && media.is_none() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Nothing left to forward".to_string(),
         ));
     }
-    let message = insert_message(&state.db, None, Some(id), None, &user, req).await?;
+    let ForwardReq { channel_id, dm_id } = req;
+    let (server, dm_users) = match (channel_id, dm_id) {
+        (Some(cid), None) => {
+            let server = channel_server(&state.db, cid).await?;
+            require_channel_sender(&state.db, &server, cid, &user).await?;
+            let types = carried(&source.content, media.is_some());
+            send_gate(&state.db, &server, cid, &user, &types).await?;
+            (Some(server), None)
+        }
+        (None, Some(did)) => {
+            let users = dm_participants(&state.db, did, &user.username).await?;
+            (None, Some(users))
+        }
+        (_, _) => {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                "Specify a channel or a DM".to_string(),
+            ));
+        }
+    };
+    send_budget(&state, &user)?;
+    let filter_status = match (&server, channel_id) {
+        (Some(server), Some(cid)) => {
+            moderate(
+                &state.db,
+                &state.http,
+                state.config.moderation_webhook.as_ref(),
+                &source.content,
+                &user.username,
+                server,
+                cid,
+            )
+            .await?
+        }
+        _ => FilterStatus::Clean,
+    };
+    let message = store_message(
+        &state.db,
+        &user,
+        NewMessage {
+            channel_id,
+            dm_id,
+            thread_root_id: None,
+            content: source.content,
+            media,
+            filter_status,
+            forwarded_from: Some(id),
+            encryption: None,
+            mass_mention: None,
+            idem: None,
+        },
+    )
+    .await?
+    .into_message();
+    if let Some(s) = &server {
+        touch_interaction(&state.db, s, &user.username).await?;
+    }
     embeds::spawn_unfurl(
         &state,
-        None,
-        None,
-        Some(id),
-        Some(users.clone()),
+        server.clone(),
+        channel_id,
+        dm_id,
+        dm_users.clone(),
         message.id,
         &message.content,
     );
     state.hub.broadcast(WsEvent::Message {
-        server: None,
-        channel_id: None,
-        dm_id: Some(id),
-        dm_users: Some(users),
+        server,
+        channel_id,
+        dm_id,
+        dm_users,
         message: Box::new(message.clone()),
+        muted: false,
+        mentioned: false,
     });
     Ok(Json(message))
 }
@@ -642,20 +1542,21 @@ pub(crate) struct MsgScope {
 }
 
 pub(crate) async fn message_scope(db: &Db, id: i64) -> Result<MsgScope, ApiError> {
-    let row = sqlx::query(
-        "SELECT channel_id, dm_id, thread_root_id, author, media_id, media_filename, media_kind FROM messages WHERE id = $1",
-    )
-    .bind(id)
-    .fetch_optional(db)
-    .await?;
+    load_scope(db, id, false).await
+}
+
+async fn load_scope(db: &Db, id: i64, deleted: bool) -> Result<MsgScope, ApiError> {
+    let sql = format!(
+        "SELECT channel_id, dm_id, thread_root_id, author, media_id, media_filename, media_kind FROM messages WHERE id = $1 AND deleted_at IS {}",
+        match deleted {
+            true => "NOT NULL",
+            false => "NULL",
+        }
+    );
+    let row = sqlx::query(&sql).bind(id).fetch_optional(db).await?;
     let r = match &row {
         Some(r) => r,
-        None => {
-            return Err(ApiError(
-                StatusCode::NOT_FOUND,
-                "Message not found".to_string(),
-            ));
-        }
+        None => return Err(message_missing()),
     };
     let channel_id: Option<i64> = r.try_get(0)?;
     let dm_id: Option<i64> = r.try_get(1)?;
@@ -687,6 +1588,13 @@ pub(crate) async fn require_can_delete(
     user: &User,
     scope: &MsgScope,
 ) -> Result<(), ApiError> {
+    if scope
+        .dm_users
+        .as_ref()
+        .is_some_and(|users| !users.contains(&user.username))
+    {
+        return Err(message_missing());
+    }
     let allowed = scope.author == user.username
         || match &scope.server {
             Some(server) => has_perm(db, server, user, Perm::DeleteMessages).await,
@@ -698,16 +1606,26 @@ pub(crate) async fn require_can_delete(
     }
 }
 
-#[utoipa::path(delete, path = "/api/messages/{id}", params(("id" = i64, Path)), responses((status = 200, description = "Deleted")), security(("bearer" = [])))]
+#[utoipa::path(delete, path = "/api/messages/{id}", params(("id" = i64, Path)), responses((status = 200, description = "Deleted", body = OkResp)), security(("bearer" = [])))]
 pub(crate) async fn delete_message(
     State(state): State<AppState>,
     Path(id): Path<i64>,
     Authed(user): Authed,
-) -> Result<Json<serde_json::Value>, ApiError> {
+) -> Result<Json<OkResp>, ApiError> {
     let scope = message_scope(&state.db, id).await?;
     require_can_delete(&state.db, &user, &scope).await?;
-    sqlx::query("DELETE FROM messages WHERE id = $1")
+    hide_message(&state, id, scope).await?;
+    Ok(ok())
+}
+
+pub(crate) async fn hide_message(
+    state: &AppState,
+    id: i64,
+    scope: MsgScope,
+) -> Result<(), ApiError> {
+    sqlx::query("UPDATE messages SET deleted_at = $2 WHERE id = $1")
         .bind(id)
+        .bind(now())
         .execute(&state.db)
         .await?;
     let MsgScope {
@@ -729,82 +1647,296 @@ pub(crate) async fn delete_message(
         id,
         thread_root_id,
     });
-    Ok(Json(serde_json::json!({ "ok": true })))
+    Ok(())
 }
 
-enum Bind {
-    S(String),
-    I(i64),
+#[utoipa::path(post, path = "/api/messages/{id}/restore", params(("id" = i64, Path)), responses((status = 200, body = Message)), security(("bearer" = [])))]
+pub(crate) async fn restore_message(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Authed(user): Authed,
+) -> Result<Json<Message>, ApiError> {
+    let scope = load_scope(&state.db, id, true).await?;
+    require_can_delete(&state.db, &user, &scope).await?;
+    let restored =
+        sqlx::query("UPDATE messages SET deleted_at = NULL WHERE id = $1 AND deleted_at > $2")
+            .bind(id)
+            .bind(now() - RESTORE_WINDOW_SECS)
+            .execute(&state.db)
+            .await?;
+    if restored.rows_affected() == 0 {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "The restore window has passed".to_string(),
+        ));
+    }
+    let message = load_message(&state.db, id)
+        .await?
+        .ok_or_else(message_missing)?;
+    let MsgScope {
+        channel_id,
+        dm_id,
+        thread_root_id: _,
+        author: _,
+        media_id: _,
+        media_filename: _,
+        media_kind: _,
+        server,
+        dm_users,
+    } = scope;
+    state.hub.broadcast(WsEvent::MessageRestored {
+        server,
+        channel_id,
+        dm_id,
+        dm_users,
+        message: Box::new(message.clone()),
+    });
+    Ok(Json(message))
 }
 
-#[utoipa::path(get, path = "/api/search", params(SearchQuery), responses((status = 200, body = Vec<SearchResult>)), security((), ("bearer" = [])))]
-pub(crate) async fn search(
+pub(crate) fn row_report(r: &AnyRow) -> Result<MessageReport, ApiError> {
+    Ok(MessageReport {
+        id: r.try_get(0)?,
+        message_id: r.try_get(1)?,
+        reporter: r.try_get(2)?,
+        reason: r.try_get(3)?,
+        status: ReportStatus::parse(&r.try_get::<String, _>(4)?)?,
+        created_at: r.try_get(5)?,
+        resolved_by: r.try_get(6)?,
+        resolved_at: r.try_get(7)?,
+        author: r.try_get(8)?,
+        content: r.try_get(9)?,
+    })
+}
+
+pub(crate) const REPORT_COLS: &str = "id, message_id, reporter, reason, status, created_at, resolved_by, resolved_at, author, content";
+
+#[utoipa::path(post, path = "/api/messages/{id}/report", params(("id" = i64, Path)), request_body = ReportReq, responses((status = 200, body = MessageReport)), security(("bearer" = [])))]
+pub(crate) async fn report_message(
     State(state): State<AppState>,
-    MaybeAuthed(user): MaybeAuthed,
+    Path(id): Path<i64>,
+    Authed(user): Authed,
     headers: HeaderMap,
-    Query(sq): Query<SearchQuery>,
-) -> Result<Json<Vec<SearchResult>>, ApiError> {
-    require_guest_ok(&state.db, user.as_ref()).await?;
-    let SearchQuery {
-        q,
-        server,
-        channel_id,
-        from,
-        has,
-        before,
-        after,
-        offset,
-        servers,
-    } = sq;
-    let mut conds: Vec<String> = Vec::new();
-    let mut binds: Vec<Bind> = Vec::new();
-    match &user {
-        Some(u) => {
-            binds.push(Bind::S(u.username.clone()));
-            conds.push(format!(
-                "c.server IN (SELECT server FROM members WHERE username = ${})",
-                binds.len()
-            ));
-            if !u.is_site_admin {
-                let mut subjects = vec![format!("u:{}", u.username)];
-                let role_rows = sqlx::query("SELECT role_id FROM user_roles WHERE username = $1")
-                    .bind(&u.username)
-                    .fetch_all(&state.db)
-                    .await?;
-                for r in &role_rows {
-                    subjects.push(format!("r:{}", r.try_get::<i64, _>(0)?));
-                }
-                let mut ph = Vec::with_capacity(subjects.len());
-                for subject in subjects {
-                    binds.push(Bind::S(subject));
-                    ph.push(format!("${}", binds.len()));
-                }
-                conds.push(format!(
-                    "(NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id) OR EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id AND cp.can_view != 0 AND cp.subject IN ({})))",
-                    ph.join(", ")
-                ));
-                conds.push(format!(
-                    "(NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id) OR EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id AND cp.can_read_history != 0 AND cp.subject IN ({})) OR m.created_at >= (SELECT joined_at FROM members mm WHERE mm.server = c.server AND mm.username = $1))",
-                    ph.join(", ")
-                ));
-            }
-        }
-        None => {
-            conds.push(
-                "NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id)"
-                    .to_string(),
-            );
-            let grants = header_grants(&headers);
-            match grants.is_empty() {
-                true => conds.push(
-                    "c.server IN (SELECT name FROM servers WHERE password_hash IS NULL)"
-                        .to_string(),
-                ),
-                false => {
-                    let mut ph = Vec::with_capacity(grants.len());
-                    for grant in grants {
-                        binds.push(Bind::S(grant));
-                        ph.push(format!("${}", binds.len()));
+    Json(req): Json<ReportReq>,
+) -> Result<Json<MessageReport>, ApiError> {
+    let message = load_message(&state.db, id)
+        .await?
+        .ok_or_else(message_missing)?;
+    require_message_view(&state.db, &headers, &message, &user).await?;
+    let reason = req.reason.trim();
+    if reason.is_empty() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Give a reason for the report".to_string(),
+        ));
+    }
+    if reason.chars().count() > MAX_REPORT_REASON {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("Reasons are limited to {MAX_REPORT_REASON} characters"),
+        ));
+    }
+    if message.author.username == user.username {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "You cannot report your own message".to_string(),
+        ));
+    }
+    let row = sqlx::query(&format!(
+        "INSERT INTO message_reports(message_id, reporter, reason, created_at, author, content) VALUES($1, $2, $3, $4, $5, $6) ON CONFLICT(message_id, reporter) DO NOTHING RETURNING {REPORT_COLS}"
+    ))
+    .bind(id)
+    .bind(&user.username)
+    .bind(reason)
+    .bind(now())
+    .bind(&message.author.username)
+    .bind(&message.content)
+    .fetch_optional(&state.db)
+    .await?;
+    let Some(row) = row else {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            "You already reported this message".to_string(),
+        ));
+    };
+    let report = row_report(&row)?;
+    state.hub.broadcast(WsEvent::MessageReported {
+        report: report.clone(),
+    });
+    Ok(Json(report))
+}
+
+async fn purge_deleted(db: &Db, at: i64) -> Result<u64, ApiError> {
+    Ok(sqlx::query("DELETE FROM messages WHERE deleted_at <= $1 AND NOT EXISTS(SELECT 1 FROM message_reports r WHERE r.message_id = messages.id AND r.status = 'open')")
+        .bind(at - DELETED_REVIEW_SECS)
+        .execute(db)
+        .await?
+        .rows_affected())
+}
+
+pub async fn sweep_deleted(state: &AppState) {
+    if let Err(ApiError(_, message)) = purge_deleted(&state.db, now()).await {
+        tracing::warn!("deleted message sweep failed: {message}");
+    }
+}
+
+async fn purge_expired(db: &Db) -> Result<Vec<WsEvent>, ApiError> {
+    let rows = sqlx::query(
+        "SELECT id, server, message_retention_hours FROM channels WHERE message_retention_hours IS NOT NULL",
+    )
+    .fetch_all(db)
+    .await?;
+    let mut events = Vec::new();
+    for r in &rows {
+        let channel_id: i64 = r.try_get(0)?;
+        let Some(cutoff) = r
+            .try_get::<i64, _>(2)?
+            .checked_mul(3600)
+            .and_then(|secs| now().checked_sub(secs))
+        else {
+            continue;
+        };
+        let mut tx = db.begin().await?;
+        let doomed = sqlx::query(
+            "SELECT id FROM messages WHERE channel_id = $1 AND created_at < $2 OR thread_root_id IN (SELECT id FROM messages WHERE channel_id = $1 AND created_at < $2)",
+        )
+        .bind(channel_id)
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+        if doomed.is_empty() {
+            continue;
+        }
+        sqlx::query("DELETE FROM messages WHERE channel_id = $1 AND created_at < $2")
+            .bind(channel_id)
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        let mut ids = Vec::with_capacity(doomed.len());
+        for d in &doomed {
+            ids.push(d.try_get(0)?);
+        }
+        events.push(WsEvent::MessagesPurged {
+            server: r.try_get(1)?,
+            channel_id,
+            ids,
+        });
+    }
+    Ok(events)
+}
+
+pub async fn sweep_idempotency_keys(state: &AppState) {
+    if let Err(e) = sqlx::query("DELETE FROM idempotency_keys WHERE created_at <= $1")
+        .bind(now() - IDEMPOTENCY_WINDOW_SECS)
+        .execute(&state.db)
+        .await
+    {
+        tracing::warn!("idempotency key sweep failed: {e}");
+    }
+}
+
+pub async fn sweep_retention(state: &AppState) {
+    let events = match purge_expired(&state.db).await {
+        Ok(events) => events,
+        Err(ApiError(_, message)) => {
+            tracing::warn!("retention sweep failed: {message}");
+            return;
+        }
+    };
+    for ev in events {
+        state.hub.broadcast(ev);
+    }
+}
+
+enum Bind {
+    S(String),
+    I(i64),
+}
+
+async fn member_channel_conds(
+    db: &Db,
+    user: &User,
+    binds: &mut Vec<Bind>,
+) -> Result<Vec<String>, ApiError> {
+    binds.push(Bind::S(user.username.clone()));
+    let me = binds.len();
+    let mut conds = vec![format!(
+        "c.server IN (SELECT server FROM members WHERE username = ${me})"
+    )];
+    if user.is_site_admin {
+        return Ok(conds);
+    }
+    let mut subjects = vec![format!("u:{}", user.username)];
+    let role_rows = sqlx::query("SELECT role_id FROM user_roles WHERE username = $1")
+        .bind(&user.username)
+        .fetch_all(db)
+        .await?;
+    for r in &role_rows {
+        subjects.push(format!("r:{}", r.try_get::<i64, _>(0)?));
+    }
+    let mut ph = Vec::with_capacity(subjects.len());
+    for subject in subjects {
+        binds.push(Bind::S(subject));
+        ph.push(format!("${}", binds.len()));
+    }
+    conds.push(format!(
+        "(NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id) OR EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id AND cp.can_view != 0 AND cp.subject IN ({})))",
+        ph.join(", ")
+    ));
+    conds.push(format!(
+        "(NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id) OR EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id AND cp.can_read_history != 0 AND cp.subject IN ({})) OR m.created_at >= (SELECT joined_at FROM members mm WHERE mm.server = c.server AND mm.username = ${me}))",
+        ph.join(", ")
+    ));
+    Ok(conds)
+}
+
+#[utoipa::path(get, path = "/api/search", params(SearchQuery), responses((status = 200, body = Vec<SearchResult>)), security((), ("bearer" = [])))]
+pub(crate) async fn search(
+    State(state): State<AppState>,
+    MaybeAuthed(user): MaybeAuthed,
+    headers: HeaderMap,
+    Query(sq): Query<SearchQuery>,
+) -> Result<Paged<Vec<SearchResult>>, ApiError> {
+    require_guest_ok(&state.db, user.as_ref()).await?;
+    let SearchQuery {
+        q,
+        server,
+        channel_id,
+        from,
+        has,
+        before,
+        after,
+        offset,
+        servers,
+    } = sq;
+    let offset = offset.unwrap_or(0).max(0);
+    let page = |items, total| Paged {
+        items,
+        total,
+        limit: SEARCH_PAGE,
+        offset,
+    };
+    let mut conds: Vec<String> = Vec::new();
+    let mut binds: Vec<Bind> = Vec::new();
+    match &user {
+        Some(u) => conds.extend(member_channel_conds(&state.db, u, &mut binds).await?),
+        None => {
+            conds.push(
+                "NOT EXISTS(SELECT 1 FROM channel_perms cp WHERE cp.channel_id = m.channel_id)"
+                    .to_string(),
+            );
+            let grants = header_grants(&headers);
+            match grants.is_empty() {
+                true => conds.push(
+                    "c.server IN (SELECT name FROM servers WHERE password_hash IS NULL)"
+                        .to_string(),
+                ),
+                false => {
+                    let mut ph = Vec::with_capacity(grants.len());
+                    for grant in grants {
+                        binds.push(Bind::S(grant));
+                        ph.push(format!("${}", binds.len()));
                     }
                     conds.push(format!(
                         "(c.server IN (SELECT name FROM servers WHERE password_hash IS NULL) OR c.server IN (SELECT server FROM guest_grants WHERE \"grant\" IN ({})))",
@@ -820,7 +1952,7 @@ pub(crate) async fn search(
                 .filter(|s| !s.is_empty())
                 .collect();
             if names.is_empty() {
-                return Ok(Json(Vec::new()));
+                return Ok(page(Vec::new(), 0));
             }
             let mut ph = Vec::with_capacity(names.len());
             for name in names {
@@ -830,6 +1962,7 @@ pub(crate) async fn search(
             conds.push(format!("c.server IN ({})", ph.join(", ")));
         }
     }
+    conds.push("m.kind != 'encrypted'".to_string());
     if let Some(text) = q.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
         binds.push(Bind::S(format!("%{}%", text.to_lowercase())));
         conds.push(format!("lower(m.content) LIKE ${}", binds.len()));
@@ -857,10 +1990,24 @@ pub(crate) async fn search(
         binds.push(Bind::I(a));
         conds.push(format!("m.id > ${}", binds.len()));
     }
-    binds.push(Bind::I(offset.unwrap_or(0).max(0)));
+    let from_where = format!(
+        "FROM messages m JOIN channels c ON c.id = m.channel_id WHERE m.deleted_at IS NULL AND {}",
+        conds.join(" AND ")
+    );
+    let count_sql = format!("SELECT COUNT(*) {from_where}");
+    let mut count = sqlx::query(&count_sql);
+    for b in &binds {
+        count = match b {
+            Bind::S(s) => count.bind(s),
+            Bind::I(i) => count.bind(*i),
+        };
+    }
+    let total: i64 = count.fetch_one(&state.db).await?.try_get(0)?;
+    binds.push(Bind::I(SEARCH_PAGE));
+    binds.push(Bind::I(offset));
     let sql = format!(
-        "SELECT {COLS}, c.server, c.name FROM messages m JOIN channels c ON c.id = m.channel_id WHERE {} ORDER BY m.id DESC LIMIT 25 OFFSET ${}",
-        conds.join(" AND "),
+        "SELECT {COLS}, c.server, c.name {from_where} ORDER BY m.id DESC LIMIT ${} OFFSET ${}",
+        binds.len() - 1,
         binds.len()
     );
     let mut query = sqlx::query(&sql);
@@ -875,7 +2022,7 @@ pub(crate) async fn search(
     let mut ctx: Vec<(String, String)> = Vec::with_capacity(rows.len());
     for r in &rows {
         msgs.push(row_message(&state.db, r).await?);
-        ctx.push((r.try_get(21)?, r.try_get(22)?));
+        ctx.push((r.try_get(28)?, r.try_get(29)?));
     }
     attach_embeds(&state.db, &mut msgs).await?;
     let out = msgs
@@ -887,7 +2034,121 @@ pub(crate) async fn search(
             channel_name,
         })
         .collect();
-    Ok(Json(out))
+    Ok(page(out, total))
+}
+
+fn snippet(content: &str, needle: &str) -> String {
+    let lower = content.to_lowercase();
+    let at = match lower.len() == content.len() {
+        true => lower.find(needle).unwrap_or(0),
+        false => 0,
+    };
+    let chars: Vec<char> = content.chars().collect();
+    let hit = content.get(..at).map_or(0, |head| head.chars().count());
+    let start = hit.saturating_sub(SNIPPET_CONTEXT);
+    let end = (hit + needle.chars().count() + SNIPPET_CONTEXT).min(chars.len());
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    out.extend(&chars[start..end]);
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+#[utoipa::path(get, path = "/api/messages/search/global", params(GlobalSearchQuery), responses((status = 200, body = GlobalSearch)), security(("bearer" = [])))]
+pub(crate) async fn search_global(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Query(gq): Query<GlobalSearchQuery>,
+) -> Result<Json<GlobalSearch>, ApiError> {
+    let needle = gq.q.trim().to_lowercase();
+    if needle.is_empty() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Query required".to_string(),
+        ));
+    }
+    let offset = gq.offset.unwrap_or(0).max(0);
+    let limit = GLOBAL_SEARCH_PAGE.min(GLOBAL_SEARCH_CAP - offset);
+    if limit <= 0 {
+        return Ok(Json(GlobalSearch {
+            groups: Vec::new(),
+            next_offset: None,
+        }));
+    }
+    let mut binds: Vec<Bind> = Vec::new();
+    let channel_conds = member_channel_conds(&state.db, &user, &mut binds).await?;
+    binds.push(Bind::S(format!("%{needle}%")));
+    let like = binds.len();
+    binds.push(Bind::I(limit));
+    binds.push(Bind::I(offset));
+    let sql = format!(
+        "SELECT m.id, m.author, m.content, m.created_at, m.channel_id, m.dm_id, c.server, c.name, d.user_a, d.user_b FROM messages m LEFT JOIN channels c ON c.id = m.channel_id LEFT JOIN dms d ON d.id = m.dm_id WHERE m.kind != 'encrypted' AND m.deleted_at IS NULL AND lower(m.content) LIKE ${like} AND ((m.channel_id IS NOT NULL AND {}) OR d.user_a = $1 OR d.user_b = $1) ORDER BY m.id DESC LIMIT ${} OFFSET ${}",
+        channel_conds.join(" AND "),
+        binds.len() - 1,
+        binds.len()
+    );
+    let mut query = sqlx::query(&sql);
+    for b in &binds {
+        query = match b {
+            Bind::S(s) => query.bind(s),
+            Bind::I(i) => query.bind(*i),
+        };
+    }
+    let rows = query.fetch_all(&state.db).await?;
+    let mut groups: Vec<SearchGroup> = Vec::new();
+    for r in &rows {
+        let channel_id: Option<i64> = r.try_get(4)?;
+        let (target_type, target_id, server, name) = match channel_id {
+            Some(id) => (
+                TargetType::Channel,
+                id,
+                r.try_get::<Option<String>, _>(6)?,
+                r.try_get::<Option<String>, _>(7)?.unwrap_or_default(),
+            ),
+            None => {
+                let user_a: String = r.try_get(8)?;
+                let user_b: String = r.try_get(9)?;
+                let peer = match user_a == user.username {
+                    true => user_b,
+                    false => user_a,
+                };
+                (TargetType::Dm, r.try_get(5)?, None, peer)
+            }
+        };
+        let content: String = r.try_get(2)?;
+        let hit = SearchHit {
+            message_id: r.try_get(0)?,
+            author: r.try_get(1)?,
+            created_at: r.try_get(3)?,
+            snippet: snippet(&content, &needle),
+        };
+        match groups
+            .iter_mut()
+            .find(|g| g.target_type == target_type && g.target_id == target_id)
+        {
+            Some(group) => group.hits.push(hit),
+            None => groups.push(SearchGroup {
+                target_type,
+                target_id,
+                server,
+                name,
+                hits: vec![hit],
+            }),
+        }
+    }
+    let next = offset + rows.len() as i64;
+    let next_offset = match rows.len() as i64 == limit && next < GLOBAL_SEARCH_CAP {
+        true => Some(next),
+        false => None,
+    };
+    Ok(Json(GlobalSearch {
+        groups,
+        next_offset,
+    }))
 }
 
 #[utoipa::path(get, path = "/api/unreads", responses((status = 200, body = Unreads)), security(("bearer" = [])))]
@@ -895,18 +2156,24 @@ pub(crate) async fn unreads(
     State(state): State<AppState>,
     Authed(user): Authed,
 ) -> Result<Json<Unreads>, ApiError> {
+    Ok(Json(Unreads {
+        items: user_unreads(&state.db, &user.username).await?,
+    }))
+}
+
+pub(crate) async fn user_unreads(db: &Db, username: &str) -> Result<Vec<Unread>, ApiError> {
     let mut items = Vec::new();
     let channel_rows = sqlx::query(
-        "SELECT 'c' || m.channel_id AS scope, MAX(m.id) AS latest, COALESCE(MAX(rs.last_read), 0) AS last_read FROM messages m JOIN channels c ON c.id = m.channel_id JOIN members mem ON mem.server = c.server AND mem.username = $1 LEFT JOIN read_state rs ON rs.username = $1 AND rs.scope = 'c' || m.channel_id WHERE c.kind = 'text' AND m.thread_root_id IS NULL GROUP BY m.channel_id",
+        "SELECT 'c' || m.channel_id AS scope, MAX(m.id) AS latest, COALESCE(MAX(rs.last_read), 0) AS last_read FROM messages m JOIN channels c ON c.id = m.channel_id JOIN members mem ON mem.server = c.server AND mem.username = $1 LEFT JOIN read_state rs ON rs.username = $1 AND rs.scope = 'c' || m.channel_id WHERE c.kind = 'text' AND m.thread_root_id IS NULL AND m.deleted_at IS NULL GROUP BY m.channel_id",
     )
-    .bind(&user.username)
-    .fetch_all(&state.db)
+    .bind(username)
+    .fetch_all(db)
     .await?;
     let dm_rows = sqlx::query(
-        "SELECT 'd' || m.dm_id AS scope, MAX(m.id) AS latest, COALESCE(MAX(rs.last_read), 0) AS last_read FROM messages m JOIN dms d ON d.id = m.dm_id LEFT JOIN read_state rs ON rs.username = $1 AND rs.scope = 'd' || m.dm_id WHERE d.user_a = $1 OR d.user_b = $1 GROUP BY m.dm_id",
+        "SELECT 'd' || m.dm_id AS scope, MAX(m.id) AS latest, COALESCE(MAX(rs.last_read), 0) AS last_read FROM messages m JOIN dms d ON d.id = m.dm_id LEFT JOIN read_state rs ON rs.username = $1 AND rs.scope = 'd' || m.dm_id WHERE (d.user_a = $1 OR d.user_b = $1) AND d.user_a <> d.user_b AND m.deleted_at IS NULL GROUP BY m.dm_id",
     )
-    .bind(&user.username)
-    .fetch_all(&state.db)
+    .bind(username)
+    .fetch_all(db)
     .await?;
     for r in channel_rows.iter().chain(dm_rows.iter()) {
         items.push(Unread {
@@ -915,7 +2182,7 @@ pub(crate) async fn unreads(
             last_read: r.try_get(2)?,
         });
     }
-    Ok(Json(Unreads { items }))
+    Ok(items)
 }
 
 #[utoipa::path(post, path = "/api/read", request_body = ReadReq, responses((status = 200, body = ReadState)), security(("bearer" = [])))]
@@ -931,15 +2198,13 @@ pub(crate) async fn mark_read(
             "Invalid scope".to_string(),
         ));
     }
-    let stored: i64 = sqlx::query(
-        "INSERT INTO read_state(username, scope, last_read) VALUES($1, $2, $3) ON CONFLICT(username, scope) DO UPDATE SET last_read = CASE WHEN excluded.last_read > read_state.last_read THEN excluded.last_read ELSE read_state.last_read END RETURNING last_read",
-    )
-    .bind(&user.username)
-    .bind(&scope)
-    .bind(last_read)
-    .fetch_one(&state.db)
-    .await?
-    .try_get(0)?;
+    let stored: i64 = sqlx::query(UPSERT_READ)
+        .bind(&user.username)
+        .bind(&scope)
+        .bind(last_read)
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
     state.hub.broadcast(WsEvent::ReadUpdated {
         username: user.username,
         scope: scope.clone(),
@@ -951,10 +2216,62 @@ pub(crate) async fn mark_read(
     }))
 }
 
+#[utoipa::path(post, path = "/api/servers/{name}/mark_read", params(("name" = String, Path)), responses((status = 200, body = ServerRead)), security(("bearer" = [])))]
+pub(crate) async fn mark_server_read(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Json<ServerRead>, ApiError> {
+    let key = name.to_lowercase();
+    crate::api::servers::require_server(&state.db, &key).await?;
+    let mut channels = crate::api::servers::visible_channels(&state.db, &user).await?;
+    let channels = match channels.remove(&key) {
+        Some(channels) => channels,
+        None => {
+            return Err(ApiError(StatusCode::FORBIDDEN, "Not a member".to_string()));
+        }
+    };
+    let mut tx = state.db.begin().await?;
+    let mut marked = Vec::new();
+    for channel in channels.iter().filter(|c| c.kind == ChannelKind::Text) {
+        let latest: Option<i64> = sqlx::query(
+            "SELECT MAX(id) FROM messages WHERE channel_id = $1 AND thread_root_id IS NULL AND deleted_at IS NULL",
+        )
+        .bind(channel.id)
+        .fetch_one(&mut *tx)
+        .await?
+        .try_get(0)?;
+        let latest = match latest {
+            Some(id) => id,
+            None => continue,
+        };
+        let scope = format!("c{}", channel.id);
+        let stored: i64 = sqlx::query(UPSERT_READ)
+            .bind(&user.username)
+            .bind(&scope)
+            .bind(latest)
+            .fetch_one(&mut *tx)
+            .await?
+            .try_get(0)?;
+        marked.push((scope, stored));
+    }
+    tx.commit().await?;
+    let updated = marked.len() as i64;
+    for (scope, last_read) in marked {
+        state.hub.broadcast(WsEvent::ReadUpdated {
+            username: user.username.clone(),
+            scope,
+            last_read,
+        });
+    }
+    Ok(Json(ServerRead { updated }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::api::test_util::{add_member, done, mem_user, temp_state};
+    use crate::rate_limit::SendLimiter;
 
     async fn general_id(db: &Db) -> i64 {
         sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
@@ -970,11 +2287,13 @@ mod tests {
             State(state.clone()),
             Path(cid),
             Authed(mem_user(name, false)),
+            HeaderMap::new(),
             Json(SendReq {
                 content: "hello".to_string(),
                 media_id: None,
                 media_spoiler: None,
                 p2p: None,
+                encryption: None,
             }),
         )
         .await
@@ -993,6 +2312,7 @@ mod tests {
             Query(PageQuery {
                 before: None,
                 limit: None,
+                include_deleted: None,
             }),
         )
         .await
@@ -1023,6 +2343,396 @@ mod tests {
         done(state, path).await;
     }
 
+    #[tokio::test]
+    async fn sequence_numbers_increase_per_channel() {
+        let (state, path) = temp_state("seq").await;
+        let db = &state.db;
+        let general = general_id(db).await;
+        let other: i64 = sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES('rchat', 'other', 1, 0) RETURNING id")
+            .fetch_one(db)
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        let names = ["amy", "bob", "cat"];
+        for name in names {
+            add_member(db, "rchat", name, 0, 0, 0).await;
+        }
+        let mut sends = tokio::task::JoinSet::new();
+        for round in 0..4 {
+            for name in names {
+                let state = state.clone();
+                let cid = [general, other][round % 2];
+                sends.spawn(async move { send(&state, cid, name).await.expect("send").0 });
+            }
+        }
+        let mut sent = sends.join_all().await;
+        assert!(sent.iter().all(|m| m.seq.is_some()));
+        sent.sort_by_key(|m| m.id);
+        for cid in [general, other] {
+            let seqs: Vec<i64> = sent
+                .iter()
+                .filter(|m| m.channel_id == Some(cid))
+                .filter_map(|m| m.seq)
+                .collect();
+            assert_eq!(seqs, (1..=6).collect::<Vec<i64>>());
+            let listed = list(&state, cid, Some("amy")).await.expect("list");
+            assert!(listed.windows(2).all(|w| w[0].seq < w[1].seq));
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn hidden_resources_look_missing() {
+        let (state, path) = temp_state("hidden").await;
+        let db = &state.db;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        let secret: i64 = sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES('rchat', 'secret', 1, 0) RETURNING id")
+            .fetch_one(db)
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        sqlx::query("INSERT INTO channel_perms(channel_id, subject, can_view, can_send, can_read_history) VALUES($1, 'u:alice', 1, 1, 1)")
+            .bind(secret)
+            .execute(db)
+            .await
+            .expect("restrict channel");
+        let did: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'alice') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("insert dm")
+                .try_get(0)
+                .expect("dm id");
+        let missing = secret + 100;
+        let shape = |r: Result<Vec<Message>, ApiError>| match r {
+            Err(ApiError(status, msg)) => (status, msg),
+            Ok(_) => panic!("hidden channel was readable"),
+        };
+        for viewer in [Some("bob"), Some("cat"), None] {
+            assert_eq!(
+                shape(list(&state, secret, viewer).await),
+                shape(list(&state, missing, viewer).await),
+            );
+        }
+        let outcome = |r: Result<Json<Message>, ApiError>| r.err().map(|ApiError(s, m)| (s, m));
+        for sender in ["bob", "cat"] {
+            let hidden = outcome(send(&state, secret, sender).await);
+            assert_eq!(
+                hidden,
+                Some((StatusCode::NOT_FOUND, "Channel not found".to_string()))
+            );
+            assert_eq!(hidden, outcome(send(&state, missing, sender).await));
+        }
+        let dm = |id| {
+            dm_messages(
+                State(state.clone()),
+                Path(id),
+                Authed(mem_user("bob", false)),
+                Query(PageQuery {
+                    before: None,
+                    limit: None,
+                    include_deleted: None,
+                }),
+            )
+        };
+        let (real, fake) = (dm(did).await, dm(did + 100).await);
+        match (real, fake) {
+            (Err(ApiError(a, x)), Err(ApiError(b, y))) => {
+                assert_eq!((a, x), (b, y));
+                assert_eq!(a, StatusCode::NOT_FOUND);
+            }
+            _ => panic!("outsider read a DM"),
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn deleted_messages_restore_within_window() {
+        let (state, path) = temp_state("restore").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        let Json(sent) = send(&state, cid, "amy").await.expect("send");
+        let mut rx = state.hub.subscribe();
+        let remove = |id| {
+            delete_message(
+                State(state.clone()),
+                Path(id),
+                Authed(mem_user("amy", false)),
+            )
+        };
+        let restore = |id, name: &str| {
+            restore_message(
+                State(state.clone()),
+                Path(id),
+                Authed(mem_user(name, false)),
+            )
+        };
+        let _ = remove(sent.id).await.expect("delete");
+        assert!(
+            list(&state, cid, Some("amy"))
+                .await
+                .expect("list")
+                .is_empty()
+        );
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::MessageDeleted { .. })));
+        let stranger = restore(sent.id, "bob").await;
+        assert!(matches!(stranger, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        let Json(back) = restore(sent.id, "amy").await.expect("restore");
+        assert_eq!(back.id, sent.id);
+        assert_eq!(list(&state, cid, Some("amy")).await.expect("list").len(), 1);
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::MessageRestored { .. })));
+        let again = restore(sent.id, "amy").await;
+        assert!(matches!(again, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let _ = remove(sent.id).await.expect("delete again");
+        sqlx::query("UPDATE messages SET deleted_at = $2 WHERE id = $1")
+            .bind(sent.id)
+            .bind(now() - RESTORE_WINDOW_SECS - 1)
+            .execute(db)
+            .await
+            .expect("age deletion");
+        let late = restore(sent.id, "amy").await;
+        assert!(matches!(late, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        assert_eq!(purge_deleted(db, now()).await.expect("kept for review"), 0);
+        assert_eq!(
+            purge_deleted(db, now() + DELETED_REVIEW_SECS)
+                .await
+                .expect("purge"),
+            1
+        );
+        let gone = restore(sent.id, "amy").await;
+        assert!(matches!(gone, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn server_mark_read_clears_unreads() {
+        let (state, path) = temp_state("server_read").await;
+        let db = &state.db;
+        let general = general_id(db).await;
+        let other: i64 = sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES('rchat', 'other', 1, 0) RETURNING id")
+            .fetch_one(db)
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES('rchat', 'quiet', 2, 0)")
+            .execute(db)
+            .await
+            .expect("insert empty channel");
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        for cid in [general, other, general] {
+            let _ = send(&state, cid, "bob").await.expect("send");
+        }
+        let unread = async || {
+            user_unreads(db, "amy")
+                .await
+                .expect("unreads")
+                .iter()
+                .filter(|u| u.latest > u.last_read)
+                .count()
+        };
+        assert_eq!(unread().await, 2);
+        let mark = |name: &str, server: &str| {
+            mark_server_read(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path(server.to_string()),
+            )
+        };
+        let mut rx = state.hub.subscribe();
+        let Json(marked) = mark("amy", "RChat").await.expect("mark read");
+        assert_eq!(marked.updated, 2);
+        assert_eq!(unread().await, 0);
+        let mut scopes = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            if let WsEvent::ReadUpdated {
+                username, scope, ..
+            } = ev
+            {
+                assert_eq!(username, "amy");
+                scopes.push(scope);
+            }
+        }
+        scopes.sort();
+        assert_eq!(scopes, vec![format!("c{general}"), format!("c{other}")]);
+        assert!(matches!(
+            mark("cat", "rchat").await,
+            Err(ApiError(StatusCode::FORBIDDEN, _))
+        ));
+        assert!(matches!(
+            mark("amy", "nowhere").await,
+            Err(ApiError(StatusCode::NOT_FOUND, _))
+        ));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn message_context_resolves_targets() {
+        let (state, path) = temp_state("context").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        let Json(sent) = send(&state, cid, "amy").await.expect("send");
+        let dm: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("open dm")
+                .try_get(0)
+                .expect("dm id");
+        let mut dm_ids = Vec::new();
+        for deleted_at in [None, Some(now())] {
+            let id: i64 = sqlx::query("INSERT INTO messages(dm_id, author, content, deleted_at, created_at) VALUES($1, 'bob', 'hi', $2, $3) RETURNING id")
+                .bind(dm)
+                .bind(deleted_at)
+                .bind(now())
+                .fetch_one(db)
+                .await
+                .expect("dm message")
+                .try_get(0)
+                .expect("message id");
+            dm_ids.push(id);
+        }
+        let context = |id, name: &str| {
+            message_context(
+                State(state.clone()),
+                Path(id),
+                Authed(mem_user(name, false)),
+                HeaderMap::new(),
+            )
+        };
+        let Json(ctx) = context(sent.id, "amy").await.expect("channel context");
+        assert_eq!(
+            (ctx.target_type, ctx.target_id, ctx.server_name.as_deref()),
+            (TargetType::Channel, cid, Some("rchat"))
+        );
+        let Json(ctx) = context(dm_ids[0], "bob").await.expect("dm context");
+        assert_eq!(
+            (ctx.target_type, ctx.target_id, ctx.server_name),
+            (TargetType::Dm, dm, None)
+        );
+        for (id, name) in [(dm_ids[0], "cat"), (dm_ids[1], "amy"), (i64::MAX, "amy")] {
+            assert!(matches!(
+                context(id, name).await,
+                Err(ApiError(StatusCode::NOT_FOUND, _))
+            ));
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn reports_are_unique_per_reporter() {
+        let (state, path) = temp_state("report").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        add_member(db, "rchat", "cat", 0, 0, 0).await;
+        let Json(sent) = send(&state, cid, "amy").await.expect("send");
+        let mut rx = state.hub.subscribe();
+        let report = |id, name: &str, reason: &str| {
+            report_message(
+                State(state.clone()),
+                Path(id),
+                Authed(mem_user(name, false)),
+                HeaderMap::new(),
+                Json(ReportReq {
+                    reason: reason.to_string(),
+                }),
+            )
+        };
+        let Json(filed) = report(sent.id, "bob", " spam ").await.expect("report");
+        assert_eq!(
+            (
+                filed.message_id,
+                filed.reporter.as_str(),
+                filed.reason.as_str()
+            ),
+            (Some(sent.id), "bob", "spam")
+        );
+        assert_eq!(
+            (filed.author.as_str(), filed.content.as_str()),
+            ("amy", sent.content.as_str())
+        );
+        assert_eq!(filed.status, ReportStatus::Open);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::MessageReported { report }) if report.id == filed.id
+        ));
+        let twice = report(sent.id, "bob", "still spam").await;
+        assert!(matches!(twice, Err(ApiError(StatusCode::CONFLICT, _))));
+        let _ = report(sent.id, "cat", "spam")
+            .await
+            .expect("second reporter");
+        let own = report(sent.id, "amy", "oops").await;
+        assert!(matches!(own, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let blank = report(sent.id, "cat", "   ").await;
+        assert!(matches!(blank, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let missing = report(sent.id + 100, "bob", "spam").await;
+        assert!(matches!(missing, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let count: i64 = sqlx::query("SELECT COUNT(*) FROM message_reports")
+            .fetch_one(db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count value");
+        assert_eq!(count, 2);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn reports_outlive_their_message() {
+        let (state, path) = temp_state("report_purge").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        let Json(sent) = send(&state, cid, "amy").await.expect("send");
+        let Json(filed) = report_message(
+            State(state.clone()),
+            Path(sent.id),
+            Authed(mem_user("bob", false)),
+            HeaderMap::new(),
+            Json(ReportReq {
+                reason: "spam".to_string(),
+            }),
+        )
+        .await
+        .expect("report");
+        let _ = delete_message(
+            State(state.clone()),
+            Path(sent.id),
+            Authed(mem_user("amy", false)),
+        )
+        .await
+        .expect("author deletes");
+        let later = now() + DELETED_REVIEW_SECS + 1;
+        assert_eq!(purge_deleted(db, later).await.expect("sweep"), 0);
+        sqlx::query("UPDATE message_reports SET status = 'resolved' WHERE id = $1")
+            .bind(filed.id)
+            .execute(db)
+            .await
+            .expect("resolve");
+        assert_eq!(purge_deleted(db, later).await.expect("sweep"), 1);
+        let row = sqlx::query(&format!(
+            "SELECT {REPORT_COLS} FROM message_reports WHERE id = $1"
+        ))
+        .bind(filed.id)
+        .fetch_one(db)
+        .await
+        .expect("report survives");
+        let kept = row_report(&row).expect("report row");
+        assert_eq!(kept.message_id, None);
+        assert_eq!(kept.author, "amy");
+        assert_eq!(kept.content, sent.content);
+        done(state, path).await;
+    }
+
     #[tokio::test]
     async fn private_channel_history() {
         let (state, path) = temp_state("history").await;
@@ -1051,9 +2761,9 @@ mod tests {
         assert_eq!(visible.len(), 1);
         assert_eq!(visible[0].content, "after");
         let bob = list(&state, cid, Some("bob")).await;
-        assert!(matches!(bob, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        assert!(matches!(bob, Err(ApiError(StatusCode::NOT_FOUND, _))));
         let guest = list(&state, cid, None).await;
-        assert!(matches!(guest, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        assert!(matches!(guest, Err(ApiError(StatusCode::NOT_FOUND, _))));
         let found = search(
             State(state.clone()),
             MaybeAuthed(Some(mem_user("alice", false))),
@@ -1072,9 +2782,964 @@ mod tests {
         )
         .await
         .expect("search")
-        .0;
+        .items;
         assert_eq!(found.len(), 1);
         assert_eq!(found[0].message.content, "after");
         done(state, path).await;
     }
+
+    #[test]
+    fn length_counts_graphemes() {
+        assert!(check_length(&"a".repeat(MAX_CONTENT_GRAPHEMES)).is_ok());
+        assert!(check_length(&"a".repeat(MAX_CONTENT_GRAPHEMES + 1)).is_err());
+        assert!(check_length(&"界".repeat(MAX_CONTENT_GRAPHEMES)).is_ok());
+        assert!(check_length(&"👍🏽".repeat(MAX_CONTENT_GRAPHEMES)).is_ok());
+        assert!(check_length(&"👍🏽".repeat(MAX_CONTENT_GRAPHEMES + 1)).is_err());
+        let family = "👨‍👩‍👧‍👦";
+        assert!(check_length(&family.repeat(1300)).is_ok());
+        assert!(check_length(&family.repeat(2000)).is_err());
+    }
+
+    #[tokio::test]
+    async fn media_attachment_checks() {
+        let (state, path) = temp_state("media_attach").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        let fresh = now();
+        for (id, uploader, uploaded_at) in [
+            ("own", "ann", fresh),
+            ("foreign", "ben", fresh),
+            ("stale", "ann", fresh - MEDIA_TTL_SECS),
+            ("gone", "ann", fresh),
+        ] {
+            sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploader, uploaded_at) VALUES($1, 'a.txt', 'text/plain', 1, $2, $3, $4)")
+                .bind(id)
+                .bind(vec![0u8])
+                .bind(uploader)
+                .bind(uploaded_at)
+                .execute(db)
+                .await
+                .expect("insert media");
+        }
+        sqlx::query("DELETE FROM media WHERE id = 'gone'")
+            .execute(db)
+            .await
+            .expect("delete media");
+        let ann = mem_user("ann", false);
+        let attach = |media_id: &str| SendReq {
+            content: String::new(),
+            media_id: Some(media_id.to_string()),
+            media_spoiler: None,
+            p2p: None,
+            encryption: None,
+        };
+        for (id, status, expected) in [
+            ("missing", StatusCode::BAD_REQUEST, "Unknown media"),
+            ("gone", StatusCode::BAD_REQUEST, "Unknown media"),
+            (
+                "foreign",
+                StatusCode::FORBIDDEN,
+                "Media was uploaded by another user",
+            ),
+            ("stale", StatusCode::BAD_REQUEST, "Media has expired"),
+        ] {
+            match draft_message(db, &ann, attach(id)).await {
+                Err(ApiError(code, msg)) => {
+                    assert_eq!(code, status, "{id}");
+                    assert_eq!(msg, expected);
+                }
+                _ => panic!("expected rejection for {id}"),
+            }
+        }
+        let sent = insert_message(
+            db,
+            Some(cid),
+            None,
+            None,
+            &ann,
+            draft_message(db, &ann, attach("own"))
+                .await
+                .expect("own media"),
+            FilterStatus::Clean,
+            None,
+            None,
+        )
+        .await
+        .expect("own media")
+        .into_message();
+        assert_eq!(sent.media.map(|m| m.id), Some("own".to_string()));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn self_dm_is_private_notes() {
+        let (state, path) = temp_state("self_dm").await;
+        crate::api::test_util::self_dm(&state.db, "amy").await;
+        let dms = crate::api::dms::user_dms(&state.db, &state.hub, "amy", &Default::default())
+            .await
+            .expect("list dms");
+        assert_eq!(dms.len(), 1);
+        assert!(dms[0].is_self);
+        let id = dms[0].id;
+        let mut rx = state.hub.subscribe();
+        let Json(sent) = send_dm_message(
+            State(state.clone()),
+            Path(id),
+            Authed(mem_user("amy", false)),
+            HeaderMap::new(),
+            Json(SendReq {
+                content: "note to self".to_string(),
+                media_id: None,
+                media_spoiler: None,
+                p2p: None,
+                encryption: None,
+            }),
+        )
+        .await
+        .expect("self dm send");
+        assert_eq!(sent.dm_id, Some(id));
+        match rx.try_recv() {
+            Ok(WsEvent::Message {
+                server: _,
+                channel_id: _,
+                dm_id,
+                dm_users,
+                message: _,
+                muted: _,
+                mentioned: _,
+            }) => {
+                assert_eq!(dm_id, Some(id));
+                assert_eq!(dm_users, Some(vec!["amy".to_string()]));
+            }
+            _ => panic!("expected a message event"),
+        }
+        let Json(unread) = unreads(State(state.clone()), Authed(mem_user("amy", false)))
+            .await
+            .expect("unreads");
+        assert!(unread.items.iter().all(|u| u.scope != format!("d{id}")));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn retention_purges_old_messages() {
+        let (state, path) = temp_state("retention").await;
+        let db = &state.db;
+        let keep = general_id(db).await;
+        sqlx::query("UPDATE channels SET message_retention_hours = $1 WHERE id = $2")
+            .bind(i64::MAX)
+            .bind(keep)
+            .execute(db)
+            .await
+            .expect("overflowing retention");
+        let purge: i64 = sqlx::query(
+            "INSERT INTO channels(server, name, message_retention_hours, created_at) VALUES('rchat', 'ephemeral', 1, 0) RETURNING id",
+        )
+        .fetch_one(db)
+        .await
+        .expect("insert channel")
+        .try_get(0)
+        .expect("channel id");
+        let mut old = Vec::new();
+        for cid in [keep, purge] {
+            for created_at in [now() - 7200, now()] {
+                let id: i64 = sqlx::query(
+                    "INSERT INTO messages(channel_id, author, content, created_at) VALUES($1, 'amy', 'hi', $2) RETURNING id",
+                )
+                .bind(cid)
+                .bind(created_at)
+                .fetch_one(db)
+                .await
+                .expect("insert message")
+                .try_get(0)
+                .expect("message id");
+                if cid == purge && created_at < now() - 3600 {
+                    old.push(id);
+                }
+            }
+        }
+        let reply: i64 = sqlx::query(
+            "INSERT INTO messages(channel_id, thread_root_id, author, content, created_at) VALUES($1, $2, 'bob', 'late reply', $3) RETURNING id",
+        )
+        .bind(purge)
+        .bind(old[0])
+        .bind(now())
+        .fetch_one(db)
+        .await
+        .expect("insert reply")
+        .try_get(0)
+        .expect("reply id");
+        let events = purge_expired(db).await.expect("purge");
+        match events.as_slice() {
+            [
+                WsEvent::MessagesPurged {
+                    server,
+                    channel_id,
+                    ids,
+                },
+            ] => {
+                assert_eq!((server.as_str(), *channel_id), ("rchat", purge));
+                let mut ids = ids.clone();
+                ids.sort_unstable();
+                assert_eq!(ids, vec![old[0], reply]);
+            }
+            _ => panic!("expected one purge event"),
+        }
+        for (cid, expected) in [(keep, 2), (purge, 1)] {
+            let count: i64 = sqlx::query("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+                .bind(cid)
+                .fetch_one(db)
+                .await
+                .expect("count")
+                .try_get(0)
+                .expect("count value");
+            assert_eq!(count, expected);
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn idempotency_key_dedupes_sends() {
+        let (state, path) = temp_state("idempotency").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        let mut headers = HeaderMap::new();
+        headers.insert("idempotency-key", "retry-1".parse().expect("header"));
+        let send = || {
+            send_channel_message(
+                State(state.clone()),
+                Path(cid),
+                Authed(mem_user("amy", false)),
+                headers.clone(),
+                Json(SendReq {
+                    content: "once".to_string(),
+                    media_id: None,
+                    media_spoiler: None,
+                    p2p: None,
+                    encryption: None,
+                }),
+            )
+        };
+        let Json(first) = send().await.expect("first send");
+        let Json(second) = send().await.expect("retry");
+        assert_eq!(first.id, second.id);
+        let thread = send_thread_message(
+            State(state.clone()),
+            Path(first.id),
+            Authed(mem_user("amy", false)),
+            headers.clone(),
+            Json(SendReq {
+                content: "elsewhere".to_string(),
+                media_id: None,
+                media_spoiler: None,
+                p2p: None,
+                encryption: None,
+            }),
+        )
+        .await;
+        assert!(matches!(thread, Err(ApiError(StatusCode::CONFLICT, _))));
+        let raced = insert_message(
+            db,
+            Some(cid),
+            None,
+            None,
+            &mem_user("amy", false),
+            Draft {
+                content: "racing".to_string(),
+                media: None,
+                encryption: None,
+            },
+            FilterStatus::Clean,
+            None,
+            IdemKey::new(Some("retry-1".to_string()), format!("c{cid}")),
+        )
+        .await
+        .expect("lost race");
+        assert!(matches!(raced, Stored::Replayed(m) if m.id == first.id));
+        let count: i64 = sqlx::query("SELECT COUNT(*) FROM messages WHERE channel_id = $1")
+            .bind(cid)
+            .fetch_one(db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count value");
+        assert_eq!(count, 1);
+        sqlx::query("UPDATE idempotency_keys SET created_at = $1")
+            .bind(now() - IDEMPOTENCY_WINDOW_SECS)
+            .execute(db)
+            .await
+            .expect("age key");
+        let Json(third) = send().await.expect("after window");
+        assert_ne!(third.id, first.id);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn channel_send_refreshes_last_seen() {
+        let (state, path) = temp_state("send_seen").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at, last_seen) VALUES('amy', 'amy', 'x', 'text', 'identicon', 0, 0, 5)")
+            .execute(db)
+            .await
+            .expect("insert user");
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        let before = now();
+        let _ = send_channel_message(
+            State(state.clone()),
+            Path(cid),
+            Authed(mem_user("amy", false)),
+            HeaderMap::new(),
+            Json(SendReq {
+                content: "still here".to_string(),
+                media_id: None,
+                media_spoiler: None,
+                p2p: None,
+                encryption: None,
+            }),
+        )
+        .await
+        .expect("send");
+        let seen: Option<i64> = sqlx::query("SELECT last_seen FROM users WHERE username = 'amy'")
+            .fetch_one(db)
+            .await
+            .expect("user")
+            .try_get(0)
+            .expect("last_seen");
+        assert!(seen.is_some_and(|t| t >= before));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn everyone_mentions_need_permission() {
+        let (state, path) = temp_state("mass_mention").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "boss", 1, 0, 0).await;
+        assert_eq!(
+            mass_mention_in("hey @here, and @everyone!"),
+            Some(MassMention::Everyone)
+        );
+        assert_eq!(mass_mention_in("@Here now"), Some(MassMention::Here));
+        assert_eq!(mass_mention_in("mail@everyone.example @hereby"), None);
+        let send = |who: &str, content: &str| {
+            send_channel_message(
+                State(state.clone()),
+                Path(cid),
+                Authed(mem_user(who, false)),
+                HeaderMap::new(),
+                Json(SendReq {
+                    content: content.to_string(),
+                    media_id: None,
+                    media_spoiler: None,
+                    p2p: None,
+                    encryption: None,
+                }),
+            )
+        };
+        let Json(plain) = send("amy", "@everyone look").await.expect("member send");
+        assert_eq!(plain.content, "@everyone look");
+        assert_eq!(plain.mass_mention, None);
+        let Json(loud) = send("boss", "@everyone look").await.expect("admin send");
+        assert_eq!(loud.mass_mention, Some(MassMention::Everyone));
+        let stored = list(&state, cid, Some("amy")).await.expect("list");
+        assert_eq!(
+            stored.iter().map(|m| m.mass_mention).collect::<Vec<_>>(),
+            [None, Some(MassMention::Everyone)]
+        );
+
+        let req = SendReq {
+            content: "@here".to_string(),
+            media_id: None,
+            media_spoiler: None,
+            p2p: None,
+            encryption: None,
+        };
+        let rejected = mass_mention_gate(db, "rchat", &mem_user("amy", false), &req, true).await;
+        assert!(matches!(rejected, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        let allowed = mass_mention_gate(db, "rchat", &mem_user("boss", false), &req, true)
+            .await
+            .expect("admin may ping");
+        assert_eq!(allowed, Some(MassMention::Here));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn text_only_channel_rejects_attachments() {
+        let (state, path) = temp_state("content_types").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        sqlx::query("UPDATE channels SET allowed_content_types = 'text' WHERE id = $1")
+            .bind(cid)
+            .execute(db)
+            .await
+            .expect("restrict channel");
+        sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploader, uploaded_at) VALUES('doc', 'a.txt', 'text/plain', 1, $1, 'amy', $2)")
+            .bind(vec![0u8])
+            .bind(now())
+            .execute(db)
+            .await
+            .expect("insert media");
+        let send = |media_id: Option<&str>| {
+            send_channel_message(
+                State(state.clone()),
+                Path(cid),
+                Authed(mem_user("amy", false)),
+                HeaderMap::new(),
+                Json(SendReq {
+                    content: "see attached".to_string(),
+                    media_id: media_id.map(str::to_string),
+                    media_spoiler: None,
+                    p2p: None,
+                    encryption: None,
+                }),
+            )
+        };
+        let rejected = send(Some("doc")).await;
+        assert!(matches!(
+            rejected,
+            Err(ApiError(StatusCode::BAD_REQUEST, ref message)) if message.contains("attachment")
+        ));
+        let _ = send(None).await.expect("plain text is allowed");
+        let stored = list(&state, cid, Some("amy")).await.expect("list");
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].media.is_none());
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn encrypted_message_round_trips() {
+        let (state, path) = temp_state("encrypted").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        let payload = "kill yourself <b>not markdown</b> https://example.com";
+        let cipher = "c2VjcmV0IG1lc3NhZ2UgdGhhdCBub2JvZHkgY2FuIHJlYWQh";
+        let send = |content: &str, encryption: Option<Encryption>| {
+            send_channel_message(
+                State(state.clone()),
+                Path(cid),
+                Authed(mem_user("amy", false)),
+                HeaderMap::new(),
+                Json(SendReq {
+                    content: content.to_string(),
+                    media_id: None,
+                    media_spoiler: None,
+                    p2p: None,
+                    encryption,
+                }),
+            )
+        };
+        let plain = send(payload, None).await;
+        assert!(matches!(plain, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let enc = || {
+            Some(Encryption {
+                key_id: "k1".to_string(),
+                algorithm: "aes-256-gcm".to_string(),
+            })
+        };
+        for fake in [
+            payload,
+            "AAAA",
+            "c2VjcmV0IG1lc3NhZ2UgdGhhdCBub2JvZHkgY2FuIHJlYWQ",
+        ] {
+            let skipped = send(fake, enc()).await;
+            assert!(matches!(skipped, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        }
+        let Json(sent) = send(cipher, enc()).await.expect("encrypted send");
+        assert_eq!(sent.kind, "encrypted");
+        assert_eq!(sent.filter_status, FilterStatus::Clean);
+        let stored = list(&state, cid, Some("amy")).await.expect("list");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].content, cipher);
+        let enc = stored[0].encryption.as_ref().expect("encryption metadata");
+        assert_eq!(
+            (enc.key_id.as_str(), enc.algorithm.as_str()),
+            ("k1", "aes-256-gcm")
+        );
+        let Paged { items: hits, .. } = search(
+            State(state.clone()),
+            MaybeAuthed(Some(mem_user("amy", false))),
+            HeaderMap::new(),
+            Query(SearchQuery {
+                q: Some("c2VjcmV0".to_string()),
+                server: None,
+                channel_id: None,
+                from: None,
+                has: None,
+                before: None,
+                after: None,
+                offset: None,
+                servers: None,
+            }),
+        )
+        .await
+        .expect("search");
+        assert!(hits.is_empty());
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn send_rate_limit_spans_scopes() {
+        let (mut state, path) = temp_state("send_rate").await;
+        state.sends = SendLimiter::new(2, std::time::Duration::from_secs(60));
+        let cid = general_id(&state.db).await;
+        add_member(&state.db, "rchat", "amy", 0, 0, 0).await;
+        add_member(&state.db, "rchat", "bob", 0, 0, 0).await;
+        let did: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(&state.db)
+                .await
+                .expect("insert dm")
+                .try_get(0)
+                .expect("dm id");
+        let req = || {
+            Json(SendReq {
+                content: "hi".to_string(),
+                media_id: None,
+                media_spoiler: None,
+                p2p: None,
+                encryption: None,
+            })
+        };
+        let amy = || Authed(mem_user("amy", false));
+        for _ in 0..2 {
+            let empty = send_dm_message(
+                State(state.clone()),
+                Path(did),
+                amy(),
+                HeaderMap::new(),
+                Json(SendReq {
+                    content: " ".to_string(),
+                    media_id: None,
+                    media_spoiler: None,
+                    p2p: None,
+                    encryption: None,
+                }),
+            )
+            .await;
+            assert!(matches!(empty, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        }
+        let _ = send(&state, cid, "amy").await.expect("channel send");
+        let _ = send_dm_message(
+            State(state.clone()),
+            Path(did),
+            amy(),
+            HeaderMap::new(),
+            req(),
+        )
+        .await
+        .expect("dm send");
+        let third = send_dm_message(
+            State(state.clone()),
+            Path(did),
+            amy(),
+            HeaderMap::new(),
+            req(),
+        )
+        .await;
+        assert!(matches!(
+            third,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        let _ = send(&state, cid, "bob")
+            .await
+            .expect("bob has a separate budget");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn dm_carries_attachment() {
+        let (state, path) = temp_state("dm_media").await;
+        let db = &state.db;
+        let did: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("insert dm")
+                .try_get(0)
+                .expect("dm id");
+        sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploader, uploaded_at) VALUES('pic', 'a.png', 'image/png', 1, $1, 'amy', $2)")
+            .bind(vec![0u8])
+            .bind(now())
+            .execute(db)
+            .await
+            .expect("insert media");
+        let mut rx = state.hub.subscribe();
+        let Json(sent) = send_dm_message(
+            State(state.clone()),
+            Path(did),
+            Authed(mem_user("amy", false)),
+            HeaderMap::new(),
+            Json(SendReq {
+                content: "look".to_string(),
+                media_id: Some("pic".to_string()),
+                media_spoiler: None,
+                p2p: None,
+                encryption: None,
+            }),
+        )
+        .await
+        .expect("dm send");
+        assert_eq!(sent.dm_id, Some(did));
+        let event_media = match rx.try_recv() {
+            Ok(WsEvent::Message {
+                server: _,
+                channel_id: _,
+                dm_id: _,
+                dm_users: _,
+                message,
+                muted: _,
+                mentioned: _,
+            }) => message.media.expect("event attachment"),
+            _ => panic!("expected a message event"),
+        };
+        assert_eq!(
+            (
+                event_media.id.as_str(),
+                event_media.size,
+                event_media.mime.as_deref()
+            ),
+            ("pic", Some(1), Some("image/png"))
+        );
+        let Json(page) = dm_messages(
+            State(state.clone()),
+            Path(did),
+            Authed(mem_user("bob", false)),
+            Query(PageQuery {
+                before: None,
+                limit: None,
+                include_deleted: None,
+            }),
+        )
+        .await
+        .expect("dm page");
+        let media = page[0].media.as_ref().expect("attachment");
+        assert_eq!(
+            (media.id.as_str(), media.filename.as_str()),
+            ("pic", "a.png")
+        );
+        assert_eq!(
+            (media.size, media.mime.as_deref()),
+            (event_media.size, event_media.mime.as_deref())
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn forward_between_channel_and_dm() {
+        let (state, path) = temp_state("forward").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        let did: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("insert dm")
+                .try_get(0)
+                .expect("dm id");
+        let forward = |id: i64, user: &str, target: serde_json::Value| {
+            forward_message(
+                State(state.clone()),
+                Path(id),
+                Authed(mem_user(user, false)),
+                HeaderMap::new(),
+                Json(serde_json::from_value(target).expect("forward req")),
+            )
+        };
+        let Json(original) = send(&state, cid, "amy").await.expect("channel send");
+        let Json(to_dm) = forward(original.id, "amy", serde_json::json!({"dm_id": did}))
+            .await
+            .expect("channel to dm");
+        assert_eq!(to_dm.dm_id, Some(did));
+        assert_eq!(to_dm.content, original.content);
+        assert_eq!(to_dm.forwarded_from, Some(original.id));
+        let Json(from_bob) = send_dm_message(
+            State(state.clone()),
+            Path(did),
+            Authed(mem_user("bob", false)),
+            HeaderMap::new(),
+            Json(SendReq {
+                content: "psst".to_string(),
+                media_id: None,
+                media_spoiler: None,
+                p2p: None,
+                encryption: None,
+            }),
+        )
+        .await
+        .expect("dm send");
+        let Json(to_channel) = forward(from_bob.id, "amy", serde_json::json!({"channel_id": cid}))
+            .await
+            .expect("dm to channel");
+        assert_eq!(to_channel.channel_id, Some(cid));
+        assert_eq!(to_channel.author.username, "amy");
+        assert_eq!(to_channel.forwarded_from, Some(from_bob.id));
+        let denied = forward(from_bob.id, "cat", serde_json::json!({"channel_id": cid})).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let denied = forward(original.id, "bob", serde_json::json!({"channel_id": cid})).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        sqlx::query("DELETE FROM messages WHERE id = $1")
+            .bind(original.id)
+            .execute(db)
+            .await
+            .expect("delete original");
+        let denied = forward(original.id, "amy", serde_json::json!({"dm_id": did})).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let copy = load_message(db, to_dm.id)
+            .await
+            .expect("load copy")
+            .expect("copy kept");
+        assert_eq!(copy.forwarded_from, None);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn global_search_stays_in_own_conversations() {
+        let (state, path) = temp_state("global_search").await;
+        let db = &state.db;
+        let general = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        sqlx::query(
+            "INSERT INTO servers(name, display_name, created_at) VALUES('hidden', 'hidden', 0)",
+        )
+        .execute(db)
+        .await
+        .expect("insert server");
+        let channel = |server: &'static str, name: &'static str| {
+            sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES($1, $2, 0, 0) RETURNING id")
+                .bind(server)
+                .bind(name)
+                .fetch_one(db)
+        };
+        let other: i64 = channel("hidden", "general")
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        let private: i64 = channel("rchat", "staff")
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        sqlx::query(
+            "INSERT INTO channel_perms(channel_id, subject, can_view) VALUES($1, 'u:root', 1)",
+        )
+        .bind(private)
+        .execute(db)
+        .await
+        .expect("restrict channel");
+        let dm = |a: &'static str, b: &'static str| {
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES($1, $2) RETURNING id")
+                .bind(a)
+                .bind(b)
+                .fetch_one(db)
+        };
+        let mine: i64 = dm("amy", "bob")
+            .await
+            .expect("insert dm")
+            .try_get(0)
+            .expect("dm id");
+        let theirs: i64 = dm("bob", "cat")
+            .await
+            .expect("insert dm")
+            .try_get(0)
+            .expect("dm id");
+        for (channel_id, dm_id) in [
+            (Some(general), None),
+            (Some(general), None),
+            (Some(other), None),
+            (Some(private), None),
+            (None, Some(mine)),
+            (None, Some(theirs)),
+        ] {
+            sqlx::query("INSERT INTO messages(channel_id, dm_id, author, content, created_at) VALUES($1, $2, 'bob', 'the Needle is here', 0)")
+                .bind(channel_id)
+                .bind(dm_id)
+                .execute(db)
+                .await
+                .expect("insert message");
+        }
+        let run = |q: &str, offset: Option<i64>| {
+            search_global(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Query(GlobalSearchQuery {
+                    q: q.to_string(),
+                    offset,
+                }),
+            )
+        };
+        let Json(found) = run("needle", None).await.expect("search");
+        let targets: Vec<(TargetType, i64, usize)> = found
+            .groups
+            .iter()
+            .map(|g| (g.target_type, g.target_id, g.hits.len()))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![(TargetType::Dm, mine, 1), (TargetType::Channel, general, 2)]
+        );
+        assert_eq!(found.groups[0].name, "bob");
+        assert_eq!(found.groups[1].server.as_deref(), Some("rchat"));
+        assert_eq!(found.groups[1].hits[0].snippet, "the Needle is here");
+        assert_eq!(found.next_offset, None);
+        let Json(past) = run("needle", Some(GLOBAL_SEARCH_CAP))
+            .await
+            .expect("search");
+        assert!(past.groups.is_empty());
+        assert!(matches!(
+            run("  ", None).await,
+            Err(ApiError(StatusCode::BAD_REQUEST, _))
+        ));
+        assert_eq!(
+            snippet(&format!("{}needle", "x".repeat(50)), "needle")
+                .chars()
+                .next(),
+            Some('…')
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn attachment_can_stand_in_for_text() {
+        let (state, path) = temp_state("attachment_only").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "ann", 0, 0, now()).await;
+        for id in ["pic", "pic2"] {
+            sqlx::query("INSERT INTO media(id, filename, mime, size, data, uploader, uploaded_at) VALUES($1, 'a.png', 'image/png', 1, $2, 'ann', $3)")
+                .bind(id)
+                .bind(vec![0u8])
+                .bind(now())
+                .execute(db)
+                .await
+                .expect("insert media");
+        }
+        let post = |content: &str, media_id: Option<&str>| {
+            send_channel_message(
+                State(state.clone()),
+                Path(cid),
+                Authed(mem_user("ann", false)),
+                HeaderMap::new(),
+                Json(SendReq {
+                    content: content.to_string(),
+                    media_id: media_id.map(str::to_string),
+                    media_spoiler: None,
+                    p2p: None,
+                    encryption: None,
+                }),
+            )
+        };
+        let Json(bare) = post("", Some("pic")).await.expect("image only");
+        assert_eq!(bare.content, "");
+        assert_eq!(bare.media.map(|m| m.id), Some("pic".to_string()));
+        let Json(captioned) = post("look at this", Some("pic2"))
+            .await
+            .expect("caption and image");
+        assert_eq!(captioned.content, "look at this");
+        assert!(captioned.media.is_some());
+        for content in ["", "  \n\t "] {
+            match post(content, None).await {
+                Err(ApiError(StatusCode::BAD_REQUEST, msg)) => assert_eq!(msg, "Message is empty"),
+                _ => panic!("expected empty message rejection for {content:?}"),
+            }
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn moderators_can_list_deleted_messages() {
+        let (state, path) = temp_state("include_deleted").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "mod", 1, Perm::DeleteMessages as i64, 0).await;
+        let Json(sent) = send(&state, cid, "amy").await.expect("send");
+        let _ = delete_message(
+            State(state.clone()),
+            Path(sent.id),
+            Authed(mem_user("amy", false)),
+        )
+        .await
+        .expect("delete");
+        let fetch = |name: &str, include_deleted: Option<bool>| {
+            channel_messages(
+                State(state.clone()),
+                MaybeAuthed(Some(mem_user(name, false))),
+                HeaderMap::new(),
+                Path(cid),
+                Query(PageQuery {
+                    before: None,
+                    limit: None,
+                    include_deleted,
+                }),
+            )
+        };
+        let Json(plain) = fetch("mod", None).await.expect("plain list");
+        assert!(plain.iter().all(|m| m.id != sent.id));
+        let Json(all) = fetch("mod", Some(true)).await.expect("moderator list");
+        let found = all
+            .iter()
+            .find(|m| m.id == sent.id)
+            .expect("deleted message");
+        assert_eq!(found.content, "hello");
+        assert!(found.deleted_at.is_some());
+        let denied = fetch("amy", Some(true)).await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn gallery_lists_only_live_media() {
+        let (state, path) = temp_state("gallery").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        let attach = |file: &'static str,
+                      mime: &'static str,
+                      removed: i64,
+                      deleted: Option<i64>| async move {
+            sqlx::query("INSERT INTO messages(channel_id, author, content, media_id, media_filename, media_mime, media_removed, deleted_at, created_at) VALUES($1, 'amy', '', $2, $2, $3, $4, $5, $6) RETURNING id")
+                .bind(cid)
+                .bind(file)
+                .bind(mime)
+                .bind(removed)
+                .bind(deleted)
+                .bind(now())
+                .fetch_one(db)
+                .await
+                .expect("insert message")
+                .try_get::<i64, _>(0)
+                .expect("id")
+        };
+        let photo = attach("photo.png", "image/png", 0, None).await;
+        attach("notes.txt", "text/plain", 0, None).await;
+        attach("old.mp3", "audio/mpeg", 1, None).await;
+        attach("gone.mp4", "video/mp4", 0, Some(now())).await;
+        let clip = attach("clip.mp4", "video/mp4", 0, None).await;
+        let _ = send(&state, cid, "amy").await.expect("plain message");
+
+        let gallery = |name: &str| {
+            channel_media(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                HeaderMap::new(),
+                Path(cid),
+                Query(GalleryQuery {
+                    before: None,
+                    limit: None,
+                }),
+            )
+        };
+        let Json(items) = gallery("amy").await.expect("gallery");
+        let ids: Vec<i64> = items.iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec![clip, photo]);
+        let outsider = gallery("eve").await;
+        assert!(matches!(outsider, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        done(state, path).await;
+    }
 }
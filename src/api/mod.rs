@@ -1,27 +1,56 @@
+pub mod activity;
 pub mod admin;
 pub mod auth;
+pub mod bootstrap;
 pub mod dms;
 pub mod embeds;
+pub mod export;
 pub mod media;
 pub mod messages;
+pub mod moderation;
 pub mod openapi;
 pub mod servers;
+pub mod stats;
+pub mod uploads;
 
 use axum::Router;
 use axum::extract::{DefaultBodyLimit, FromRequestParts, Request, State};
 use axum::http::request::Parts;
-use axum::http::{HeaderMap, StatusCode, header};
+use axum::http::{HeaderMap, Method, StatusCode, header};
 use axum::middleware::{Next, from_fn_with_state};
 use axum::response::{IntoResponse, Response};
-use axum::routing::{delete, get, patch, post};
-use rustrict::CensorStr;
+use axum::routing::{delete, get, patch, post, put};
+use rustrict::{Censor, CensorStr, Type};
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
 use utoipa::ToSchema;
 
-use crate::db::{AvatarKind, ChannelKind, Db, MediaKind, User, get_user, setting_on};
+use crate::config::AppConfig;
+use crate::db::{
+    AvatarKind, ChannelKind, ContentType, Db, FilterStatus, MassMention, MediaKind, ReportStatus,
+    User, get_user, now, setting_on,
+};
 use crate::state::AppState;
+use crate::ws::PresenceStatus;
+
+#[derive(Serialize, ToSchema)]
+pub struct OkResp {
+    pub ok: bool,
+}
+
+pub(crate) fn ok() -> axum::Json<OkResp> {
+    axum::Json(OkResp { ok: true })
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
 
 #[derive(Debug)]
 pub struct ApiError(pub StatusCode, pub String);
@@ -29,7 +58,12 @@ pub struct ApiError(pub StatusCode, pub String);
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let ApiError(status, error) = self;
-        (status, axum::Json(serde_json::json!({ "error": error }))).into_response()
+        let body = ErrorBody {
+            error: &error,
+            field: None,
+            code: None,
+        };
+        (status, axum::Json(body)).into_response()
     }
 }
 
@@ -45,12 +79,112 @@ impl From<sqlx::Error> for ApiError {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub(crate) fn new(field: &'static str, code: &'static str, message: impl Into<String>) -> Self {
+        FieldError {
+            field,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<FieldError> for ApiError {
+    fn from(e: FieldError) -> ApiError {
+        ApiError(StatusCode::BAD_REQUEST, e.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum FormError {
+    Field(FieldError),
+    Api(ApiError),
+}
+
+impl IntoResponse for FormError {
+    fn into_response(self) -> Response {
+        match self {
+            FormError::Field(FieldError {
+                field,
+                code,
+                message,
+            }) => {
+                let body = ErrorBody {
+                    error: &message,
+                    field: Some(field),
+                    code: Some(code),
+                };
+                (StatusCode::BAD_REQUEST, axum::Json(body)).into_response()
+            }
+            FormError::Api(e) => e.into_response(),
+        }
+    }
+}
+
+impl From<FieldError> for FormError {
+    fn from(e: FieldError) -> FormError {
+        FormError::Field(e)
+    }
+}
+
+impl From<ApiError> for FormError {
+    fn from(e: ApiError) -> FormError {
+        FormError::Api(e)
+    }
+}
+
+impl From<sqlx::Error> for FormError {
+    fn from(e: sqlx::Error) -> FormError {
+        FormError::Api(e.into())
+    }
+}
+
+impl From<anyhow::Error> for FormError {
+    fn from(e: anyhow::Error) -> FormError {
+        FormError::Api(e.into())
+    }
+}
+
+pub const TOTAL_COUNT: &str = "x-total-count";
+pub const PAGE_LIMIT: &str = "x-limit";
+pub const PAGE_OFFSET: &str = "x-offset";
+
+pub struct Paged<T> {
+    pub items: T,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl<T: Serialize> IntoResponse for Paged<T> {
+    fn into_response(self) -> Response {
+        let mut resp = axum::Json(self.items).into_response();
+        for (name, value) in [
+            (TOTAL_COUNT, self.total),
+            (PAGE_LIMIT, self.limit),
+            (PAGE_OFFSET, self.offset),
+        ] {
+            resp.headers_mut()
+                .insert(header::HeaderName::from_static(name), value.into());
+        }
+        resp
+    }
+}
+
 #[derive(Clone, Serialize, ToSchema)]
 pub struct UserRef {
     pub username: String,
     pub display_name: String,
     pub avatar_kind: AvatarKind,
     pub avatar_color: Option<String>,
+    pub avatar_url: Option<String>,
 }
 
 impl UserRef {
@@ -60,19 +194,31 @@ impl UserRef {
             display_name: user.display_name.clone(),
             avatar_kind: user.avatar_kind,
             avatar_color: user.avatar_color.clone(),
+            avatar_url: avatar_url(user),
         }
     }
-}
 
-pub async fn user_ref(db: &Db, username: &str) -> UserRef {
-    match get_user(db, username).await {
-        Ok(Some(user)) => UserRef::from_user(&user),
-        _ => UserRef {
+    pub fn unknown(username: &str) -> UserRef {
+        UserRef {
             username: username.to_string(),
             display_name: username.to_string(),
             avatar_kind: AvatarKind::Color,
             avatar_color: Some("#9e9e9e".to_string()),
-        },
+            avatar_url: None,
+        }
+    }
+}
+
+pub(crate) fn avatar_url(user: &User) -> Option<String> {
+    user.avatar_media_id
+        .as_ref()
+        .map(|id| format!("/api/media/{id}"))
+}
+
+pub async fn user_ref(db: &Db, username: &str) -> UserRef {
+    match get_user(db, username).await {
+        Ok(Some(user)) => UserRef::from_user(&user),
+        _ => UserRef::unknown(username),
     }
 }
 
@@ -98,6 +244,8 @@ pub struct Channel {
     pub name: String,
     pub kind: ChannelKind,
     pub slowmode_seconds: i64,
+    pub message_retention_hours: Option<i64>,
+    pub allowed_content_types: Option<Vec<ContentType>>,
 }
 
 #[derive(Clone, Serialize, ToSchema)]
@@ -123,6 +271,8 @@ pub struct Member {
     pub is_admin: bool,
     pub is_creator: bool,
     pub online: bool,
+    pub status: PresenceStatus,
+    pub last_seen: Option<i64>,
     pub perms: i64,
     pub role_ids: Vec<i64>,
 }
@@ -133,6 +283,7 @@ pub struct ServerDetail {
     pub display_name: String,
     pub creator: Option<String>,
     pub has_password: bool,
+    pub welcome_message: Option<String>,
     pub channels: Vec<Channel>,
     pub roles: Vec<Role>,
     pub member_count: i64,
@@ -144,6 +295,9 @@ pub struct DmSummary {
     pub id: i64,
     pub other: UserRef,
     pub is_self: bool,
+    pub pinned: bool,
+    pub muted: bool,
+    pub status: PresenceStatus,
 }
 
 #[derive(Clone, Serialize, ToSchema)]
@@ -161,15 +315,22 @@ pub struct MediaRef {
 }
 
 impl MediaRef {
-    pub fn server(id: String, filename: String, spoiler: bool, expires_at: i64) -> MediaRef {
+    pub fn server(
+        id: String,
+        filename: String,
+        spoiler: bool,
+        expires_at: i64,
+        size: i64,
+        mime: String,
+    ) -> MediaRef {
         MediaRef {
             id,
             filename,
             kind: MediaKind::Server,
             hoster: None,
             expires_at: Some(expires_at),
-            size: None,
-            mime: None,
+            size: Some(size),
+            mime: Some(mime),
             removed: false,
             removed_by_author: false,
             spoiler,
@@ -196,6 +357,12 @@ pub struct CallLog {
     pub outcome: Option<String>,
 }
 
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct Encryption {
+    pub key_id: String,
+    pub algorithm: String,
+}
+
 #[derive(Clone, Serialize, ToSchema)]
 pub struct Message {
     pub id: i64,
@@ -210,6 +377,26 @@ pub struct Message {
     pub embeds: Vec<Embed>,
     pub kind: String,
     pub call: Option<CallLog>,
+    pub filter_status: FilterStatus,
+    pub forwarded_from: Option<i64>,
+    pub encryption: Option<Encryption>,
+    pub seq: Option<i64>,
+    pub mass_mention: Option<MassMention>,
+    pub deleted_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct MessageReport {
+    pub id: i64,
+    pub message_id: Option<i64>,
+    pub author: String,
+    pub content: String,
+    pub reporter: String,
+    pub reason: String,
+    pub status: ReportStatus,
+    pub created_at: i64,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<i64>,
 }
 
 #[derive(Clone, Copy, Serialize, ToSchema)]
@@ -218,15 +405,23 @@ pub struct Settings {
     pub asset_previews: bool,
     pub asset_uploads: bool,
     pub guests_enabled: bool,
+    pub max_upload_mb: usize,
+    pub servers_admin_only: bool,
+    pub registration_open: bool,
+    pub max_servers_per_user: Option<i64>,
 }
 
 impl Settings {
-    pub async fn load(db: &Db) -> Settings {
+    pub async fn load(db: &Db, config: &AppConfig) -> Settings {
         Settings {
             profanity_filter: setting_on(db, "profanity_filter").await,
             asset_previews: setting_on(db, "asset_previews").await,
             asset_uploads: setting_on(db, "asset_uploads").await,
             guests_enabled: setting_on(db, "guests_enabled").await,
+            max_upload_mb: config.max_upload_mb,
+            servers_admin_only: servers::creation_policy(config).admins_only,
+            registration_open: config.registration_open,
+            max_servers_per_user: servers::server_cap(db, config).await,
         }
     }
 }
@@ -326,6 +521,26 @@ pub fn valid_color(color: &str) -> bool {
     color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
 }
 
+pub fn normalize_color(color: &str) -> Option<String> {
+    let hex = color.strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 => Some(format!("#{}", hex.to_lowercase())),
+        3 => Some(
+            hex.to_lowercase()
+                .chars()
+                .fold(String::from("#"), |mut out, c| {
+                    out.push(c);
+                    out.push(c);
+                    out
+                }),
+        ),
+        _ => None,
+    }
+}
+
 pub async fn check_profanity<'e, E>(ex: E, text: &str) -> Result<(), ApiError>
 where
     E: sqlx::Executor<'e, Database = sqlx::Any>,
@@ -339,23 +554,86 @@ where
     }
 }
 
+pub fn filter_status(text: &str) -> FilterStatus {
+    let typ = Censor::from_str(text).analyze();
+    match (
+        typ.is(Type::INAPPROPRIATE & Type::SEVERE),
+        typ.is(Type::INAPPROPRIATE),
+    ) {
+        (true, _) => FilterStatus::Filtered,
+        (false, true) => FilterStatus::Warning,
+        (false, false) => FilterStatus::Clean,
+    }
+}
+
+pub async fn filter_message<'e, E>(ex: E, text: &str) -> Result<FilterStatus, ApiError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    if !setting_on(ex, "profanity_filter").await {
+        return Ok(FilterStatus::Clean);
+    }
+    match filter_status(text) {
+        FilterStatus::Filtered => Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Content blocked by profanity filter".to_string(),
+        )),
+        status => Ok(status),
+    }
+}
+
 pub struct Authed(pub User);
 pub struct MaybeAuthed(pub Option<User>);
 
+pub async fn cleanup_now(state: &AppState) {
+    media::sweep_expired(state).await;
+    uploads::sweep_abandoned(state).await;
+    messages::sweep_retention(state).await;
+    messages::sweep_idempotency_keys(state).await;
+    messages::sweep_deleted(state).await;
+    auth::sweep_expired_tokens(state).await;
+    servers::sweep_transfers(state).await;
+    servers::sweep_server_creations(state).await;
+    activity::sweep_activity(state).await;
+    state.sends.prune();
+    state.dm_opens.prune();
+}
+
 pub(crate) async fn user_for_token(state: &AppState, token: &str) -> Option<User> {
-    let row = sqlx::query("SELECT username FROM tokens WHERE token = $1")
-        .bind(token)
-        .fetch_optional(&state.db)
-        .await
-        .ok()??;
+    token_session(state, token).await.map(|(user, _)| user)
+}
+
+async fn token_session(state: &AppState, token: &str) -> Option<(User, bool)> {
+    let row = sqlx::query(
+        "SELECT username, impersonated_by FROM tokens WHERE token = $1 AND (expires_at IS NULL OR expires_at > $2)",
+    )
+    .bind(token)
+    .bind(now())
+    .fetch_optional(&state.db)
+    .await
+    .ok()??;
     let username: String = row.try_get(0).ok()?;
-    get_user(&state.db, &username).await.ok()?
+    let impersonated = row.try_get::<Option<String>, _>(1).ok()?.is_some();
+    let user = get_user(&state.db, &username).await.ok()??;
+    Some((user, impersonated))
 }
 
-async fn bearer_user(parts: &Parts, state: &AppState) -> Option<User> {
-    let header = parts.headers.get(header::AUTHORIZATION)?.to_str().ok()?;
-    let token = header.strip_prefix("Bearer ")?;
-    user_for_token(state, token).await
+async fn bearer_user(parts: &Parts, state: &AppState) -> Result<Option<User>, ApiError> {
+    let token = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let Some(token) = token else {
+        return Ok(None);
+    };
+    match token_session(state, token).await {
+        Some((_, true)) if !matches!(parts.method, Method::GET | Method::HEAD) => Err(ApiError(
+            StatusCode::FORBIDDEN,
+            "Impersonation sessions are read-only".to_string(),
+        )),
+        session => Ok(session.map(|(user, _)| user)),
+    }
 }
 
 pub(crate) fn request_token(headers: &HeaderMap) -> Option<String> {
@@ -389,7 +667,7 @@ impl FromRequestParts<AppState> for Authed {
     type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Authed, ApiError> {
-        match bearer_user(parts, state).await {
+        match bearer_user(parts, state).await? {
             Some(user) => Ok(Authed(user)),
             None => Err(ApiError(
                 StatusCode::UNAUTHORIZED,
@@ -406,10 +684,13 @@ impl FromRequestParts<AppState> for MaybeAuthed {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<MaybeAuthed, ApiError> {
-        Ok(MaybeAuthed(bearer_user(parts, state).await))
+        Ok(MaybeAuthed(bearer_user(parts, state).await?))
     }
 }
 
+const WORDS_BURST: u32 = 10;
+const WORDS_REFILL_MS: u64 = 3000;
+
 pub fn routes(state: AppState) -> Router<AppState> {
     let strict = GovernorConfigBuilder::default()
         .per_millisecond(500)
@@ -419,16 +700,31 @@ pub fn routes(state: AppState) -> Router<AppState> {
     let strict_routes = Router::new()
         .route("/auth/register", post(auth::register))
         .route("/auth/login", post(auth::login))
-        .route("/auth/words/{username}", get(auth::words))
+        .route("/auth/verify", post(auth::verify))
         .route(
             "/media",
-            post(media::upload_media).layer(DefaultBodyLimit::max(26 * 1024 * 1024)),
+            post(media::upload_media).layer(DefaultBodyLimit::max(
+                (state.config.max_upload_mb + 1) * 1024 * 1024,
+            )),
         )
+        .route("/media/uploads", post(uploads::start_upload))
         .route("/servers/{name}/guest_access", post(servers::guest_access))
         .route_layer(GovernorLayer::new(strict));
+    let words = GovernorConfigBuilder::default()
+        .per_millisecond(WORDS_REFILL_MS)
+        .burst_size(WORDS_BURST)
+        .finish()
+        .expect("invalid word set rate limit config");
+    let words_routes = Router::new()
+        .route("/auth/words/{username}", get(auth::words))
+        .route_layer(GovernorLayer::new(words));
     Router::new()
+        .route("/auth/logout", post(auth::logout))
         .route("/me", get(auth::me).patch(auth::patch_me))
+        .route("/me/export", get(export::export_me))
+        .route("/bootstrap", get(bootstrap::bootstrap))
         .route("/servers", post(servers::create_server))
+        .route("/servers/channels", get(servers::my_channels))
         .route(
             "/servers/{name}",
             get(servers::get_server)
@@ -436,12 +732,33 @@ pub fn routes(state: AppState) -> Router<AppState> {
                 .delete(servers::delete_server),
         )
         .route("/servers/{name}/exists", get(servers::server_exists))
+        .route("/servers/{name}/membership", get(servers::get_membership))
+        .route("/servers/{name}/export", get(export::export_server))
+        .route("/servers/{name}/activity", get(activity::server_activity))
+        .route("/servers/{name}/stats", get(stats::server_stats))
+        .route(
+            "/servers/{name}/permissions",
+            get(servers::server_permissions),
+        )
+        .route(
+            "/channels/{id}/permissions",
+            get(servers::channel_permissions),
+        )
         .route("/server_search", get(servers::search_servers))
         .route("/servers/{name}/members", get(servers::list_members))
+        .route(
+            "/servers/{name}/members/bulk",
+            post(servers::bulk_add_members),
+        )
         .route("/servers/{name}/interacted", get(servers::list_interacted))
+        .route("/users/{username}/presence", get(servers::user_presence))
         .route("/servers/{name}/join", post(servers::join_server))
         .route("/servers/{name}/leave", post(servers::leave_server))
         .route("/servers/{name}/channels", post(servers::create_channel))
+        .route(
+            "/servers/{name}/channels/reorder",
+            post(servers::reorder_channels),
+        )
         .route(
             "/channels/{id}",
             patch(servers::update_channel).delete(servers::delete_channel),
@@ -456,6 +773,15 @@ pub fn routes(state: AppState) -> Router<AppState> {
             "/servers/{name}/transfer_admin",
             post(servers::transfer_admin),
         )
+        .route(
+            "/servers/{name}/transfer/accept",
+            post(servers::accept_transfer),
+        )
+        .route(
+            "/servers/{name}/transfer/decline",
+            post(servers::decline_transfer),
+        )
+        .route("/transfers", get(servers::list_transfers))
         .route(
             "/servers/{name}/admins/{username}/perms",
             patch(servers::set_admin_perms),
@@ -485,42 +811,88 @@ pub fn routes(state: AppState) -> Router<AppState> {
             "/channels/{id}/messages",
             get(messages::channel_messages).post(messages::send_channel_message),
         )
+        .route("/channels/{id}/media", get(messages::channel_media))
         .route("/dms", get(dms::list_dms).post(dms::open_dm))
+        .route("/dms/send", post(dms::send_dm))
+        .route("/dms/{id}/pin", put(dms::pin_dm))
+        .route("/dms/{id}/mute", put(dms::mute_dm))
         .route(
             "/dms/{id}/messages",
             get(messages::dm_messages).post(messages::send_dm_message),
         )
         .route("/messages/{id}", delete(messages::delete_message))
         .route("/messages/{id}/media", delete(media::delete_media))
+        .route("/messages/{id}/forward", post(messages::forward_message))
+        .route("/messages/{id}/context", get(messages::message_context))
+        .route("/messages/{id}/restore", post(messages::restore_message))
+        .route("/messages/{id}/report", post(messages::report_message))
         .route("/messages/{id}/embeds/{ord}", delete(embeds::delete_embed))
         .route(
             "/messages/{id}/thread",
             get(messages::thread_messages).post(messages::send_thread_message),
         )
         .route("/search", get(messages::search))
+        .route("/messages/search/global", get(messages::search_global))
         .route("/unreads", get(messages::unreads))
         .route("/read", post(messages::mark_read))
+        .route(
+            "/servers/{name}/mark_read",
+            post(messages::mark_server_read),
+        )
         .route("/media/{id}", get(media::download_media))
+        .route("/media/uploads/{id}", get(uploads::upload_status))
+        .route(
+            "/media/uploads/{id}/chunk",
+            put(uploads::put_chunk).layer(DefaultBodyLimit::max(uploads::CHUNK_MAX_BYTES)),
+        )
+        .route(
+            "/media/uploads/{id}/complete",
+            post(uploads::complete_upload),
+        )
         .route("/settings", get(admin::get_settings))
         .route("/admin/settings", patch(admin::patch_settings))
         .route("/admin/overview", get(admin::overview))
         .route("/admin/users", get(admin::list_users))
         .route("/admin/servers", get(admin::list_servers))
-        .route("/admin/servers/{name}", delete(admin::delete_server))
+        .route("/admin/memberships", get(admin::list_memberships))
+        .route(
+            "/admin/servers/{name}",
+            get(admin::get_server)
+                .patch(admin::patch_server)
+                .delete(admin::delete_server),
+        )
         .route("/admin/users/{username}", delete(admin::delete_user))
         .route("/admin/users/{username}/servers", get(admin::user_servers))
+        .route(
+            "/admin/users/{username}/impersonate",
+            post(admin::impersonate),
+        )
         .route("/admin/messages/{id}", delete(admin::delete_message))
         .route("/admin/ban", post(admin::ban_user))
+        .route("/admin/cleanup", post(admin::run_cleanup))
+        .route("/admin/announce", post(admin::announce))
+        .route(
+            "/admin/invites",
+            get(admin::list_invites).post(admin::create_invite),
+        )
+        .route("/admin/invites/{code}", delete(admin::delete_invite))
+        .route("/admin/reports", get(admin::list_reports))
+        .route("/admin/reports/{id}/resolve", post(admin::resolve_report))
         .route("/ws", get(crate::ws::handler))
         .merge(strict_routes)
+        .merge(words_routes)
         .merge(openapi::routes().route_layer(from_fn_with_state(state, require_token)))
+        .layer(DefaultBodyLimit::max(1024 * 1024))
 }
 
 #[cfg(test)]
 pub(crate) mod test_util {
     use std::path::PathBuf;
+    use std::sync::Arc;
 
+    use crate::config::AppConfig;
     use crate::db::{AvatarKind, Db, User, now, open};
+    use crate::rate_limit::SendLimiter;
     use crate::state::AppState;
     use crate::ws::Hub;
 
@@ -528,11 +900,17 @@ pub(crate) mod test_util {
         let path = std::env::temp_dir().join(format!("rchat_{tag}_{}.db", std::process::id()));
         let _ = std::fs::remove_file(&path);
         let db = open(path.to_str()).await.expect("open db");
+        let config = AppConfig::default_for_tests();
         (
             AppState {
                 db,
                 hub: Hub::new(),
                 s3: None,
+                sends: SendLimiter::messages(&config),
+                announces: SendLimiter::announcements(),
+                dm_opens: SendLimiter::dm_opens(&config),
+                http: reqwest::Client::new(),
+                config: Arc::new(config),
             },
             path,
         )
@@ -553,6 +931,7 @@ pub(crate) mod test_util {
             password_kind: "text".to_string(),
             avatar_kind: AvatarKind::Identicon,
             avatar_color: None,
+            avatar_media_id: None,
             is_site_admin: site_admin,
             created_at: now(),
         }
@@ -576,4 +955,174 @@ pub(crate) mod test_util {
             .await
             .expect("insert member");
     }
+
+    pub(crate) async fn self_dm(db: &Db, username: &str) {
+        sqlx::query("INSERT INTO dms(user_a, user_b) VALUES($1, $1)")
+            .bind(username)
+            .execute(db)
+            .await
+            .expect("insert self dm");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiError, FieldError, FormError, WORDS_BURST, filter_status, ok, routes};
+    use crate::api::test_util::{done, temp_state};
+    use crate::db::{FilterStatus, now};
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode, header};
+    use axum::response::{IntoResponse, Response};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn json_body(resp: Response) -> Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .expect("body");
+        serde_json::from_slice(&bytes).expect("json")
+    }
+
+    fn collect_keys(value: &Value, out: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (k, v) in map {
+                    out.push(k.clone());
+                    collect_keys(v, out);
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|v| collect_keys(v, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn filter_tiers() {
+        for (text, tier) in [
+            ("hello there", FilterStatus::Clean),
+            ("damn it", FilterStatus::Warning),
+            ("shit", FilterStatus::Warning),
+            ("kill yourself", FilterStatus::Filtered),
+        ] {
+            assert_eq!(filter_status(text), tier, "{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn json_body_limit() {
+        let (state, path) = temp_state("body_limit").await;
+        sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES('amy', 'amy', 'x', 'text', 'identicon', 0, $1)")
+            .bind(now())
+            .execute(&state.db)
+            .await
+            .expect("insert user");
+        sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES('tok', 'amy', 0)")
+            .execute(&state.db)
+            .await
+            .expect("insert token");
+        let app = routes(state.clone()).with_state(state.clone());
+        let body = format!("{{\"name\":\"{}\"}}", "a".repeat(1536 * 1024));
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/servers")
+            .header(header::AUTHORIZATION, "Bearer tok")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("request");
+        let resp = app.oneshot(req).await.expect("response");
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn responses_use_snake_case_keys() {
+        let (state, path) = temp_state("snake_case").await;
+        sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES('amy', 'amy', 'x', 'text', 'identicon', 0, $1)")
+            .bind(now())
+            .execute(&state.db)
+            .await
+            .expect("insert user");
+        sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES('tok', 'amy', 0)")
+            .execute(&state.db)
+            .await
+            .expect("insert token");
+        let app = routes(state.clone()).with_state(state.clone());
+        let get = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(header::AUTHORIZATION, "Bearer tok")
+                .body(Body::empty())
+                .expect("request")
+        };
+        let mut keys = Vec::new();
+        for uri in ["/me", "/bootstrap", "/servers/rchat"] {
+            let resp = app.clone().oneshot(get(uri)).await.expect("response");
+            assert_eq!(resp.status(), StatusCode::OK, "{uri}");
+            collect_keys(&json_body(resp).await, &mut keys);
+        }
+        for key in [
+            "display_name",
+            "avatar_kind",
+            "is_site_admin",
+            "member_count",
+        ] {
+            assert!(keys.iter().any(|k| k == key), "missing {key}");
+        }
+        let bad: Vec<&String> = keys
+            .iter()
+            .filter(|k| {
+                !k.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            })
+            .collect();
+        assert!(bad.is_empty(), "non snake_case keys: {bad:?}");
+
+        let ok_body = json_body(ok().into_response()).await;
+        assert_eq!(ok_body, serde_json::json!({ "ok": true }));
+        let api = ApiError(StatusCode::NOT_FOUND, "Not found".to_string());
+        assert_eq!(
+            json_body(api.into_response()).await,
+            serde_json::json!({ "error": "Not found" })
+        );
+        let form = FormError::Field(FieldError::new("username", "taken", "Username is taken"));
+        assert_eq!(
+            json_body(form.into_response()).await,
+            serde_json::json!({ "error": "Username is taken", "field": "username", "code": "taken" })
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn word_sets_are_throttled_per_ip() {
+        let (state, path) = temp_state("words_throttle").await;
+        let app = routes(state.clone()).with_state(state.clone());
+        let get = |ip: [u8; 4], n: u32| {
+            let mut req = Request::builder()
+                .uri(format!("/auth/words/user{n}"))
+                .body(Body::empty())
+                .expect("request");
+            req.extensions_mut()
+                .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                    ip, 4000,
+                ))));
+            req
+        };
+        for n in 0..WORDS_BURST {
+            let resp = app
+                .clone()
+                .oneshot(get([10, 0, 0, 1], n))
+                .await
+                .expect("response");
+            assert_eq!(resp.status(), StatusCode::OK, "request {n}");
+        }
+        let resp = app
+            .clone()
+            .oneshot(get([10, 0, 0, 1], WORDS_BURST))
+            .await
+            .expect("response");
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let resp = app.oneshot(get([10, 0, 0, 2], 0)).await.expect("response");
+        assert_eq!(resp.status(), StatusCode::OK);
+        done(state, path).await;
+    }
 }
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::api::{ApiError, filter_message};
+use crate::db::{Db, FilterStatus};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize)]
+struct WebhookReq<'a> {
+    content: &'a str,
+    author: &'a str,
+    server: &'a str,
+    channel_id: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Verdict {
+    Allow,
+    Flag,
+    Block,
+}
+
+#[derive(Deserialize)]
+struct WebhookResp {
+    action: Verdict,
+}
+
+async fn ask(http: &reqwest::Client, url: &Url, req: &WebhookReq<'_>) -> anyhow::Result<Verdict> {
+    let resp = http
+        .post(url.clone())
+        .timeout(WEBHOOK_TIMEOUT)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(req)?)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("status {}", resp.status());
+    }
+    Ok(serde_json::from_slice::<WebhookResp>(&resp.bytes().await?)?.action)
+}
+
+pub(crate) async fn moderate(
+    db: &Db,
+    http: &reqwest::Client,
+    webhook: Option<&Url>,
+    content: &str,
+    author: &str,
+    server: &str,
+    channel_id: i64,
+) -> Result<FilterStatus, ApiError> {
+    let Some(url) = webhook else {
+        return filter_message(db, content).await;
+    };
+    let req = WebhookReq {
+        content,
+        author,
+        server,
+        channel_id,
+    };
+    match ask(http, url, &req).await {
+        Ok(Verdict::Allow) => Ok(FilterStatus::Clean),
+        Ok(Verdict::Flag) => Ok(FilterStatus::Warning),
+        Ok(Verdict::Block) => Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Content blocked by moderation".to_string(),
+        )),
+        Err(e) => {
+            tracing::warn!("moderation webhook failed, using local filter: {e}");
+            filter_message(db, content).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{done, temp_state};
+    use axum::Router;
+    use axum::routing::post;
+
+    async fn mock(action: &'static str) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let url = format!("http://{}/check", listener.local_addr().expect("addr"));
+        let app = Router::new().route(
+            "/check",
+            post(move || async move { axum::Json(serde_json::json!({ "action": action })) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Url::parse(&url).expect("url")
+    }
+
+    #[tokio::test]
+    async fn webhook_verdicts_and_fallback() {
+        let (state, path) = temp_state("moderation").await;
+        let db = &state.db;
+        let http = &state.http;
+        let check = |url: Option<Url>, content: &'static str| async move {
+            moderate(db, http, url.as_ref(), content, "amy", "rchat", 1).await
+        };
+        let blocked = check(Some(mock("block").await), "hello").await;
+        assert!(matches!(blocked, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        assert_eq!(
+            check(Some(mock("flag").await), "hello")
+                .await
+                .expect("flag"),
+            FilterStatus::Warning
+        );
+        assert_eq!(
+            check(Some(mock("allow").await), "hello")
+                .await
+                .expect("allow"),
+            FilterStatus::Clean
+        );
+
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let dead = Url::parse(&format!(
+            "http://{}/check",
+            closed.local_addr().expect("addr")
+        ))
+        .expect("url");
+        drop(closed);
+        assert_eq!(
+            check(Some(dead), "hello").await.expect("fallback"),
+            FilterStatus::Clean
+        );
+        assert_eq!(
+            check(None, "hello").await.expect("local only"),
+            FilterStatus::Clean
+        );
+        done(state, path).await;
+    }
+}
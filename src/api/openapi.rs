@@ -26,15 +26,27 @@ impl Modify for Bearer {
         super::auth::register,
         super::auth::login,
         super::auth::words,
+        super::auth::logout,
+        super::auth::verify,
         super::auth::me,
         super::auth::patch_me,
+        super::export::export_me,
+        super::export::export_server,
+        super::activity::server_activity,
+        super::stats::server_stats,
+        super::bootstrap::bootstrap,
         super::servers::create_server,
         super::servers::get_server,
+        super::servers::my_channels,
         super::servers::server_exists,
+        super::servers::get_membership,
+        super::servers::server_permissions,
+        super::servers::channel_permissions,
         super::servers::search_servers,
         super::servers::guest_access,
         super::servers::list_members,
         super::servers::list_interacted,
+        super::servers::user_presence,
         super::servers::join_server,
         super::servers::leave_server,
         super::servers::update_server,
@@ -42,10 +54,15 @@ impl Modify for Bearer {
         super::servers::create_channel,
         super::servers::update_channel,
         super::servers::delete_channel,
+        super::servers::reorder_channels,
         super::servers::kick_member,
+        super::servers::bulk_add_members,
         super::servers::grant_admin,
         super::servers::revoke_admin,
         super::servers::transfer_admin,
+        super::servers::accept_transfer,
+        super::servers::decline_transfer,
+        super::servers::list_transfers,
         super::servers::set_admin_perms,
         super::servers::create_role,
         super::servers::update_role,
@@ -56,21 +73,35 @@ impl Modify for Bearer {
         super::servers::set_channel_perm,
         super::servers::clear_channel_perm,
         super::messages::channel_messages,
+        super::messages::channel_media,
         super::messages::send_channel_message,
         super::messages::thread_messages,
         super::messages::send_thread_message,
         super::messages::dm_messages,
         super::messages::send_dm_message,
+        super::messages::forward_message,
+        super::messages::restore_message,
+        super::messages::report_message,
+        super::messages::message_context,
         super::messages::delete_message,
         super::messages::search,
+        super::messages::search_global,
         super::messages::unreads,
         super::messages::mark_read,
+        super::messages::mark_server_read,
         super::embeds::delete_embed,
         super::dms::list_dms,
         super::dms::open_dm,
+        super::dms::send_dm,
+        super::dms::pin_dm,
+        super::dms::mute_dm,
         super::media::upload_media,
         super::media::download_media,
         super::media::delete_media,
+        super::uploads::start_upload,
+        super::uploads::upload_status,
+        super::uploads::put_chunk,
+        super::uploads::complete_upload,
         super::admin::get_settings,
         crate::ws::handler,
     ),
@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
 use axum::Json;
 use axum::extract::{Path, Query, State};
 use axum::http::{HeaderMap, StatusCode};
@@ -8,13 +10,18 @@ use utoipa::{IntoParams, ToSchema};
 use crate::api::admin::{fuzzy_match, is_fuzzy, like_pattern};
 use crate::api::auth::{hash_password, new_token, verify_password};
 use crate::api::{
-    ApiError, Authed, Channel, ChannelPerm, MaybeAuthed, Member, Role, ServerDetail,
-    ServerSummaryLite, UserRef, check_profanity, require_guest_ok, require_server_view, user_ref,
-    valid_color,
+    ApiError, Authed, Channel, ChannelPerm, MaybeAuthed, Member, OkResp, Paged, Role, ServerDetail,
+    ServerSummaryLite, UserRef, check_profanity, ok, require_guest_ok, require_server_view,
+    user_ref, valid_color,
+};
+use crate::config::{AppConfig, ServerCreation};
+use crate::db::{
+    ALL_PERMS, ActivityKind, ChannelAccess, ChannelKind, ContentType, Db, MAX_CHANNEL_NAME_CHARS,
+    Perm, SYSTEM_USERNAME, User, channel_access, effective_perms, get_user, has_perm, now,
+    record_activity,
 };
-use crate::db::{ALL_PERMS, ChannelKind, Db, Perm, User, channel_access, get_user, has_perm, now};
 use crate::state::AppState;
-use crate::ws::{Hub, WsEvent, evict_unviewable};
+use crate::ws::{Hub, PresenceStatus, WsEvent, evict_unviewable};
 
 #[derive(Deserialize, ToSchema)]
 pub struct CreateChannelReq {
@@ -22,6 +29,11 @@ pub struct CreateChannelReq {
     kind: Option<ChannelKind>,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct ReorderChannelsReq {
+    ids: Vec<i64>,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct CreateServerReq {
     name: String,
@@ -37,12 +49,15 @@ pub struct JoinReq {
 pub struct ServerPatch {
     name: Option<String>,
     password: Option<String>,
+    welcome_message: Option<String>,
 }
 
 #[derive(Deserialize, ToSchema)]
 pub struct ChannelPatch {
     name: Option<String>,
     slowmode_seconds: Option<i64>,
+    message_retention_hours: Option<i64>,
+    allowed_content_types: Option<Vec<ContentType>>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -50,6 +65,27 @@ pub struct UsernameReq {
     username: String,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct BulkMembersReq {
+    usernames: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkOutcome {
+    Added,
+    AlreadyMember,
+    Banned,
+    NotFound,
+    AtLimit,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BulkMemberResult {
+    username: String,
+    outcome: BulkOutcome,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct PermsReq {
     perms: i64,
@@ -79,6 +115,14 @@ pub struct GuestGrant {
     grant: String,
 }
 
+#[derive(Clone, Serialize, ToSchema)]
+pub struct PendingTransfer {
+    pub server: String,
+    pub from_user: String,
+    pub to_user: String,
+    pub expires_at: i64,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct ServerExists {
     has_password: bool,
@@ -97,8 +141,39 @@ pub struct ServerMatch {
 }
 
 #[derive(Serialize, ToSchema)]
-pub struct OkResp {
-    ok: bool,
+pub struct Membership {
+    server: ServerDetail,
+    member: Member,
+    effective_perms: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ServerPresence {
+    server: String,
+    status: PresenceStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserPresence {
+    username: String,
+    status: PresenceStatus,
+    last_seen: Option<i64>,
+    servers: Vec<ServerPresence>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Capabilities {
+    is_member: bool,
+    effective_perms: i64,
+    can_view: bool,
+    can_read_history: bool,
+    can_post: bool,
+    can_delete_others: bool,
+    can_manage_channels: bool,
+    can_kick: bool,
+    can_manage_admins: bool,
+    can_delete_server: bool,
+    can_mention_everyone: bool,
 }
 
 #[derive(Deserialize, IntoParams)]
@@ -107,8 +182,28 @@ pub struct MembersQuery {
     limit: Option<i64>,
 }
 
-fn ok() -> Json<OkResp> {
-    Json(OkResp { ok: true })
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberRoleFilter {
+    Admin,
+    Member,
+}
+
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberSort {
+    Name,
+    Joined,
+    LastSeen,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct MemberListQuery {
+    offset: Option<i64>,
+    limit: Option<i64>,
+    role: Option<MemberRoleFilter>,
+    sort: Option<MemberSort>,
+    online: Option<bool>,
 }
 
 fn bad(msg: &str) -> ApiError {
@@ -130,13 +225,148 @@ async fn check_name(db: &Db, name: &str) -> Result<(), ApiError> {
     check_profanity(db, name).await
 }
 
-fn guard_rchat(key: &str) -> Result<(), ApiError> {
+async fn check_channel_name(db: &Db, name: &str) -> Result<(), ApiError> {
+    check_name(db, name).await?;
+    match name.chars().count() > MAX_CHANNEL_NAME_CHARS {
+        true => Err(bad(&format!(
+            "Channel names are limited to {MAX_CHANNEL_NAME_CHARS} characters"
+        ))),
+        false => Ok(()),
+    }
+}
+
+fn channel_name_taken(e: sqlx::Error) -> ApiError {
+    match &e {
+        sqlx::Error::Database(d) if d.is_unique_violation() => {
+            ApiError(StatusCode::CONFLICT, "Channel name is taken".to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+const RESERVED_SERVER_NAMES: &[&str] = &["rchat", "admin", "administrator", "system", "api"];
+const TRANSFER_TTL_SECS: i64 = 7 * 86400;
+
+fn squash(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn reserved_extra(config: &AppConfig) -> Vec<String> {
+    config
+        .reserved_server_names
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(squash)
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+fn check_reserved(name: &str, extra: &[String]) -> Result<(), ApiError> {
+    let squashed = squash(name);
+    let hit = RESERVED_SERVER_NAMES.contains(&squashed.as_str()) || extra.contains(&squashed);
+    match hit {
+        true => Err(bad("That server name is reserved")),
+        false => Ok(()),
+    }
+}
+
+pub(crate) fn guard_rchat(key: &str) -> Result<(), ApiError> {
     match key {
         "rchat" => Err(forbidden("The rchat server is protected")),
         _ => Ok(()),
     }
 }
 
+#[derive(Clone, Copy, Default)]
+pub(crate) struct CreationPolicy {
+    pub(crate) admins_only: bool,
+    pub(crate) per_day: Option<i64>,
+}
+
+pub(crate) fn creation_policy(config: &AppConfig) -> CreationPolicy {
+    CreationPolicy {
+        admins_only: config.server_creation == ServerCreation::Admins,
+        per_day: config.servers_per_day.filter(|n| *n > 0),
+    }
+}
+
+const CREATION_WINDOW_SECS: i64 = 86400;
+const MAX_RETENTION_HOURS: i64 = 87600;
+
+async fn check_creation(db: &Db, user: &User, policy: CreationPolicy) -> Result<(), ApiError> {
+    if user.is_site_admin {
+        return Ok(());
+    }
+    if policy.admins_only {
+        return Err(forbidden("Only site admins can create servers"));
+    }
+    let Some(limit) = policy.per_day else {
+        return Ok(());
+    };
+    let created: i64 = sqlx::query(
+        "SELECT COUNT(*) FROM server_creations WHERE username = $1 AND created_at > $2",
+    )
+    .bind(&user.username)
+    .bind(now() - CREATION_WINDOW_SECS)
+    .fetch_one(db)
+    .await?
+    .try_get(0)?;
+    match created < limit {
+        true => Ok(()),
+        false => Err(ApiError(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("You can create at most {limit} servers per day"),
+        )),
+    }
+}
+
+pub(crate) async fn server_cap(db: &Db, config: &AppConfig) -> Option<i64> {
+    let stored = sqlx::query("SELECT value FROM settings WHERE key = 'max_servers_per_user'")
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|r| r.try_get::<String, _>(0).ok());
+    match stored {
+        Some(v) => v.trim().parse().ok(),
+        None => config.max_servers_per_user,
+    }
+    .filter(|n: &i64| *n > 0)
+}
+
+async fn check_server_cap<'e, E>(
+    ex: E,
+    username: &str,
+    server: &str,
+    cap: Option<i64>,
+) -> Result<(), ApiError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    let Some(cap) = cap else {
+        return Ok(());
+    };
+    if server == "rchat" {
+        return Ok(());
+    }
+    let joined: i64 = sqlx::query(
+        "SELECT COUNT(*) FROM members WHERE username = $1 AND server <> 'rchat' AND server <> $2",
+    )
+    .bind(username)
+    .bind(server)
+    .fetch_one(ex)
+    .await?
+    .try_get(0)?;
+    match joined < cap {
+        true => Ok(()),
+        false => Err(bad(&format!("You can be in at most {cap} servers"))),
+    }
+}
+
 pub(crate) async fn server_lite(
     db: &Db,
     name: &str,
@@ -164,14 +394,19 @@ pub(crate) async fn require_server(db: &Db, name: &str) -> Result<ServerSummaryL
         .ok_or_else(|| not_found("Server not found"))
 }
 
-async fn require_perm(db: &Db, server: &str, user: &User, perm: Perm) -> Result<(), ApiError> {
+pub(crate) async fn require_perm(
+    db: &Db,
+    server: &str,
+    user: &User,
+    perm: Perm,
+) -> Result<(), ApiError> {
     match has_perm(db, server, user, perm).await {
         true => Ok(()),
         false => Err(forbidden("Missing permission")),
     }
 }
 
-async fn role_ids(db: &Db, server: &str, username: &str) -> Result<Vec<i64>, ApiError> {
+pub(crate) async fn role_ids(db: &Db, server: &str, username: &str) -> Result<Vec<i64>, ApiError> {
     let rows = sqlx::query(
         "SELECT role_id FROM user_roles WHERE server = $1 AND username = $2 ORDER BY role_id",
     )
@@ -186,6 +421,30 @@ async fn role_ids(db: &Db, server: &str, username: &str) -> Result<Vec<i64>, Api
     Ok(ids)
 }
 
+async fn server_role_ids(db: &Db, server: &str) -> Result<HashMap<String, Vec<i64>>, ApiError> {
+    let rows =
+        sqlx::query("SELECT username, role_id FROM user_roles WHERE server = $1 ORDER BY role_id")
+            .bind(server)
+            .fetch_all(db)
+            .await?;
+    let mut ids: HashMap<String, Vec<i64>> = HashMap::new();
+    for r in &rows {
+        ids.entry(r.try_get(0)?).or_default().push(r.try_get(1)?);
+    }
+    Ok(ids)
+}
+
+pub(crate) async fn is_member(db: &Db, server: &str, username: &str) -> Result<bool, ApiError> {
+    Ok(
+        sqlx::query("SELECT 1 FROM members WHERE server = $1 AND username = $2")
+            .bind(server)
+            .bind(username)
+            .fetch_optional(db)
+            .await?
+            .is_some(),
+    )
+}
+
 async fn channel_server(db: &Db, id: i64) -> Result<String, ApiError> {
     let row = sqlx::query("SELECT server FROM channels WHERE id = $1")
         .bind(id)
@@ -197,6 +456,13 @@ async fn channel_server(db: &Db, id: i64) -> Result<String, ApiError> {
     }
 }
 
+async fn require_channel_view(db: &Db, server: &str, id: i64, user: &User) -> Result<(), ApiError> {
+    match channel_access(db, server, id, Some(user)).await?.view {
+        true => Ok(()),
+        false => Err(not_found("Channel not found")),
+    }
+}
+
 async fn build_member(
     db: &Db,
     hub: &Hub,
@@ -211,11 +477,24 @@ async fn build_member(
         is_admin,
         is_creator: creator == Some(username),
         online: hub.is_online(server, username),
+        status: hub.status(server, username),
+        last_seen: last_seen(db, username).await?,
         perms,
         role_ids: role_ids(db, server, username).await?,
     })
 }
 
+async fn last_seen(db: &Db, username: &str) -> Result<Option<i64>, ApiError> {
+    let row = sqlx::query("SELECT last_seen FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(db)
+        .await?;
+    match row {
+        Some(r) => Ok(r.try_get(0)?),
+        None => Ok(None),
+    }
+}
+
 pub(crate) async fn server_roles(db: &Db, server: &str) -> Result<Vec<Role>, ApiError> {
     let rows =
         sqlx::query("SELECT id, name, color, perms FROM roles WHERE server = $1 ORDER BY id")
@@ -234,6 +513,17 @@ pub(crate) async fn server_roles(db: &Db, server: &str) -> Result<Vec<Role>, Api
     Ok(roles)
 }
 
+async fn welcome_message(db: &Db, server: &str) -> Result<Option<String>, ApiError> {
+    let row = sqlx::query("SELECT welcome_message FROM servers WHERE name = $1")
+        .bind(server)
+        .fetch_optional(db)
+        .await?;
+    match row {
+        Some(r) => Ok(r.try_get(0)?),
+        None => Ok(None),
+    }
+}
+
 async fn server_detail(
     db: &Db,
     hub: &Hub,
@@ -247,7 +537,7 @@ async fn server_detail(
         has_password,
     } = require_server(db, name).await?;
     let rows = sqlx::query(
-        "SELECT id, name, kind, slowmode_seconds FROM channels WHERE server = $1 ORDER BY id",
+        "SELECT id, name, kind, slowmode_seconds, message_retention_hours, allowed_content_types FROM channels WHERE server = $1 ORDER BY position, id",
     )
     .bind(&name)
     .fetch_all(db)
@@ -279,6 +569,8 @@ async fn server_detail(
             name: r.try_get(1)?,
             kind: ChannelKind::parse(&r.try_get::<String, _>(2)?)?,
             slowmode_seconds: r.try_get(3)?,
+            message_retention_hours: r.try_get(4)?,
+            allowed_content_types: ContentType::parse_list(r.try_get(5)?)?,
         });
     }
     let member_count: i64 = sqlx::query("SELECT COUNT(*) FROM members WHERE server = $1")
@@ -292,6 +584,7 @@ async fn server_detail(
         display_name,
         creator,
         has_password,
+        welcome_message: welcome_message(db, &name).await?,
         channels,
         roles: server_roles(db, &name).await?,
         member_count,
@@ -299,51 +592,93 @@ async fn server_detail(
     })
 }
 
-#[utoipa::path(get, path = "/api/servers/{name}/members", params(("name" = String, Path), MembersQuery), responses((status = 200, body = Vec<Member>)), security((), ("bearer" = [])))]
+#[utoipa::path(get, path = "/api/servers/{name}/members", params(("name" = String, Path), MemberListQuery), responses((status = 200, body = Vec<Member>)), security((), ("bearer" = [])))]
 pub(crate) async fn list_members(
     State(state): State<AppState>,
     MaybeAuthed(viewer): MaybeAuthed,
     headers: HeaderMap,
     Path(name): Path<String>,
-    Query(q): Query<MembersQuery>,
-) -> Result<Json<Vec<Member>>, ApiError> {
+    Query(q): Query<MemberListQuery>,
+) -> Result<Paged<Vec<Member>>, ApiError> {
     require_guest_ok(&state.db, viewer.as_ref()).await?;
     let key = name.to_lowercase();
     let lite = require_server(&state.db, &key).await?;
     require_server_view(&state.db, &headers, &lite, viewer.as_ref()).await?;
     let creator = lite.creator;
-    let offset = q.offset.unwrap_or(0).max(0) as usize;
-    let limit = q.limit.unwrap_or(50).clamp(1, 50) as usize;
+    let show_seen = match &viewer {
+        Some(v) if v.is_site_admin => true,
+        Some(v) => is_member(&state.db, &key, &v.username).await?,
+        None => false,
+    };
+    let MemberListQuery {
+        offset,
+        limit,
+        role,
+        sort,
+        online: online_only,
+    } = q;
+    let offset = offset.unwrap_or(0).max(0);
+    let limit = limit.unwrap_or(50).clamp(1, 50);
     let online = state.hub.online_set(&key);
-    let rows = sqlx::query(
-        "SELECT username, is_admin, perms FROM members WHERE server = $1 ORDER BY username",
-    )
+    let role_filter = match role {
+        Some(MemberRoleFilter::Admin) => " AND m.is_admin != 0",
+        Some(MemberRoleFilter::Member) => " AND m.is_admin = 0",
+        None => "",
+    };
+    let order = match sort {
+        Some(MemberSort::Joined) => "m.joined_at, m.username",
+        Some(MemberSort::LastSeen) => "COALESCE(i.last_at, 0) DESC, m.username",
+        Some(MemberSort::Name) | None => "m.username",
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT m.username AS member, m.is_admin AS member_admin, m.perms AS member_perms, u.* FROM members m LEFT JOIN users u ON u.username = m.username LEFT JOIN interactions i ON i.server = m.server AND i.username = m.username WHERE m.server = $1{role_filter} ORDER BY {order}"
+    ))
     .bind(&key)
     .fetch_all(&state.db)
     .await?;
-    let mut on = Vec::new();
-    let mut off = Vec::new();
+    let mut entries = Vec::with_capacity(rows.len());
     for r in &rows {
-        let (username, is_admin, perms): (String, i64, i64) =
-            (r.try_get(0)?, r.try_get(1)?, r.try_get(2)?);
-        match online.contains(&username) {
-            true => on.push((username, is_admin, perms)),
-            false => off.push((username, is_admin, perms)),
+        let username: String = r.try_get("member")?;
+        if online_only == Some(true) && !online.contains(&username) {
+            continue;
         }
+        entries.push((username, r));
+    }
+    if sort.is_none() {
+        entries.sort_by_key(|(username, _)| !online.contains(username));
     }
-    on.append(&mut off);
+    let total = entries.len() as i64;
+    let mut roles = server_role_ids(&state.db, &key).await?;
     let mut members = Vec::new();
-    for (username, is_admin, perms) in on.into_iter().skip(offset).take(limit) {
+    for (username, r) in entries
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+    {
+        let (user, last_seen) = match r.try_get::<Option<String>, _>("username")? {
+            Some(_) => (
+                UserRef::from_user(&User::from_row(r)?),
+                r.try_get("last_seen")?,
+            ),
+            None => (UserRef::unknown(&username), None),
+        };
         members.push(Member {
             online: online.contains(&username),
-            is_admin: is_admin != 0,
+            status: state.hub.status(&key, &username),
+            last_seen: last_seen.filter(|_| show_seen),
+            is_admin: r.try_get::<i64, _>("member_admin")? != 0,
             is_creator: creator.as_deref() == Some(username.as_str()),
-            perms,
-            role_ids: role_ids(&state.db, &key, &username).await?,
-            user: user_ref(&state.db, &username).await,
+            perms: r.try_get("member_perms")?,
+            role_ids: roles.remove(&username).unwrap_or_default(),
+            user,
         });
     }
-    Ok(Json(members))
+    Ok(Paged {
+        items: members,
+        total,
+        limit,
+        offset,
+    })
 }
 
 #[utoipa::path(get, path = "/api/servers/{name}/interacted", params(("name" = String, Path), MembersQuery), responses((status = 200, body = Vec<UserRef>)), security((), ("bearer" = [])))]
@@ -353,16 +688,23 @@ pub(crate) async fn list_interacted(
     headers: HeaderMap,
     Path(name): Path<String>,
     Query(q): Query<MembersQuery>,
-) -> Result<Json<Vec<UserRef>>, ApiError> {
+) -> Result<Paged<Vec<UserRef>>, ApiError> {
     require_guest_ok(&state.db, viewer.as_ref()).await?;
     let key = name.to_lowercase();
     let lite = require_server(&state.db, &key).await?;
     require_server_view(&state.db, &headers, &lite, viewer.as_ref()).await?;
     let offset = q.offset.unwrap_or(0).max(0);
     let limit = q.limit.unwrap_or(50).clamp(1, 50);
-    let rows = sqlx::query(
-        "SELECT username FROM interactions WHERE server = $1 AND username NOT IN (SELECT username FROM members WHERE server = $2) ORDER BY last_at DESC LIMIT $3 OFFSET $4",
-    )
+    let filter = "server = $1 AND username NOT IN (SELECT username FROM members WHERE server = $2)";
+    let total: i64 = sqlx::query(&format!("SELECT COUNT(*) FROM interactions WHERE {filter}"))
+        .bind(&key)
+        .bind(&key)
+        .fetch_one(&state.db)
+        .await?
+        .try_get(0)?;
+    let rows = sqlx::query(&format!(
+        "SELECT username FROM interactions WHERE {filter} ORDER BY last_at DESC LIMIT $3 OFFSET $4"
+    ))
     .bind(&key)
     .bind(&key)
     .bind(limit)
@@ -373,7 +715,49 @@ pub(crate) async fn list_interacted(
     for r in &rows {
         users.push(user_ref(&state.db, &r.try_get::<String, _>(0)?).await);
     }
-    Ok(Json(users))
+    Ok(Paged {
+        items: users,
+        total,
+        limit,
+        offset,
+    })
+}
+
+#[utoipa::path(get, path = "/api/users/{username}/presence", params(("username" = String, Path)), responses((status = 200, body = UserPresence)), security(("bearer" = [])))]
+pub(crate) async fn user_presence(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(username): Path<String>,
+) -> Result<Json<UserPresence>, ApiError> {
+    let target = username.to_lowercase();
+    let rows = sqlx::query(
+        "SELECT a.server FROM members a JOIN members b ON b.server = a.server WHERE a.username = $1 AND b.username = $2 ORDER BY a.server",
+    )
+    .bind(&target)
+    .bind(&user.username)
+    .fetch_all(&state.db)
+    .await?;
+    if target == SYSTEM_USERNAME || (rows.is_empty() && target != user.username) {
+        return Err(not_found("User not found"));
+    }
+    let mut servers = Vec::with_capacity(rows.len());
+    for r in &rows {
+        let server: String = r.try_get(0)?;
+        servers.push(ServerPresence {
+            status: state.hub.status(&server, &target),
+            server,
+        });
+    }
+    let status = match servers.iter().any(|s| s.status != PresenceStatus::Offline) {
+        true => state.hub.user_status(&target),
+        false => PresenceStatus::Offline,
+    };
+    Ok(Json(UserPresence {
+        last_seen: last_seen(&state.db, &target).await?,
+        username: target,
+        status,
+        servers,
+    }))
 }
 
 #[utoipa::path(post, path = "/api/servers", request_body = CreateServerReq, responses((status = 200, body = ServerDetail)), security(("bearer" = [])))]
@@ -382,9 +766,11 @@ pub(crate) async fn create_server(
     Authed(user): Authed,
     Json(req): Json<CreateServerReq>,
 ) -> Result<Json<ServerDetail>, ApiError> {
+    check_creation(&state.db, &user, creation_policy(&state.config)).await?;
     let display = req.name.trim().to_string();
     let key = display.to_lowercase();
     check_name(&state.db, &display).await?;
+    check_reserved(&display, &reserved_extra(&state.config))?;
     if server_lite(&state.db, &key).await?.is_some() {
         return Err(bad("Server name is taken"));
     }
@@ -397,10 +783,15 @@ pub(crate) async fn create_server(
         Some(password) => Some(hash_password(password)?),
         None => None,
     };
+    let cap = match user.is_site_admin {
+        true => None,
+        false => server_cap(&state.db, &state.config).await,
+    };
     let t = now();
     let mut tx = state.db.begin().await?;
-    sqlx::query(
-        "INSERT INTO servers(name, display_name, creator, password_hash, created_at) VALUES($1, $2, $3, $4, $5)",
+    check_server_cap(&mut *tx, &user.username, &key, cap).await?;
+    let inserted = sqlx::query(
+        "INSERT INTO servers(name, display_name, creator, password_hash, created_at) VALUES($1, $2, $3, $4, $5) ON CONFLICT(name) DO NOTHING",
     )
     .bind(&key)
     .bind(&display)
@@ -408,7 +799,11 @@ pub(crate) async fn create_server(
     .bind(&password_hash)
     .bind(t)
     .execute(&mut *tx)
-    .await?;
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        return Err(bad("Server name is taken"));
+    }
     sqlx::query("INSERT INTO members(server, username, is_admin, joined_at) VALUES($1, $2, 1, $3)")
         .bind(&key)
         .bind(&user.username)
@@ -420,6 +815,12 @@ pub(crate) async fn create_server(
         .bind(t)
         .execute(&mut *tx)
         .await?;
+    sqlx::query("INSERT INTO server_creations(username, server, created_at) VALUES($1, $2, $3)")
+        .bind(&user.username)
+        .bind(&key)
+        .bind(t)
+        .execute(&mut *tx)
+        .await?;
     tx.commit().await?;
     let detail = server_detail(&state.db, &state.hub, &key, Some(&user)).await?;
     state.hub.broadcast(WsEvent::ServerCreated {
@@ -449,6 +850,166 @@ pub(crate) async fn get_server(
     ))
 }
 
+#[utoipa::path(get, path = "/api/servers/channels", responses((status = 200, description = "Visible channels of every server the caller belongs to, keyed by server name", body = BTreeMap<String, Vec<Channel>>)), security(("bearer" = [])))]
+pub(crate) async fn my_channels(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Json<BTreeMap<String, Vec<Channel>>>, ApiError> {
+    Ok(Json(visible_channels(&state.db, &user).await?))
+}
+
+pub(crate) async fn visible_channels(
+    db: &Db,
+    user: &User,
+) -> Result<BTreeMap<String, Vec<Channel>>, ApiError> {
+    let rows = sqlx::query(
+        "SELECT c.id, c.server, c.name, c.kind, c.slowmode_seconds, c.message_retention_hours, c.allowed_content_types FROM channels c JOIN members m ON m.server = c.server AND m.username = $1 ORDER BY c.server, c.position, c.id",
+    )
+    .bind(&user.username)
+    .fetch_all(db)
+    .await?;
+    let restricted_rows = sqlx::query(
+        "SELECT DISTINCT cp.channel_id FROM channel_perms cp JOIN channels c ON c.id = cp.channel_id JOIN members m ON m.server = c.server AND m.username = $1",
+    )
+    .bind(&user.username)
+    .fetch_all(db)
+    .await?;
+    let mut restricted = HashSet::with_capacity(restricted_rows.len());
+    for r in &restricted_rows {
+        restricted.insert(r.try_get::<i64, _>(0)?);
+    }
+    let mut map: BTreeMap<String, Vec<Channel>> = BTreeMap::new();
+    for r in &rows {
+        let id: i64 = r.try_get(0)?;
+        let server: String = r.try_get(1)?;
+        let visible =
+            !restricted.contains(&id) || channel_access(db, &server, id, Some(user)).await?.view;
+        let channels = map.entry(server).or_default();
+        if !visible {
+            continue;
+        }
+        channels.push(Channel {
+            id,
+            name: r.try_get(2)?,
+            kind: ChannelKind::parse(&r.try_get::<String, _>(3)?)?,
+            slowmode_seconds: r.try_get(4)?,
+            message_retention_hours: r.try_get(5)?,
+            allowed_content_types: ContentType::parse_list(r.try_get(6)?)?,
+        });
+    }
+    Ok(map)
+}
+
+#[utoipa::path(get, path = "/api/servers/{name}/membership", params(("name" = String, Path)), responses((status = 200, body = Membership)), security(("bearer" = [])))]
+pub(crate) async fn get_membership(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Json<Membership>, ApiError> {
+    let key = name.to_lowercase();
+    let lite = require_server(&state.db, &key).await?;
+    let row =
+        sqlx::query("SELECT is_admin, perms FROM members WHERE server = $1 AND username = $2")
+            .bind(&key)
+            .bind(&user.username)
+            .fetch_optional(&state.db)
+            .await?
+            .ok_or_else(|| forbidden("Not a member"))?;
+    let member = build_member(
+        &state.db,
+        &state.hub,
+        &key,
+        lite.creator.as_deref(),
+        &user.username,
+        row.try_get::<i64, _>(0)? != 0,
+        row.try_get(1)?,
+    )
+    .await?;
+    Ok(Json(Membership {
+        server: server_detail(&state.db, &state.hub, &key, Some(&user)).await?,
+        member,
+        effective_perms: effective_perms(&state.db, &key, &user).await?,
+    }))
+}
+
+async fn capabilities(
+    db: &Db,
+    server: &str,
+    user: &User,
+    channel_id: Option<i64>,
+) -> Result<Capabilities, ApiError> {
+    let is_member = sqlx::query("SELECT 1 FROM members WHERE server = $1 AND username = $2")
+        .bind(server)
+        .bind(&user.username)
+        .fetch_optional(db)
+        .await?
+        .is_some();
+    let perms = effective_perms(db, server, user).await?;
+    let has = |perm: Perm| perms & perm as i64 != 0;
+    let (access, postable) = match channel_id {
+        Some(id) => {
+            let kind: String = sqlx::query("SELECT kind FROM channels WHERE id = $1")
+                .bind(id)
+                .fetch_one(db)
+                .await?
+                .try_get(0)?;
+            (
+                channel_access(db, server, id, Some(user)).await?,
+                ChannelKind::parse(&kind)? == ChannelKind::Text,
+            )
+        }
+        None => {
+            let open =
+                is_member || user.is_site_admin || !require_server(db, server).await?.has_password;
+            (
+                ChannelAccess {
+                    view: open,
+                    send: is_member,
+                    history: open,
+                },
+                true,
+            )
+        }
+    };
+    Ok(Capabilities {
+        is_member,
+        effective_perms: perms,
+        can_view: access.view,
+        can_read_history: access.history,
+        can_post: is_member && postable && access.send,
+        can_delete_others: has(Perm::DeleteMessages),
+        can_manage_channels: has(Perm::ManageChannels),
+        can_kick: has(Perm::Kick),
+        can_manage_admins: has(Perm::ManageAdmins),
+        can_delete_server: has(Perm::DeleteServer),
+        can_mention_everyone: has(Perm::MentionEveryone),
+    })
+}
+
+#[utoipa::path(get, path = "/api/servers/{name}/permissions", params(("name" = String, Path)), responses((status = 200, body = Capabilities)), security(("bearer" = [])))]
+pub(crate) async fn server_permissions(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Json<Capabilities>, ApiError> {
+    let key = name.to_lowercase();
+    require_server(&state.db, &key).await?;
+    Ok(Json(capabilities(&state.db, &key, &user, None).await?))
+}
+
+#[utoipa::path(get, path = "/api/channels/{id}/permissions", params(("id" = i64, Path)), responses((status = 200, body = Capabilities)), security(("bearer" = [])))]
+pub(crate) async fn channel_permissions(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<i64>,
+) -> Result<Json<Capabilities>, ApiError> {
+    let server = channel_server(&state.db, id).await?;
+    require_channel_view(&state.db, &server, id, &user).await?;
+    Ok(Json(
+        capabilities(&state.db, &server, &user, Some(id)).await?,
+    ))
+}
+
 #[utoipa::path(get, path = "/api/servers/{name}/exists", params(("name" = String, Path)), responses((status = 200, body = ServerExists)), security((), ("bearer" = [])))]
 pub(crate) async fn server_exists(
     State(state): State<AppState>,
@@ -573,33 +1134,78 @@ pub(crate) async fn join_server(
             }
         }
     }
-    let inserted = sqlx::query(
-        "INSERT INTO members(server, username, is_admin, joined_at) VALUES($1, $2, 0, $3) ON CONFLICT(server, username) DO NOTHING",
-    )
-    .bind(&key)
-    .bind(&user.username)
-    .bind(now())
-    .execute(&state.db)
-    .await?
-    .rows_affected();
+    let cap = match user.is_site_admin {
+        true => None,
+        false => server_cap(&state.db, &state.config).await,
+    };
+    let mut tx = state.db.begin().await?;
+    let added = admit_member(&mut tx, &key, &user.username, &user.username, cap).await?;
+    tx.commit().await?;
     let detail = server_detail(&state.db, &state.hub, &key, Some(&user)).await?;
-    let member = build_member(
-        &state.db,
-        &state.hub,
-        &key,
-        lite.creator.as_deref(),
-        &user.username,
-        false,
-        0,
+    if added {
+        announce_join(
+            &state,
+            &key,
+            lite.creator.as_deref(),
+            &user.username,
+            detail.welcome_message.clone(),
+        )
+        .await?;
+    }
+    Ok(Json(detail))
+}
+
+async fn admit_member(
+    conn: &mut sqlx::AnyConnection,
+    server: &str,
+    username: &str,
+    actor: &str,
+    cap: Option<i64>,
+) -> Result<bool, ApiError> {
+    check_server_cap(&mut *conn, username, server, cap).await?;
+    let inserted = sqlx::query(
+        "INSERT INTO members(server, username, is_admin, joined_at) VALUES($1, $2, 0, $3) ON CONFLICT(server, username) DO NOTHING",
     )
-    .await?;
+    .bind(server)
+    .bind(username)
+    .bind(now())
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
     if inserted > 0 {
-        state.hub.broadcast(WsEvent::MemberJoined {
-            server: key,
-            member,
+        record_activity(
+            &mut *conn,
+            server,
+            ActivityKind::MemberJoined,
+            actor,
+            username,
+            None,
+        )
+        .await?;
+    }
+    Ok(inserted > 0)
+}
+
+async fn announce_join(
+    state: &AppState,
+    server: &str,
+    creator: Option<&str>,
+    username: &str,
+    welcome: Option<String>,
+) -> Result<(), ApiError> {
+    let member = build_member(&state.db, &state.hub, server, creator, username, false, 0).await?;
+    state.hub.broadcast(WsEvent::MemberJoined {
+        server: server.to_string(),
+        member,
+    });
+    if let Some(message) = welcome {
+        state.hub.broadcast(WsEvent::ServerWelcome {
+            server: server.to_string(),
+            username: username.to_string(),
+            message,
         });
     }
-    Ok(Json(detail))
+    Ok(())
 }
 
 #[utoipa::path(post, path = "/api/servers/{name}/leave", params(("name" = String, Path)), responses((status = 200, body = OkResp)), security(("bearer" = [])))]
@@ -610,10 +1216,11 @@ pub(crate) async fn leave_server(
 ) -> Result<Json<OkResp>, ApiError> {
     let key = name.to_lowercase();
     require_server(&state.db, &key).await?;
+    let mut tx = state.db.begin().await?;
     let removed = sqlx::query("DELETE FROM members WHERE server = $1 AND username = $2")
         .bind(&key)
         .bind(&user.username)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await?
         .rows_affected();
     match removed {
@@ -622,8 +1229,18 @@ pub(crate) async fn leave_server(
             sqlx::query("DELETE FROM user_roles WHERE server = $1 AND username = $2")
                 .bind(&key)
                 .bind(&user.username)
-                .execute(&state.db)
+                .execute(&mut *tx)
                 .await?;
+            record_activity(
+                &mut *tx,
+                &key,
+                ActivityKind::MemberLeft,
+                &user.username,
+                &user.username,
+                None,
+            )
+            .await?;
+            tx.commit().await?;
             state.hub.broadcast(WsEvent::MemberLeft {
                 server: key.clone(),
                 username: user.username.clone(),
@@ -645,14 +1262,30 @@ pub(crate) async fn update_server(
     guard_rchat(&key)?;
     let lite = require_server(&state.db, &key).await?;
     require_perm(&state.db, &key, &user, Perm::DeleteServer).await?;
-    let ServerPatch { name, password } = req;
+    Ok(Json(apply_server_patch(&state, key, lite, req).await?))
+}
+
+pub(crate) async fn apply_server_patch(
+    state: &AppState,
+    key: String,
+    lite: ServerSummaryLite,
+    req: ServerPatch,
+) -> Result<ServerSummaryLite, ApiError> {
+    let ServerPatch {
+        name,
+        password,
+        welcome_message,
+    } = req;
     let (new_key, display) = match &name {
         Some(n) => {
             let display = n.trim().to_string();
             let new_key = display.to_lowercase();
             check_name(&state.db, &display).await?;
-            if new_key != key && server_lite(&state.db, &new_key).await?.is_some() {
-                return Err(bad("Server name is taken"));
+            if new_key != key {
+                check_reserved(&display, &reserved_extra(&state.config))?;
+                if server_lite(&state.db, &new_key).await?.is_some() {
+                    return Err(bad("Server name is taken"));
+                }
             }
             (new_key, display)
         }
@@ -663,6 +1296,17 @@ pub(crate) async fn update_server(
         Some(p) => Some(Some(hash_password(p)?)),
         None => None,
     };
+    let welcome = match welcome_message.as_deref().map(str::trim) {
+        Some("") => Some(None),
+        Some(text) if text.chars().count() > MAX_WELCOME_CHARS => {
+            return Err(bad("Welcome message is too long"));
+        }
+        Some(text) => {
+            check_profanity(&state.db, text).await?;
+            Some(Some(text.to_string()))
+        }
+        None => None,
+    };
     let mut tx = state.db.begin().await?;
     sqlx::query("UPDATE servers SET name = $1, display_name = $2 WHERE name = $3")
         .bind(&new_key)
@@ -670,6 +1314,13 @@ pub(crate) async fn update_server(
         .bind(&key)
         .execute(&mut *tx)
         .await?;
+    if let Some(text) = &welcome {
+        sqlx::query("UPDATE servers SET welcome_message = $1 WHERE name = $2")
+            .bind(text)
+            .bind(&new_key)
+            .execute(&mut *tx)
+            .await?;
+    }
     if let Some(hash) = &password_hash {
         sqlx::query("UPDATE servers SET password_hash = $1 WHERE name = $2")
             .bind(hash)
@@ -695,7 +1346,7 @@ pub(crate) async fn update_server(
         old_name: key,
         server: server.clone(),
     });
-    Ok(Json(server))
+    Ok(server)
 }
 
 #[utoipa::path(delete, path = "/api/servers/{name}", params(("name" = String, Path)), responses((status = 200, body = OkResp)), security(("bearer" = [])))]
@@ -726,33 +1377,38 @@ pub(crate) async fn create_channel(
     let key = name.to_lowercase();
     let channel_name = req.name.trim().to_string();
     let kind = req.kind.unwrap_or(ChannelKind::Text);
-    check_name(&state.db, &channel_name).await?;
+    check_channel_name(&state.db, &channel_name).await?;
     require_server(&state.db, &key).await?;
     require_perm(&state.db, &key, &user, Perm::ManageChannels).await?;
-    let taken = sqlx::query("SELECT 1 FROM channels WHERE server = $1 AND name = $2")
-        .bind(&key)
-        .bind(&channel_name)
-        .fetch_optional(&state.db)
-        .await?
-        .is_some();
-    if taken {
-        return Err(bad("Channel name is taken"));
-    }
+    let mut tx = state.db.begin().await?;
     let id: i64 = sqlx::query(
-        "INSERT INTO channels(server, name, kind, created_at) VALUES($1, $2, $3, $4) RETURNING id",
+        "INSERT INTO channels(server, name, kind, position, created_at) VALUES($1, $2, $3, (SELECT COALESCE(MAX(position), -1) + 1 FROM channels WHERE server = $1), $4) RETURNING id",
     )
     .bind(&key)
     .bind(&channel_name)
     .bind(kind.as_str())
     .bind(now())
-    .fetch_one(&state.db)
-    .await?
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(channel_name_taken)?
     .try_get(0)?;
+    record_activity(
+        &mut *tx,
+        &key,
+        ActivityKind::ChannelCreated,
+        &user.username,
+        &channel_name,
+        Some(id),
+    )
+    .await?;
+    tx.commit().await?;
     let channel = Channel {
         id,
         name: channel_name,
         kind,
         slowmode_seconds: 0,
+        message_retention_hours: None,
+        allowed_content_types: None,
     };
     state.hub.broadcast(WsEvent::ChannelCreated {
         server: key,
@@ -761,6 +1417,46 @@ pub(crate) async fn create_channel(
     Ok(Json(channel))
 }
 
+#[utoipa::path(post, path = "/api/servers/{name}/channels/reorder", params(("name" = String, Path)), request_body = ReorderChannelsReq, responses((status = 200, body = OkResp)), security(("bearer" = [])))]
+pub(crate) async fn reorder_channels(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+    Json(req): Json<ReorderChannelsReq>,
+) -> Result<Json<OkResp>, ApiError> {
+    let key = name.to_lowercase();
+    require_server(&state.db, &key).await?;
+    require_perm(&state.db, &key, &user, Perm::ManageChannels).await?;
+    let ReorderChannelsReq { ids } = req;
+    let rows = sqlx::query("SELECT id FROM channels WHERE server = $1")
+        .bind(&key)
+        .fetch_all(&state.db)
+        .await?;
+    let mut existing = Vec::with_capacity(rows.len());
+    for r in &rows {
+        existing.push(r.try_get::<i64, _>(0)?);
+    }
+    let mut wanted = ids.clone();
+    existing.sort_unstable();
+    wanted.sort_unstable();
+    if existing != wanted {
+        return Err(bad("List every channel of the server exactly once"));
+    }
+    let mut tx = state.db.begin().await?;
+    for (position, id) in ids.iter().enumerate() {
+        sqlx::query("UPDATE channels SET position = $1 WHERE id = $2")
+            .bind(position as i64)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    state
+        .hub
+        .broadcast(WsEvent::ChannelsReordered { server: key, ids });
+    Ok(ok())
+}
+
 #[utoipa::path(patch, path = "/api/channels/{id}", params(("id" = i64, Path)), request_body = ChannelPatch, responses((status = 200, body = Channel)), security(("bearer" = [])))]
 pub(crate) async fn update_channel(
     State(state): State<AppState>,
@@ -771,28 +1467,24 @@ pub(crate) async fn update_channel(
     let ChannelPatch {
         name,
         slowmode_seconds,
+        message_retention_hours,
+        allowed_content_types,
     } = req;
     let server = channel_server(&state.db, id).await?;
+    require_channel_view(&state.db, &server, id, &user).await?;
     require_perm(&state.db, &server, &user, Perm::ManageChannels).await?;
+    if message_retention_hours.is_some_and(|hours| hours > MAX_RETENTION_HOURS) {
+        return Err(bad("Message retention can be at most 87600 hours"));
+    }
     if let Some(name) = &name {
         let channel_name = name.trim().to_string();
-        check_name(&state.db, &channel_name).await?;
-        let taken =
-            sqlx::query("SELECT 1 FROM channels WHERE server = $1 AND name = $2 AND id != $3")
-                .bind(&server)
-                .bind(&channel_name)
-                .bind(id)
-                .fetch_optional(&state.db)
-                .await?
-                .is_some();
-        if taken {
-            return Err(bad("Channel name is taken"));
-        }
+        check_channel_name(&state.db, &channel_name).await?;
         sqlx::query("UPDATE channels SET name = $1 WHERE id = $2")
             .bind(&channel_name)
             .bind(id)
             .execute(&state.db)
-            .await?;
+            .await
+            .map_err(channel_name_taken)?;
     }
     if let Some(secs) = slowmode_seconds {
         sqlx::query("UPDATE channels SET slowmode_seconds = $1 WHERE id = $2")
@@ -801,15 +1493,33 @@ pub(crate) async fn update_channel(
             .execute(&state.db)
             .await?;
     }
-    let row = sqlx::query("SELECT name, kind, slowmode_seconds FROM channels WHERE id = $1")
-        .bind(id)
-        .fetch_one(&state.db)
-        .await?;
+    if let Some(hours) = message_retention_hours {
+        sqlx::query("UPDATE channels SET message_retention_hours = $1 WHERE id = $2")
+            .bind((hours > 0).then_some(hours))
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+    if let Some(types) = allowed_content_types {
+        sqlx::query("UPDATE channels SET allowed_content_types = $1 WHERE id = $2")
+            .bind(ContentType::join(&types))
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+    }
+    let row = sqlx::query(
+        "SELECT name, kind, slowmode_seconds, message_retention_hours, allowed_content_types FROM channels WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_one(&state.db)
+    .await?;
     let channel = Channel {
         id,
         name: row.try_get(0)?,
         kind: ChannelKind::parse(&row.try_get::<String, _>(1)?)?,
         slowmode_seconds: row.try_get(2)?,
+        message_retention_hours: row.try_get(3)?,
+        allowed_content_types: ContentType::parse_list(row.try_get(4)?)?,
     };
     state.hub.broadcast(WsEvent::ChannelRenamed {
         server,
@@ -824,17 +1534,19 @@ pub(crate) async fn delete_channel(
     Authed(user): Authed,
     Path(id): Path<i64>,
 ) -> Result<Json<OkResp>, ApiError> {
-    let row = sqlx::query("SELECT server, kind FROM channels WHERE id = $1")
+    let row = sqlx::query("SELECT server, kind, name FROM channels WHERE id = $1")
         .bind(id)
         .fetch_optional(&state.db)
         .await?;
-    let (server, kind): (String, ChannelKind) = match &row {
+    let (server, kind, channel_name): (String, ChannelKind, String) = match &row {
         Some(r) => (
             r.try_get(0)?,
             ChannelKind::parse(&r.try_get::<String, _>(1)?)?,
+            r.try_get(2)?,
         ),
         None => return Err(not_found("Channel not found")),
     };
+    require_channel_view(&state.db, &server, id, &user).await?;
     require_perm(&state.db, &server, &user, Perm::ManageChannels).await?;
     let count: i64 = sqlx::query("SELECT COUNT(*) FROM channels WHERE server = $1")
         .bind(&server)
@@ -855,10 +1567,21 @@ pub(crate) async fn delete_channel(
             return Err(bad("Cannot delete the only text channel"));
         }
     }
+    let mut tx = state.db.begin().await?;
     sqlx::query("DELETE FROM channels WHERE id = $1")
         .bind(id)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await?;
+    record_activity(
+        &mut *tx,
+        &server,
+        ActivityKind::ChannelDeleted,
+        &user.username,
+        &channel_name,
+        Some(id),
+    )
+    .await?;
+    tx.commit().await?;
     state.hub.broadcast(WsEvent::ChannelDeleted {
         server,
         channel_id: id,
@@ -877,10 +1600,11 @@ pub(crate) async fn kick_member(
     let target = req.username.to_lowercase();
     require_server(&state.db, &key).await?;
     require_perm(&state.db, &key, &user, Perm::Kick).await?;
+    let mut tx = state.db.begin().await?;
     let removed = sqlx::query("DELETE FROM members WHERE server = $1 AND username = $2")
         .bind(&key)
         .bind(&target)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await?
         .rows_affected();
     match removed {
@@ -889,8 +1613,18 @@ pub(crate) async fn kick_member(
             sqlx::query("DELETE FROM user_roles WHERE server = $1 AND username = $2")
                 .bind(&key)
                 .bind(&target)
-                .execute(&state.db)
+                .execute(&mut *tx)
                 .await?;
+            record_activity(
+                &mut *tx,
+                &key,
+                ActivityKind::MemberKicked,
+                &user.username,
+                &target,
+                None,
+            )
+            .await?;
+            tx.commit().await?;
             state.hub.broadcast(WsEvent::MemberKicked {
                 server: key.clone(),
                 username: target.clone(),
@@ -901,6 +1635,61 @@ pub(crate) async fn kick_member(
     }
 }
 
+const BULK_MEMBERS_MAX: usize = 500;
+const MAX_WELCOME_CHARS: usize = 2000;
+
+#[utoipa::path(post, path = "/api/servers/{name}/members/bulk", params(("name" = String, Path)), request_body = BulkMembersReq, responses((status = 200, body = [BulkMemberResult])), security(("bearer" = [])))]
+pub(crate) async fn bulk_add_members(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+    Json(req): Json<BulkMembersReq>,
+) -> Result<Json<Vec<BulkMemberResult>>, ApiError> {
+    let key = name.to_lowercase();
+    let lite = require_server(&state.db, &key).await?;
+    require_perm(&state.db, &key, &user, Perm::Kick).await?;
+    if req.usernames.len() > BULK_MEMBERS_MAX {
+        return Err(bad("Too many usernames"));
+    }
+    let mut results: Vec<BulkMemberResult> = Vec::with_capacity(req.usernames.len());
+    let cap = server_cap(&state.db, &state.config).await;
+    let mut tx = state.db.begin().await?;
+    for raw in &req.usernames {
+        let username = raw.trim().to_lowercase();
+        if results.iter().any(|r| r.username == username) {
+            continue;
+        }
+        let exists = sqlx::query("SELECT 1 FROM users WHERE username = $1")
+            .bind(&username)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        let banned = sqlx::query("SELECT 1 FROM banned_usernames WHERE username = $1")
+            .bind(&username)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        let outcome = match (exists, banned) {
+            (_, true) => BulkOutcome::Banned,
+            (false, false) => BulkOutcome::NotFound,
+            (true, false) => {
+                match admit_member(&mut tx, &key, &username, &user.username, cap).await {
+                    Ok(true) => BulkOutcome::Added,
+                    Ok(false) => BulkOutcome::AlreadyMember,
+                    Err(ApiError(StatusCode::BAD_REQUEST, _)) => BulkOutcome::AtLimit,
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        results.push(BulkMemberResult { username, outcome });
+    }
+    tx.commit().await?;
+    for r in results.iter().filter(|r| r.outcome == BulkOutcome::Added) {
+        announce_join(&state, &key, lite.creator.as_deref(), &r.username, None).await?;
+    }
+    Ok(Json(results))
+}
+
 async fn set_admin(
     state: &AppState,
     actor: &User,
@@ -966,13 +1755,13 @@ pub(crate) async fn revoke_admin(
     .await
 }
 
-#[utoipa::path(post, path = "/api/servers/{name}/transfer_admin", params(("name" = String, Path)), request_body = UsernameReq, responses((status = 200, body = OkResp)), security(("bearer" = [])))]
+#[utoipa::path(post, path = "/api/servers/{name}/transfer_admin", params(("name" = String, Path)), request_body = UsernameReq, responses((status = 200, body = PendingTransfer)), security(("bearer" = [])))]
 pub(crate) async fn transfer_admin(
     State(state): State<AppState>,
     Authed(user): Authed,
     Path(name): Path<String>,
     Json(req): Json<UsernameReq>,
-) -> Result<Json<OkResp>, ApiError> {
+) -> Result<Json<PendingTransfer>, ApiError> {
     let key = name.to_lowercase();
     let target = req.username.to_lowercase();
     if target == user.username {
@@ -980,42 +1769,228 @@ pub(crate) async fn transfer_admin(
     }
     require_server(&state.db, &key).await?;
     require_perm(&state.db, &key, &user, Perm::ManageAdmins).await?;
+    let member = sqlx::query("SELECT 1 FROM members WHERE server = $1 AND username = $2")
+        .bind(&key)
+        .bind(&target)
+        .fetch_optional(&state.db)
+        .await?
+        .is_some();
+    if !member {
+        return Err(not_found("Not a member"));
+    }
+    let transfer = PendingTransfer {
+        server: key,
+        from_user: user.username,
+        to_user: target,
+        expires_at: now() + TRANSFER_TTL_SECS,
+    };
     let mut tx = state.db.begin().await?;
+    let replaced =
+        sqlx::query("DELETE FROM server_transfers WHERE server = $1 RETURNING from_user, to_user")
+            .bind(&transfer.server)
+            .fetch_optional(&mut *tx)
+            .await?;
+    sqlx::query(
+        "INSERT INTO server_transfers(server, from_user, to_user, expires_at) VALUES($1, $2, $3, $4)",
+    )
+    .bind(&transfer.server)
+    .bind(&transfer.from_user)
+    .bind(&transfer.to_user)
+    .bind(transfer.expires_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    if let Some(r) = replaced {
+        state.hub.broadcast(WsEvent::TransferClosed {
+            server: transfer.server.clone(),
+            from_user: r.try_get(0)?,
+            to_user: r.try_get(1)?,
+            accepted: false,
+        });
+    }
+    state.hub.broadcast(WsEvent::TransferOffered {
+        transfer: transfer.clone(),
+    });
+    Ok(Json(transfer))
+}
+
+#[utoipa::path(post, path = "/api/servers/{name}/transfer/accept", params(("name" = String, Path)), responses((status = 200, body = OkResp)), security(("bearer" = [])))]
+pub(crate) async fn accept_transfer(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Json<OkResp>, ApiError> {
+    let key = name.to_lowercase();
+    require_server(&state.db, &key).await?;
+    let row = sqlx::query(
+        "SELECT from_user FROM server_transfers WHERE server = $1 AND to_user = $2 AND expires_at > $3",
+    )
+    .bind(&key)
+    .bind(&user.username)
+    .bind(now())
+    .fetch_optional(&state.db)
+    .await?;
+    let from: String = match row {
+        Some(r) => r.try_get(0)?,
+        None => return Err(not_found("No pending transfer")),
+    };
+    let allowed = match get_user(&state.db, &from).await? {
+        Some(initiator) => has_perm(&state.db, &key, &initiator, Perm::ManageAdmins).await,
+        None => false,
+    };
+    let mut tx = state.db.begin().await?;
+    let claimed = sqlx::query(
+        "DELETE FROM server_transfers WHERE server = $1 AND from_user = $2 AND to_user = $3",
+    )
+    .bind(&key)
+    .bind(&from)
+    .bind(&user.username)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if claimed == 0 {
+        return Err(not_found("No pending transfer"));
+    }
+    if !allowed {
+        tx.commit().await?;
+        close_transfer(&state, &key, from, user.username, false);
+        return Err(forbidden("Transfer is no longer valid"));
+    }
     let granted =
         sqlx::query("UPDATE members SET is_admin = 1 WHERE server = $1 AND username = $2")
             .bind(&key)
-            .bind(&target)
+            .bind(&user.username)
             .execute(&mut *tx)
             .await?
             .rows_affected();
     if granted == 0 {
-        return Err(not_found("Not a member"));
+        tx.commit().await?;
+        close_transfer(&state, &key, from, user.username, false);
+        return Err(bad("You are no longer a member of this server"));
     }
     let revoked =
         sqlx::query("UPDATE members SET is_admin = 0 WHERE server = $1 AND username = $2")
             .bind(&key)
-            .bind(&user.username)
+            .bind(&from)
             .execute(&mut *tx)
             .await?
             .rows_affected();
     tx.commit().await?;
     state.hub.broadcast(WsEvent::AdminChanged {
         server: key.clone(),
-        username: target,
+        username: user.username.clone(),
         is_admin: true,
         perms: 0,
     });
     if revoked > 0 {
         state.hub.broadcast(WsEvent::AdminChanged {
-            server: key,
-            username: user.username,
+            server: key.clone(),
+            username: from.clone(),
             is_admin: false,
             perms: 0,
         });
     }
+    close_transfer(&state, &key, from, user.username, true);
     Ok(ok())
 }
 
+#[utoipa::path(post, path = "/api/servers/{name}/transfer/decline", params(("name" = String, Path)), responses((status = 200, body = OkResp)), security(("bearer" = [])))]
+pub(crate) async fn decline_transfer(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+) -> Result<Json<OkResp>, ApiError> {
+    let key = name.to_lowercase();
+    let row = sqlx::query(
+        "DELETE FROM server_transfers WHERE server = $1 AND (from_user = $2 OR to_user = $2) RETURNING from_user, to_user",
+    )
+    .bind(&key)
+    .bind(&user.username)
+    .fetch_optional(&state.db)
+    .await?;
+    match row {
+        Some(r) => {
+            close_transfer(&state, &key, r.try_get(0)?, r.try_get(1)?, false);
+            Ok(ok())
+        }
+        None => Err(not_found("No pending transfer")),
+    }
+}
+
+#[utoipa::path(get, path = "/api/transfers", responses((status = 200, body = Vec<PendingTransfer>)), security(("bearer" = [])))]
+pub(crate) async fn list_transfers(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+) -> Result<Json<Vec<PendingTransfer>>, ApiError> {
+    let rows = sqlx::query(
+        "SELECT server, from_user, to_user, expires_at FROM server_transfers WHERE (from_user = $1 OR to_user = $1) AND expires_at > $2 ORDER BY expires_at",
+    )
+    .bind(&user.username)
+    .bind(now())
+    .fetch_all(&state.db)
+    .await?;
+    let mut out = Vec::with_capacity(rows.len());
+    for r in &rows {
+        out.push(PendingTransfer {
+            server: r.try_get(0)?,
+            from_user: r.try_get(1)?,
+            to_user: r.try_get(2)?,
+            expires_at: r.try_get(3)?,
+        });
+    }
+    Ok(Json(out))
+}
+
+fn close_transfer(
+    state: &AppState,
+    server: &str,
+    from_user: String,
+    to_user: String,
+    accepted: bool,
+) {
+    state.hub.broadcast(WsEvent::TransferClosed {
+        server: server.to_string(),
+        from_user,
+        to_user,
+        accepted,
+    });
+}
+
+async fn expire_transfers(state: &AppState, at: i64) -> Result<(), ApiError> {
+    let rows = sqlx::query(
+        "DELETE FROM server_transfers WHERE expires_at <= $1 RETURNING server, from_user, to_user",
+    )
+    .bind(at)
+    .fetch_all(&state.db)
+    .await?;
+    for r in &rows {
+        close_transfer(
+            state,
+            &r.try_get::<String, _>(0)?,
+            r.try_get(1)?,
+            r.try_get(2)?,
+            false,
+        );
+    }
+    Ok(())
+}
+
+pub async fn sweep_transfers(state: &AppState) {
+    if let Err(ApiError(_, message)) = expire_transfers(state, now()).await {
+        tracing::warn!("transfer sweep failed: {message}");
+    }
+}
+
+pub async fn sweep_server_creations(state: &AppState) {
+    if let Err(e) = sqlx::query("DELETE FROM server_creations WHERE created_at <= $1")
+        .bind(now() - CREATION_WINDOW_SECS)
+        .execute(&state.db)
+        .await
+    {
+        tracing::warn!("server creation log sweep failed: {e}");
+    }
+}
+
 #[utoipa::path(patch, path = "/api/servers/{name}/admins/{username}/perms", params(("name" = String, Path), ("username" = String, Path)), request_body = PermsReq, responses((status = 200, body = OkResp)), security(("bearer" = [])))]
 pub(crate) async fn set_admin_perms(
     State(state): State<AppState>,
@@ -1243,6 +2218,7 @@ pub(crate) async fn unassign_role(
 
 async fn channel_perm_guard(state: &AppState, id: i64, user: &User) -> Result<String, ApiError> {
     let server = channel_server(&state.db, id).await?;
+    require_channel_view(&state.db, &server, id, user).await?;
     require_perm(&state.db, &server, user, Perm::ManageChannels).await?;
     Ok(server)
 }
@@ -1349,7 +2325,8 @@ pub(crate) async fn clear_channel_perm(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api::test_util::{done, mem_user, temp_state};
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+    use serde_json::json;
 
     #[tokio::test]
     async fn sole_channel_guard() {
@@ -1409,4 +2386,928 @@ mod tests {
         ));
         done(state, path).await;
     }
+
+    #[tokio::test]
+    async fn channel_names_are_unique_per_server() {
+        let (state, path) = temp_state("channel_names").await;
+        let create = |server: &str, name: &str| {
+            create_channel(
+                State(state.clone()),
+                Authed(mem_user("root", true)),
+                Path(server.to_string()),
+                Json(CreateChannelReq {
+                    name: name.to_string(),
+                    kind: None,
+                }),
+            )
+        };
+        let dup = create("rchat", " General ").await;
+        assert!(matches!(dup, Err(ApiError(StatusCode::CONFLICT, _))));
+        let long = create("rchat", &"x".repeat(MAX_CHANNEL_NAME_CHARS + 1)).await;
+        assert!(matches!(long, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let Json(random) = create("rchat", "random").await.expect("new name");
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('den', 'Den', NULL, 0)")
+            .execute(&state.db)
+            .await
+            .expect("insert server");
+        let _ = create("den", "general").await.expect("other server");
+        let rename = |name: &str| {
+            update_channel(
+                State(state.clone()),
+                Authed(mem_user("root", true)),
+                Path(random.id),
+                Json(ChannelPatch {
+                    name: Some(name.to_string()),
+                    slowmode_seconds: None,
+                    message_retention_hours: None,
+                    allowed_content_types: None,
+                }),
+            )
+        };
+        let clash = rename("GENERAL").await;
+        assert!(matches!(clash, Err(ApiError(StatusCode::CONFLICT, _))));
+        let Json(recased) = rename("Random").await.expect("own name in new case");
+        assert_eq!(recased.name, "Random");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn retention_is_capped() {
+        let (state, path) = temp_state("retention_cap").await;
+        let retain = |hours: i64| {
+            update_channel(
+                State(state.clone()),
+                Authed(mem_user("root", true)),
+                Path(1),
+                Json(ChannelPatch {
+                    name: None,
+                    slowmode_seconds: None,
+                    message_retention_hours: Some(hours),
+                    allowed_content_types: None,
+                }),
+            )
+        };
+        let huge = retain(i64::MAX).await;
+        assert!(matches!(huge, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let Json(kept) = retain(MAX_RETENTION_HOURS).await.expect("at the cap");
+        assert_eq!(kept.message_retention_hours, Some(MAX_RETENTION_HOURS));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn reorder_channels_changes_order() {
+        let (state, path) = temp_state("reorder_channels").await;
+        for name in ["second", "third"] {
+            sqlx::query(
+                "INSERT INTO channels(server, name, position, created_at) VALUES('rchat', $1, 0, $2)",
+            )
+            .bind(name)
+            .bind(now())
+            .execute(&state.db)
+            .await
+            .expect("insert channel");
+        }
+        let before = server_detail(&state.db, &state.hub, "rchat", None)
+            .await
+            .expect("detail");
+        let mut ids: Vec<i64> = before.channels.iter().map(|c| c.id).collect();
+        assert_eq!(ids.len(), 3);
+        ids.reverse();
+        let partial = reorder_channels(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("rchat".to_string()),
+            Json(ReorderChannelsReq {
+                ids: ids[..2].to_vec(),
+            }),
+        )
+        .await;
+        assert!(matches!(partial, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let denied = reorder_channels(
+            State(state.clone()),
+            Authed(mem_user("ned", false)),
+            Path("rchat".to_string()),
+            Json(ReorderChannelsReq { ids: ids.clone() }),
+        )
+        .await;
+        assert!(denied.is_err());
+        let _ = reorder_channels(
+            State(state.clone()),
+            Authed(mem_user("root", true)),
+            Path("RChat".to_string()),
+            Json(ReorderChannelsReq { ids: ids.clone() }),
+        )
+        .await
+        .expect("reorder");
+        let after = server_detail(&state.db, &state.hub, "rchat", None)
+            .await
+            .expect("detail");
+        let order: Vec<i64> = after.channels.iter().map(|c| c.id).collect();
+        assert_eq!(order, ids);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn reserved_server_names() {
+        let extra = vec![squash("Help Desk")];
+        for name in [
+            "RChat",
+            "r-chat",
+            "R C H A T",
+            "Admin",
+            "SYSTEM",
+            "api",
+            "help.desk",
+        ] {
+            assert!(
+                matches!(
+                    check_reserved(name, &extra),
+                    Err(ApiError(StatusCode::BAD_REQUEST, _))
+                ),
+                "{name} should be reserved"
+            );
+        }
+        for name in ["rchat fans", "admins", "systems lab", "helpdesk2"] {
+            assert!(
+                check_reserved(name, &extra).is_ok(),
+                "{name} should be allowed"
+            );
+        }
+        let (state, path) = temp_state("reserved_names").await;
+        let blocked = create_server(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Json(CreateServerReq {
+                name: "R.Chat".to_string(),
+                password: None,
+            }),
+        )
+        .await;
+        assert!(matches!(blocked, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn racing_creates_make_one_server() {
+        let (state, path) = temp_state("create_race").await;
+        let mut creates = tokio::task::JoinSet::new();
+        for name in ["amy", "bob", "cat", "dan", "eve", "fay"] {
+            let state = state.clone();
+            creates.spawn(async move {
+                create_server(
+                    State(state),
+                    Authed(mem_user(name, false)),
+                    Json(CreateServerReq {
+                        name: "Racers".to_string(),
+                        password: None,
+                    }),
+                )
+                .await
+                .map(|Json(detail)| detail.name)
+            });
+        }
+        let results = creates.join_all().await;
+        let won: Vec<&String> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+        assert_eq!(won, vec!["racers"]);
+        for lost in results.iter().filter_map(|r| r.as_ref().err()) {
+            assert_eq!(lost.0, StatusCode::BAD_REQUEST);
+            assert_eq!(lost.1, "Server name is taken");
+        }
+        let members: i64 = sqlx::query("SELECT COUNT(*) FROM members WHERE server = 'racers'")
+            .fetch_one(&state.db)
+            .await
+            .expect("count members")
+            .try_get(0)
+            .expect("count");
+        assert_eq!(members, 1);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn server_creation_policy() {
+        let (state, path) = temp_state("creation_policy").await;
+        let db = &state.db;
+        let admins_only = CreationPolicy {
+            admins_only: true,
+            per_day: None,
+        };
+        let blocked = check_creation(db, &mem_user("amy", false), admins_only).await;
+        assert!(matches!(blocked, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        check_creation(db, &mem_user("root", true), admins_only)
+            .await
+            .expect("site admin may create");
+        for (name, at) in [("old", 0), ("one", now()), ("gone", now())] {
+            sqlx::query(
+                "INSERT INTO server_creations(username, server, created_at) VALUES('amy', $1, $2)",
+            )
+            .bind(name)
+            .bind(at)
+            .execute(db)
+            .await
+            .expect("log creation");
+        }
+        let limited = |per_day| CreationPolicy {
+            admins_only: false,
+            per_day: Some(per_day),
+        };
+        check_creation(db, &mem_user("amy", false), limited(3))
+            .await
+            .expect("under the limit");
+        let capped = check_creation(db, &mem_user("amy", false), limited(2)).await;
+        assert!(matches!(
+            capped,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        check_creation(db, &mem_user("bob", false), limited(2))
+            .await
+            .expect("limit is per user");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn my_channels_covers_member_servers() {
+        let (state, path) = temp_state("my_channels").await;
+        let db = &state.db;
+        for name in ["amys", "other"] {
+            sqlx::query("INSERT INTO servers(name, display_name, created_at) VALUES($1, $1, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert server");
+            sqlx::query("INSERT INTO channels(server, name, created_at) VALUES($1, 'general', 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert channel");
+        }
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "amys", "amy", 1, 0, 0).await;
+        let hidden: i64 = sqlx::query(
+            "INSERT INTO channels(server, name, created_at) VALUES('rchat', 'staff', 0) RETURNING id",
+        )
+        .fetch_one(db)
+        .await
+        .expect("insert private channel")
+        .try_get(0)
+        .expect("channel id");
+        sqlx::query(
+            "INSERT INTO channel_perms(channel_id, subject, can_view, can_send, can_read_history) VALUES($1, 'u:bob', 1, 1, 1)",
+        )
+        .bind(hidden)
+        .execute(db)
+        .await
+        .expect("restrict channel");
+        let Json(map) = my_channels(State(state.clone()), Authed(mem_user("amy", false)))
+            .await
+            .expect("my channels");
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["amys", "rchat"]);
+        let names: Vec<&str> = map["rchat"].iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["general"]);
+        assert_eq!(map["amys"].len(), 1);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn membership_requires_member() {
+        let (state, path) = temp_state("membership").await;
+        add_member(&state.db, "rchat", "mia", 1, Perm::Kick as i64, now()).await;
+        let outsider = get_membership(
+            State(state.clone()),
+            Authed(mem_user("ned", false)),
+            Path("RChat".to_string()),
+        )
+        .await;
+        assert!(matches!(outsider, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        let Json(Membership {
+            server,
+            member,
+            effective_perms,
+        }) = get_membership(
+            State(state.clone()),
+            Authed(mem_user("mia", false)),
+            Path("rchat".to_string()),
+        )
+        .await
+        .expect("member view");
+        assert_eq!(server.name, "rchat");
+        assert!(member.is_admin);
+        assert!(!member.is_creator);
+        assert_eq!(member.perms, Perm::Kick as i64);
+        assert_eq!(effective_perms, Perm::Kick as i64);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn member_filters_and_sorts() {
+        let (state, path) = temp_state("member_sort").await;
+        let db = &state.db;
+        add_member(db, "rchat", "ann", 1, 0, 3).await;
+        add_member(db, "rchat", "bob", 0, 0, 1).await;
+        add_member(db, "rchat", "cat", 0, 0, 2).await;
+        for (user, at) in [("bob", 50), ("ann", 10)] {
+            sqlx::query(
+                "INSERT INTO interactions(server, username, last_at) VALUES('rchat', $1, $2)",
+            )
+            .bind(user)
+            .bind(at)
+            .execute(db)
+            .await
+            .expect("insert interaction");
+        }
+        state.hub.set_viewing("cat", None, Some("rchat"));
+        let names = |query: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                let Paged { items: members, .. } = list_members(
+                    State(state),
+                    MaybeAuthed(Some(mem_user("ann", false))),
+                    HeaderMap::new(),
+                    Path("rchat".to_string()),
+                    Query(serde_json::from_value(query).expect("query")),
+                )
+                .await
+                .expect("list members");
+                members
+                    .into_iter()
+                    .map(|m| m.user.username)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(names(json!({})).await, ["cat", "ann", "bob"]);
+        assert_eq!(names(json!({ "role": "admin" })).await, ["ann"]);
+        assert_eq!(names(json!({ "role": "member" })).await, ["cat", "bob"]);
+        assert_eq!(
+            names(json!({ "sort": "name" })).await,
+            ["ann", "bob", "cat"]
+        );
+        assert_eq!(
+            names(json!({ "sort": "joined" })).await,
+            ["bob", "cat", "ann"]
+        );
+        assert_eq!(
+            names(json!({ "sort": "last_seen" })).await,
+            ["bob", "ann", "cat"]
+        );
+        assert_eq!(names(json!({ "online": true })).await, ["cat"]);
+        assert_eq!(
+            names(json!({ "role": "member", "sort": "joined" })).await,
+            ["bob", "cat"]
+        );
+        assert!(
+            names(json!({ "role": "admin", "online": true }))
+                .await
+                .is_empty()
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn bulk_add_members_reports_each_name() {
+        let (state, path) = temp_state("bulk_members").await;
+        for name in ["amy", "bob", "cat"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(&state.db)
+                .await
+                .expect("insert user");
+        }
+        sqlx::query("INSERT INTO banned_usernames(username) VALUES('bob')")
+            .execute(&state.db)
+            .await
+            .expect("ban bob");
+        add_member(&state.db, "rchat", "cat", 0, 0, now()).await;
+        let req = || {
+            Json(BulkMembersReq {
+                usernames: ["Amy", "bob", "ghost", "cat", "amy"]
+                    .map(String::from)
+                    .to_vec(),
+            })
+        };
+        let denied = bulk_add_members(
+            State(state.clone()),
+            Authed(mem_user("cat", false)),
+            Path("rchat".to_string()),
+            req(),
+        )
+        .await;
+        assert!(matches!(denied, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        add_member(
+            &state.db,
+            "rchat",
+            "keeper",
+            1,
+            Perm::ManageAdmins as i64,
+            now(),
+        )
+        .await;
+        add_member(&state.db, "rchat", "bouncer", 1, Perm::Kick as i64, now()).await;
+        let keeper = bulk_add_members(
+            State(state.clone()),
+            Authed(mem_user("keeper", false)),
+            Path("rchat".to_string()),
+            req(),
+        )
+        .await;
+        assert!(matches!(keeper, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        let Json(results) = bulk_add_members(
+            State(state.clone()),
+            Authed(mem_user("bouncer", false)),
+            Path("rchat".to_string()),
+            req(),
+        )
+        .await
+        .expect("bulk add");
+        let got: Vec<(&str, BulkOutcome)> = results
+            .iter()
+            .map(|r| (r.username.as_str(), r.outcome))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("amy", BulkOutcome::Added),
+                ("bob", BulkOutcome::Banned),
+                ("ghost", BulkOutcome::NotFound),
+                ("cat", BulkOutcome::AlreadyMember),
+            ]
+        );
+        let members: Vec<String> =
+            sqlx::query("SELECT username FROM members WHERE server = 'rchat' ORDER BY username")
+                .fetch_all(&state.db)
+                .await
+                .expect("members")
+                .iter()
+                .map(|r| r.try_get(0).expect("username"))
+                .collect();
+        assert!(members.contains(&"amy".to_string()));
+        assert!(!members.contains(&"bob".to_string()));
+        assert!(!members.contains(&"ghost".to_string()));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn presence_needs_a_shared_server() {
+        let (state, path) = temp_state("user_presence").await;
+        let db = &state.db;
+        for name in ["amy", "bob", "cat"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert user");
+        }
+        sqlx::query(
+            "INSERT INTO servers(name, display_name, created_at) VALUES('hidden', 'hidden', 0)",
+        )
+        .execute(db)
+        .await
+        .expect("insert server");
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        add_member(db, "hidden", "bob", 0, 0, 0).await;
+        add_member(db, "hidden", "cat", 0, 0, 0).await;
+        crate::db::mark_seen(db, "bob").await.expect("mark seen");
+        state.hub.connect("bob", 1);
+        state.hub.set_viewing("bob", None, Some("hidden"));
+        let presence = |viewer: &str, target: &str| {
+            user_presence(
+                State(state.clone()),
+                Authed(mem_user(viewer, false)),
+                Path(target.to_string()),
+            )
+        };
+        let Json(seen) = presence("amy", "Bob").await.expect("shared server");
+        assert_eq!(seen.username, "bob");
+        assert!(seen.last_seen.is_some());
+        assert_eq!(seen.status, PresenceStatus::Offline);
+        let servers: Vec<&str> = seen.servers.iter().map(|s| s.server.as_str()).collect();
+        assert_eq!(servers, vec!["rchat"]);
+        let Json(from_cat) = presence("cat", "bob").await.expect("shared server");
+        assert_eq!(from_cat.status, PresenceStatus::Online);
+        assert!(matches!(
+            presence("amy", "cat").await,
+            Err(ApiError(StatusCode::NOT_FOUND, _))
+        ));
+        assert!(matches!(
+            presence("amy", "nobody").await,
+            Err(ApiError(StatusCode::NOT_FOUND, _))
+        ));
+        let Json(own) = presence("amy", "amy").await.expect("self");
+        assert_eq!(own.last_seen, None);
+        let bob_seen = |viewer: Option<User>, server: &str| {
+            let listing = list_members(
+                State(state.clone()),
+                MaybeAuthed(viewer),
+                HeaderMap::new(),
+                Path(server.to_string()),
+                Query(MemberListQuery {
+                    offset: None,
+                    limit: None,
+                    role: None,
+                    sort: None,
+                    online: None,
+                }),
+            );
+            async move {
+                listing
+                    .await
+                    .expect("members")
+                    .items
+                    .into_iter()
+                    .find(|m| m.user.username == "bob")
+                    .expect("bob listed")
+                    .last_seen
+            }
+        };
+        assert_eq!(
+            bob_seen(Some(mem_user("amy", false)), "rchat").await,
+            seen.last_seen
+        );
+        assert_eq!(bob_seen(None, "rchat").await, None);
+        assert_eq!(bob_seen(Some(mem_user("amy", false)), "hidden").await, None);
+        assert_eq!(
+            bob_seen(Some(mem_user("root", true)), "hidden").await,
+            seen.last_seen
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn server_cap_limits_joins() {
+        let (state, path) = temp_state("server_cap").await;
+        let db = &state.db;
+        for name in ["one", "two"] {
+            sqlx::query("INSERT INTO servers(name, display_name, created_at) VALUES($1, $1, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert server");
+        }
+        sqlx::query("INSERT INTO settings(key, value) VALUES('max_servers_per_user', '1')")
+            .execute(db)
+            .await
+            .expect("set cap");
+        assert_eq!(server_cap(db, &state.config).await, Some(1));
+        let join = |name: &str, server: &str, admin: bool| {
+            join_server(
+                State(state.clone()),
+                Authed(mem_user(name, admin)),
+                Path(server.to_string()),
+                None,
+            )
+        };
+        let _ = join("amy", "one", false).await.expect("first server");
+        let _ = join("amy", "one", false).await.expect("rejoin is a no-op");
+        let _ = join("amy", "rchat", false).await.expect("rchat is exempt");
+        assert!(matches!(
+            join("amy", "two", false).await,
+            Err(ApiError(StatusCode::BAD_REQUEST, _))
+        ));
+        let _ = join("root", "one", true).await.expect("site admin");
+        let _ = join("root", "two", true)
+            .await
+            .expect("site admin is exempt");
+        sqlx::query("UPDATE settings SET value = '2' WHERE key = 'max_servers_per_user'")
+            .execute(db)
+            .await
+            .expect("raise cap");
+        let _ = join("amy", "two", false).await.expect("raised cap");
+        let create = |name: &str, admin: bool| {
+            create_server(
+                State(state.clone()),
+                Authed(mem_user(name, admin)),
+                Json(serde_json::from_value(serde_json::json!({ "name": "three" })).expect("req")),
+            )
+        };
+        assert!(matches!(
+            create("amy", false).await,
+            Err(ApiError(StatusCode::BAD_REQUEST, _))
+        ));
+        let _ = create("root", true).await.expect("site admin is exempt");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn welcome_message_greets_new_members_once() {
+        let (state, path) = temp_state("welcome").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('club', 'club', 'root', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        add_member(db, "club", "root", 1, ALL_PERMS, 0).await;
+        let lite = require_server(db, "club").await.expect("server");
+        let _ = apply_server_patch(
+            &state,
+            "club".to_string(),
+            lite,
+            ServerPatch {
+                name: None,
+                password: None,
+                welcome_message: Some("  Read the rules first  ".to_string()),
+            },
+        )
+        .await
+        .expect("set welcome");
+        let mut rx = state.hub.subscribe();
+        for _ in 0..2 {
+            let Json(detail) = join_server(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Path("club".to_string()),
+                None,
+            )
+            .await
+            .expect("join");
+            assert_eq!(
+                detail.welcome_message.as_deref(),
+                Some("Read the rules first")
+            );
+        }
+        let mut welcomes = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            if let WsEvent::ServerWelcome {
+                server,
+                username,
+                message,
+            } = ev
+            {
+                welcomes.push((server, username, message));
+            }
+        }
+        assert_eq!(
+            welcomes,
+            vec![(
+                "club".to_string(),
+                "amy".to_string(),
+                "Read the rules first".to_string()
+            )]
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn capabilities_by_role() {
+        let (state, path) = temp_state("capabilities").await;
+        let db = &state.db;
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "mod", 1, Perm::DeleteMessages as i64, 0).await;
+        add_member(db, "rchat", "boss", 1, 0, 0).await;
+        let staff: i64 = sqlx::query("INSERT INTO channels(server, name, position, created_at) VALUES('rchat', 'staff', 1, 0) RETURNING id")
+            .fetch_one(db)
+            .await
+            .expect("insert channel")
+            .try_get(0)
+            .expect("channel id");
+        sqlx::query("INSERT INTO channel_perms(channel_id, subject, can_view, can_send, can_read_history) VALUES($1, 'u:mod', 1, 0, 1)")
+            .bind(staff)
+            .execute(db)
+            .await
+            .expect("restrict channel");
+        let server_caps = |name: &str, admin: bool| {
+            server_permissions(
+                State(state.clone()),
+                Authed(mem_user(name, admin)),
+                Path("RChat".to_string()),
+            )
+        };
+        let Json(amy) = server_caps("amy", false).await.expect("member");
+        assert!(amy.is_member && amy.can_post);
+        assert!(!amy.can_delete_others && !amy.can_kick && !amy.can_manage_channels);
+        let Json(moderator) = server_caps("mod", false).await.expect("moderator");
+        assert!(moderator.can_delete_others && !moderator.can_kick);
+        let Json(boss) = server_caps("boss", false).await.expect("server admin");
+        assert_eq!(boss.effective_perms, ALL_PERMS);
+        assert!(boss.can_manage_admins && boss.can_delete_server);
+        let Json(root) = server_caps("root", true).await.expect("site admin");
+        assert!(!root.is_member && !root.can_post);
+        assert_eq!(root.effective_perms, ALL_PERMS);
+        let Json(outsider) = server_caps("zed", false).await.expect("open server");
+        assert!(outsider.can_view && !outsider.can_post);
+        sqlx::query("INSERT INTO servers(name, display_name, password_hash, created_at) VALUES('vault', 'Vault', 'x', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        let vault = |name: &str, admin: bool| {
+            server_permissions(
+                State(state.clone()),
+                Authed(mem_user(name, admin)),
+                Path("vault".to_string()),
+            )
+        };
+        let Json(locked) = vault("amy", false).await.expect("locked server");
+        assert!(!locked.is_member && !locked.can_view && !locked.can_read_history);
+        assert!(!locked.can_post);
+        let Json(root) = vault("root", true).await.expect("site admin");
+        assert!(root.can_view && !root.can_post);
+        let channel_caps = |name: &str| {
+            channel_permissions(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path(staff),
+            )
+        };
+        let hidden = channel_caps("amy").await;
+        assert!(matches!(hidden, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let Json(moderator) = channel_caps("mod").await.expect("moderator");
+        assert!(moderator.can_view && moderator.can_read_history && !moderator.can_post);
+        let Json(boss) = channel_caps("boss").await.expect("server admin");
+        assert!(boss.can_view && boss.can_post);
+        assert!(matches!(
+            server_permissions(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Path("nowhere".to_string()),
+            )
+            .await,
+            Err(ApiError(StatusCode::NOT_FOUND, _))
+        ));
+        done(state, path).await;
+    }
+
+    async fn transfer_state(tag: &str) -> (AppState, std::path::PathBuf) {
+        let (state, path) = temp_state(tag).await;
+        for (name, is_admin) in [("boss", 1), ("amy", 0)] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(&state.db)
+                .await
+                .expect("insert user");
+            add_member(&state.db, "rchat", name, is_admin, 0, 0).await;
+        }
+        (state, path)
+    }
+
+    async fn is_admin(db: &Db, username: &str) -> bool {
+        sqlx::query("SELECT is_admin FROM members WHERE server = 'rchat' AND username = $1")
+            .bind(username)
+            .fetch_one(db)
+            .await
+            .expect("member")
+            .try_get::<i64, _>(0)
+            .expect("is_admin")
+            != 0
+    }
+
+    async fn offer(state: &AppState) -> PendingTransfer {
+        let Json(transfer) = transfer_admin(
+            State(state.clone()),
+            Authed(mem_user("boss", false)),
+            Path("rchat".to_string()),
+            Json(UsernameReq {
+                username: "Amy".to_string(),
+            }),
+        )
+        .await
+        .expect("offer transfer");
+        transfer
+    }
+
+    #[tokio::test]
+    async fn transfer_waits_for_acceptance() {
+        let (state, path) = transfer_state("transfer_accept").await;
+        let db = &state.db;
+        let mut rx = state.hub.subscribe();
+        let transfer = offer(&state).await;
+        assert_eq!(transfer.to_user, "amy");
+        assert!(is_admin(db, "boss").await && !is_admin(db, "amy").await);
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::TransferOffered { .. })));
+        assert!(rx.try_recv().is_err());
+        let respond = |name: &str, accept: bool| {
+            let (state, user) = (state.clone(), mem_user(name, false));
+            async move {
+                let path = Path("rchat".to_string());
+                match accept {
+                    true => accept_transfer(State(state), Authed(user), path).await,
+                    false => decline_transfer(State(state), Authed(user), path).await,
+                }
+            }
+        };
+        let stranger = respond("boss", true).await;
+        assert!(matches!(stranger, Err(ApiError(StatusCode::NOT_FOUND, _))));
+
+        let _ = respond("amy", false).await.expect("decline");
+        assert!(is_admin(db, "boss").await && !is_admin(db, "amy").await);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::TransferClosed {
+                accepted: false,
+                ..
+            })
+        ));
+        let gone = respond("amy", true).await;
+        assert!(matches!(gone, Err(ApiError(StatusCode::NOT_FOUND, _))));
+
+        offer(&state).await;
+        let _ = rx.try_recv();
+        let _ = respond("amy", true).await.expect("accept");
+        assert!(!is_admin(db, "boss").await && is_admin(db, "amy").await);
+        let mut changed = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            match ev {
+                WsEvent::AdminChanged {
+                    username, is_admin, ..
+                } => changed.push((username, is_admin)),
+                WsEvent::TransferClosed { accepted, .. } => assert!(accepted),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            changed,
+            vec![("amy".to_string(), true), ("boss".to_string(), false)]
+        );
+        let again = respond("amy", true).await;
+        assert!(matches!(again, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn transfer_fails_once_the_target_leaves() {
+        let (state, path) = transfer_state("transfer_left").await;
+        let db = &state.db;
+        offer(&state).await;
+        let _ = leave_server(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path("rchat".to_string()),
+        )
+        .await
+        .expect("leave");
+        let mut rx = state.hub.subscribe();
+        let accept = || {
+            accept_transfer(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Path("rchat".to_string()),
+            )
+        };
+        let left = accept().await;
+        assert!(matches!(left, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::TransferClosed {
+                accepted: false,
+                ..
+            })
+        ));
+        assert!(is_admin(db, "boss").await);
+        let amy: i64 = sqlx::query("SELECT COUNT(*) FROM members WHERE username = 'amy'")
+            .fetch_one(db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count");
+        assert_eq!(amy, 0);
+        let again = accept().await;
+        assert!(matches!(again, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn unaccepted_transfers_expire() {
+        let (state, path) = transfer_state("transfer_expiry").await;
+        let db = &state.db;
+        let transfer = offer(&state).await;
+        let pending =
+            |name: &str| list_transfers(State(state.clone()), Authed(mem_user(name, false)));
+        let Json(listed) = pending("amy").await.expect("list");
+        assert_eq!(listed.len(), 1);
+        expire_transfers(&state, transfer.expires_at - 1)
+            .await
+            .expect("early sweep");
+        let Json(listed) = pending("boss").await.expect("list");
+        assert_eq!(listed.len(), 1);
+
+        sqlx::query("UPDATE server_transfers SET expires_at = $1")
+            .bind(now())
+            .execute(db)
+            .await
+            .expect("age transfer");
+        let Json(listed) = pending("amy").await.expect("list");
+        assert!(listed.is_empty());
+        let late = accept_transfer(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path("rchat".to_string()),
+        )
+        .await;
+        assert!(matches!(late, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let mut rx = state.hub.subscribe();
+        expire_transfers(&state, now()).await.expect("sweep");
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::TransferClosed {
+                accepted: false,
+                ..
+            })
+        ));
+        let left: i64 = sqlx::query("SELECT COUNT(*) FROM server_transfers")
+            .fetch_one(db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count");
+        assert_eq!(left, 0);
+        assert!(is_admin(db, "boss").await && !is_admin(db, "amy").await);
+        done(state, path).await;
+    }
 }
@@ -0,0 +1,252 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::servers::{require_perm, require_server};
+use crate::api::{ApiError, Authed, UserRef, user_ref};
+use crate::db::{ActivityKind, Db, Perm, now};
+use crate::state::AppState;
+
+const DAY_SECS: i64 = 86400;
+const MAX_DAYS: i64 = 90;
+const TOP_MEMBERS: i64 = 10;
+const COUNTED_KINDS: &str = "m.kind IN ('user', 'encrypted')";
+
+#[derive(Default, Deserialize, IntoParams)]
+pub struct StatsQuery {
+    days: Option<i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DayCount {
+    pub day: i64,
+    pub count: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChannelVolume {
+    pub channel_id: i64,
+    pub name: String,
+    pub messages: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MemberVolume {
+    pub user: UserRef,
+    pub messages: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ServerStats {
+    pub since: i64,
+    pub members: i64,
+    pub joins: Vec<DayCount>,
+    pub channels: Vec<ChannelVolume>,
+    pub top_members: Vec<MemberVolume>,
+}
+
+async fn joins_per_day(db: &Db, server: &str, since: i64) -> Result<Vec<DayCount>, ApiError> {
+    let rows = sqlx::query(
+        "SELECT created_at / 86400, COUNT(*) FROM server_activity WHERE server = $1 AND kind = $2 AND created_at >= $3 GROUP BY created_at / 86400 ORDER BY created_at / 86400",
+    )
+    .bind(server)
+    .bind(ActivityKind::MemberJoined.as_str())
+    .bind(since)
+    .fetch_all(db)
+    .await?;
+    let mut days = Vec::with_capacity(rows.len());
+    for r in &rows {
+        days.push(DayCount {
+            day: r.try_get::<i64, _>(0)? * DAY_SECS,
+            count: r.try_get(1)?,
+        });
+    }
+    Ok(days)
+}
+
+#[utoipa::path(get, path = "/api/servers/{name}/stats", params(("name" = String, Path), StatsQuery), responses((status = 200, body = ServerStats)), security(("bearer" = [])))]
+pub(crate) async fn server_stats(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(name): Path<String>,
+    Query(q): Query<StatsQuery>,
+) -> Result<Json<ServerStats>, ApiError> {
+    let db = &state.db;
+    let server = require_server(db, &name.to_lowercase()).await?;
+    require_perm(db, &server.name, &user, Perm::DeleteServer).await?;
+    let days = q.days.unwrap_or(30).clamp(1, MAX_DAYS);
+    let since = (now() / DAY_SECS - days + 1) * DAY_SECS;
+    let members: i64 = sqlx::query("SELECT COUNT(*) FROM members WHERE server = $1")
+        .bind(&server.name)
+        .fetch_one(db)
+        .await?
+        .try_get(0)?;
+    let joins = joins_per_day(db, &server.name, since).await?;
+    let volume_sql = format!(
+        "SELECT c.id, c.name, COUNT(m.id) FROM channels c LEFT JOIN messages m ON m.channel_id = c.id AND m.deleted_at IS NULL AND m.created_at >= $2 AND {COUNTED_KINDS} WHERE c.server = $1 GROUP BY c.id, c.name ORDER BY COUNT(m.id) DESC, c.id"
+    );
+    let rows = sqlx::query(&volume_sql)
+        .bind(&server.name)
+        .bind(since)
+        .fetch_all(db)
+        .await?;
+    let mut channels = Vec::with_capacity(rows.len());
+    for r in &rows {
+        channels.push(ChannelVolume {
+            channel_id: r.try_get(0)?,
+            name: r.try_get(1)?,
+            messages: r.try_get(2)?,
+        });
+    }
+    let top_sql = format!(
+        "SELECT m.author, COUNT(*) FROM messages m JOIN channels c ON c.id = m.channel_id WHERE c.server = $1 AND m.deleted_at IS NULL AND m.created_at >= $2 AND {COUNTED_KINDS} GROUP BY m.author ORDER BY COUNT(*) DESC, m.author LIMIT $3"
+    );
+    let rows = sqlx::query(&top_sql)
+        .bind(&server.name)
+        .bind(since)
+        .bind(TOP_MEMBERS)
+        .fetch_all(db)
+        .await?;
+    let mut top_members = Vec::with_capacity(rows.len());
+    for r in &rows {
+        let author: String = r.try_get(0)?;
+        top_members.push(MemberVolume {
+            user: user_ref(db, &author).await,
+            messages: r.try_get(1)?,
+        });
+    }
+    Ok(Json(ServerStats {
+        since,
+        members,
+        joins,
+        channels,
+        top_members,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn joins_bucket_by_day() {
+        let (state, path) = temp_state("stats").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('den', 'Den', 'amy', 0)")
+            .execute(db)
+            .await
+            .expect("insert server");
+        let today = now() / DAY_SECS * DAY_SECS;
+        let yesterday = today - DAY_SECS;
+        add_member(db, "den", "amy", 1, 0, 0).await;
+        add_member(db, "den", "bob", 0, 0, 0).await;
+        add_member(db, "den", "cat", 0, 0, 0).await;
+        add_member(db, "den", "kit", 1, Perm::Kick as i64, 0).await;
+        for (kind, username, at) in [
+            ("member_joined", "amy", today - 40 * DAY_SECS),
+            ("member_joined", "bob", yesterday),
+            ("member_joined", "cat", yesterday + DAY_SECS - 1),
+            ("member_left", "cat", today),
+            ("member_joined", "dan", today),
+            ("member_left", "dan", today),
+        ] {
+            sqlx::query("INSERT INTO server_activity(server, kind, actor, target, created_at) VALUES('den', $1, $2, $2, $3)")
+                .bind(kind)
+                .bind(username)
+                .bind(at)
+                .execute(db)
+                .await
+                .expect("insert activity");
+        }
+
+        let stats = |name: &str, days: Option<i64>| {
+            server_stats(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path("Den".to_string()),
+                Query(StatsQuery { days }),
+            )
+        };
+        let Json(week) = stats("amy", Some(7)).await.expect("stats");
+        assert_eq!(week.members, 4);
+        assert_eq!(
+            week.joins,
+            vec![
+                DayCount {
+                    day: yesterday,
+                    count: 2
+                },
+                DayCount {
+                    day: today,
+                    count: 1
+                },
+            ]
+        );
+        let Json(today_only) = stats("amy", Some(1)).await.expect("stats");
+        assert_eq!(
+            today_only.joins,
+            vec![DayCount {
+                day: today,
+                count: 1
+            }]
+        );
+        let Json(wide) = stats("amy", Some(1000)).await.expect("stats");
+        assert_eq!(wide.since, today - (MAX_DAYS - 1) * DAY_SECS);
+        assert_eq!(wide.joins.first().map(|d| d.count), Some(1));
+
+        for name in ["bob", "kit"] {
+            let denied = stats(name, None).await;
+            assert!(matches!(denied, Err(ApiError(StatusCode::FORBIDDEN, _))));
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn volume_and_top_members_count_the_same_kinds() {
+        let (state, path) = temp_state("stats_kinds").await;
+        let db = &state.db;
+        add_member(db, "rchat", "amy", 1, 0, 0).await;
+        let general: i64 = sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("general")
+            .try_get(0)
+            .expect("channel id");
+        for (author, kind) in [
+            ("amy", "user"),
+            ("bob", "encrypted"),
+            ("bob", "encrypted"),
+            ("amy", "announcement"),
+        ] {
+            sqlx::query("INSERT INTO messages(channel_id, author, content, kind, created_at) VALUES($1, $2, 'x', $3, $4)")
+                .bind(general)
+                .bind(author)
+                .bind(kind)
+                .bind(now())
+                .execute(db)
+                .await
+                .expect("insert message");
+        }
+        let Json(stats) = server_stats(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path("rchat".to_string()),
+            Query(StatsQuery::default()),
+        )
+        .await
+        .expect("stats");
+        let volume: i64 = stats.channels.iter().map(|c| c.messages).sum();
+        let top: Vec<(&str, i64)> = stats
+            .top_members
+            .iter()
+            .map(|m| (m.user.username.as_str(), m.messages))
+            .collect();
+        assert_eq!(top, vec![("bob", 2), ("amy", 1)]);
+        assert_eq!(volume, top.iter().map(|(_, n)| n).sum::<i64>());
+        done(state, path).await;
+    }
+}
@@ -0,0 +1,504 @@
+use std::io::SeekFrom;
+use std::path::PathBuf;
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::media::{require_uploads_on, store_media};
+use crate::api::{ApiError, Authed, MediaRef};
+use crate::config::AppConfig;
+use crate::db::{Db, User, now};
+use crate::state::AppState;
+
+pub(crate) const CHUNK_MAX_BYTES: usize = 4 * 1024 * 1024;
+const UPLOAD_TTL_SECS: i64 = 6 * 3600;
+const MAX_PENDING_UPLOADS: i64 = 4;
+const PENDING_SIZE_FACTOR: i64 = 2;
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewUploadReq {
+    filename: String,
+    mime: Option<String>,
+    size: i64,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ChunkQuery {
+    offset: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PendingUpload {
+    pub id: String,
+    pub size: i64,
+    pub received: i64,
+}
+
+struct UploadRow {
+    filename: String,
+    mime: String,
+    size: i64,
+    received: i64,
+}
+
+fn part_path(config: &AppConfig, id: &str) -> PathBuf {
+    config.upload_dir().join(format!("{id}.part"))
+}
+
+fn io_error(e: std::io::Error) -> ApiError {
+    ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn fresh_upload_id(db: &Db) -> Result<String, ApiError> {
+    loop {
+        let mut bytes = [0u8; 16];
+        rand::rng().fill_bytes(&mut bytes);
+        let id = hex::encode(bytes);
+        let taken = sqlx::query("SELECT 1 FROM pending_uploads WHERE id = $1")
+            .bind(&id)
+            .fetch_optional(db)
+            .await?
+            .is_some();
+        if !taken {
+            return Ok(id);
+        }
+    }
+}
+
+async fn require_upload(db: &Db, id: &str, user: &User) -> Result<UploadRow, ApiError> {
+    let row = sqlx::query(
+        "SELECT filename, mime, size, received FROM pending_uploads WHERE id = $1 AND uploader = $2",
+    )
+    .bind(id)
+    .bind(&user.username)
+    .fetch_optional(db)
+    .await?
+    .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "Upload not found".to_string()))?;
+    Ok(UploadRow {
+        filename: row.try_get(0)?,
+        mime: row.try_get(1)?,
+        size: row.try_get(2)?,
+        received: row.try_get(3)?,
+    })
+}
+
+async fn discard(state: &AppState, id: &str) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM pending_uploads WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    let _ = tokio::fs::remove_file(part_path(&state.config, id)).await;
+    Ok(())
+}
+
+#[utoipa::path(post, path = "/api/media/uploads", request_body = NewUploadReq, responses((status = 200, body = PendingUpload)), security(("bearer" = [])))]
+pub(crate) async fn start_upload(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Json(req): Json<NewUploadReq>,
+) -> Result<Json<PendingUpload>, ApiError> {
+    require_uploads_on(&state.db).await?;
+    let max_mb = state.config.max_upload_mb;
+    let max = (max_mb * 1024 * 1024) as i64;
+    if req.size <= 0 {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "Empty file".to_string()));
+    }
+    if req.size > max {
+        return Err(ApiError(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File exceeds {max_mb}MB limit"),
+        ));
+    }
+    let filename = match req.filename.trim() {
+        "" => "file".to_string(),
+        name => name.to_string(),
+    };
+    let mime = req
+        .mime
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let id = fresh_upload_id(&state.db).await?;
+    let admitted = sqlx::query(
+        "INSERT INTO pending_uploads(id, uploader, filename, mime, size, received, created_at) SELECT $1, $2, $3, $4, $5, 0, $6 WHERE (SELECT COUNT(*) FROM pending_uploads WHERE uploader = $2) < $7 AND (SELECT COALESCE(SUM(size), 0) FROM pending_uploads WHERE uploader = $2) + $5 <= $8",
+    )
+    .bind(&id)
+    .bind(&user.username)
+    .bind(&filename)
+    .bind(&mime)
+    .bind(req.size)
+    .bind(now())
+    .bind(MAX_PENDING_UPLOADS)
+    .bind(max * PENDING_SIZE_FACTOR)
+    .execute(&state.db)
+    .await?
+    .rows_affected();
+    if admitted == 0 {
+        return Err(ApiError(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many uploads in progress".to_string(),
+        ));
+    }
+    let created = async {
+        tokio::fs::create_dir_all(state.config.upload_dir()).await?;
+        tokio::fs::File::create(part_path(&state.config, &id)).await
+    };
+    if let Err(e) = created.await {
+        discard(&state, &id).await?;
+        return Err(io_error(e));
+    }
+    Ok(Json(PendingUpload {
+        id,
+        size: req.size,
+        received: 0,
+    }))
+}
+
+#[utoipa::path(get, path = "/api/media/uploads/{id}", params(("id" = String, Path)), responses((status = 200, body = PendingUpload)), security(("bearer" = [])))]
+pub(crate) async fn upload_status(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<String>,
+) -> Result<Json<PendingUpload>, ApiError> {
+    let upload = require_upload(&state.db, &id, &user).await?;
+    Ok(Json(PendingUpload {
+        id,
+        size: upload.size,
+        received: upload.received,
+    }))
+}
+
+#[utoipa::path(put, path = "/api/media/uploads/{id}/chunk", params(("id" = String, Path), ChunkQuery), request_body(content = Vec<u8>, content_type = "application/octet-stream"), responses((status = 200, body = PendingUpload)), security(("bearer" = [])))]
+pub(crate) async fn put_chunk(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<String>,
+    Query(q): Query<ChunkQuery>,
+    body: Bytes,
+) -> Result<Json<PendingUpload>, ApiError> {
+    let upload = require_upload(&state.db, &id, &user).await?;
+    if q.offset != upload.received {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("Expected offset {}", upload.received),
+        ));
+    }
+    if body.is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "Empty chunk".to_string()));
+    }
+    let received = upload.received + body.len() as i64;
+    if received > upload.size {
+        return Err(ApiError(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Chunk runs past the declared size".to_string(),
+        ));
+    }
+    let reserved = sqlx::query(
+        "UPDATE pending_uploads SET reserved = $1 WHERE id = $2 AND received = $3 AND reserved = received",
+    )
+    .bind(received)
+    .bind(&id)
+    .bind(upload.received)
+    .execute(&state.db)
+    .await?
+    .rows_affected();
+    if reserved == 0 {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("Expected offset {}", upload.received),
+        ));
+    }
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(part_path(&state.config, &id))
+            .await?;
+        file.seek(SeekFrom::Start(q.offset as u64)).await?;
+        file.write_all(&body).await?;
+        file.flush().await
+    };
+    let stored = written.await;
+    let settle = match &stored {
+        Ok(()) => "UPDATE pending_uploads SET received = reserved WHERE id = $1 AND reserved = $2",
+        Err(_) => "UPDATE pending_uploads SET reserved = received WHERE id = $1 AND reserved = $2",
+    };
+    sqlx::query(settle)
+        .bind(&id)
+        .bind(received)
+        .execute(&state.db)
+        .await?;
+    stored.map_err(io_error)?;
+    Ok(Json(PendingUpload {
+        id,
+        size: upload.size,
+        received,
+    }))
+}
+
+#[utoipa::path(post, path = "/api/media/uploads/{id}/complete", params(("id" = String, Path)), responses((status = 200, body = MediaRef)), security(("bearer" = [])))]
+pub(crate) async fn complete_upload(
+    State(state): State<AppState>,
+    Authed(user): Authed,
+    Path(id): Path<String>,
+) -> Result<Json<MediaRef>, ApiError> {
+    require_uploads_on(&state.db).await?;
+    let upload = require_upload(&state.db, &id, &user).await?;
+    if upload.received != upload.size {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Upload incomplete: {} of {} bytes",
+                upload.received, upload.size
+            ),
+        ));
+    }
+    let claimed = sqlx::query(
+        "DELETE FROM pending_uploads WHERE id = $1 AND uploader = $2 AND received = size AND reserved = received",
+    )
+    .bind(&id)
+    .bind(&user.username)
+    .execute(&state.db)
+    .await?
+    .rows_affected();
+    if claimed == 0 {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            "Upload is already being completed".to_string(),
+        ));
+    }
+    let mut data = Vec::with_capacity(upload.size as usize);
+    let read = async {
+        tokio::fs::File::open(part_path(&state.config, &id))
+            .await?
+            .take(upload.size as u64)
+            .read_to_end(&mut data)
+            .await
+    };
+    let read = read.await;
+    let _ = tokio::fs::remove_file(part_path(&state.config, &id)).await;
+    read.map_err(io_error)?;
+    if data.len() as i64 != upload.size {
+        return Err(ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Upload data was lost".to_string(),
+        ));
+    }
+    Ok(Json(
+        store_media(&state, &user, upload.filename, upload.mime, data).await?,
+    ))
+}
+
+async fn sweep(state: &AppState, at: i64) -> Result<(), ApiError> {
+    let rows = sqlx::query("SELECT id FROM pending_uploads WHERE created_at <= $1")
+        .bind(at - UPLOAD_TTL_SECS)
+        .fetch_all(&state.db)
+        .await?;
+    for r in &rows {
+        discard(state, &r.try_get::<String, _>(0)?).await?;
+    }
+    Ok(())
+}
+
+pub async fn sweep_abandoned(state: &AppState) {
+    if let Err(ApiError(_, message)) = sweep(state, now()).await {
+        tracing::warn!("upload sweep failed: {message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_util::{done, mem_user, temp_state};
+
+    #[tokio::test]
+    async fn chunks_assemble_into_media() {
+        let (state, path) = temp_state("uploads").await;
+        let payload: Vec<u8> = (0..250u32).map(|i| (i % 251) as u8).collect();
+        let req = serde_json::from_value(serde_json::json!({
+            "filename": "notes.bin",
+            "size": payload.len(),
+        }))
+        .expect("request");
+        let Json(upload) = start_upload(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Json(req),
+        )
+        .await
+        .expect("start");
+        assert_eq!(upload.received, 0);
+        let chunk = |name: &str, offset: usize, end: usize| {
+            put_chunk(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Path(upload.id.clone()),
+                Query(ChunkQuery {
+                    offset: offset as i64,
+                }),
+                Bytes::copy_from_slice(&payload[offset..end]),
+            )
+        };
+        let Json(first) = chunk("amy", 0, 100).await.expect("first chunk");
+        assert_eq!(first.received, 100);
+        let skipped = chunk("amy", 200, 250).await;
+        assert!(matches!(skipped, Err(ApiError(StatusCode::CONFLICT, _))));
+        let stranger = chunk("bob", 100, 200).await;
+        assert!(matches!(stranger, Err(ApiError(StatusCode::NOT_FOUND, _))));
+        let early = complete_upload(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path(upload.id.clone()),
+        )
+        .await;
+        assert!(matches!(early, Err(ApiError(StatusCode::BAD_REQUEST, _))));
+        let _ = chunk("amy", 100, 200).await.expect("second chunk");
+        let Json(status) = upload_status(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path(upload.id.clone()),
+        )
+        .await
+        .expect("status");
+        assert_eq!(status.received, 200);
+        let Json(last) = chunk("amy", 200, 250).await.expect("last chunk");
+        assert_eq!(last.received, last.size);
+
+        let Json(media) = complete_upload(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Path(upload.id.clone()),
+        )
+        .await
+        .expect("complete");
+        assert_eq!(media.filename, "notes.bin");
+        assert_eq!(media.size, Some(250));
+        let stored: Vec<u8> = sqlx::query("SELECT data FROM media WHERE id = $1")
+            .bind(&media.id)
+            .fetch_one(&state.db)
+            .await
+            .expect("media row")
+            .try_get(0)
+            .expect("data");
+        assert_eq!(stored, payload);
+        assert!(!part_path(&state.config, &upload.id).exists());
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn pending_uploads_are_capped() {
+        let (state, path) = temp_state("uploads_cap").await;
+        let max = (state.config.max_upload_mb * 1024 * 1024) as i64;
+        let start = |name: &str, size: i64| {
+            start_upload(
+                State(state.clone()),
+                Authed(mem_user(name, false)),
+                Json(
+                    serde_json::from_value(serde_json::json!({ "filename": "f", "size": size }))
+                        .expect("request"),
+                ),
+            )
+        };
+        for _ in 0..PENDING_SIZE_FACTOR {
+            let _ = start("amy", max).await.expect("within the size budget");
+        }
+        let over = start("amy", 1).await;
+        assert!(matches!(
+            over,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        for _ in 0..MAX_PENDING_UPLOADS {
+            let _ = start("bob", 1).await.expect("within the count");
+        }
+        let crowded = start("bob", 1).await;
+        assert!(matches!(
+            crowded,
+            Err(ApiError(StatusCode::TOO_MANY_REQUESTS, _))
+        ));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn racing_writers_and_completions_do_not_collide() {
+        let (state, path) = temp_state("uploads_race").await;
+        let req = serde_json::from_value(serde_json::json!({ "filename": "a", "size": 4 }))
+            .expect("request");
+        let Json(upload) = start_upload(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Json(req),
+        )
+        .await
+        .expect("start");
+        sqlx::query("UPDATE pending_uploads SET reserved = 2 WHERE id = $1")
+            .bind(&upload.id)
+            .execute(&state.db)
+            .await
+            .expect("hold a reservation");
+        let chunk = || {
+            put_chunk(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Path(upload.id.clone()),
+                Query(ChunkQuery { offset: 0 }),
+                Bytes::from_static(b"data"),
+            )
+        };
+        let blocked = chunk().await;
+        assert!(matches!(blocked, Err(ApiError(StatusCode::CONFLICT, _))));
+        sqlx::query("UPDATE pending_uploads SET reserved = 0 WHERE id = $1")
+            .bind(&upload.id)
+            .execute(&state.db)
+            .await
+            .expect("release");
+        let Json(full) = chunk().await.expect("chunk");
+        assert_eq!(full.received, 4);
+        let complete = || {
+            complete_upload(
+                State(state.clone()),
+                Authed(mem_user("amy", false)),
+                Path(upload.id.clone()),
+            )
+        };
+        let (a, b) = tokio::join!(complete(), complete());
+        assert_eq!(a.is_ok() as u8 + b.is_ok() as u8, 1);
+        let media: i64 = sqlx::query("SELECT COUNT(*) FROM media")
+            .fetch_one(&state.db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count");
+        assert_eq!(media, 1);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn abandoned_uploads_are_swept() {
+        let (state, path) = temp_state("uploads_sweep").await;
+        let req = serde_json::from_value(serde_json::json!({ "filename": "a", "size": 10 }))
+            .expect("request");
+        let Json(upload) = start_upload(
+            State(state.clone()),
+            Authed(mem_user("amy", false)),
+            Json(req),
+        )
+        .await
+        .expect("start");
+        sweep(&state, now()).await.expect("early sweep");
+        assert!(part_path(&state.config, &upload.id).exists());
+        sweep(&state, now() + UPLOAD_TTL_SECS).await.expect("sweep");
+        assert!(!part_path(&state.config, &upload.id).exists());
+        let left: i64 = sqlx::query("SELECT COUNT(*) FROM pending_uploads")
+            .fetch_one(&state.db)
+            .await
+            .expect("count")
+            .try_get(0)
+            .expect("count");
+        assert_eq!(left, 0);
+        done(state, path).await;
+    }
+}
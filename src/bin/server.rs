@@ -1,12 +1,12 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 
-use axum::http::{HeaderName, Method, header};
 use clap::Parser;
 use rust_next::config::{AppConfig, AppMode, CliOverrides};
+use rust_next::rate_limit::SendLimiter;
 use rust_next::server::build_router;
 use rust_next::state::AppState;
 use rust_next::ws::Hub;
-use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::info;
 
 #[tokio::main]
@@ -28,20 +28,24 @@ async fn main() -> anyhow::Result<()> {
         db,
         hub: Hub::new(),
         s3: config.s3()?,
+        sends: SendLimiter::messages(&config),
+        announces: SendLimiter::announcements(),
+        dm_opens: SendLimiter::dm_opens(&config),
+        http: reqwest::Client::new(),
+        config: Arc::new(config.clone()),
     };
     let sweeper = state.clone();
+    let every = std::time::Duration::from_secs(config.cleanup_interval_secs.max(1));
+    rust_next::api::cleanup_now(&sweeper).await;
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
         loop {
             interval.tick().await;
-            rust_next::api::media::sweep_expired(&sweeper).await;
+            rust_next::api::cleanup_now(&sweeper).await;
         }
     });
     let voice = state.clone();
-    let idle: i64 = std::env::var("VOICE_IDLE_SECS")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(60);
+    let idle = config.voice_idle_secs;
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
         loop {
@@ -50,30 +54,8 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    let cors = CorsLayer::new()
-        .allow_origin(AllowOrigin::mirror_request())
-        .allow_methods([
-            Method::GET,
-            Method::POST,
-            Method::PUT,
-            Method::DELETE,
-            Method::PATCH,
-            Method::OPTIONS,
-        ])
-        .allow_headers([
-            header::CONTENT_TYPE,
-            header::AUTHORIZATION,
-            header::ACCEPT,
-            header::UPGRADE,
-            header::CONNECTION,
-            HeaderName::from_static("sec-websocket-key"),
-            HeaderName::from_static("sec-websocket-version"),
-            HeaderName::from_static("sec-websocket-protocol"),
-        ])
-        .allow_credentials(true);
-
     let proxy_url = config.proxy_url();
-    let app = build_router(proxy_url.as_deref(), &config, state).layer(cors);
+    let app = build_router(proxy_url.as_deref(), &config, state);
 
     let addr = config.addr();
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use s3::creds::Credentials;
 use s3::{Bucket, Region};
 use serde::Deserialize;
+use url::Url;
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -11,6 +12,22 @@ pub enum AppMode {
     ApiOnly,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerCreation {
+    #[default]
+    Anyone,
+    Admins,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MassMentionPolicy {
+    #[default]
+    Plain,
+    Reject,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AppConfig {
     pub app_mode: AppMode,
@@ -26,18 +43,66 @@ pub struct AppConfig {
     pub s3_access_key: Option<String>,
     pub s3_secret_key: Option<String>,
     pub s3_region: Option<String>,
+    pub cors_origins: Option<String>,
+    pub cors_credentials: bool,
+    pub cleanup_interval_secs: u64,
+    pub voice_idle_secs: i64,
+    pub presence_away_secs: u64,
+    pub message_rate_limit: u32,
+    pub message_rate_window_secs: u64,
+    pub dm_open_rate_limit: u32,
+    pub dm_open_rate_window_secs: u64,
+    pub max_upload_mb: usize,
+    pub upload_dir: Option<String>,
+    pub ascii_usernames: bool,
+    pub password_min_length: usize,
+    pub password_min_classes: usize,
+    pub avatar_palette: Option<String>,
+    pub registration_open: bool,
+    pub reserved_server_names: Option<String>,
+    pub server_creation: ServerCreation,
+    pub servers_per_day: Option<i64>,
+    pub max_servers_per_user: Option<i64>,
+    pub mass_mention_policy: MassMentionPolicy,
+    pub moderation_webhook_url: Option<String>,
+    #[serde(skip)]
+    pub moderation_webhook: Option<Url>,
 }
 
 impl AppConfig {
-    pub fn load(cli: &CliOverrides) -> anyhow::Result<Self> {
-        let mut builder = config::Config::builder()
+    fn defaults() -> anyhow::Result<config::ConfigBuilder<config::builder::DefaultState>> {
+        Ok(config::Config::builder()
             .set_default("app_mode", "full")?
             .set_default("host", "127.0.0.1")?
             .set_default("server_port", 3000_i64)?
             .set_default("port", 3001_i64)?
             .set_default("rate_limit_per_second", 10_i64)?
             .set_default("rate_limit_burst", 60_i64)?
-            .add_source(config::Environment::default());
+            .set_default("cors_credentials", false)?
+            .set_default("cleanup_interval_secs", 60_i64)?
+            .set_default("voice_idle_secs", 60_i64)?
+            .set_default("presence_away_secs", 300_i64)?
+            .set_default("message_rate_limit", 30_i64)?
+            .set_default("message_rate_window_secs", 60_i64)?
+            .set_default("dm_open_rate_limit", 10_i64)?
+            .set_default("dm_open_rate_window_secs", 600_i64)?
+            .set_default("max_upload_mb", 25_i64)?
+            .set_default("ascii_usernames", false)?
+            .set_default("password_min_length", 1_i64)?
+            .set_default("password_min_classes", 1_i64)?
+            .set_default("registration_open", true)?
+            .set_default("server_creation", "anyone")?
+            .set_default("mass_mention_policy", "plain")?)
+    }
+
+    pub fn default_for_tests() -> Self {
+        Self::defaults()
+            .and_then(|b| Ok(b.build()?.try_deserialize::<Self>()?))
+            .expect("default config")
+    }
+
+    pub fn load(cli: &CliOverrides) -> anyhow::Result<Self> {
+        let mut builder = Self::defaults()?.add_source(config::Environment::default());
 
         if let Some(ref host) = cli.host {
             builder = builder.set_override("host", host.as_str())?;
@@ -49,7 +114,22 @@ impl AppConfig {
             builder = builder.set_override("app_mode", mode.as_str())?;
         }
 
-        Ok(builder.build()?.try_deserialize()?)
+        let mut config: Self = builder.build()?.try_deserialize()?;
+        config.moderation_webhook = match config.moderation_webhook_url.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(raw) => match Url::parse(raw) {
+                Ok(url) => Some(url),
+                Err(e) => anyhow::bail!("MODERATION_WEBHOOK_URL is not a valid URL: {e}"),
+            },
+        };
+        Ok(config)
+    }
+
+    pub fn upload_dir(&self) -> std::path::PathBuf {
+        match &self.upload_dir {
+            Some(dir) => dir.into(),
+            None => std::env::temp_dir().join("rchat_uploads"),
+        }
     }
 
     pub fn s3(&self) -> anyhow::Result<Option<Arc<Bucket>>> {
@@ -10,64 +10,92 @@ const SQLITE_SCHEMA: &str = "
 CREATE TABLE IF NOT EXISTS users(
   username TEXT PRIMARY KEY, display_name TEXT NOT NULL, password_hash TEXT NOT NULL,
   password_kind TEXT NOT NULL CHECK(password_kind IN ('text','words')),
-  avatar_kind TEXT NOT NULL CHECK(avatar_kind IN ('identicon','color')), avatar_color TEXT,
-  is_site_admin INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL);
-CREATE TABLE IF NOT EXISTS tokens(token TEXT PRIMARY KEY, username TEXT NOT NULL, created_at INTEGER NOT NULL);
+  avatar_kind TEXT NOT NULL CHECK(avatar_kind IN ('identicon','color','image')), avatar_color TEXT, avatar_media_id TEXT,
+  is_site_admin INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL, last_seen INTEGER);
+CREATE TABLE IF NOT EXISTS tokens(token TEXT PRIMARY KEY, username TEXT NOT NULL, created_at INTEGER NOT NULL, expires_at INTEGER, impersonated_by TEXT);
 CREATE TABLE IF NOT EXISTS login_attempts(username TEXT PRIMARY KEY, day TEXT NOT NULL, count INTEGER NOT NULL, last_at INTEGER NOT NULL);
 CREATE TABLE IF NOT EXISTS banned_usernames(username TEXT PRIMARY KEY);
-CREATE TABLE IF NOT EXISTS servers(name TEXT PRIMARY KEY, display_name TEXT NOT NULL, creator TEXT, password_hash TEXT, created_at INTEGER NOT NULL);
-CREATE TABLE IF NOT EXISTS channels(id INTEGER PRIMARY KEY AUTOINCREMENT, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, name TEXT NOT NULL, kind TEXT NOT NULL DEFAULT 'text' CHECK(kind IN ('text','voice')), slowmode_seconds INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL, UNIQUE(server, name));
+CREATE TABLE IF NOT EXISTS servers(name TEXT PRIMARY KEY, display_name TEXT NOT NULL, creator TEXT, password_hash TEXT, created_at INTEGER NOT NULL, welcome_message TEXT);
+CREATE TABLE IF NOT EXISTS channels(id INTEGER PRIMARY KEY AUTOINCREMENT, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, name TEXT NOT NULL, kind TEXT NOT NULL DEFAULT 'text' CHECK(kind IN ('text','voice')), slowmode_seconds INTEGER NOT NULL DEFAULT 0, message_retention_hours INTEGER, allowed_content_types TEXT, position INTEGER NOT NULL DEFAULT 0, last_seq INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL, UNIQUE(server, name));
 CREATE TABLE IF NOT EXISTS members(server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, username TEXT NOT NULL, is_admin INTEGER NOT NULL DEFAULT 0, perms INTEGER NOT NULL DEFAULT 0, joined_at INTEGER NOT NULL, PRIMARY KEY(server, username));
 CREATE TABLE IF NOT EXISTS roles(id INTEGER PRIMARY KEY AUTOINCREMENT, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, name TEXT NOT NULL, color TEXT NOT NULL, perms INTEGER NOT NULL DEFAULT 0);
 CREATE TABLE IF NOT EXISTS user_roles(server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, username TEXT NOT NULL, role_id INTEGER NOT NULL REFERENCES roles(id) ON DELETE CASCADE, PRIMARY KEY(server, username, role_id));
 CREATE TABLE IF NOT EXISTS channel_perms(channel_id INTEGER NOT NULL REFERENCES channels(id) ON DELETE CASCADE, subject TEXT NOT NULL, can_view INTEGER NOT NULL DEFAULT 1, can_send INTEGER NOT NULL DEFAULT 1, can_read_history INTEGER NOT NULL DEFAULT 1, PRIMARY KEY(channel_id, subject));
 CREATE TABLE IF NOT EXISTS dms(id INTEGER PRIMARY KEY AUTOINCREMENT, user_a TEXT NOT NULL, user_b TEXT NOT NULL, UNIQUE(user_a, user_b));
-CREATE TABLE IF NOT EXISTS messages(id INTEGER PRIMARY KEY AUTOINCREMENT, channel_id INTEGER REFERENCES channels(id) ON DELETE CASCADE, dm_id INTEGER REFERENCES dms(id) ON DELETE CASCADE, thread_root_id INTEGER REFERENCES messages(id) ON DELETE CASCADE, author TEXT NOT NULL, content TEXT NOT NULL, media_id TEXT, media_filename TEXT, media_removed INTEGER NOT NULL DEFAULT 0, media_spoiler INTEGER NOT NULL DEFAULT 0, media_kind TEXT NOT NULL DEFAULT 'server' CHECK(media_kind IN ('server','p2p')), media_hoster TEXT, media_expires_at INTEGER, media_size INTEGER, media_mime TEXT, kind TEXT NOT NULL DEFAULT 'user', call_answered_at INTEGER, call_ended_at INTEGER, call_outcome TEXT, created_at INTEGER NOT NULL);
-CREATE TABLE IF NOT EXISTS media(id TEXT PRIMARY KEY, filename TEXT NOT NULL, mime TEXT NOT NULL, size INTEGER NOT NULL, data BLOB, uploaded_at INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS messages(id INTEGER PRIMARY KEY AUTOINCREMENT, channel_id INTEGER REFERENCES channels(id) ON DELETE CASCADE, dm_id INTEGER REFERENCES dms(id) ON DELETE CASCADE, thread_root_id INTEGER REFERENCES messages(id) ON DELETE CASCADE, author TEXT NOT NULL, content TEXT NOT NULL, media_id TEXT, media_filename TEXT, media_removed INTEGER NOT NULL DEFAULT 0, media_spoiler INTEGER NOT NULL DEFAULT 0, media_kind TEXT NOT NULL DEFAULT 'server' CHECK(media_kind IN ('server','p2p')), media_hoster TEXT, media_expires_at INTEGER, media_size INTEGER, media_mime TEXT, kind TEXT NOT NULL DEFAULT 'user', call_answered_at INTEGER, call_ended_at INTEGER, call_outcome TEXT, filter_status TEXT NOT NULL DEFAULT 'clean', forwarded_from INTEGER REFERENCES messages(id) ON DELETE SET NULL, enc_key_id TEXT, enc_alg TEXT, deleted_at INTEGER, seq INTEGER, mass_mention TEXT, created_at INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS media(id TEXT PRIMARY KEY, filename TEXT NOT NULL, mime TEXT NOT NULL, size INTEGER NOT NULL, data BLOB, uploader TEXT, uploaded_at INTEGER NOT NULL);
 CREATE TABLE IF NOT EXISTS settings(key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS data_migrations(name TEXT PRIMARY KEY, applied_at INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS server_creations(username TEXT NOT NULL, server TEXT NOT NULL, created_at INTEGER NOT NULL);
 CREATE TABLE IF NOT EXISTS embeds(url TEXT PRIMARY KEY, site_name TEXT, title TEXT, description TEXT, image_url TEXT, fetched_at INTEGER NOT NULL);
 CREATE TABLE IF NOT EXISTS message_embeds(message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE, ord INTEGER NOT NULL, url TEXT NOT NULL, banner_removed INTEGER NOT NULL DEFAULT 0, removed INTEGER NOT NULL DEFAULT 0, PRIMARY KEY(message_id, ord));
 CREATE TABLE IF NOT EXISTS interactions(server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, username TEXT NOT NULL, last_at INTEGER NOT NULL, PRIMARY KEY(server, username));
 CREATE TABLE IF NOT EXISTS guest_grants(\"grant\" TEXT PRIMARY KEY, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, created_at INTEGER NOT NULL);
 CREATE TABLE IF NOT EXISTS read_state(username TEXT NOT NULL, scope TEXT NOT NULL, last_read INTEGER NOT NULL, PRIMARY KEY(username, scope));
+CREATE TABLE IF NOT EXISTS dm_pins(username TEXT NOT NULL, dm_id INTEGER NOT NULL REFERENCES dms(id) ON DELETE CASCADE, PRIMARY KEY(username, dm_id));
+CREATE TABLE IF NOT EXISTS dm_mutes(username TEXT NOT NULL, dm_id INTEGER NOT NULL REFERENCES dms(id) ON DELETE CASCADE, PRIMARY KEY(username, dm_id));
+CREATE TABLE IF NOT EXISTS idempotency_keys(username TEXT NOT NULL, idem_key TEXT NOT NULL, target TEXT, message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE, created_at INTEGER NOT NULL, PRIMARY KEY(username, idem_key));
+CREATE TABLE IF NOT EXISTS message_reports(id INTEGER PRIMARY KEY AUTOINCREMENT, message_id INTEGER REFERENCES messages(id) ON DELETE SET NULL, reporter TEXT NOT NULL, reason TEXT NOT NULL, status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open','resolved')), resolved_by TEXT, resolved_at INTEGER, created_at INTEGER NOT NULL, author TEXT NOT NULL DEFAULT '', content TEXT NOT NULL DEFAULT '', UNIQUE(message_id, reporter));
+CREATE TABLE IF NOT EXISTS site_invites(code TEXT PRIMARY KEY, created_by TEXT NOT NULL, created_at INTEGER NOT NULL, used_by TEXT, used_at INTEGER);
+CREATE TABLE IF NOT EXISTS impersonation_log(id INTEGER PRIMARY KEY AUTOINCREMENT, admin TEXT NOT NULL, username TEXT NOT NULL, created_at INTEGER NOT NULL, expires_at INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS server_transfers(server TEXT PRIMARY KEY REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, from_user TEXT NOT NULL, to_user TEXT NOT NULL, expires_at INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS pending_uploads(id TEXT PRIMARY KEY, uploader TEXT NOT NULL, filename TEXT NOT NULL, mime TEXT NOT NULL, size INTEGER NOT NULL, received INTEGER NOT NULL DEFAULT 0, reserved INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS server_activity(id INTEGER PRIMARY KEY AUTOINCREMENT, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, kind TEXT NOT NULL, actor TEXT, target TEXT, channel_id INTEGER, created_at INTEGER NOT NULL);
 CREATE INDEX IF NOT EXISTS idx_members_username ON members(username);
 CREATE INDEX IF NOT EXISTS idx_messages_channel_id ON messages(channel_id, id);
 CREATE INDEX IF NOT EXISTS idx_messages_dm_id ON messages(dm_id, id);
 CREATE INDEX IF NOT EXISTS idx_messages_author ON messages(author);
+CREATE INDEX IF NOT EXISTS idx_messages_channel_author ON messages(channel_id, author, created_at);
 CREATE INDEX IF NOT EXISTS idx_messages_thread_root ON messages(thread_root_id, id);
 CREATE INDEX IF NOT EXISTS idx_media_uploaded_at ON media(uploaded_at);
+CREATE INDEX IF NOT EXISTS idx_server_activity ON server_activity(server, id);
+CREATE INDEX IF NOT EXISTS idx_server_creations ON server_creations(username, created_at);
 ";
 
 const POSTGRES_SCHEMA: &str = "
 CREATE TABLE IF NOT EXISTS users(
   username TEXT PRIMARY KEY, display_name TEXT NOT NULL, password_hash TEXT NOT NULL,
   password_kind TEXT NOT NULL CHECK(password_kind IN ('text','words')),
-  avatar_kind TEXT NOT NULL CHECK(avatar_kind IN ('identicon','color')), avatar_color TEXT,
-  is_site_admin BIGINT NOT NULL DEFAULT 0, created_at BIGINT NOT NULL);
-CREATE TABLE IF NOT EXISTS tokens(token TEXT PRIMARY KEY, username TEXT NOT NULL, created_at BIGINT NOT NULL);
+  avatar_kind TEXT NOT NULL CHECK(avatar_kind IN ('identicon','color','image')), avatar_color TEXT, avatar_media_id TEXT,
+  is_site_admin BIGINT NOT NULL DEFAULT 0, created_at BIGINT NOT NULL, last_seen BIGINT);
+CREATE TABLE IF NOT EXISTS tokens(token TEXT PRIMARY KEY, username TEXT NOT NULL, created_at BIGINT NOT NULL, expires_at BIGINT, impersonated_by TEXT);
 CREATE TABLE IF NOT EXISTS login_attempts(username TEXT PRIMARY KEY, day TEXT NOT NULL, count BIGINT NOT NULL, last_at BIGINT NOT NULL);
 CREATE TABLE IF NOT EXISTS banned_usernames(username TEXT PRIMARY KEY);
-CREATE TABLE IF NOT EXISTS servers(name TEXT PRIMARY KEY, display_name TEXT NOT NULL, creator TEXT, password_hash TEXT, created_at BIGINT NOT NULL);
-CREATE TABLE IF NOT EXISTS channels(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, name TEXT NOT NULL, kind TEXT NOT NULL DEFAULT 'text' CHECK(kind IN ('text','voice')), slowmode_seconds BIGINT NOT NULL DEFAULT 0, created_at BIGINT NOT NULL, UNIQUE(server, name));
+CREATE TABLE IF NOT EXISTS servers(name TEXT PRIMARY KEY, display_name TEXT NOT NULL, creator TEXT, password_hash TEXT, created_at BIGINT NOT NULL, welcome_message TEXT);
+CREATE TABLE IF NOT EXISTS channels(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, name TEXT NOT NULL, kind TEXT NOT NULL DEFAULT 'text' CHECK(kind IN ('text','voice')), slowmode_seconds BIGINT NOT NULL DEFAULT 0, message_retention_hours BIGINT, allowed_content_types TEXT, position BIGINT NOT NULL DEFAULT 0, last_seq BIGINT NOT NULL DEFAULT 0, created_at BIGINT NOT NULL, UNIQUE(server, name));
 CREATE TABLE IF NOT EXISTS members(server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, username TEXT NOT NULL, is_admin BIGINT NOT NULL DEFAULT 0, perms BIGINT NOT NULL DEFAULT 0, joined_at BIGINT NOT NULL, PRIMARY KEY(server, username));
 CREATE TABLE IF NOT EXISTS roles(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, name TEXT NOT NULL, color TEXT NOT NULL, perms BIGINT NOT NULL DEFAULT 0);
 CREATE TABLE IF NOT EXISTS user_roles(server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, username TEXT NOT NULL, role_id BIGINT NOT NULL REFERENCES roles(id) ON DELETE CASCADE, PRIMARY KEY(server, username, role_id));
 CREATE TABLE IF NOT EXISTS channel_perms(channel_id BIGINT NOT NULL REFERENCES channels(id) ON DELETE CASCADE, subject TEXT NOT NULL, can_view BIGINT NOT NULL DEFAULT 1, can_send BIGINT NOT NULL DEFAULT 1, can_read_history BIGINT NOT NULL DEFAULT 1, PRIMARY KEY(channel_id, subject));
 CREATE TABLE IF NOT EXISTS dms(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, user_a TEXT NOT NULL, user_b TEXT NOT NULL, UNIQUE(user_a, user_b));
-CREATE TABLE IF NOT EXISTS messages(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, channel_id BIGINT REFERENCES channels(id) ON DELETE CASCADE, dm_id BIGINT REFERENCES dms(id) ON DELETE CASCADE, thread_root_id BIGINT REFERENCES messages(id) ON DELETE CASCADE, author TEXT NOT NULL, content TEXT NOT NULL, media_id TEXT, media_filename TEXT, media_removed BIGINT NOT NULL DEFAULT 0, media_spoiler BIGINT NOT NULL DEFAULT 0, media_kind TEXT NOT NULL DEFAULT 'server' CHECK(media_kind IN ('server','p2p')), media_hoster TEXT, media_expires_at BIGINT, media_size BIGINT, media_mime TEXT, kind TEXT NOT NULL DEFAULT 'user', call_answered_at BIGINT, call_ended_at BIGINT, call_outcome TEXT, created_at BIGINT NOT NULL);
-CREATE TABLE IF NOT EXISTS media(id TEXT PRIMARY KEY, filename TEXT NOT NULL, mime TEXT NOT NULL, size BIGINT NOT NULL, data BYTEA, uploaded_at BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS messages(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, channel_id BIGINT REFERENCES channels(id) ON DELETE CASCADE, dm_id BIGINT REFERENCES dms(id) ON DELETE CASCADE, thread_root_id BIGINT REFERENCES messages(id) ON DELETE CASCADE, author TEXT NOT NULL, content TEXT NOT NULL, media_id TEXT, media_filename TEXT, media_removed BIGINT NOT NULL DEFAULT 0, media_spoiler BIGINT NOT NULL DEFAULT 0, media_kind TEXT NOT NULL DEFAULT 'server' CHECK(media_kind IN ('server','p2p')), media_hoster TEXT, media_expires_at BIGINT, media_size BIGINT, media_mime TEXT, kind TEXT NOT NULL DEFAULT 'user', call_answered_at BIGINT, call_ended_at BIGINT, call_outcome TEXT, filter_status TEXT NOT NULL DEFAULT 'clean', forwarded_from BIGINT REFERENCES messages(id) ON DELETE SET NULL, enc_key_id TEXT, enc_alg TEXT, deleted_at BIGINT, seq BIGINT, mass_mention TEXT, created_at BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS media(id TEXT PRIMARY KEY, filename TEXT NOT NULL, mime TEXT NOT NULL, size BIGINT NOT NULL, data BYTEA, uploader TEXT, uploaded_at BIGINT NOT NULL);
 CREATE TABLE IF NOT EXISTS settings(key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS data_migrations(name TEXT PRIMARY KEY, applied_at BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS server_creations(username TEXT NOT NULL, server TEXT NOT NULL, created_at BIGINT NOT NULL);
 CREATE TABLE IF NOT EXISTS embeds(url TEXT PRIMARY KEY, site_name TEXT, title TEXT, description TEXT, image_url TEXT, fetched_at BIGINT NOT NULL);
 CREATE TABLE IF NOT EXISTS message_embeds(message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE, ord BIGINT NOT NULL, url TEXT NOT NULL, banner_removed BIGINT NOT NULL DEFAULT 0, removed BIGINT NOT NULL DEFAULT 0, PRIMARY KEY(message_id, ord));
 CREATE TABLE IF NOT EXISTS interactions(server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, username TEXT NOT NULL, last_at BIGINT NOT NULL, PRIMARY KEY(server, username));
 CREATE TABLE IF NOT EXISTS guest_grants(\"grant\" TEXT PRIMARY KEY, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, created_at BIGINT NOT NULL);
 CREATE TABLE IF NOT EXISTS read_state(username TEXT NOT NULL, scope TEXT NOT NULL, last_read BIGINT NOT NULL, PRIMARY KEY(username, scope));
+CREATE TABLE IF NOT EXISTS dm_pins(username TEXT NOT NULL, dm_id BIGINT NOT NULL REFERENCES dms(id) ON DELETE CASCADE, PRIMARY KEY(username, dm_id));
+CREATE TABLE IF NOT EXISTS dm_mutes(username TEXT NOT NULL, dm_id BIGINT NOT NULL REFERENCES dms(id) ON DELETE CASCADE, PRIMARY KEY(username, dm_id));
+CREATE TABLE IF NOT EXISTS idempotency_keys(username TEXT NOT NULL, idem_key TEXT NOT NULL, target TEXT, message_id BIGINT NOT NULL REFERENCES messages(id) ON DELETE CASCADE, created_at BIGINT NOT NULL, PRIMARY KEY(username, idem_key));
+CREATE TABLE IF NOT EXISTS message_reports(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, message_id BIGINT REFERENCES messages(id) ON DELETE SET NULL, reporter TEXT NOT NULL, reason TEXT NOT NULL, status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open','resolved')), resolved_by TEXT, resolved_at BIGINT, created_at BIGINT NOT NULL, author TEXT NOT NULL DEFAULT '', content TEXT NOT NULL DEFAULT '', UNIQUE(message_id, reporter));
+CREATE TABLE IF NOT EXISTS site_invites(code TEXT PRIMARY KEY, created_by TEXT NOT NULL, created_at BIGINT NOT NULL, used_by TEXT, used_at BIGINT);
+CREATE TABLE IF NOT EXISTS impersonation_log(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, admin TEXT NOT NULL, username TEXT NOT NULL, created_at BIGINT NOT NULL, expires_at BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS server_transfers(server TEXT PRIMARY KEY REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, from_user TEXT NOT NULL, to_user TEXT NOT NULL, expires_at BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS pending_uploads(id TEXT PRIMARY KEY, uploader TEXT NOT NULL, filename TEXT NOT NULL, mime TEXT NOT NULL, size BIGINT NOT NULL, received BIGINT NOT NULL DEFAULT 0, reserved BIGINT NOT NULL DEFAULT 0, created_at BIGINT NOT NULL);
+CREATE TABLE IF NOT EXISTS server_activity(id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY, server TEXT NOT NULL REFERENCES servers(name) ON DELETE CASCADE ON UPDATE CASCADE, kind TEXT NOT NULL, actor TEXT, target TEXT, channel_id BIGINT, created_at BIGINT NOT NULL);
 CREATE INDEX IF NOT EXISTS idx_members_username ON members(username);
 CREATE INDEX IF NOT EXISTS idx_messages_channel_id ON messages(channel_id, id);
 CREATE INDEX IF NOT EXISTS idx_messages_dm_id ON messages(dm_id, id);
 CREATE INDEX IF NOT EXISTS idx_messages_author ON messages(author);
+CREATE INDEX IF NOT EXISTS idx_messages_channel_author ON messages(channel_id, author, created_at);
 CREATE INDEX IF NOT EXISTS idx_messages_thread_root ON messages(thread_root_id, id);
 CREATE INDEX IF NOT EXISTS idx_media_uploaded_at ON media(uploaded_at);
+CREATE INDEX IF NOT EXISTS idx_server_activity ON server_activity(server, id);
+CREATE INDEX IF NOT EXISTS idx_server_creations ON server_creations(username, created_at);
 ";
 
 const MIGRATIONS: &[(&str, &str)] = &[
@@ -91,8 +119,123 @@ const MIGRATIONS: &[(&str, &str)] = &[
     ("messages", "call_answered_at {INT}"),
     ("messages", "call_ended_at {INT}"),
     ("messages", "call_outcome TEXT"),
+    ("media", "uploader TEXT"),
+    ("messages", "filter_status TEXT NOT NULL DEFAULT 'clean'"),
+    (
+        "messages",
+        "forwarded_from {INT} REFERENCES messages(id) ON DELETE SET NULL",
+    ),
+    ("channels", "message_retention_hours {INT}"),
+    ("channels", "position {INT} NOT NULL DEFAULT 0"),
+    ("messages", "enc_key_id TEXT"),
+    ("messages", "enc_alg TEXT"),
+    ("tokens", "expires_at {INT}"),
+    ("tokens", "impersonated_by TEXT"),
+    ("users", "last_seen {INT}"),
+    ("servers", "welcome_message TEXT"),
+    ("messages", "deleted_at {INT}"),
+    ("channels", "last_seq {INT} NOT NULL DEFAULT 0"),
+    ("messages", "seq {INT}"),
+    ("users", "avatar_media_id TEXT"),
+    ("channels", "allowed_content_types TEXT"),
+    ("messages", "mass_mention TEXT"),
+    ("idempotency_keys", "target TEXT"),
+    ("pending_uploads", "reserved {INT} NOT NULL DEFAULT 0"),
 ];
 
+const SELF_DMS: &str = "INSERT INTO dms(user_a, user_b) SELECT username, username FROM users u WHERE NOT EXISTS(SELECT 1 FROM dms d WHERE d.user_a = u.username AND d.user_b = u.username)";
+
+const SQLITE_REPORT_SNAPSHOTS: &str = "
+CREATE TABLE message_reports_new(id INTEGER PRIMARY KEY AUTOINCREMENT, message_id INTEGER REFERENCES messages(id) ON DELETE SET NULL, reporter TEXT NOT NULL, reason TEXT NOT NULL, status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open','resolved')), resolved_by TEXT, resolved_at INTEGER, created_at INTEGER NOT NULL, author TEXT NOT NULL DEFAULT '', content TEXT NOT NULL DEFAULT '', UNIQUE(message_id, reporter));
+INSERT INTO message_reports_new(id, message_id, reporter, reason, status, resolved_by, resolved_at, created_at, author, content) SELECT r.id, r.message_id, r.reporter, r.reason, r.status, r.resolved_by, r.resolved_at, r.created_at, COALESCE(m.author, ''), COALESCE(m.content, '') FROM message_reports r LEFT JOIN messages m ON m.id = r.message_id;
+DROP TABLE message_reports;
+ALTER TABLE message_reports_new RENAME TO message_reports;
+";
+
+const POSTGRES_REPORT_SNAPSHOTS: &str = "
+ALTER TABLE message_reports ADD COLUMN IF NOT EXISTS author TEXT NOT NULL DEFAULT '';
+ALTER TABLE message_reports ADD COLUMN IF NOT EXISTS content TEXT NOT NULL DEFAULT '';
+UPDATE message_reports r SET author = m.author, content = m.content FROM messages m WHERE m.id = r.message_id AND r.author = '';
+ALTER TABLE message_reports ALTER COLUMN message_id DROP NOT NULL;
+ALTER TABLE message_reports DROP CONSTRAINT IF EXISTS message_reports_message_id_fkey;
+ALTER TABLE message_reports ADD CONSTRAINT message_reports_message_id_fkey FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE SET NULL;
+";
+
+const SQLITE_IMAGE_AVATARS: &str = "
+CREATE TABLE users_new(
+  username TEXT PRIMARY KEY, display_name TEXT NOT NULL, password_hash TEXT NOT NULL,
+  password_kind TEXT NOT NULL CHECK(password_kind IN ('text','words')),
+  avatar_kind TEXT NOT NULL CHECK(avatar_kind IN ('identicon','color','image')), avatar_color TEXT, avatar_media_id TEXT,
+  is_site_admin INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL, last_seen INTEGER);
+INSERT INTO users_new(username, display_name, password_hash, password_kind, avatar_kind, avatar_color, avatar_media_id, is_site_admin, created_at, last_seen) SELECT username, display_name, password_hash, password_kind, CASE WHEN avatar_media_id IS NULL THEN avatar_kind ELSE 'image' END, avatar_color, avatar_media_id, is_site_admin, created_at, last_seen FROM users;
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
+";
+
+const POSTGRES_IMAGE_AVATARS: &str = "
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_avatar_kind_check;
+ALTER TABLE users ADD CONSTRAINT users_avatar_kind_check CHECK(avatar_kind IN ('identicon','color','image'));
+UPDATE users SET avatar_kind = 'image' WHERE avatar_media_id IS NOT NULL;
+";
+
+const MENTION_EVERYONE_GRANTS: &str = "
+UPDATE members SET perms = 63 WHERE is_admin != 0 AND perms = 31;
+UPDATE roles SET perms = 63 WHERE perms = 31;
+";
+
+const DATA_MIGRATIONS: &[(&str, &str, &str)] = &[
+    ("self_dms", SELF_DMS, SELF_DMS),
+    (
+        "report_snapshots",
+        SQLITE_REPORT_SNAPSHOTS,
+        POSTGRES_REPORT_SNAPSHOTS,
+    ),
+    (
+        "image_avatars",
+        SQLITE_IMAGE_AVATARS,
+        POSTGRES_IMAGE_AVATARS,
+    ),
+    (
+        "mention_everyone_grants",
+        MENTION_EVERYONE_GRANTS,
+        MENTION_EVERYONE_GRANTS,
+    ),
+];
+
+async fn first_run<'e, E>(ex: E, name: &str) -> sqlx::Result<bool>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    Ok(sqlx::query(
+        "INSERT INTO data_migrations(name, applied_at) VALUES($1, $2) ON CONFLICT(name) DO NOTHING",
+    )
+    .bind(name)
+    .bind(now())
+    .execute(ex)
+    .await?
+    .rows_affected()
+        == 1)
+}
+
+async fn run_data_migrations(pool: &Db, is_sqlite: bool) -> anyhow::Result<()> {
+    for (name, sqlite, postgres) in DATA_MIGRATIONS {
+        let sql = match is_sqlite {
+            true => sqlite,
+            false => postgres,
+        };
+        let mut tx = pool.begin().await?;
+        if first_run(&mut *tx, name).await? {
+            let mut rows = 0;
+            for stmt in sql.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+                rows += sqlx::query(stmt).execute(&mut *tx).await?.rows_affected();
+            }
+            tracing::info!("applied data migration {name} ({rows} rows)");
+        }
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
 async fn reconcile_columns(pool: &Db, is_sqlite: bool) -> anyhow::Result<()> {
     let int = match is_sqlite {
         true => "INTEGER",
@@ -116,11 +259,104 @@ async fn reconcile_columns(pool: &Db, is_sqlite: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn backfill_seq(pool: &Db) -> anyhow::Result<()> {
+    let rows = sqlx::query(
+        "SELECT id FROM channels WHERE last_seq = 0 AND EXISTS(SELECT 1 FROM messages WHERE channel_id = channels.id)",
+    )
+    .fetch_all(pool)
+    .await?;
+    for r in &rows {
+        let id: i64 = r.try_get(0)?;
+        sqlx::query(
+            "UPDATE messages SET seq = (SELECT COUNT(*) FROM messages p WHERE p.channel_id = $1 AND p.id <= messages.id) WHERE channel_id = $1",
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+        sqlx::query(
+            "UPDATE channels SET last_seq = (SELECT COUNT(*) FROM messages WHERE channel_id = $1) WHERE id = $1",
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+pub const MAX_CHANNEL_NAME_CHARS: usize = 100;
+
+fn renamed_channel(name: &str, id: i64, attempt: usize) -> String {
+    let suffix = match attempt {
+        0 => format!("-{id}"),
+        n => format!("-{id}-{n}"),
+    };
+    let base: String = name
+        .chars()
+        .take(MAX_CHANNEL_NAME_CHARS.saturating_sub(suffix.len()))
+        .collect();
+    format!("{base}{suffix}")
+}
+
+async fn unique_channel_names(pool: &Db) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    if first_run(&mut *tx, "unique_channel_names").await? {
+        let rows = sqlx::query("SELECT id, server, name FROM channels ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut taken = std::collections::HashSet::new();
+        let mut clashes = Vec::new();
+        for r in &rows {
+            let (id, server, name): (i64, String, String) =
+                (r.try_get(0)?, r.try_get(1)?, r.try_get(2)?);
+            if !taken.insert((server.clone(), name.to_lowercase())) {
+                clashes.push((id, server, name));
+            }
+        }
+        for (id, server, name) in &clashes {
+            let renamed = (0..)
+                .map(|attempt| renamed_channel(name, *id, attempt))
+                .find(|c| !taken.contains(&(server.clone(), c.to_lowercase())))
+                .unwrap_or_default();
+            taken.insert((server.clone(), renamed.to_lowercase()));
+            sqlx::query("UPDATE channels SET name = $1 WHERE id = $2")
+                .bind(&renamed)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if !clashes.is_empty() {
+            tracing::warn!(
+                "renamed {} channels whose names clashed within their server",
+                clashes.len()
+            );
+        }
+        sqlx::query(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_channels_server_name_ci ON channels(server, LOWER(name))",
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn next_seq<'e, E>(ex: E, channel_id: i64) -> sqlx::Result<i64>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    sqlx::query("UPDATE channels SET last_seq = last_seq + 1 WHERE id = $1 RETURNING last_seq")
+        .bind(channel_id)
+        .fetch_one(ex)
+        .await?
+        .try_get(0)
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AvatarKind {
     Identicon,
     Color,
+    Image,
 }
 
 impl AvatarKind {
@@ -128,6 +364,7 @@ impl AvatarKind {
         match self {
             AvatarKind::Identicon => "identicon",
             AvatarKind::Color => "color",
+            AvatarKind::Image => "image",
         }
     }
 
@@ -135,6 +372,7 @@ impl AvatarKind {
         match s {
             "identicon" => Ok(AvatarKind::Identicon),
             "color" => Ok(AvatarKind::Color),
+            "image" => Ok(AvatarKind::Image),
             other => Err(sqlx::Error::Decode(
                 format!("invalid avatar_kind: {other}").into(),
             )),
@@ -168,6 +406,85 @@ impl ChannelKind {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    Text,
+    Attachment,
+}
+
+impl ContentType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentType::Text => "text",
+            ContentType::Attachment => "attachment",
+        }
+    }
+
+    pub fn parse(s: &str) -> sqlx::Result<ContentType> {
+        match s {
+            "text" => Ok(ContentType::Text),
+            "attachment" => Ok(ContentType::Attachment),
+            other => Err(sqlx::Error::Decode(
+                format!("invalid content type: {other}").into(),
+            )),
+        }
+    }
+
+    pub fn parse_list(s: Option<String>) -> sqlx::Result<Option<Vec<ContentType>>> {
+        s.map(|s| s.split(',').map(ContentType::parse).collect())
+            .transpose()
+    }
+
+    pub fn join(types: &[ContentType]) -> Option<String> {
+        match types.is_empty() {
+            true => None,
+            false => Some(
+                types
+                    .iter()
+                    .map(|t| t.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    MemberJoined,
+    MemberLeft,
+    MemberKicked,
+    ChannelCreated,
+    ChannelDeleted,
+}
+
+impl ActivityKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ActivityKind::MemberJoined => "member_joined",
+            ActivityKind::MemberLeft => "member_left",
+            ActivityKind::MemberKicked => "member_kicked",
+            ActivityKind::ChannelCreated => "channel_created",
+            ActivityKind::ChannelDeleted => "channel_deleted",
+        }
+    }
+
+    pub fn parse(s: &str) -> sqlx::Result<ActivityKind> {
+        match s {
+            "member_joined" => Ok(ActivityKind::MemberJoined),
+            "member_left" => Ok(ActivityKind::MemberLeft),
+            "member_kicked" => Ok(ActivityKind::MemberKicked),
+            "channel_created" => Ok(ActivityKind::ChannelCreated),
+            "channel_deleted" => Ok(ActivityKind::ChannelDeleted),
+            other => Err(sqlx::Error::Decode(
+                format!("invalid activity kind: {other}").into(),
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum MediaKind {
@@ -194,6 +511,87 @@ impl MediaKind {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterStatus {
+    Clean,
+    Warning,
+    Filtered,
+}
+
+impl FilterStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilterStatus::Clean => "clean",
+            FilterStatus::Warning => "warning",
+            FilterStatus::Filtered => "filtered",
+        }
+    }
+
+    pub fn parse(s: &str) -> sqlx::Result<FilterStatus> {
+        match s {
+            "clean" => Ok(FilterStatus::Clean),
+            "warning" => Ok(FilterStatus::Warning),
+            "filtered" => Ok(FilterStatus::Filtered),
+            other => Err(sqlx::Error::Decode(
+                format!("invalid filter status: {other}").into(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MassMention {
+    Everyone,
+    Here,
+}
+
+impl MassMention {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MassMention::Everyone => "everyone",
+            MassMention::Here => "here",
+        }
+    }
+
+    pub fn parse(s: &str) -> sqlx::Result<MassMention> {
+        match s {
+            "everyone" => Ok(MassMention::Everyone),
+            "here" => Ok(MassMention::Here),
+            other => Err(sqlx::Error::Decode(
+                format!("invalid mass mention: {other}").into(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Open,
+    Resolved,
+}
+
+impl ReportStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Resolved => "resolved",
+        }
+    }
+
+    pub fn parse(s: &str) -> sqlx::Result<ReportStatus> {
+        match s {
+            "open" => Ok(ReportStatus::Open),
+            "resolved" => Ok(ReportStatus::Resolved),
+            other => Err(sqlx::Error::Decode(
+                format!("invalid report status: {other}").into(),
+            )),
+        }
+    }
+}
+
 pub struct User {
     pub username: String,
     pub display_name: String,
@@ -201,6 +599,7 @@ pub struct User {
     pub password_kind: String,
     pub avatar_kind: AvatarKind,
     pub avatar_color: Option<String>,
+    pub avatar_media_id: Option<String>,
     pub is_site_admin: bool,
     pub created_at: i64,
 }
@@ -214,12 +613,15 @@ impl User {
             password_kind: row.try_get("password_kind")?,
             avatar_kind: AvatarKind::parse(&row.try_get::<String, _>("avatar_kind")?)?,
             avatar_color: row.try_get("avatar_color")?,
+            avatar_media_id: row.try_get("avatar_media_id")?,
             is_site_admin: row.try_get::<i64, _>("is_site_admin")? != 0,
             created_at: row.try_get("created_at")?,
         })
     }
 }
 
+pub const SYSTEM_USERNAME: &str = "system";
+
 pub async fn get_user<'e, E>(ex: E, username: &str) -> sqlx::Result<Option<User>>
 where
     E: sqlx::Executor<'e, Database = sqlx::Any>,
@@ -240,9 +642,10 @@ pub enum Perm {
     Kick = 4,
     DeleteServer = 8,
     ManageAdmins = 16,
+    MentionEveryone = 32,
 }
 
-pub const ALL_PERMS: i64 = 31;
+pub const ALL_PERMS: i64 = 63;
 
 pub async fn effective_perms(db: &Db, server: &str, user: &User) -> sqlx::Result<i64> {
     if user.is_site_admin {
@@ -391,6 +794,40 @@ pub async fn touch_interaction(db: &Db, server: &str, username: &str) -> sqlx::R
     .map(|_| ())
 }
 
+pub async fn record_activity<'e, E>(
+    ex: E,
+    server: &str,
+    kind: ActivityKind,
+    actor: &str,
+    target: &str,
+    channel_id: Option<i64>,
+) -> sqlx::Result<()>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    sqlx::query(
+        "INSERT INTO server_activity(server, kind, actor, target, channel_id, created_at) VALUES($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(server)
+    .bind(kind.as_str())
+    .bind(actor)
+    .bind(target)
+    .bind(channel_id)
+    .bind(now())
+    .execute(ex)
+    .await?;
+    Ok(())
+}
+
+pub async fn mark_seen(db: &Db, username: &str) -> sqlx::Result<()> {
+    sqlx::query("UPDATE users SET last_seen = $1 WHERE username = $2")
+        .bind(now())
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+}
+
 pub fn now() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -440,6 +877,9 @@ pub async fn open(database_url: Option<&str>) -> anyhow::Result<Db> {
         sqlx::query(stmt).execute(&pool).await?;
     }
     reconcile_columns(&pool, is_sqlite).await?;
+    backfill_seq(&pool).await?;
+    unique_channel_names(&pool).await?;
+    run_data_migrations(&pool, is_sqlite).await?;
     let t = now();
     sqlx::query("INSERT INTO servers(name, display_name, creator, created_at) VALUES('rchat', 'RChat', NULL, $1) ON CONFLICT(name) DO NOTHING")
         .bind(t)
@@ -476,6 +916,227 @@ mod tests {
             .expect("channel id")
     }
 
+    #[tokio::test]
+    async fn legacy_messages_get_sequence_numbers() {
+        let (state, path) = temp_state("seq_backfill").await;
+        let db = &state.db;
+        let cid = general_id(db).await;
+        for content in ["a", "b", "c"] {
+            sqlx::query("INSERT INTO messages(channel_id, author, content, created_at) VALUES($1, 'amy', $2, 0)")
+                .bind(cid)
+                .bind(content)
+                .execute(db)
+                .await
+                .expect("insert legacy message");
+        }
+        backfill_seq(db).await.expect("backfill");
+        let seqs: Vec<i64> = sqlx::query("SELECT seq FROM messages ORDER BY id")
+            .fetch_all(db)
+            .await
+            .expect("seqs")
+            .iter()
+            .map(|r| r.try_get(0).expect("seq"))
+            .collect();
+        assert_eq!(seqs, [1, 2, 3]);
+        assert_eq!(next_seq(db, cid).await.expect("next"), 4);
+        backfill_seq(db).await.expect("second backfill is a no-op");
+        assert_eq!(next_seq(db, cid).await.expect("next"), 5);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn self_dms_are_backfilled_once() {
+        let (state, path) = temp_state("self_dm_backfill").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES('amy', 'amy', 'x', 'text', 'identicon', 0, 0)")
+            .execute(db)
+            .await
+            .expect("insert user");
+        let self_dms = || async {
+            sqlx::query("SELECT COUNT(*) FROM dms WHERE user_a = 'amy' AND user_b = 'amy'")
+                .fetch_one(db)
+                .await
+                .expect("count")
+                .try_get::<i64, _>(0)
+                .expect("count")
+        };
+        run_data_migrations(db, true)
+            .await
+            .expect("already applied");
+        assert_eq!(self_dms().await, 0);
+        sqlx::query("DELETE FROM data_migrations WHERE name = 'self_dms'")
+            .execute(db)
+            .await
+            .expect("forget migration");
+        run_data_migrations(db, true).await.expect("backfill");
+        assert_eq!(self_dms().await, 1);
+        run_data_migrations(db, true)
+            .await
+            .expect("second run is a no-op");
+        assert_eq!(self_dms().await, 1);
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn legacy_reports_gain_snapshots() {
+        let (state, path) = temp_state("report_snapshots").await;
+        let db = &state.db;
+        for stmt in [
+            "DROP TABLE message_reports",
+            "CREATE TABLE message_reports(id INTEGER PRIMARY KEY AUTOINCREMENT, message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE, reporter TEXT NOT NULL, reason TEXT NOT NULL, status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open','resolved')), resolved_by TEXT, resolved_at INTEGER, created_at INTEGER NOT NULL, UNIQUE(message_id, reporter))",
+            "INSERT INTO messages(id, channel_id, author, content, created_at) SELECT 7, id, 'amy', 'rude words', 0 FROM channels WHERE server = 'rchat'",
+            "INSERT INTO message_reports(message_id, reporter, reason, created_at) VALUES(7, 'bob', 'rude', 0)",
+            "DELETE FROM data_migrations WHERE name = 'report_snapshots'",
+        ] {
+            sqlx::query(stmt).execute(db).await.expect(stmt);
+        }
+        run_data_migrations(db, true)
+            .await
+            .expect("rebuild reports");
+        sqlx::query("DELETE FROM messages WHERE id = 7")
+            .execute(db)
+            .await
+            .expect("delete message");
+        let r = sqlx::query("SELECT message_id, author, content, reason FROM message_reports")
+            .fetch_one(db)
+            .await
+            .expect("report survives");
+        assert_eq!(r.try_get::<Option<i64>, _>(0).expect("message_id"), None);
+        assert_eq!(r.try_get::<String, _>(1).expect("author"), "amy");
+        assert_eq!(r.try_get::<String, _>(2).expect("content"), "rude words");
+        assert_eq!(r.try_get::<String, _>(3).expect("reason"), "rude");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn legacy_image_avatars_are_stored() {
+        let (state, path) = temp_state("image_avatars").await;
+        let db = &state.db;
+        for stmt in [
+            "DROP TABLE users",
+            "CREATE TABLE users(username TEXT PRIMARY KEY, display_name TEXT NOT NULL, password_hash TEXT NOT NULL, password_kind TEXT NOT NULL CHECK(password_kind IN ('text','words')), avatar_kind TEXT NOT NULL CHECK(avatar_kind IN ('identicon','color')), avatar_color TEXT, avatar_media_id TEXT, is_site_admin INTEGER NOT NULL DEFAULT 0, created_at INTEGER NOT NULL, last_seen INTEGER)",
+            "INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, avatar_media_id, created_at) VALUES('amy', 'amy', 'x', 'text', 'color', 'pic', 0)",
+            "INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, created_at) VALUES('bob', 'bob', 'x', 'text', 'identicon', 0)",
+            "DELETE FROM data_migrations WHERE name = 'image_avatars'",
+        ] {
+            sqlx::query(stmt).execute(db).await.expect(stmt);
+        }
+        run_data_migrations(db, true).await.expect("rebuild users");
+        let amy = get_user(db, "amy").await.expect("get").expect("amy");
+        assert_eq!(amy.avatar_kind, AvatarKind::Image);
+        assert_eq!(amy.avatar_media_id.as_deref(), Some("pic"));
+        let bob = get_user(db, "bob").await.expect("get").expect("bob");
+        assert_eq!(bob.avatar_kind, AvatarKind::Identicon);
+        sqlx::query("UPDATE users SET avatar_kind = 'image' WHERE username = 'bob'")
+            .execute(db)
+            .await
+            .expect("image kind allowed");
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn full_grants_gain_mention_everyone() {
+        let (state, path) = temp_state("mention_grants").await;
+        let db = &state.db;
+        add_member(db, "rchat", "full", 1, 31, 0).await;
+        add_member(db, "rchat", "narrow", 1, Perm::Kick as i64, 0).await;
+        for stmt in [
+            "INSERT INTO roles(server, name, color, perms) VALUES('rchat', 'mods', '#fff', 31)",
+            "INSERT INTO roles(server, name, color, perms) VALUES('rchat', 'kickers', '#fff', 4)",
+            "DELETE FROM data_migrations WHERE name = 'mention_everyone_grants'",
+        ] {
+            sqlx::query(stmt).execute(db).await.expect(stmt);
+        }
+        run_data_migrations(db, true).await.expect("grant");
+        let perms = async |sql: &str| -> Vec<i64> {
+            sqlx::query(sql)
+                .fetch_all(db)
+                .await
+                .expect(sql)
+                .iter()
+                .map(|r| r.try_get(0).expect("perms"))
+                .collect()
+        };
+        assert_eq!(
+            perms(
+                "SELECT perms FROM members WHERE username IN ('full', 'narrow') ORDER BY username"
+            )
+            .await,
+            [ALL_PERMS, Perm::Kick as i64]
+        );
+        assert_eq!(
+            perms("SELECT perms FROM roles ORDER BY name").await,
+            [Perm::Kick as i64, ALL_PERMS]
+        );
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn clashing_channel_names_are_renamed() {
+        let (state, path) = temp_state("channel_names_ci").await;
+        let db = &state.db;
+        sqlx::query("DROP INDEX idx_channels_server_name_ci")
+            .execute(db)
+            .await
+            .expect("drop index");
+        let long = "x".repeat(MAX_CHANNEL_NAME_CHARS);
+        let shout = long.to_uppercase();
+        let mut ids = Vec::new();
+        for name in ["General", long.as_str(), shout.as_str()] {
+            let id: i64 = sqlx::query(
+                "INSERT INTO channels(server, name, created_at) VALUES('rchat', $1, 0) RETURNING id",
+            )
+            .bind(name)
+            .fetch_one(db)
+            .await
+            .expect("insert clash")
+            .try_get(0)
+            .expect("id");
+            ids.push(id);
+        }
+        let dup = ids[0];
+        sqlx::query("INSERT INTO channels(server, name, created_at) VALUES('rchat', $1, 0)")
+            .bind(format!("general-{dup}"))
+            .execute(db)
+            .await
+            .expect("insert lookalike");
+        sqlx::query("DELETE FROM data_migrations WHERE name = 'unique_channel_names'")
+            .execute(db)
+            .await
+            .expect("forget migration");
+        unique_channel_names(db).await.expect("dedupe");
+        let names: Vec<String> =
+            sqlx::query("SELECT name FROM channels WHERE server = 'rchat' ORDER BY id")
+                .fetch_all(db)
+                .await
+                .expect("names")
+                .iter()
+                .map(|r| r.try_get(0).expect("name"))
+                .collect();
+        let suffix = format!("-{}", ids[2]);
+        let trimmed = format!(
+            "{}{suffix}",
+            "X".repeat(MAX_CHANNEL_NAME_CHARS - suffix.len())
+        );
+        assert_eq!(
+            names,
+            [
+                "general".to_string(),
+                format!("General-{dup}-1"),
+                long.clone(),
+                trimmed,
+                format!("general-{dup}"),
+            ]
+        );
+        let clash = sqlx::query(
+            "INSERT INTO channels(server, name, created_at) VALUES('rchat', 'GENERAL', 0)",
+        )
+        .execute(db)
+        .await;
+        assert!(clash.is_err());
+        done(state, path).await;
+    }
+
     #[tokio::test]
     async fn effective_perms_matrix() {
         let (state, path) = temp_state("perm_matrix").await;
@@ -1,6 +1,7 @@
 pub mod api;
 pub mod config;
 pub mod db;
+pub mod rate_limit;
 pub mod server;
 pub mod state;
 pub mod ws;
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::AppConfig;
+
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+}
+
+#[derive(Clone)]
+pub struct SendLimiter {
+    capacity: f64,
+    per_sec: f64,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl SendLimiter {
+    pub fn new(per_window: u32, window: Duration) -> Self {
+        SendLimiter {
+            capacity: f64::from(per_window),
+            per_sec: f64::from(per_window) / window.as_secs_f64().max(1.0),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn messages(config: &AppConfig) -> Self {
+        SendLimiter::new(
+            config.message_rate_limit,
+            Duration::from_secs(config.message_rate_window_secs),
+        )
+    }
+
+    pub fn dm_opens(config: &AppConfig) -> Self {
+        SendLimiter::new(
+            config.dm_open_rate_limit,
+            Duration::from_secs(config.dm_open_rate_window_secs),
+        )
+    }
+
+    pub fn announcements() -> Self {
+        SendLimiter::new(3, Duration::from_secs(600))
+    }
+
+    pub fn check(&self, username: &str) -> Result<(), u64> {
+        self.check_at(username, Instant::now())
+    }
+
+    fn check_at(&self, username: &str, now: Instant) -> Result<(), u64> {
+        if self.capacity == 0.0 {
+            return Ok(());
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(username.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_sec).min(self.capacity);
+        bucket.at = now;
+        match bucket.tokens >= 1.0 {
+            true => {
+                bucket.tokens -= 1.0;
+                Ok(())
+            }
+            false => Err(((1.0 - bucket.tokens) / self.per_sec).ceil() as u64),
+        }
+    }
+
+    pub fn prune(&self) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, b| {
+            b.tokens + now.saturating_duration_since(b.at).as_secs_f64() * self.per_sec
+                < self.capacity
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_then_recover() {
+        let limiter = SendLimiter::new(3, Duration::from_secs(60));
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_at("amy", t0).is_ok());
+        }
+        assert_eq!(limiter.check_at("amy", t0), Err(20));
+        assert!(limiter.check_at("bob", t0).is_ok());
+        let later = t0 + Duration::from_secs(20);
+        assert!(limiter.check_at("amy", later).is_ok());
+        assert!(limiter.check_at("amy", later).is_err());
+        let unlimited = SendLimiter::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(unlimited.check_at("amy", t0).is_ok());
+        }
+    }
+}
@@ -4,7 +4,7 @@ use axum::{
     Router,
     body::Body,
     extract::Request,
-    http::StatusCode,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use hyper::upgrade::OnUpgrade;
@@ -13,7 +13,9 @@ use hyper_util::rt::{TokioExecutor, TokioIo};
 
 type ProxyClient = Client<HttpConnector, Body>;
 use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
+use crate::api::{PAGE_LIMIT, PAGE_OFFSET, TOTAL_COUNT};
 use crate::config::AppConfig;
 use crate::state::AppState;
 
@@ -26,7 +28,8 @@ pub fn register_routes(proxy_url: Option<&str>, config: &AppConfig, state: AppSt
 
     let api_routes = crate::api::routes(state.clone())
         .with_state(state)
-        .layer(GovernorLayer::new(governor_conf));
+        .layer(GovernorLayer::new(governor_conf))
+        .layer(cors_layer(config));
     let router = Router::new().nest("/api", api_routes);
 
     match proxy_url {
@@ -43,6 +46,89 @@ pub fn register_routes(proxy_url: Option<&str>, config: &AppConfig, state: AppSt
     }
 }
 
+pub fn cors_layer(config: &AppConfig) -> CorsLayer {
+    let origins: Vec<&str> = config
+        .cors_origins
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .collect();
+    let layer = CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::PATCH,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::ACCEPT,
+            HeaderName::from_static("x-guest-grant"),
+            HeaderName::from_static("idempotency-key"),
+        ])
+        .expose_headers([
+            HeaderName::from_static(TOTAL_COUNT),
+            HeaderName::from_static(PAGE_LIMIT),
+            HeaderName::from_static(PAGE_OFFSET),
+        ])
+        .allow_credentials(config.cors_credentials);
+    match origins.as_slice() {
+        [] => layer,
+        list if list.contains(&"*") => {
+            if list.len() > 1 {
+                tracing::warn!("CORS_ORIGINS contains *, so every origin is allowed");
+            }
+            layer.allow_origin(AllowOrigin::mirror_request())
+        }
+        list => layer.allow_origin(AllowOrigin::list(
+            list.iter().filter_map(|o| HeaderValue::from_str(o).ok()),
+        )),
+    }
+}
+
+const HOP_HEADERS: [HeaderName; 7] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+fn upstream_uri(proxy_url: &str, path_query: &str) -> Option<hyper::Uri> {
+    if !path_query.starts_with('/') || path_query.starts_with("//") {
+        return None;
+    }
+    let base: hyper::Uri = proxy_url.parse().ok()?;
+    let uri: hyper::Uri = format!("{proxy_url}{path_query}").parse().ok()?;
+    match uri.authority() == base.authority() && uri.scheme() == base.scheme() {
+        true => Some(uri),
+        false => None,
+    }
+}
+
+fn strip_hop_headers(headers: &mut HeaderMap, upgrade: bool) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+    for name in listed.iter().chain(HOP_HEADERS.iter()) {
+        if upgrade && (name == header::CONNECTION || name == header::UPGRADE) {
+            continue;
+        }
+        headers.remove(name);
+    }
+}
+
 async fn proxy_to_frontend(proxy_url: Arc<str>, client: ProxyClient, mut req: Request) -> Response {
     let path_query = req
         .uri()
@@ -50,12 +136,11 @@ async fn proxy_to_frontend(proxy_url: Arc<str>, client: ProxyClient, mut req: Re
         .map(|pq| pq.as_str())
         .unwrap_or(req.uri().path());
 
-    let new_uri = format!("{proxy_url}{path_query}");
-    match new_uri.parse() {
-        Ok(uri) => *req.uri_mut() = uri,
-        Err(e) => {
-            tracing::error!("Failed to parse URI {new_uri}: {e}");
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Invalid URI").into_response();
+    match upstream_uri(&proxy_url, path_query) {
+        Some(uri) => *req.uri_mut() = uri,
+        None => {
+            tracing::warn!("Rejected proxy path {path_query}");
+            return (StatusCode::BAD_REQUEST, "Invalid request path").into_response();
         }
     }
 
@@ -72,12 +157,14 @@ async fn proxy_to_frontend(proxy_url: Arc<str>, client: ProxyClient, mut req: Re
     }
 
     let client_upgrade = req.extensions_mut().remove::<OnUpgrade>();
+    let upgrade = client_upgrade.is_some() && req.headers().contains_key(header::UPGRADE);
+    strip_hop_headers(req.headers_mut(), upgrade);
 
     match client.request(req).await {
         Ok(mut response) => {
-            if response.status() == StatusCode::SWITCHING_PROTOCOLS
-                && let Some(client_upgrade) = client_upgrade
-            {
+            let switching = response.status() == StatusCode::SWITCHING_PROTOCOLS;
+            strip_hop_headers(response.headers_mut(), switching);
+            if switching && let Some(client_upgrade) = client_upgrade {
                 let backend_upgrade = hyper::upgrade::on(&mut response);
                 tokio::spawn(async move {
                     match (client_upgrade.await, backend_upgrade.await) {
@@ -99,3 +186,146 @@ async fn proxy_to_frontend(proxy_url: Arc<str>, client: ProxyClient, mut req: Re
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ProxyClient, cors_layer, proxy_to_frontend, strip_hop_headers, upstream_uri};
+    use crate::config::{AppConfig, CliOverrides};
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::{HeaderMap, Method, Request, StatusCode, header};
+    use axum::routing::get;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn preflight(origins: Option<&str>, origin: &str) -> axum::http::HeaderMap {
+        let mut config = AppConfig::load(&CliOverrides {
+            host: None,
+            port: None,
+            mode: None,
+        })
+        .expect("config");
+        config.cors_origins = origins.map(str::to_string);
+        config.cors_credentials = true;
+        let app = Router::new()
+            .route("/x", get(|| async {}))
+            .layer(cors_layer(&config));
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/x")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+            .body(Body::empty())
+            .expect("request");
+        app.oneshot(req).await.expect("response").headers().clone()
+    }
+
+    #[tokio::test]
+    async fn cors_preflight() {
+        let allowed = preflight(
+            Some("https://a.example, https://b.example"),
+            "https://b.example",
+        )
+        .await;
+        assert_eq!(
+            allowed[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://b.example"
+        );
+        assert_eq!(allowed[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        let methods = allowed[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .expect("methods");
+        assert!(methods.contains("POST"));
+        let headers = allowed[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .expect("headers");
+        assert!(headers.contains("authorization"));
+        let foreign = preflight(Some("https://a.example"), "https://evil.example").await;
+        assert!(!foreign.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        let closed = preflight(None, "https://a.example").await;
+        assert!(!closed.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn wildcard_in_a_list_allows_any_origin() {
+        let mixed = preflight(Some("https://a.example, *"), "https://z.example").await;
+        assert_eq!(
+            mixed[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://z.example"
+        );
+    }
+
+    #[test]
+    fn crafted_paths_stay_on_the_frontend() {
+        let base = "http://127.0.0.1:3001";
+        assert_eq!(
+            upstream_uri(base, "/s/rchat?x=1").map(|u| u.to_string()),
+            Some("http://127.0.0.1:3001/s/rchat?x=1".to_string())
+        );
+        for path in [
+            "@evil.example/x",
+            "//evil.example/x",
+            ".evil.example/x",
+            "",
+            "/a b",
+        ] {
+            assert!(upstream_uri(base, path).is_none(), "{path} was forwarded");
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, "x-secret, keep-alive".parse().unwrap());
+        headers.insert("x-secret", "1".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert(header::TRANSFER_ENCODING, "chunked".parse().unwrap());
+        headers.insert(header::ACCEPT, "text/html".parse().unwrap());
+        strip_hop_headers(&mut headers, false);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(header::ACCEPT));
+        let mut upgrade = HeaderMap::new();
+        upgrade.insert(header::CONNECTION, "Upgrade".parse().unwrap());
+        upgrade.insert(header::UPGRADE, "websocket".parse().unwrap());
+        strip_hop_headers(&mut upgrade, true);
+        assert_eq!(upgrade.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn proxy_passes_upstream_status() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let url = format!("http://{}", listener.local_addr().expect("addr"));
+        let upstream = Router::new().fallback(|| async {
+            (
+                StatusCode::NOT_FOUND,
+                [("connection", "x-internal"), ("x-internal", "1")],
+                "no such page",
+            )
+        });
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+        let client: ProxyClient = Client::builder(TokioExecutor::new()).build_http();
+        let get = |path: &str| {
+            Request::builder()
+                .uri(path)
+                .body(Body::empty())
+                .expect("request")
+        };
+        let resp =
+            proxy_to_frontend(Arc::from(url.as_str()), client.clone(), get("/missing")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(!resp.headers().contains_key("x-internal"));
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .expect("body");
+        assert_eq!(&body[..], b"no such page");
+
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let dead = format!("http://{}", closed.local_addr().expect("addr"));
+        drop(closed);
+        let resp = proxy_to_frontend(Arc::from(dead.as_str()), client, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+}
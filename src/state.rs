@@ -2,12 +2,19 @@ use std::sync::Arc;
 
 use s3::Bucket;
 
+use crate::config::AppConfig;
 use crate::db::Db;
+use crate::rate_limit::SendLimiter;
 use crate::ws::Hub;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Db,
+    pub config: Arc<AppConfig>,
     pub hub: Hub,
     pub s3: Option<Arc<Bucket>>,
+    pub sends: SendLimiter,
+    pub announces: SendLimiter,
+    pub dm_opens: SendLimiter,
+    pub http: reqwest::Client,
 }
@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use axum::extract::State;
 use axum::extract::ws::{Message as Frame, WebSocket, WebSocketUpgrade};
@@ -8,11 +9,19 @@ use axum::response::Response;
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use tokio::sync::broadcast;
+use tokio::time::Instant;
+use utoipa::ToSchema;
 
+use crate::api::dms::dm_muted;
+use crate::api::servers::PendingTransfer;
 use crate::api::{
-    Channel, Embed, Member, Message, ServerSummaryLite, Settings, UserRef, grant_matches,
+    Channel, Embed, Member, Message, MessageReport, ServerSummaryLite, Settings, UserRef,
+    grant_matches,
+};
+use crate::db::{
+    ChannelKind, Db, MassMention, channel_access, channel_viewable, get_user, mark_seen, now,
+    setting_on, touch_interaction,
 };
-use crate::db::{ChannelKind, Db, channel_viewable, now, setting_on, touch_interaction};
 use crate::state::AppState;
 
 #[derive(Clone, Copy, PartialEq, Eq, Serialize)]
@@ -23,6 +32,15 @@ pub enum CallPhase {
     Ended,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementLevel {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CallKind {
@@ -30,7 +48,17 @@ pub enum CallKind {
     P2p,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
 const P2P_IDS_CAP: usize = 256;
+const TYPING_WINDOW: Duration = Duration::from_secs(8);
+const AUTH_RECHECK: Duration = Duration::from_secs(60);
 
 #[derive(Clone, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -41,6 +69,8 @@ pub enum WsEvent {
         dm_id: Option<i64>,
         dm_users: Option<Vec<String>>,
         message: Box<Message>,
+        muted: bool,
+        mentioned: bool,
     },
     MessageUpdated {
         message: Box<Message>,
@@ -55,6 +85,18 @@ pub enum WsEvent {
         id: i64,
         thread_root_id: Option<i64>,
     },
+    MessageRestored {
+        server: Option<String>,
+        channel_id: Option<i64>,
+        dm_id: Option<i64>,
+        dm_users: Option<Vec<String>>,
+        message: Box<Message>,
+    },
+    MessagesPurged {
+        server: String,
+        channel_id: i64,
+        ids: Vec<i64>,
+    },
     MediaRemoved {
         server: Option<String>,
         channel_id: Option<i64>,
@@ -93,6 +135,10 @@ pub enum WsEvent {
         server: String,
         channel_id: i64,
     },
+    ChannelsReordered {
+        server: String,
+        ids: Vec<i64>,
+    },
     ServerCreated {
         server: ServerSummaryLite,
     },
@@ -115,6 +161,11 @@ pub enum WsEvent {
         server: String,
         username: String,
     },
+    ServerWelcome {
+        server: String,
+        username: String,
+        message: String,
+    },
     AdminChanged {
         server: String,
         username: String,
@@ -138,12 +189,54 @@ pub enum WsEvent {
         server: String,
         username: String,
         online: bool,
+        status: PresenceStatus,
+    },
+    StatusChanged {
+        server: String,
+        username: String,
+        status: PresenceStatus,
+    },
+    Typing {
+        server: Option<String>,
+        channel_id: Option<i64>,
+        dm_id: Option<i64>,
+        dm_users: Option<Vec<String>>,
+        username: String,
+    },
+    TypingStopped {
+        server: Option<String>,
+        channel_id: Option<i64>,
+        dm_id: Option<i64>,
+        dm_users: Option<Vec<String>>,
+        username: String,
     },
     ReadUpdated {
         username: String,
         scope: String,
         last_read: i64,
     },
+    DmPinned {
+        username: String,
+        dm_id: i64,
+        pinned: bool,
+    },
+    DmMuted {
+        username: String,
+        dm_id: i64,
+        muted: bool,
+    },
+    TransferOffered {
+        transfer: PendingTransfer,
+    },
+    TransferClosed {
+        server: String,
+        from_user: String,
+        to_user: String,
+        accepted: bool,
+    },
+    MessageReported {
+        report: MessageReport,
+    },
     VoiceState {
         server: String,
         channel_id: i64,
@@ -183,15 +276,23 @@ pub enum WsEvent {
     Error {
         message: String,
     },
+    ResyncRequired {
+        missed: u64,
+    },
     DmCreated {
         dm_users: Vec<String>,
     },
     Banned {
         username: String,
+        purged: bool,
     },
     SettingsChanged {
         settings: Settings,
     },
+    Announcement {
+        message: String,
+        level: AnnouncementLevel,
+    },
 }
 
 #[derive(Deserialize)]
@@ -204,6 +305,17 @@ enum ClientMsg {
     Viewing {
         server: Option<String>,
     },
+    Presence {
+        status: PresenceStatus,
+    },
+    Typing {
+        channel_id: Option<i64>,
+        dm_id: Option<i64>,
+    },
+    StopTyping {
+        channel_id: Option<i64>,
+        dm_id: Option<i64>,
+    },
     Subscribe {
         servers: Vec<String>,
         #[serde(default)]
@@ -242,6 +354,42 @@ enum ClientMsg {
     },
 }
 
+#[derive(Clone, PartialEq)]
+struct TypingScope {
+    server: Option<String>,
+    channel_id: Option<i64>,
+    dm_id: Option<i64>,
+    dm_users: Option<Vec<String>>,
+}
+
+impl TypingScope {
+    fn event(&self, username: &str, typing: bool) -> WsEvent {
+        let TypingScope {
+            server,
+            channel_id,
+            dm_id,
+            dm_users,
+        } = self.clone();
+        let username = username.to_string();
+        match typing {
+            true => WsEvent::Typing {
+                server,
+                channel_id,
+                dm_id,
+                dm_users,
+                username,
+            },
+            false => WsEvent::TypingStopped {
+                server,
+                channel_id,
+                dm_id,
+                dm_users,
+                username,
+            },
+        }
+    }
+}
+
 struct Room {
     server: String,
     users: Vec<(String, u64)>,
@@ -365,6 +513,8 @@ impl VoiceMap {
 pub struct Hub {
     tx: broadcast::Sender<WsEvent>,
     presence: Arc<Mutex<HashMap<String, HashMap<String, usize>>>>,
+    conns: Arc<Mutex<HashMap<String, HashSet<u64>>>>,
+    away: Arc<Mutex<HashMap<String, HashSet<u64>>>>,
     voice: Arc<Mutex<VoiceMap>>,
     p2p: Arc<Mutex<HashMap<String, BTreeMap<u64, P2pHost>>>>,
 }
@@ -380,6 +530,8 @@ impl Hub {
         Hub {
             tx: broadcast::channel(256).0,
             presence: Arc::new(Mutex::new(HashMap::new())),
+            conns: Arc::new(Mutex::new(HashMap::new())),
+            away: Arc::new(Mutex::new(HashMap::new())),
             voice: Arc::new(Mutex::new(VoiceMap::default())),
             p2p: Arc::new(Mutex::new(HashMap::new())),
         }
@@ -402,16 +554,22 @@ impl Hub {
                 WsEvent::ServerDeleted { name } => {
                     p.remove(name);
                 }
-                WsEvent::Banned { username } => {
+                WsEvent::Banned {
+                    username,
+                    purged: _,
+                } => {
                     for (server, users) in p.iter_mut() {
                         if users.remove(username).is_some() {
                             extra.push(WsEvent::PresenceChanged {
                                 server: server.clone(),
                                 username: username.clone(),
                                 online: false,
+                                status: PresenceStatus::Offline,
                             });
                         }
                     }
+                    self.conns.lock().unwrap().remove(username);
+                    self.away.lock().unwrap().remove(username);
                     self.p2p.lock().unwrap().remove(username);
                 }
                 _ => {}
@@ -440,7 +598,10 @@ impl Hub {
                 | WsEvent::MemberKicked { server, username } => {
                     v.leave_rooms(|s, u, _| s == server && u == username, &mut extra);
                 }
-                WsEvent::Banned { username } => {
+                WsEvent::Banned {
+                    username,
+                    purged: _,
+                } => {
                     v.leave_rooms(|_, u, _| u == username, &mut extra);
                     v.end_calls(|c| c.dm_users.iter().any(|u| u == username), &mut extra);
                 }
@@ -735,6 +896,125 @@ impl Hub {
             .is_some_and(|users| users.contains_key(username))
     }
 
+    pub fn status(&self, server: &str, username: &str) -> PresenceStatus {
+        match self.is_online(server, username) {
+            false => PresenceStatus::Offline,
+            true if self.is_away(username) => PresenceStatus::Away,
+            true => PresenceStatus::Online,
+        }
+    }
+
+    fn is_away(&self, user: &str) -> bool {
+        let conns = self.conns.lock().unwrap();
+        let away = self.away.lock().unwrap();
+        match (conns.get(user), away.get(user)) {
+            (Some(live), Some(idle)) => !live.is_empty() && live.is_subset(idle),
+            (_, _) => false,
+        }
+    }
+
+    pub fn connect(&self, user: &str, conn: u64) -> bool {
+        let was_away = self.is_away(user);
+        let first = {
+            let mut conns = self.conns.lock().unwrap();
+            let live = conns.entry(user.to_string()).or_default();
+            live.insert(conn);
+            live.len() == 1
+        };
+        if was_away {
+            self.broadcast_status(user);
+        }
+        first
+    }
+
+    pub fn disconnect(&self, user: &str, conn: u64) -> bool {
+        let was_away = self.is_away(user);
+        let last = {
+            let mut conns = self.conns.lock().unwrap();
+            let Some(live) = conns.get_mut(user) else {
+                return false;
+            };
+            if !live.remove(&conn) {
+                return false;
+            }
+            let last = live.is_empty();
+            if last {
+                conns.remove(user);
+            }
+            last
+        };
+        {
+            let mut away = self.away.lock().unwrap();
+            if let Some(idle) = away.get_mut(user) {
+                idle.remove(&conn);
+                if idle.is_empty() {
+                    away.remove(user);
+                }
+            }
+        }
+        if !last && self.is_away(user) != was_away {
+            self.broadcast_status(user);
+        }
+        last
+    }
+
+    pub fn is_connected(&self, user: &str) -> bool {
+        self.conns.lock().unwrap().contains_key(user)
+    }
+
+    pub fn user_status(&self, username: &str) -> PresenceStatus {
+        match self.is_connected(username) {
+            false => PresenceStatus::Offline,
+            true if self.is_away(username) => PresenceStatus::Away,
+            true => PresenceStatus::Online,
+        }
+    }
+
+    pub fn set_away(&self, user: &str, conn: u64, away: bool) {
+        let was_away = self.is_away(user);
+        {
+            let mut a = self.away.lock().unwrap();
+            match away {
+                true => {
+                    a.entry(user.to_string()).or_default().insert(conn);
+                }
+                false => {
+                    if let Some(idle) = a.get_mut(user) {
+                        idle.remove(&conn);
+                        if idle.is_empty() {
+                            a.remove(user);
+                        }
+                    }
+                }
+            }
+        }
+        if self.is_away(user) != was_away {
+            self.broadcast_status(user);
+        }
+    }
+
+    fn broadcast_status(&self, user: &str) {
+        let servers: Vec<String> = self
+            .presence
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, users)| users.contains_key(user))
+            .map(|(server, _)| server.clone())
+            .collect();
+        let status = match self.is_away(user) {
+            true => PresenceStatus::Away,
+            false => PresenceStatus::Online,
+        };
+        for server in servers {
+            self.broadcast(WsEvent::StatusChanged {
+                server,
+                username: user.to_string(),
+                status,
+            });
+        }
+    }
+
     pub fn set_viewing(&self, user: &str, old: Option<&str>, new: Option<&str>) {
         if old == new {
             return;
@@ -767,11 +1047,18 @@ impl Hub {
                 }
             }
         }
+        let away = self.is_away(user);
         for (server, online) in deltas {
+            let status = match (online, away) {
+                (false, _) => PresenceStatus::Offline,
+                (true, true) => PresenceStatus::Away,
+                (true, false) => PresenceStatus::Online,
+            };
             self.broadcast(WsEvent::PresenceChanged {
                 server,
                 username: user.to_string(),
                 online,
+                status,
             });
         }
     }
@@ -788,6 +1075,7 @@ impl Hub {
                 server: server.to_string(),
                 username: user.to_string(),
                 online: false,
+                status: PresenceStatus::Offline,
             });
         }
     }
@@ -884,6 +1172,8 @@ async fn broadcast_call_log(state: &AppState, dm_id: i64, dm_users: Vec<String>,
             dm_id: Some(dm_id),
             dm_users: Some(dm_users),
             message: Box::new(message),
+            muted: false,
+            mentioned: false,
         });
     }
 }
@@ -942,7 +1232,7 @@ pub async fn sweep_and_log(state: &AppState, idle: i64) {
 
 #[utoipa::path(get, path = "/api/ws", responses((status = 101, description = "WebSocket upgrade")))]
 pub async fn handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(move |socket| run(state, socket))
+    ws.on_upgrade(move |socket| run(state, socket, AUTH_RECHECK))
 }
 
 static CONN_SEQ: AtomicU64 = AtomicU64::new(0);
@@ -1055,6 +1345,15 @@ async fn voice_msg(state: &AppState, user: &str, conn: u64, msg: ClientMsg) -> R
         }
         ClientMsg::Auth { token: _ }
         | ClientMsg::Viewing { server: _ }
+        | ClientMsg::Presence { status: _ }
+        | ClientMsg::Typing {
+            channel_id: _,
+            dm_id: _,
+        }
+        | ClientMsg::StopTyping {
+            channel_id: _,
+            dm_id: _,
+        }
         | ClientMsg::Subscribe {
             servers: _,
             grants: _,
@@ -1106,6 +1405,63 @@ async fn server_password_hash(db: &Db, server: &str) -> Option<Option<String>> {
         .map(|r| r.try_get(0).unwrap_or(None))
 }
 
+async fn typing_scope(
+    state: &AppState,
+    user: &str,
+    channel_id: Option<i64>,
+    dm_id: Option<i64>,
+) -> Result<TypingScope, String> {
+    let db_err = |e: sqlx::Error| e.to_string();
+    match (channel_id, dm_id) {
+        (Some(cid), None) => {
+            let server: String = sqlx::query("SELECT server FROM channels WHERE id = $1")
+                .bind(cid)
+                .fetch_optional(&state.db)
+                .await
+                .map_err(db_err)?
+                .ok_or_else(|| "Channel not found".to_string())?
+                .try_get(0)
+                .map_err(db_err)?;
+            let me = get_user(&state.db, user).await.map_err(db_err)?;
+            let can_send = is_member(&state.db, &server, user).await
+                && channel_access(&state.db, &server, cid, me.as_ref())
+                    .await
+                    .is_ok_and(|a| a.send);
+            if !can_send {
+                return Err("Not allowed".to_string());
+            }
+            Ok(TypingScope {
+                server: Some(server),
+                channel_id: Some(cid),
+                dm_id: None,
+                dm_users: None,
+            })
+        }
+        (None, Some(id)) => {
+            let row = sqlx::query("SELECT user_a, user_b FROM dms WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&state.db)
+                .await
+                .map_err(db_err)?
+                .ok_or_else(|| "DM not found".to_string())?;
+            let (a, b): (String, String) = (
+                row.try_get(0).map_err(db_err)?,
+                row.try_get(1).map_err(db_err)?,
+            );
+            if a != user && b != user {
+                return Err("Not a participant".to_string());
+            }
+            Ok(TypingScope {
+                server: None,
+                channel_id: None,
+                dm_id: Some(id),
+                dm_users: Some(vec![a, b]),
+            })
+        }
+        (_, _) => Err("Specify a channel or a DM".to_string()),
+    }
+}
+
 async fn is_member(db: &Db, server: &str, user: &str) -> bool {
     sqlx::query("SELECT 1 FROM members WHERE server = $1 AND username = $2")
         .bind(server)
@@ -1134,6 +1490,37 @@ async fn authed_sub_ok(db: &Db, server: &str, user: &str, site_admin: bool) -> b
     }
 }
 
+async fn identify(state: &AppState, token: Option<String>) -> Option<(Option<String>, bool)> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            return match setting_on(&state.db, "guests_enabled").await {
+                true => Some((None, false)),
+                false => None,
+            };
+        }
+    };
+    let row = sqlx::query(
+        "SELECT u.username, u.is_site_admin FROM tokens t JOIN users u ON u.username = t.username WHERE t.token = $1 AND (t.expires_at IS NULL OR t.expires_at > $2) AND t.impersonated_by IS NULL AND NOT EXISTS (SELECT 1 FROM banned_usernames b WHERE b.username = u.username)",
+    )
+    .bind(token)
+    .bind(now())
+    .fetch_optional(&state.db)
+    .await
+    .ok()??;
+    Some((
+        Some(row.try_get(0).ok()?),
+        row.try_get::<i64, _>(1).map(|v| v != 0).unwrap_or(false),
+    ))
+}
+
+async fn token_owned_by(state: &AppState, user: &str, token: &str) -> bool {
+    matches!(
+        identify(state, Some(token.to_string())).await,
+        Some((Some(owner), _)) if owner == user
+    )
+}
+
 async fn member_servers_of(db: &Db, user: &str) -> HashSet<String> {
     sqlx::query("SELECT server FROM members WHERE username = $1")
         .bind(user)
@@ -1160,6 +1547,15 @@ async fn channel_gate(
     }
 }
 
+fn mentions(hub: &Hub, server: &str, user: &str, message: &Message) -> bool {
+    match (message.author.username == user, message.mass_mention) {
+        (true, _) => false,
+        (false, Some(MassMention::Everyone)) => true,
+        (false, Some(MassMention::Here)) => hub.status(server, user) == PresenceStatus::Online,
+        (false, None) => message.content.to_lowercase().contains(&format!("@{user}")),
+    }
+}
+
 async fn send_voice_snapshot(
     state: &AppState,
     socket: &mut WebSocket,
@@ -1187,7 +1583,15 @@ async fn send_voice_snapshot(
     true
 }
 
-async fn run(state: AppState, mut socket: WebSocket) {
+async fn next_event(rx: &mut broadcast::Receiver<WsEvent>) -> Option<Result<WsEvent, u64>> {
+    match rx.recv().await {
+        Ok(ev) => Some(Ok(ev)),
+        Err(broadcast::error::RecvError::Lagged(missed)) => Some(Err(missed)),
+        Err(broadcast::error::RecvError::Closed) => None,
+    }
+}
+
+async fn run(state: AppState, mut socket: WebSocket, recheck: Duration) {
     let conn = CONN_SEQ.fetch_add(1, Ordering::Relaxed);
     let token = loop {
         match socket.recv().await {
@@ -1199,38 +1603,33 @@ async fn run(state: AppState, mut socket: WebSocket) {
             Some(Ok(_)) => {}
         }
     };
-    let mut rx = state.hub.subscribe();
-    let (username, is_site_admin): (Option<String>, bool) = match token {
-        Some(token) => {
-            let row = sqlx::query(
-                "SELECT u.username, u.is_site_admin FROM tokens t JOIN users u ON u.username = t.username WHERE t.token = $1",
-            )
-            .bind(token)
-            .fetch_optional(&state.db)
-            .await
-            .ok()
-            .flatten();
-            match &row {
-                Some(r) => (
-                    r.try_get(0).ok(),
-                    r.try_get::<i64, _>(1).map(|v| v != 0).unwrap_or(false),
-                ),
-                None => (None, false),
+    let (username, is_site_admin) = match identify(&state, token.clone()).await {
+        Some(identity) => identity,
+        None => {
+            if let Ok(json) = serde_json::to_string(&WsEvent::Error {
+                message: "Unauthorized".to_string(),
+            }) {
+                let _ = socket.send(Frame::Text(json.into())).await;
             }
+            return;
         }
-        None => (None, false),
     };
+    let mut rx = state.hub.subscribe();
     let mut member_servers: HashSet<String> = match &username {
         Some(user) => member_servers_of(&state.db, user).await,
         None => HashSet::new(),
     };
-    if username.is_none() && !setting_on(&state.db, "guests_enabled").await {
-        return;
-    }
     let mut subs: HashSet<String> = HashSet::new();
     let mut guest_grants: HashMap<String, String> = HashMap::new();
     let mut viewing: Option<String> = None;
     let mut viewable: HashMap<i64, bool> = HashMap::new();
+    let away_after = Duration::from_secs(state.config.presence_away_secs);
+    let mut idle_at = Instant::now() + away_after;
+    let mut idle = false;
+    let mut typing: Option<TypingScope> = None;
+    let mut typing_until = Instant::now();
+    let mut token = token;
+    let mut recheck_at = Instant::now() + recheck;
     if !send_voice_snapshot(
         &state,
         &mut socket,
@@ -1244,12 +1643,18 @@ async fn run(state: AppState, mut socket: WebSocket) {
     {
         return;
     }
+    if let Some(user) = &username
+        && state.hub.connect(user, conn)
+    {
+        seen(&state.db, user).await;
+    }
     loop {
         tokio::select! {
-            ev = rx.recv() => {
-                let ev = match ev {
-                    Ok(ev) => ev,
-                    Err(broadcast::error::RecvError::Lagged(_)) => {
+            ev = next_event(&mut rx) => {
+                let mut ev = match ev {
+                    Some(Ok(ev)) => ev,
+                    Some(Err(missed)) => {
+                        tracing::debug!("ws connection {conn} lagged by {missed} events");
                         viewable.clear();
                         match &username {
                             Some(user) => {
@@ -1271,9 +1676,14 @@ async fn run(state: AppState, mut socket: WebSocket) {
                                 }
                             }
                         }
+                        if let Ok(json) = serde_json::to_string(&WsEvent::ResyncRequired { missed })
+                            && socket.send(Frame::Text(json.into())).await.is_err()
+                        {
+                            break;
+                        }
                         continue;
                     }
-                    Err(broadcast::error::RecvError::Closed) => break,
+                    None => break,
                 };
                 let mut deliver = wants(&ev, username.as_deref(), is_site_admin, &member_servers, &subs);
                 track(&ev, username.as_deref(), &mut member_servers, &mut subs, &mut viewing, &mut viewable);
@@ -1300,105 +1710,210 @@ async fn run(state: AppState, mut socket: WebSocket) {
                 if deliver && let Some((server, cid)) = event_channel(&ev) {
                     deliver = channel_gate(&state, &mut viewable, username.as_deref(), server, cid).await;
                 }
+                if deliver && let WsEvent::ChannelsReordered { server, ids } = &mut ev {
+                    let mut visible = Vec::with_capacity(ids.len());
+                    for cid in ids.iter().copied() {
+                        if channel_gate(&state, &mut viewable, username.as_deref(), server, cid).await {
+                            visible.push(cid);
+                        }
+                    }
+                    *ids = visible;
+                }
+                if deliver
+                    && let (Some(user), WsEvent::Message { server: _, channel_id: _, dm_id: Some(dm), dm_users: _, message: _, muted, mentioned: _ }) = (&username, &mut ev)
+                {
+                    *muted = dm_muted(&state.db, user, *dm).await;
+                }
+                if deliver
+                    && let (Some(user), WsEvent::Message { server: Some(server), channel_id: Some(_), dm_id: _, dm_users: _, message, muted: _, mentioned }) = (&username, &mut ev)
+                {
+                    *mentioned = mentions(&state.hub, server, user, message);
+                }
                 if deliver
                     && let Ok(json) = serde_json::to_string(&ev)
                         && socket.send(Frame::Text(json.into())).await.is_err() {
                             break;
                         }
                 match &ev {
-                    WsEvent::Banned { username: banned } if Some(banned.as_str()) == username.as_deref() => break,
+                    WsEvent::Banned { username: banned, purged: _ } if Some(banned.as_str()) == username.as_deref() => break,
                     WsEvent::SettingsChanged { settings } if username.is_none() && !settings.guests_enabled => break,
                     _ => {}
                 }
             }
+            _ = tokio::time::sleep_until(idle_at), if username.is_some() && !idle => {
+                idle = true;
+                if let Some(user) = &username {
+                    state.hub.set_away(user, conn, true);
+                }
+            }
+            _ = tokio::time::sleep_until(recheck_at), if username.is_some() => {
+                let alive = match (&username, &token) {
+                    (Some(user), Some(t)) => token_owned_by(&state, user, t).await,
+                    (_, _) => false,
+                };
+                if !alive {
+                    if let Ok(json) = serde_json::to_string(&WsEvent::Error {
+                        message: "Session expired".to_string(),
+                    }) {
+                        let _ = socket.send(Frame::Text(json.into())).await;
+                    }
+                    break;
+                }
+                recheck_at = Instant::now() + recheck;
+            }
+            _ = tokio::time::sleep_until(typing_until), if typing.is_some() => {
+                if let (Some(user), Some(scope)) = (&username, typing.take()) {
+                    state.hub.broadcast(scope.event(user, false));
+                }
+            }
             msg = socket.recv() => {
                 match msg {
-                    Some(Ok(Frame::Text(text))) => match serde_json::from_str::<ClientMsg>(&text) {
-                        Ok(ClientMsg::Viewing { server }) => {
-                            let target = match (&username, server) {
-                                (Some(user), Some(s)) => {
-                                    let s = s.to_lowercase();
-                                    is_member(&state.db, &s, user).await.then_some(s)
-                                }
-                                (_, _) => None,
-                            };
-                            if let Some(user) = &username { state.hub.set_viewing(user, viewing.as_deref(), target.as_deref()) }
-                            viewing = target;
+                    Some(Ok(Frame::Text(text))) => {
+                        let parsed = serde_json::from_str::<ClientMsg>(&text);
+                        if let Some(user) = &username
+                            && !matches!(parsed, Ok(ClientMsg::Presence { status: _ }))
+                        {
+                            idle_at = Instant::now() + away_after;
+                            if idle {
+                                idle = false;
+                                state.hub.set_away(user, conn, false);
+                            }
                         }
-                        Ok(ClientMsg::Subscribe { servers, grants }) => {
-                            subs.clear();
-                            guest_grants.clear();
-                            for s in servers {
-                                let s = s.to_lowercase();
-                                let grant = grants.get(&s).cloned();
-                                let allowed = match &username {
-                                    Some(user) => authed_sub_ok(&state.db, &s, user, is_site_admin).await,
-                                    None => guest_sub_ok(&state.db, &s, grant.as_deref()).await,
+                        match parsed {
+                            Ok(ClientMsg::Viewing { server }) => {
+                                let target = match (&username, server) {
+                                    (Some(user), Some(s)) => {
+                                        let s = s.to_lowercase();
+                                        is_member(&state.db, &s, user).await.then_some(s)
+                                    }
+                                    (_, _) => None,
                                 };
-                                if allowed {
-                                    if let Some(g) = grant {
-                                        guest_grants.insert(s.clone(), g);
+                                if let Some(user) = &username { state.hub.set_viewing(user, viewing.as_deref(), target.as_deref()) }
+                                viewing = target;
+                            }
+                            Ok(ClientMsg::Typing { channel_id, dm_id }) => {
+                                let Some(user) = &username else { continue };
+                                match typing_scope(&state, user, channel_id, dm_id).await {
+                                    Ok(scope) => {
+                                        if let Some(old) = typing.take_if(|old| *old != scope) {
+                                            state.hub.broadcast(old.event(user, false));
+                                        }
+                                        state.hub.broadcast(scope.event(user, true));
+                                        typing = Some(scope);
+                                        typing_until = Instant::now() + TYPING_WINDOW;
+                                    }
+                                    Err(message) => {
+                                        if let Ok(json) = serde_json::to_string(&WsEvent::Error { message })
+                                            && socket.send(Frame::Text(json.into())).await.is_err() {
+                                                break;
+                                            }
                                     }
-                                    subs.insert(s);
                                 }
                             }
-                            if !send_voice_snapshot(&state, &mut socket, username.as_deref(), is_site_admin, &member_servers, &subs, &mut viewable).await {
-                                break;
+                            Ok(ClientMsg::StopTyping { channel_id, dm_id }) => {
+                                if let Some(user) = &username
+                                    && let Some(scope) = typing.take_if(|s| s.channel_id == channel_id && s.dm_id == dm_id)
+                                {
+                                    state.hub.broadcast(scope.event(user, false));
+                                }
                             }
-                        }
-                        Ok(ClientMsg::P2pHosting { peer_id, ids }) => {
-                            if let Some(user) = &username {
-                                let ids: Vec<String> = ids.into_iter().take(P2P_IDS_CAP).collect();
-                                state.hub.set_p2p(user, conn, peer_id.clone(), ids.clone());
-                                let (scope_servers, scope_users) = p2p_scope(&state.db, user).await;
-                                state.hub.broadcast(WsEvent::P2pAvailability {
-                                    hoster: user.clone(),
-                                    peer_id: Some(peer_id),
-                                    ids,
-                                    online: true,
-                                    scope_servers,
-                                    scope_users,
-                                });
+                            Ok(ClientMsg::Presence { status }) => {
+                                if let Some(user) = &username {
+                                    idle = status != PresenceStatus::Online;
+                                    idle_at = Instant::now() + away_after;
+                                    state.hub.set_away(user, conn, idle);
+                                }
                             }
-                        }
-                        Ok(ClientMsg::P2pWho { hosters }) => {
-                            let mut closed = false;
-                            for hoster in hosters.into_iter().take(P2P_IDS_CAP) {
-                                let hoster = hoster.to_lowercase();
-                                let ev = match state.hub.p2p_of(&hoster) {
-                                    Some((peer_id, ids)) => WsEvent::P2pAvailability {
-                                        hoster,
+                            Ok(ClientMsg::Subscribe { servers, grants }) => {
+                                subs.clear();
+                                guest_grants.clear();
+                                for s in servers {
+                                    let s = s.to_lowercase();
+                                    let grant = grants.get(&s).cloned();
+                                    let allowed = match &username {
+                                        Some(user) => authed_sub_ok(&state.db, &s, user, is_site_admin).await,
+                                        None => guest_sub_ok(&state.db, &s, grant.as_deref()).await,
+                                    };
+                                    if allowed {
+                                        if let Some(g) = grant {
+                                            guest_grants.insert(s.clone(), g);
+                                        }
+                                        subs.insert(s);
+                                    }
+                                }
+                                if !send_voice_snapshot(&state, &mut socket, username.as_deref(), is_site_admin, &member_servers, &subs, &mut viewable).await {
+                                    break;
+                                }
+                            }
+                            Ok(ClientMsg::P2pHosting { peer_id, ids }) => {
+                                if let Some(user) = &username {
+                                    let ids: Vec<String> = ids.into_iter().take(P2P_IDS_CAP).collect();
+                                    state.hub.set_p2p(user, conn, peer_id.clone(), ids.clone());
+                                    let (scope_servers, scope_users) = p2p_scope(&state.db, user).await;
+                                    state.hub.broadcast(WsEvent::P2pAvailability {
+                                        hoster: user.clone(),
                                         peer_id: Some(peer_id),
                                         ids,
                                         online: true,
-                                        scope_servers: Vec::new(),
-                                        scope_users: Vec::new(),
-                                    },
-                                    None => p2p_offline(hoster, Vec::new(), Vec::new()),
-                                };
-                                if let Ok(json) = serde_json::to_string(&ev)
-                                    && socket.send(Frame::Text(json.into())).await.is_err() {
-                                        closed = true;
-                                        break;
-                                    }
+                                        scope_servers,
+                                        scope_users,
+                                    });
+                                }
                             }
-                            if closed {
-                                break;
+                            Ok(ClientMsg::P2pWho { hosters }) => {
+                                let mut closed = false;
+                                for hoster in hosters.into_iter().take(P2P_IDS_CAP) {
+                                    let hoster = hoster.to_lowercase();
+                                    let ev = match state.hub.p2p_of(&hoster) {
+                                        Some((peer_id, ids)) => WsEvent::P2pAvailability {
+                                            hoster,
+                                            peer_id: Some(peer_id),
+                                            ids,
+                                            online: true,
+                                            scope_servers: Vec::new(),
+                                            scope_users: Vec::new(),
+                                        },
+                                        None => p2p_offline(hoster, Vec::new(), Vec::new()),
+                                    };
+                                    if let Ok(json) = serde_json::to_string(&ev)
+                                        && socket.send(Frame::Text(json.into())).await.is_err() {
+                                            closed = true;
+                                            break;
+                                        }
+                                }
+                                if closed {
+                                    break;
+                                }
                             }
-                        }
-                        Ok(ClientMsg::Auth { token: _ }) | Err(_) => {}
-                        Ok(other) => {
-                            let res = match &username {
-                                Some(user) => voice_msg(&state, user, conn, other).await,
-                                None => Err("Create an account to join voice".to_string()),
-                            };
-                            if let Err(message) = res
-                                && let Ok(json) = serde_json::to_string(&WsEvent::Error { message })
-                                    && socket.send(Frame::Text(json.into())).await.is_err() {
-                                        break;
+                            Ok(ClientMsg::Auth { token: fresh }) => {
+                                let (Some(user), Some(fresh)) = (&username, fresh) else { continue };
+                                match token_owned_by(&state, user, &fresh).await {
+                                    true => {
+                                        token = Some(fresh);
+                                        recheck_at = Instant::now() + recheck;
+                                    }
+                                    false => {
+                                        if let Ok(json) = serde_json::to_string(&WsEvent::Error { message: "Invalid token".to_string() })
+                                            && socket.send(Frame::Text(json.into())).await.is_err() {
+                                                break;
+                                            }
                                     }
+                                }
+                            }
+                            Err(_) => {}
+                            Ok(other) => {
+                                let res = match &username {
+                                    Some(user) => voice_msg(&state, user, conn, other).await,
+                                    None => Err("Create an account to join voice".to_string()),
+                                };
+                                if let Err(message) = res
+                                    && let Ok(json) = serde_json::to_string(&WsEvent::Error { message })
+                                        && socket.send(Frame::Text(json.into())).await.is_err() {
+                                            break;
+                                        }
+                            }
                         }
-                    },
+                    }
                     Some(Ok(Frame::Close(_))) | None => break,
                     Some(Ok(_)) => {}
                     Some(Err(_)) => break,
@@ -1407,6 +1922,9 @@ async fn run(state: AppState, mut socket: WebSocket) {
         }
     }
     if let Some(user) = &username {
+        if let Some(scope) = typing {
+            state.hub.broadcast(scope.event(user, false));
+        }
         for end in state.hub.drop_conn(user, conn) {
             finalize_call_log(&state, end, false).await;
         }
@@ -1433,6 +1951,15 @@ async fn run(state: AppState, mut socket: WebSocket) {
         if let Some(server) = &viewing {
             state.hub.set_viewing(user, Some(server), None)
         }
+        if state.hub.disconnect(user, conn) {
+            seen(&state.db, user).await;
+        }
+    }
+}
+
+async fn seen(db: &Db, user: &str) {
+    if let Err(e) = mark_seen(db, user).await {
+        tracing::warn!("last seen update failed: {e}");
     }
 }
 
@@ -1445,37 +1972,29 @@ fn track(
     viewable: &mut HashMap<i64, bool>,
 ) {
     match ev {
-        WsEvent::MemberJoined { server, member } => {
-            if Some(member.user.username.as_str()) == me {
-                member_servers.insert(server.clone());
-                viewable.clear();
-            }
+        WsEvent::MemberJoined { server, member } if Some(member.user.username.as_str()) == me => {
+            member_servers.insert(server.clone());
+            viewable.clear();
         }
-        WsEvent::MemberLeft { server, username } | WsEvent::MemberKicked { server, username } => {
-            if Some(username.as_str()) == me {
-                member_servers.remove(server);
-                viewable.clear();
-                if viewing.as_deref() == Some(server.as_str()) {
-                    *viewing = None;
-                }
+        WsEvent::MemberLeft { server, username } | WsEvent::MemberKicked { server, username }
+            if Some(username.as_str()) == me =>
+        {
+            member_servers.remove(server);
+            viewable.clear();
+            if viewing.as_deref() == Some(server.as_str()) {
+                *viewing = None;
             }
         }
-        WsEvent::ServerCreated { server } => {
-            if server.creator.as_deref() == me {
-                member_servers.insert(server.name.clone());
-                viewable.clear();
-            }
+        WsEvent::ServerCreated { server } if server.creator.as_deref() == me => {
+            member_servers.insert(server.name.clone());
+            viewable.clear();
         }
         WsEvent::AdminChanged {
             server: _,
             username,
             is_admin: _,
             perms: _,
-        } => {
-            if Some(username.as_str()) == me {
-                viewable.clear();
-            }
-        }
+        } if Some(username.as_str()) == me => viewable.clear(),
         WsEvent::RolesChanged { server: _ } => viewable.clear(),
         WsEvent::ChannelPermsChanged {
             server: _,
@@ -1523,6 +2042,15 @@ fn event_channel(ev: &WsEvent) -> Option<(&str, i64)> {
             dm_id: _,
             dm_users: _,
             message: _,
+            muted: _,
+            mentioned: _,
+        }
+        | WsEvent::MessageRestored {
+            server,
+            channel_id,
+            dm_id: _,
+            dm_users: _,
+            message: _,
         } => scoped(server, channel_id),
         WsEvent::MessageDeleted {
             server,
@@ -1532,6 +2060,11 @@ fn event_channel(ev: &WsEvent) -> Option<(&str, i64)> {
             id: _,
             thread_root_id: _,
         } => scoped(server, channel_id),
+        WsEvent::MessagesPurged {
+            server,
+            channel_id,
+            ids: _,
+        } => Some((server.as_str(), *channel_id)),
         WsEvent::MediaRemoved {
             server,
             channel_id,
@@ -1558,6 +2091,20 @@ fn event_channel(ev: &WsEvent) -> Option<(&str, i64)> {
             ord: _,
             banner: _,
         } => scoped(server, channel_id),
+        WsEvent::Typing {
+            server,
+            channel_id,
+            dm_id: _,
+            dm_users: _,
+            username: _,
+        }
+        | WsEvent::TypingStopped {
+            server,
+            channel_id,
+            dm_id: _,
+            dm_users: _,
+            username: _,
+        } => scoped(server, channel_id),
         WsEvent::ChannelRenamed { server, channel } => Some((server.as_str(), channel.id)),
         WsEvent::VoiceState {
             server,
@@ -1597,6 +2144,15 @@ fn wants(
             dm_id: _,
             dm_users,
             message: _,
+            muted: _,
+            mentioned: _,
+        }
+        | WsEvent::MessageRestored {
+            server,
+            channel_id: _,
+            dm_id: _,
+            dm_users,
+            message: _,
         } => scoped(server, dm_users),
         WsEvent::MessageUpdated {
             message: _,
@@ -1610,6 +2166,11 @@ fn wants(
             id: _,
             thread_root_id: _,
         } => scoped(server, dm_users),
+        WsEvent::MessagesPurged {
+            server,
+            channel_id: _,
+            ids: _,
+        } => in_server(server),
         WsEvent::MediaRemoved {
             server,
             channel_id: _,
@@ -1642,6 +2203,7 @@ fn wants(
             server,
             channel_id: _,
         } => in_server(server),
+        WsEvent::ChannelsReordered { server, ids: _ } => in_server(server),
         WsEvent::MemberJoined { server, member } => {
             in_server(server) || Some(member.user.username.as_str()) == me
         }
@@ -1664,19 +2226,72 @@ fn wants(
             server,
             username: _,
             online: _,
+            status: _,
+        }
+        | WsEvent::StatusChanged {
+            server,
+            username: _,
+            status: _,
         } => in_server(server),
+        WsEvent::Typing {
+            server,
+            channel_id: _,
+            dm_id: _,
+            dm_users,
+            username,
+        }
+        | WsEvent::TypingStopped {
+            server,
+            channel_id: _,
+            dm_id: _,
+            dm_users,
+            username,
+        } => scoped(server, dm_users) && Some(username.as_str()) != me,
         WsEvent::ReadUpdated {
             username,
             scope: _,
             last_read: _,
+        }
+        | WsEvent::DmPinned {
+            username,
+            dm_id: _,
+            pinned: _,
+        }
+        | WsEvent::DmMuted {
+            username,
+            dm_id: _,
+            muted: _,
+        }
+        | WsEvent::ServerWelcome {
+            server: _,
+            username,
+            message: _,
         } => Some(username.as_str()) == me,
+        WsEvent::TransferOffered { transfer } => {
+            me.is_some_and(|user| user == transfer.from_user || user == transfer.to_user)
+        }
+        WsEvent::TransferClosed {
+            server: _,
+            from_user,
+            to_user,
+            accepted: _,
+        } => me.is_some_and(|user| user == from_user || user == to_user),
+        WsEvent::MessageReported { report: _ } => is_site_admin,
         WsEvent::ServerCreated { server } => is_site_admin || server.creator.as_deref() == me,
         WsEvent::ServerRenamed {
             old_name,
             server: _,
         } => is_site_admin || in_server(old_name),
         WsEvent::ServerDeleted { name } => is_site_admin || in_server(name),
-        WsEvent::Banned { username: _ } | WsEvent::SettingsChanged { settings: _ } => true,
+        WsEvent::Banned {
+            username: _,
+            purged: _,
+        }
+        | WsEvent::SettingsChanged { settings: _ }
+        | WsEvent::Announcement {
+            message: _,
+            level: _,
+        } => true,
         WsEvent::DmCreated { dm_users } => {
             me.is_some_and(|user| dm_users.iter().any(|u| u == user))
         }
@@ -1718,6 +2333,512 @@ fn wants(
             dm_users,
             reason: _,
         } => scoped(server, dm_users),
-        WsEvent::Error { message: _ } => false,
+        WsEvent::Error { message: _ } | WsEvent::ResyncRequired { missed: _ } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use axum::Json;
+    use axum::Router;
+    use axum::extract::ws::WebSocketUpgrade;
+    use axum::extract::{Path, State};
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use sqlx::Row;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Message as ClientFrame;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    use super::{Hub, PresenceStatus, WsEvent, identify, next_event, run, typing_scope, wants};
+    use crate::api::Authed;
+    use crate::api::messages::{send_channel_message, send_to_dm};
+    use crate::api::test_util::{add_member, done, mem_user, temp_state};
+    use crate::db::now;
+    use crate::state::AppState;
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn serve(state: AppState, recheck: Duration) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let url = format!("ws://{}/ws", listener.local_addr().expect("addr"));
+        let app = Router::new().route(
+            "/ws",
+            get(move |ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(move |socket| run(state, socket, recheck))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    async fn client(url: &str, token: &str) -> Client {
+        let (mut ws, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("connect");
+        say(&mut ws, json!({ "type": "auth", "token": token })).await;
+        ws
+    }
+
+    async fn say(ws: &mut Client, msg: Value) {
+        ws.send(ClientFrame::text(msg.to_string()))
+            .await
+            .expect("send frame");
+    }
+
+    async fn listen(ws: &mut Client, window: Duration) -> (Vec<Value>, bool) {
+        let mut events = Vec::new();
+        let deadline = tokio::time::Instant::now() + window;
+        loop {
+            match tokio::time::timeout_at(deadline, ws.next()).await {
+                Err(_) => return (events, false),
+                Ok(Some(Ok(ClientFrame::Text(text)))) => {
+                    events.push(serde_json::from_str(&text).expect("event json"))
+                }
+                Ok(Some(Ok(ClientFrame::Close(_)))) | Ok(Some(Err(_))) | Ok(None) => {
+                    return (events, true);
+                }
+                Ok(Some(Ok(_))) => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn revoked_tokens_close_sockets_unless_refreshed() {
+        let (state, path) = temp_state("ws_refresh").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES('amy', 'amy', 'x', 'text', 'identicon', 0, 0)")
+            .execute(db)
+            .await
+            .expect("insert user");
+        for token in ["old", "fresh"] {
+            sqlx::query(
+                "INSERT INTO tokens(token, username, created_at, expires_at) VALUES($1, 'amy', 0, $2)",
+            )
+            .bind(token)
+            .bind(now() + 3600)
+            .execute(db)
+            .await
+            .expect("insert token");
+        }
+        let url = serve(state.clone(), Duration::from_millis(300)).await;
+        let mut stale = client(&url, "old").await;
+        let mut renewed = client(&url, "old").await;
+        say(&mut renewed, json!({ "type": "auth", "token": "fresh" })).await;
+        let (_, closed) = listen(&mut renewed, Duration::from_millis(100)).await;
+        assert!(!closed);
+        sqlx::query("DELETE FROM tokens WHERE token = 'old'")
+            .execute(db)
+            .await
+            .expect("revoke token");
+
+        let (events, closed) = listen(&mut stale, Duration::from_secs(2)).await;
+        assert!(closed);
+        assert!(
+            events
+                .iter()
+                .any(|e| e["type"] == "error" && e["message"] == "Session expired")
+        );
+        let (events, closed) = listen(&mut renewed, Duration::from_secs(1)).await;
+        assert!(!closed);
+        assert!(events.iter().all(|e| e["type"] != "error"));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn reorders_hide_restricted_channels() {
+        let (state, path) = temp_state("ws_reorder").await;
+        let db = &state.db;
+        for name in ["amy", "bob"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert user");
+            sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES($1, $1, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert token");
+            add_member(db, "rchat", name, 0, 0, 0).await;
+        }
+        let general: i64 = sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("general")
+            .try_get(0)
+            .expect("id");
+        let secret: i64 = sqlx::query(
+            "INSERT INTO channels(server, name, created_at) VALUES('rchat', 'secret', 0) RETURNING id",
+        )
+        .fetch_one(db)
+        .await
+        .expect("insert channel")
+        .try_get(0)
+        .expect("id");
+        sqlx::query("INSERT INTO channel_perms(channel_id, subject, can_view, can_send, can_read_history) VALUES($1, 'u:amy', 1, 1, 1)")
+            .bind(secret)
+            .execute(db)
+            .await
+            .expect("restrict channel");
+        let url = serve(state.clone(), Duration::from_secs(60)).await;
+        let mut amy = client(&url, "amy").await;
+        let mut bob = client(&url, "bob").await;
+        listen(&mut amy, Duration::from_millis(100)).await;
+        listen(&mut bob, Duration::from_millis(100)).await;
+        state.hub.broadcast(WsEvent::ChannelsReordered {
+            server: "rchat".to_string(),
+            ids: vec![secret, general],
+        });
+        let order = |events: Vec<Value>| {
+            events
+                .into_iter()
+                .find(|e| e["type"] == "channels_reordered")
+                .map(|e| e["ids"].clone())
+        };
+        let (events, _) = listen(&mut amy, Duration::from_millis(300)).await;
+        assert_eq!(order(events), Some(json!([secret, general])));
+        let (events, _) = listen(&mut bob, Duration::from_millis(300)).await;
+        assert_eq!(order(events), Some(json!([general])));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn dm_mute_flag_is_per_recipient_socket() {
+        let (state, path) = temp_state("ws_dm_mute").await;
+        let db = &state.db;
+        for name in ["amy", "bob"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert user");
+            sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES($1, $1, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert token");
+        }
+        let dm: i64 =
+            sqlx::query("INSERT INTO dms(user_a, user_b) VALUES('amy', 'bob') RETURNING id")
+                .fetch_one(db)
+                .await
+                .expect("insert dm")
+                .try_get(0)
+                .expect("id");
+        sqlx::query("INSERT INTO dm_mutes(username, dm_id) VALUES('amy', $1)")
+            .bind(dm)
+            .execute(db)
+            .await
+            .expect("mute dm");
+        let url = serve(state.clone(), Duration::from_secs(60)).await;
+        let mut amy = client(&url, "amy").await;
+        let mut bob = client(&url, "bob").await;
+        listen(&mut amy, Duration::from_millis(100)).await;
+        listen(&mut bob, Duration::from_millis(100)).await;
+        send_to_dm(
+            &state,
+            &mem_user("bob", false),
+            dm,
+            None,
+            serde_json::from_value(json!({ "content": "hi" })).expect("req"),
+        )
+        .await
+        .expect("send dm");
+        let muted = |events: Vec<Value>| {
+            events
+                .into_iter()
+                .find(|e| e["type"] == "message")
+                .map(|e| e["muted"].clone())
+        };
+        let (events, _) = listen(&mut amy, Duration::from_millis(300)).await;
+        assert_eq!(muted(events), Some(json!(true)));
+        let (events, _) = listen(&mut bob, Duration::from_millis(300)).await;
+        assert_eq!(muted(events), Some(json!(false)));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn mass_mentions_notify_everyone_or_the_online() {
+        let (state, path) = temp_state("ws_mass_mention").await;
+        let db = &state.db;
+        for name in ["amy", "bob", "cat"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert user");
+            sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES($1, $1, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert token");
+        }
+        add_member(db, "rchat", "amy", 1, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        add_member(db, "rchat", "cat", 0, 0, 0).await;
+        let cid: i64 = sqlx::query("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("general")
+            .try_get(0)
+            .expect("id");
+        let url = serve(state.clone(), Duration::from_secs(60)).await;
+        let mut amy = client(&url, "amy").await;
+        let mut bob = client(&url, "bob").await;
+        let mut cat = client(&url, "cat").await;
+        for ws in [&mut amy, &mut bob, &mut cat] {
+            say(ws, json!({ "type": "viewing", "server": "rchat" })).await;
+        }
+        say(&mut cat, json!({ "type": "presence", "status": "away" })).await;
+        for ws in [&mut amy, &mut bob, &mut cat] {
+            listen(ws, Duration::from_millis(100)).await;
+        }
+        let mentioned = |events: Vec<Value>| {
+            events
+                .into_iter()
+                .find(|e| e["type"] == "message")
+                .map(|e| e["mentioned"].clone())
+        };
+        for (content, cat_pinged) in [("@everyone up", true), ("@here up", false)] {
+            let _ = send_channel_message(
+                State(state.clone()),
+                Path(cid),
+                Authed(mem_user("amy", false)),
+                HeaderMap::new(),
+                Json(serde_json::from_value(json!({ "content": content })).expect("req")),
+            )
+            .await
+            .expect("send");
+            let (events, _) = listen(&mut amy, Duration::from_millis(300)).await;
+            assert_eq!(mentioned(events), Some(json!(false)));
+            let (events, _) = listen(&mut bob, Duration::from_millis(300)).await;
+            assert_eq!(mentioned(events), Some(json!(true)));
+            let (events, _) = listen(&mut cat, Duration::from_millis(300)).await;
+            assert_eq!(mentioned(events), Some(json!(cat_pinged)));
+        }
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn identify_rejects_bad_tokens() {
+        let (state, path) = temp_state("ws_identify").await;
+        let db = &state.db;
+        sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES('amy', 'amy', 'x', 'text', 'identicon', 0, 0)")
+            .execute(db)
+            .await
+            .expect("insert user");
+        sqlx::query("INSERT INTO tokens(token, username, created_at) VALUES('good', 'amy', 0)")
+            .execute(db)
+            .await
+            .expect("insert token");
+        assert_eq!(
+            identify(&state, Some("good".to_string())).await,
+            Some((Some("amy".to_string()), false))
+        );
+        assert_eq!(identify(&state, Some("bogus".to_string())).await, None);
+        sqlx::query("INSERT INTO tokens(token, username, created_at, impersonated_by) VALUES('imp', 'amy', 0, 'root')")
+            .execute(db)
+            .await
+            .expect("insert impersonation token");
+        assert_eq!(identify(&state, Some("imp".to_string())).await, None);
+        assert_eq!(identify(&state, None).await, Some((None, false)));
+        sqlx::query("UPDATE settings SET value = '0' WHERE key = 'guests_enabled'")
+            .execute(db)
+            .await
+            .expect("disable guests");
+        assert_eq!(identify(&state, None).await, None);
+        sqlx::query("INSERT INTO banned_usernames(username) VALUES('amy')")
+            .execute(db)
+            .await
+            .expect("ban amy");
+        assert_eq!(identify(&state, Some("good".to_string())).await, None);
+        done(state, path).await;
+    }
+
+    #[test]
+    fn away_is_broadcast_to_viewed_servers() {
+        let hub = Hub::new();
+        let mut rx = hub.subscribe();
+        hub.connect("amy", 1);
+        hub.set_viewing("amy", None, Some("rchat"));
+        hub.set_away("amy", 1, true);
+        hub.set_away("amy", 1, true);
+        hub.set_away("amy", 1, false);
+        let mut seen = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            match ev {
+                WsEvent::PresenceChanged {
+                    server,
+                    username: _,
+                    online: _,
+                    status,
+                }
+                | WsEvent::StatusChanged {
+                    server,
+                    username: _,
+                    status,
+                } => seen.push((server, status)),
+                _ => {}
+            }
+        }
+        let rchat = |status| ("rchat".to_string(), status);
+        assert_eq!(
+            seen,
+            vec![
+                rchat(PresenceStatus::Online),
+                rchat(PresenceStatus::Away),
+                rchat(PresenceStatus::Online),
+            ]
+        );
+        hub.set_away("amy", 1, true);
+        assert_eq!(hub.status("rchat", "amy"), PresenceStatus::Away);
+        hub.set_viewing("amy", Some("rchat"), None);
+        hub.disconnect("amy", 1);
+        assert_eq!(hub.status("rchat", "amy"), PresenceStatus::Offline);
+        hub.connect("amy", 2);
+        hub.set_viewing("amy", None, Some("rchat"));
+        assert_eq!(hub.status("rchat", "amy"), PresenceStatus::Online);
+    }
+
+    #[test]
+    fn away_needs_every_connection_idle() {
+        let hub = Hub::new();
+        hub.connect("amy", 1);
+        hub.connect("amy", 2);
+        hub.set_viewing("amy", None, Some("rchat"));
+        let mut rx = hub.subscribe();
+        hub.set_away("amy", 1, true);
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Online);
+        assert!(rx.try_recv().is_err());
+        hub.set_away("amy", 2, true);
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Away);
+        hub.set_away("amy", 1, false);
+        assert_eq!(hub.status("rchat", "amy"), PresenceStatus::Online);
+        hub.set_away("amy", 1, true);
+        hub.connect("amy", 3);
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Online);
+        hub.disconnect("amy", 3);
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Away);
+        let mut seen = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            if let WsEvent::StatusChanged {
+                server: _,
+                username: _,
+                status,
+            } = ev
+            {
+                seen.push(status);
+            }
+        }
+        assert_eq!(
+            seen,
+            vec![
+                PresenceStatus::Away,
+                PresenceStatus::Online,
+                PresenceStatus::Away,
+                PresenceStatus::Online,
+                PresenceStatus::Away,
+            ]
+        );
+    }
+
+    #[test]
+    fn second_tab_keeps_user_online() {
+        let hub = Hub::new();
+        assert!(hub.connect("amy", 1));
+        assert!(!hub.connect("amy", 2));
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Online);
+        assert!(!hub.disconnect("amy", 1));
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Online);
+        assert!(hub.disconnect("amy", 2));
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Offline);
+        assert!(!hub.disconnect("amy", 2));
+        assert!(hub.connect("amy", 3));
+    }
+
+    #[test]
+    fn ban_drops_every_connection() {
+        let hub = Hub::new();
+        hub.connect("amy", 1);
+        hub.connect("amy", 2);
+        hub.set_away("amy", 1, true);
+        hub.broadcast(WsEvent::Banned {
+            username: "amy".to_string(),
+            purged: false,
+        });
+        assert_eq!(hub.user_status("amy"), PresenceStatus::Offline);
+        assert!(!hub.disconnect("amy", 1));
+        assert!(!hub.disconnect("amy", 2));
+        assert!(hub.connect("amy", 3));
+    }
+
+    #[tokio::test]
+    async fn typing_stop_reaches_others() {
+        let (state, path) = temp_state("ws_typing").await;
+        let db = &state.db;
+        for name in ["amy", "bob", "cat"] {
+            sqlx::query("INSERT INTO users(username, display_name, password_hash, password_kind, avatar_kind, is_site_admin, created_at) VALUES($1, $1, 'x', 'text', 'identicon', 0, 0)")
+                .bind(name)
+                .execute(db)
+                .await
+                .expect("insert user");
+        }
+        add_member(db, "rchat", "amy", 0, 0, 0).await;
+        add_member(db, "rchat", "bob", 0, 0, 0).await;
+        let cid: i64 = sqlx::query_scalar("SELECT id FROM channels WHERE server = 'rchat'")
+            .fetch_one(db)
+            .await
+            .expect("channel");
+        let scope = typing_scope(&state, "amy", Some(cid), None)
+            .await
+            .expect("amy can type");
+        assert!(typing_scope(&state, "cat", Some(cid), None).await.is_err());
+        assert!(typing_scope(&state, "amy", None, None).await.is_err());
+        let servers: HashSet<String> = ["rchat".to_string()].into();
+        let none = HashSet::new();
+        let stop = scope.event("amy", false);
+        assert!(matches!(stop, WsEvent::TypingStopped { .. }));
+        assert!(wants(&stop, Some("bob"), false, &servers, &none));
+        assert!(!wants(&stop, Some("amy"), false, &servers, &none));
+        assert!(!wants(&stop, Some("cat"), false, &none, &none));
+        done(state, path).await;
+    }
+
+    #[tokio::test]
+    async fn lag_becomes_resync() {
+        let hub = Hub::new();
+        let mut rx = hub.subscribe();
+        for n in 0..300 {
+            hub.broadcast(WsEvent::Error {
+                message: n.to_string(),
+            });
+        }
+        let missed = match next_event(&mut rx).await {
+            Some(Err(missed)) => missed,
+            _ => panic!("expected a lag"),
+        };
+        assert_eq!(missed, 300 - 256);
+        let resync = WsEvent::ResyncRequired { missed };
+        assert!(!wants(
+            &resync,
+            Some("amy"),
+            true,
+            &HashSet::new(),
+            &HashSet::new()
+        ));
+        let json = serde_json::to_value(&resync).expect("serialize");
+        assert_eq!(json["type"], "resync_required");
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Some(Ok(WsEvent::Error { .. }))
+        ));
     }
 }